@@ -24,9 +24,14 @@ fn main() -> io::Result<()> {
 
     while let Ok((buf, _mmap)) = capture.take_frame() {
         println!(
-            "used {} flags {:08x} field {:?} seq {} length {} input {} t {}/{}",
-            buf.bytesused, buf.flags, buf.field, buf.sequence, buf.length, buf.input,
-            buf.timestamp.tv_sec, buf.timestamp.tv_usec
+            "used {} flags {:08x} field {:?} seq {} length {} t {}/{}",
+            buf.bytesused,
+            buf.flags,
+            buf.field,
+            buf.sequence,
+            buf.length,
+            buf.timestamp.tv_sec,
+            buf.timestamp.tv_usec
         );
         capture.return_frame(&buf)?;
     }
@@ -0,0 +1,267 @@
+//! The ISP of the Raspberry Pi, as exposed by the `bcm2835-isp` driver.
+//!
+//! The ISP is a memory-to-memory device split over several video nodes:
+//! raw Bayer frames are written to the input node, and processed YUV or
+//! RGB frames are read from the output nodes. Processing is tuned through
+//! the private compound controls of `bcm2835-isp.h`, which the input node
+//! carries.
+
+#![allow(non_camel_case_types)]
+
+use std::io;
+use std::time::Duration;
+
+use crate::capture::{Capture, IoMethod};
+use crate::frame::Frame;
+use crate::output::Output;
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+/// Node frames to process are written to.
+pub const INPUT_NODE: &str = "/dev/video13";
+/// Node of the full resolution output.
+pub const OUTPUT0_NODE: &str = "/dev/video14";
+/// Node of the low resolution output.
+pub const OUTPUT1_NODE: &str = "/dev/video15";
+/// Node of the statistics output.
+pub const STATS_NODE: &str = "/dev/video16";
+
+pub const V4L2_CID_USER_BCM2835_ISP_BASE: u32 = V4L2_CID_BASE + 0x11c0;
+pub const V4L2_CID_USER_BCM2835_ISP_CC_MATRIX: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0001;
+pub const V4L2_CID_USER_BCM2835_ISP_LENS_SHADING: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0002;
+pub const V4L2_CID_USER_BCM2835_ISP_BLACK_LEVEL: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0003;
+pub const V4L2_CID_USER_BCM2835_ISP_GEQ: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0004;
+pub const V4L2_CID_USER_BCM2835_ISP_GAMMA: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0005;
+pub const V4L2_CID_USER_BCM2835_ISP_DENOISE: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0006;
+pub const V4L2_CID_USER_BCM2835_ISP_SHARPEN: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0007;
+pub const V4L2_CID_USER_BCM2835_ISP_DPC: u32 = V4L2_CID_USER_BCM2835_ISP_BASE + 0x0008;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_rational {
+    pub num: i32,
+    pub den: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_ccm {
+    pub ccm: [[bcm2835_isp_rational; 3]; 3],
+    pub offsets: [bcm2835_isp_rational; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_custom_ccm {
+    pub enabled: u32,
+    pub ccm: bcm2835_isp_ccm,
+}
+
+/* values for bcm2835_isp_lens_shading.gain_format */
+pub const GAIN_FORMAT_U0P8_1: u32 = 0;
+pub const GAIN_FORMAT_U1P7_0: u32 = 1;
+pub const GAIN_FORMAT_U1P7_1: u32 = 2;
+pub const GAIN_FORMAT_U2P6_0: u32 = 3;
+pub const GAIN_FORMAT_U2P6_1: u32 = 4;
+pub const GAIN_FORMAT_U3P5_0: u32 = 5;
+pub const GAIN_FORMAT_U3P5_1: u32 = 6;
+pub const GAIN_FORMAT_U4P10: u32 = 7;
+
+/// Lens shading gains, read from the dmabuf `dmabuf` holding a
+/// `grid_width` by `grid_height` table for each Bayer channel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_lens_shading {
+    pub enabled: u32,
+    pub grid_cell_size: u32,
+    pub grid_width: u32,
+    pub grid_stride: u32,
+    pub grid_height: u32,
+    pub dmabuf: i32,
+    pub ref_transform: u32,
+    pub corner_sampled: u32,
+    pub gain_format: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_black_level {
+    pub enabled: u32,
+    pub black_level_r: u16,
+    pub black_level_g: u16,
+    pub black_level_b: u16,
+    pub padding: [u8; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_geq {
+    pub enabled: u32,
+    pub offset: u32,
+    pub slope: bcm2835_isp_rational,
+}
+
+pub const BCM2835_NUM_GAMMA_PTS: usize = 33;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct bcm2835_isp_gamma {
+    pub enabled: u32,
+    pub x: [u16; BCM2835_NUM_GAMMA_PTS],
+    pub y: [u16; BCM2835_NUM_GAMMA_PTS],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_denoise {
+    pub enabled: u32,
+    pub constant: u32,
+    pub slope: bcm2835_isp_rational,
+    pub strength: bcm2835_isp_rational,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_sharpen {
+    pub enabled: u32,
+    pub threshold: bcm2835_isp_rational,
+    pub strength: bcm2835_isp_rational,
+    pub limit: bcm2835_isp_rational,
+}
+
+/* values for bcm2835_isp_dpc.strength */
+pub const DPC_MODE_OFF: u32 = 0;
+pub const DPC_MODE_NORMAL: u32 = 1;
+pub const DPC_MODE_STRONG: u32 = 2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct bcm2835_isp_dpc {
+    pub enabled: u32,
+    pub strength: u32,
+}
+
+/// The ISP set up to turn Bayer frames into frames of the full
+/// resolution output.
+pub struct Bcm2835Isp {
+    input: Output,
+    output: Capture,
+}
+
+impl Bcm2835Isp {
+    /// Opens the ISP at its usual nodes, converting frames in `input`
+    /// format, a Bayer format, to `output` format, e.g. YUV420.
+    pub fn open(input: &v4l2_pix_format, output: &v4l2_pix_format) -> io::Result<Bcm2835Isp> {
+        Bcm2835Isp::open_nodes(INPUT_NODE, OUTPUT0_NODE, input, output)
+    }
+
+    /// Like `open`, for ISPs at other nodes, e.g. of a second instance.
+    pub fn open_nodes(
+        input_node: &str,
+        output_node: &str,
+        input: &v4l2_pix_format,
+        output: &v4l2_pix_format,
+    ) -> io::Result<Bcm2835Isp> {
+        let input = Output::open(input_node, input)?;
+
+        let device = V4l2Device::open(output_node)?;
+        let format = device.set_capture_format(output)?;
+        let output = Capture::new(device, output_node, format, IoMethod::Mmap);
+
+        Ok(Bcm2835Isp { input, output })
+    }
+
+    /// Returns the input node, which carries the ISP controls.
+    pub fn device(&self) -> &V4l2Device {
+        self.input.device()
+    }
+
+    pub fn input_format(&self) -> &v4l2_pix_format {
+        self.input.format()
+    }
+
+    pub fn output_format(&self) -> &v4l2_pix_format {
+        self.output.format()
+    }
+
+    /// Prepares `count` buffers on both sides and starts the output.
+    /// The input starts with the first queued frame.
+    pub fn start(&mut self, count: usize) -> io::Result<()> {
+        self.input.prepare_mmapped(count)?;
+        self.output.prepare_mmapped(count)?;
+        self.output.start()
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.input.stop()?;
+        self.output.stop()
+    }
+
+    /// Copies a Bayer frame into a free input buffer and queues it.
+    pub fn queue_input(&mut self, data: &[u8], timestamp: Duration) -> io::Result<()> {
+        let buffer = self.input.get_writable_buffer()?;
+        if data.len() > buffer.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame does not fit into an input buffer",
+            ));
+        }
+        buffer[..data.len()].copy_from_slice(data);
+        self.input.submit(data.len() as u32, timestamp)
+    }
+
+    /// Dequeues a processed frame, blocking until there is one. It must be
+    /// given back with `return_output(frame.buffer())`.
+    pub fn dequeue_output(&self) -> io::Result<Frame<'_>> {
+        self.output.dequeue_frame()
+    }
+
+    pub fn return_output(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        self.output.return_frame(buf)
+    }
+
+    /// Sets the white balance gains and the digital gain, in 1/1000.
+    pub fn set_gains(&self, red: i32, blue: i32, digital: i32) -> io::Result<()> {
+        let device = self.device();
+        device.set_control(V4L2_CID_RED_BALANCE, red)?;
+        device.set_control(V4L2_CID_BLUE_BALANCE, blue)?;
+        device.set_control(V4L2_CID_DIGITAL_GAIN, digital)
+    }
+
+    pub fn set_lens_shading(&self, value: &bcm2835_isp_lens_shading) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_LENS_SHADING, value)
+    }
+
+    pub fn set_color_matrix(&self, value: &bcm2835_isp_custom_ccm) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_CC_MATRIX, value)
+    }
+
+    pub fn set_black_level(&self, value: &bcm2835_isp_black_level) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_BLACK_LEVEL, value)
+    }
+
+    pub fn set_green_equalisation(&self, value: &bcm2835_isp_geq) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_GEQ, value)
+    }
+
+    pub fn set_gamma(&self, value: &bcm2835_isp_gamma) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_GAMMA, value)
+    }
+
+    pub fn set_denoise(&self, value: &bcm2835_isp_denoise) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_DENOISE, value)
+    }
+
+    pub fn set_sharpen(&self, value: &bcm2835_isp_sharpen) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_SHARPEN, value)
+    }
+
+    pub fn set_defective_pixel_correction(&self, value: &bcm2835_isp_dpc) -> io::Result<()> {
+        self.set(V4L2_CID_USER_BCM2835_ISP_DPC, value)
+    }
+
+    fn set<T: Copy>(&self, id: u32, value: &T) -> io::Result<()> {
+        // The payload types above mirror bcm2835-isp.h.
+        unsafe { self.device().set_compound_control(id, value) }
+    }
+}
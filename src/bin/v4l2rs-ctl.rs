@@ -0,0 +1,263 @@
+//! Small command line tool built on top of the `v4l2` crate.
+//!
+//! ```text
+//! v4l2rs-ctl list
+//! v4l2rs-ctl info [-d DEVICE]
+//! v4l2rs-ctl formats [-d DEVICE]
+//! v4l2rs-ctl controls [-d DEVICE]
+//! v4l2rs-ctl get [-d DEVICE] ID
+//! v4l2rs-ctl set [-d DEVICE] ID VALUE
+//! v4l2rs-ctl capture [-d DEVICE] [-s WxH] [-f FOURCC] [-n COUNT] [-o DIR]
+//! ```
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use v4l2::sys::uapi::*;
+use v4l2::sys::{FrameSize, OpenOptions, V4l2Device};
+use v4l2::Capture;
+
+const USAGE: &str = "usage: v4l2rs-ctl <command> [options]
+
+commands:
+    list                      list video devices
+    info                      print a full report of the device
+    formats                   list pixel formats and frame sizes
+    controls                  list controls with their current values
+    get ID                    print the value of a control
+    set ID VALUE              set the value of a control
+    capture                   capture frames to raw files
+
+options:
+    -d DEVICE                 device node (default /dev/video0)
+    -s WxH                    capture size
+    -f FOURCC                 capture pixel format (default YUYV)
+    -n COUNT                  number of frames to capture (default 1)
+    -o DIR                    output directory (default .)";
+
+struct Options {
+    device: String,
+    size: (u32, u32),
+    fourcc: u32,
+    count: usize,
+    output: PathBuf,
+    args: Vec<String>,
+}
+
+fn cstr(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+fn fourcc_to_string(fourcc: u32) -> String {
+    cstr(&fourcc.to_le_bytes())
+}
+
+fn parse_fourcc(s: &str) -> Option<u32> {
+    let b = s.as_bytes();
+    if b.len() != 4 {
+        return None;
+    }
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let mut it = s.splitn(2, 'x');
+    let w = it.next()?.parse().ok()?;
+    let h = it.next()?.parse().ok()?;
+    Some((w, h))
+}
+
+fn parse_u32(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn parse_options(args: impl Iterator<Item = String>) -> io::Result<Options> {
+    let mut opts = Options {
+        device: "/dev/video0".to_string(),
+        size: (640, 480),
+        fourcc: V4L2_PIX_FMT_YUYV,
+        count: 1,
+        output: PathBuf::from("."),
+        args: Vec::new(),
+    };
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| invalid("missing option value"));
+        match arg.as_str() {
+            "-d" => opts.device = value()?,
+            "-s" => opts.size = parse_size(&value()?).ok_or_else(|| invalid("bad size"))?,
+            "-f" => opts.fourcc = parse_fourcc(&value()?).ok_or_else(|| invalid("bad fourcc"))?,
+            "-n" => opts.count = value()?.parse().map_err(|_| invalid("bad count"))?,
+            "-o" => opts.output = PathBuf::from(value()?),
+            _ => opts.args.push(arg),
+        }
+    }
+
+    Ok(opts)
+}
+
+fn list() -> io::Result<()> {
+    let mut nodes = fs::read_dir("/dev")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("video"))
+        })
+        .collect::<Vec<_>>();
+    nodes.sort();
+
+    for path in nodes {
+        match open_read_only(&path).and_then(|dev| dev.capability()) {
+            Ok(caps) => println!(
+                "{}: {} ({}, {})",
+                path.display(),
+                cstr(&caps.card),
+                cstr(&caps.driver),
+                cstr(&caps.bus_info)
+            ),
+            Err(e) => println!("{}: {}", path.display(), e),
+        }
+    }
+
+    Ok(())
+}
+
+fn formats(dev: &V4l2Device) -> io::Result<()> {
+    for fmtdesc in dev.supported_formats(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE) {
+        println!(
+            "{} ({})",
+            fourcc_to_string(fmtdesc.pixelformat),
+            cstr(&fmtdesc.description)
+        );
+
+        for frmsize in dev.supported_frame_sizes(fmtdesc.pixelformat) {
+            match frmsize {
+                FrameSize::Discrete { width, height } => println!("    {}x{}", width, height),
+                FrameSize::Stepwise {
+                    min_width,
+                    max_width,
+                    step_width,
+                    min_height,
+                    max_height,
+                    step_height,
+                } => println!(
+                    "    {}x{} - {}x{} step {}x{}",
+                    min_width, min_height, max_width, max_height, step_width, step_height
+                ),
+                FrameSize::Continuous {
+                    min_width,
+                    max_width,
+                    min_height,
+                    max_height,
+                } => println!(
+                    "    {}x{} - {}x{}",
+                    min_width, min_height, max_width, max_height
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn controls(dev: &V4l2Device) -> io::Result<()> {
+    for ctrl in dev.control_report() {
+        if ctrl.is_class() {
+            println!("{}", ctrl.name);
+            continue;
+        }
+
+        let value = ctrl
+            .value
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "    0x{:08x} {:<32} min={} max={} step={} default={} value={}",
+            ctrl.id, ctrl.name, ctrl.minimum, ctrl.maximum, ctrl.step, ctrl.default_value, value
+        );
+    }
+
+    Ok(())
+}
+
+fn capture(opts: &Options) -> io::Result<()> {
+    let mut capture = Capture::with_device(&opts.device)
+        .video_size(opts.size.0, opts.size.1)
+        .pixel_format(opts.fourcc)
+        .buffers(4)
+        .open()?;
+
+    capture.start()?;
+
+    for i in 0..opts.count {
+        let (buf, mmap) = capture.take_frame()?;
+        let path = Path::new(&opts.output).join(format!("frame-{:04}.raw", i));
+        fs::File::create(&path)?.write_all(&mmap[..buf.bytesused as usize])?;
+        println!("{}: {} bytes", path.display(), buf.bytesused);
+        capture.return_frame(&buf)?;
+    }
+
+    capture.stop()
+}
+
+/// Opens `path` for queries, which also works on nodes the user may only
+/// read.
+fn open_read_only<P: AsRef<Path>>(path: P) -> io::Result<V4l2Device> {
+    OpenOptions::new().write(false).open(path)
+}
+
+fn run() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let command = args.next().ok_or_else(|| invalid("missing command"))?;
+    let opts = parse_options(args)?;
+
+    match command.as_str() {
+        "list" => list(),
+        "info" => {
+            print!("{}", open_read_only(&opts.device)?.info()?);
+            Ok(())
+        }
+        "formats" => formats(&open_read_only(&opts.device)?),
+        "controls" => controls(&open_read_only(&opts.device)?),
+        "get" => {
+            let id = opts.args.first().and_then(|s| parse_u32(s));
+            let id = id.ok_or_else(|| invalid("missing control id"))?;
+            println!("{}", open_read_only(&opts.device)?.control(id)?);
+            Ok(())
+        }
+        "set" => {
+            let id = opts.args.first().and_then(|s| parse_u32(s));
+            let id = id.ok_or_else(|| invalid("missing control id"))?;
+            let value = opts.args.get(1).and_then(|s| s.parse().ok());
+            let value = value.ok_or_else(|| invalid("missing control value"))?;
+            V4l2Device::open(&opts.device)?.set_control(id, value)
+        }
+        "capture" => capture(&opts),
+        _ => Err(invalid("unknown command")),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("v4l2rs-ctl: {}", e);
+        if e.kind() == io::ErrorKind::InvalidInput {
+            eprintln!("{}", USAGE);
+        }
+        process::exit(1);
+    }
+}
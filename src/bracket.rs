@@ -0,0 +1,110 @@
+//! Exposure bracketing, for stacking frames taken at several exposures into
+//! an HDR image.
+
+use std::io;
+use std::time::Duration;
+
+use crate::capture::Capture;
+use crate::sys::uapi::*;
+use crate::sys::ControlInfo;
+
+/// A frame captured at one exposure of a bracket.
+pub struct BracketFrame {
+    /// The value the exposure control was set to.
+    pub exposure: i32,
+    pub sequence: u32,
+    pub timestamp: Duration,
+    pub format: v4l2_pix_format,
+    pub data: Vec<u8>,
+}
+
+/// A sequence of exposures to capture one frame at each.
+///
+/// The camera is switched to manual exposure for the bracket. Frames
+/// captured right after an exposure change often still show the old
+/// exposure, so a number of settle frames is dropped after each change.
+pub struct Bracket {
+    exposures: Vec<i32>,
+    control: u32,
+    settle_frames: usize,
+}
+
+impl Bracket {
+    /// Brackets `exposures`, values of `V4L2_CID_EXPOSURE_ABSOLUTE` in
+    /// 100 µs units, dropping two settle frames after each change.
+    pub fn new(exposures: &[i32]) -> Bracket {
+        Bracket {
+            exposures: exposures.to_vec(),
+            control: V4L2_CID_EXPOSURE_ABSOLUTE,
+            settle_frames: 2,
+        }
+    }
+
+    /// Sets the exposure control to change instead, e.g.
+    /// `V4L2_CID_EXPOSURE` for raw sensors.
+    pub fn control(mut self, id: u32) -> Self {
+        self.control = id;
+        self
+    }
+
+    /// Sets the number of frames dropped after each exposure change.
+    pub fn settle_frames(mut self, count: usize) -> Self {
+        self.settle_frames = count;
+        self
+    }
+
+    /// Captures one frame per exposure from the started `capture`, in the
+    /// order of the exposures. The exposure settings are restored
+    /// afterwards, also on failure.
+    pub fn capture(&self, capture: &Capture) -> io::Result<Vec<BracketFrame>> {
+        if !capture.is_streaming() {
+            return Err(io::Error::other("capture is not streaming"));
+        }
+
+        let device = capture.device();
+        let info = ControlInfo::from_raw(&device.query_control(self.control)?, None);
+        for &exposure in &self.exposures {
+            info.validate(exposure)?;
+        }
+
+        // Cameras without an auto exposure control are always manual.
+        let auto = device.control(V4L2_CID_EXPOSURE_AUTO).ok();
+        let previous = device.control(self.control)?;
+        if auto.is_some() {
+            device.set_control(V4L2_CID_EXPOSURE_AUTO, V4L2_EXPOSURE_MANUAL)?;
+        }
+
+        let frames = self.capture_frames(capture);
+
+        let restored = device.set_control(self.control, previous);
+        if let Some(mode) = auto {
+            device.set_control(V4L2_CID_EXPOSURE_AUTO, mode)?;
+        }
+        let frames = frames?;
+        restored?;
+        Ok(frames)
+    }
+
+    fn capture_frames(&self, capture: &Capture) -> io::Result<Vec<BracketFrame>> {
+        let mut frames = Vec::with_capacity(self.exposures.len());
+        for &exposure in &self.exposures {
+            capture.device().set_control(self.control, exposure)?;
+
+            for _ in 0..self.settle_frames {
+                let frame = capture.dequeue_frame()?;
+                capture.return_frame(frame.buffer())?;
+            }
+
+            let frame = capture.dequeue_frame()?;
+            frames.push(BracketFrame {
+                exposure,
+                sequence: frame.sequence(),
+                timestamp: frame.timestamp(),
+                format: *frame.format(),
+                data: frame.data().to_vec(),
+            });
+            capture.return_frame(frame.buffer())?;
+        }
+        Ok(frames)
+    }
+}
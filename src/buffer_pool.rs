@@ -0,0 +1,323 @@
+//! Buffers shared between several V4L2 queues.
+//!
+//! A `BufferPool` owns a set of dmabufs, either exported from the mmap
+//! buffers of one queue or allocated from a DMA heap, and queues them on
+//! any number of attached queues. It records which queue holds each
+//! buffer, so that a buffer is never handed to two devices at once.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+
+use memmap::{MmapMut, MmapOptions};
+
+use crate::sys::ioctl::{iowr, IoctlRequest};
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+#[repr(C)]
+struct dma_heap_allocation_data {
+    len: u64,
+    fd: u32,
+    fd_flags: u32,
+    heap_flags: u64,
+}
+
+const DMA_HEAP_IOCTL_ALLOC: IoctlRequest =
+    iowr(b'H', 0, std::mem::size_of::<dma_heap_allocation_data>());
+
+/// The default DMA heap, backed by system memory.
+pub const DMA_HEAP_SYSTEM: &str = "/dev/dma_heap/system";
+
+/// A buffer of a pool.
+pub struct PoolBuffer {
+    fd: OwnedFd,
+    size: u32,
+    mmap: Option<MmapMut>,
+}
+
+impl PoolBuffer {
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the contents of the buffer, if it is mapped.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.mmap.as_deref()
+    }
+
+    pub fn data_mut(&mut self) -> Option<&mut [u8]> {
+        self.mmap.as_deref_mut()
+    }
+}
+
+struct Attachment<'a> {
+    device: &'a V4l2Device,
+    buf_type: v4l2_buf_type,
+    memory: v4l2_memory,
+}
+
+/// Identifies a queue attached to a `BufferPool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueId(usize);
+
+/// The single owner of a set of buffers shared by several queues.
+pub struct BufferPool<'a> {
+    buffers: Vec<PoolBuffer>,
+    attachments: Vec<Attachment<'a>>,
+    /* Queue holding each buffer, None if the application owns it */
+    owners: Vec<Option<QueueId>>,
+}
+
+impl<'a> BufferPool<'a> {
+    /// Allocates `count` mmap buffers on a queue of `device` and exports
+    /// them. The queue is attached to the pool as the returned `QueueId`.
+    pub fn from_device(
+        device: &'a V4l2Device,
+        buf_type: v4l2_buf_type,
+        count: usize,
+    ) -> io::Result<(BufferPool<'a>, QueueId)> {
+        let memory = v4l2_memory::V4L2_MEMORY_MMAP;
+        let n = device.request_buffers(buf_type, memory, count)?;
+
+        let buffers = match export_buffers(device, buf_type, n) {
+            Ok(buffers) => buffers,
+            Err(e) => {
+                // The buffers exported so far are unmapped and closed by
+                // now, so the driver can free them all.
+                let _ = device.request_buffers(buf_type, memory, 0);
+                return Err(e);
+            }
+        };
+
+        let mut pool = BufferPool::from_buffers(buffers);
+        pool.attachments.push(Attachment {
+            device,
+            buf_type,
+            memory,
+        });
+        Ok((pool, QueueId(0)))
+    }
+
+    /// Allocates `count` buffers of `size` bytes from the DMA heap at
+    /// `heap`, e.g. `DMA_HEAP_SYSTEM`.
+    pub fn allocate<P: AsRef<Path>>(
+        heap: P,
+        count: usize,
+        size: u32,
+    ) -> io::Result<BufferPool<'a>> {
+        let heap = OpenOptions::new().read(true).open(heap)?;
+
+        let mut buffers = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut data = dma_heap_allocation_data {
+                len: size as u64,
+                fd: 0,
+                fd_flags: (libc::O_RDWR | libc::O_CLOEXEC) as u32,
+                heap_flags: 0,
+            };
+            if unsafe { libc::ioctl(heap.as_raw_fd(), DMA_HEAP_IOCTL_ALLOC, &mut data) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let file = unsafe { File::from_raw_fd(data.fd as RawFd) };
+            let mmap = unsafe { MmapOptions::new().len(size as usize).map_mut(&file)? };
+            buffers.push(PoolBuffer {
+                fd: OwnedFd::from(file),
+                size,
+                mmap: Some(mmap),
+            });
+        }
+
+        Ok(BufferPool::from_buffers(buffers))
+    }
+
+    /// Takes ownership of dmabufs allocated elsewhere, e.g. by a GPU, given
+    /// with their sizes. They are not mapped.
+    pub fn from_dmabufs(dmabufs: Vec<(OwnedFd, u32)>) -> BufferPool<'a> {
+        BufferPool::from_buffers(
+            dmabufs
+                .into_iter()
+                .map(|(fd, size)| PoolBuffer {
+                    fd,
+                    size,
+                    mmap: None,
+                })
+                .collect(),
+        )
+    }
+
+    fn from_buffers(buffers: Vec<PoolBuffer>) -> BufferPool<'a> {
+        BufferPool {
+            owners: vec![None; buffers.len()],
+            buffers,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Attaches a queue of `device`, which imports the buffers as dmabufs.
+    pub fn attach(
+        &mut self,
+        device: &'a V4l2Device,
+        buf_type: v4l2_buf_type,
+    ) -> io::Result<QueueId> {
+        let memory = v4l2_memory::V4L2_MEMORY_DMABUF;
+        let n = device.request_buffers(buf_type, memory, self.buffers.len())?;
+        if n < self.buffers.len() {
+            let _ = device.request_buffers(buf_type, memory, 0);
+            return Err(io::Error::other("queue cannot hold all pool buffers"));
+        }
+
+        self.attachments.push(Attachment {
+            device,
+            buf_type,
+            memory,
+        });
+        Ok(QueueId(self.attachments.len() - 1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub fn buffer(&self, index: usize) -> Option<&PoolBuffer> {
+        self.buffers.get(index)
+    }
+
+    /// Returns the buffer `index` for writing, if the application owns it.
+    pub fn buffer_mut(&mut self, index: usize) -> Option<&mut PoolBuffer> {
+        match self.owners.get(index) {
+            Some(None) => self.buffers.get_mut(index),
+            _ => None,
+        }
+    }
+
+    /// Returns the queue holding buffer `index`, or `None` if the
+    /// application owns it.
+    pub fn owner(&self, index: usize) -> Option<QueueId> {
+        self.owners.get(index).copied().flatten()
+    }
+
+    /// Returns the index of a buffer the application owns.
+    pub fn next_free(&self) -> Option<usize> {
+        self.owners.iter().position(Option::is_none)
+    }
+
+    /// Returns the number of buffers queued on any queue.
+    pub fn in_flight(&self) -> usize {
+        self.owners.iter().filter(|owner| owner.is_some()).count()
+    }
+
+    /// Queues buffer `index`, holding `bytes_used` bytes, on `queue`. Fails
+    /// if another queue holds the buffer.
+    pub fn queue(&mut self, queue: QueueId, index: usize, bytes_used: u32) -> io::Result<()> {
+        let attachment = self
+            .attachments
+            .get(queue.0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no such queue"))?;
+        match self.owners.get(index) {
+            Some(None) => {}
+            Some(Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "buffer is already queued",
+                ))
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no such buffer",
+                ))
+            }
+        }
+
+        let buffer = &self.buffers[index];
+        let mut buf = v4l2_buffer::new(attachment.buf_type, attachment.memory);
+        buf.index = index as u32;
+        buf.bytesused = bytes_used;
+        buf.field = v4l2_field::V4L2_FIELD_NONE;
+        if attachment.memory == v4l2_memory::V4L2_MEMORY_DMABUF {
+            buf.m.fd = buffer.fd();
+            buf.length = buffer.size;
+        }
+        attachment.device.queue_buffer(&buf)?;
+
+        self.owners[index] = Some(queue);
+        Ok(())
+    }
+
+    /// Dequeues a buffer from `queue`, blocking until one is done, and gives
+    /// it back to the application.
+    pub fn dequeue(&mut self, queue: QueueId) -> io::Result<v4l2_buffer> {
+        let attachment = self
+            .attachments
+            .get(queue.0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no such queue"))?;
+        let buf = attachment
+            .device
+            .dequeue_buffer(attachment.buf_type, attachment.memory)?;
+
+        if let Some(owner) = self.owners.get_mut(buf.index as usize) {
+            *owner = None;
+        }
+        Ok(buf)
+    }
+
+    /// Marks all buffers held by `queue` as owned by the application,
+    /// after the queue was stopped with `VIDIOC_STREAMOFF`.
+    pub fn reclaim(&mut self, queue: QueueId) {
+        for owner in self.owners.iter_mut() {
+            if *owner == Some(queue) {
+                *owner = None;
+            }
+        }
+    }
+}
+
+/// Exports and maps the `n` mmap buffers requested on `buf_type`.
+fn export_buffers(
+    device: &V4l2Device,
+    buf_type: v4l2_buf_type,
+    n: usize,
+) -> io::Result<Vec<PoolBuffer>> {
+    let mut buffers = Vec::with_capacity(n);
+    for (index, buf) in device
+        .buffers(buf_type, v4l2_memory::V4L2_MEMORY_MMAP)
+        .enumerate()
+    {
+        let file = File::from(device.export_buffer(buf_type, index, 0)?);
+        let mmap = unsafe { MmapOptions::new().len(buf.length as usize).map_mut(&file)? };
+        buffers.push(PoolBuffer {
+            fd: OwnedFd::from(file),
+            size: buf.length,
+            mmap: Some(mmap),
+        });
+    }
+    if buffers.len() != n {
+        return Err(io::Error::other("failed to export all buffers"));
+    }
+    Ok(buffers)
+}
+
+impl<'a> Drop for BufferPool<'a> {
+    fn drop(&mut self) {
+        // Releases the imports; the exporting queue keeps its buffers
+        // until the exported fds are closed.
+        for attachment in &self.attachments {
+            if attachment.memory == v4l2_memory::V4L2_MEMORY_DMABUF {
+                let _ =
+                    attachment
+                        .device
+                        .request_buffers(attachment.buf_type, attachment.memory, 0);
+            }
+        }
+    }
+}
@@ -1,112 +1,1554 @@
-use std::fs::File;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::VecDeque;
 use std::io;
 use std::mem;
-use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::{AsFd, AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time::Duration;
 
-use memmap::{MmapMut, MmapOptions};
+use memmap::MmapMut;
 
+use crate::config::{self, CaptureConfig};
+use crate::device_id::DeviceId;
+use crate::dmabuf::DmabufBuffer;
+use crate::frame::{Frame, OwnedFrame, Recycler};
+use crate::handoff;
+use crate::input::{self, InputStatus};
+use crate::stats::{self, BufferMapping, BufferUsage, CaptureStats};
 use crate::sys::uapi::*;
-use crate::sys::V4l2Device;
+use crate::sys::{cvt, ControlInfo, DeviceOps, Event, OpenOptions, V4l2Device};
+use crate::typestate::{Configured, TypedCapture};
+use crate::userptr;
 
-pub struct Capture {
-    device: V4l2Device,
-    buffers: Vec<MmapMut>,
+/// How frames are transferred from the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoMethod {
+    /// Streaming I/O with driver buffers mapped into memory.
+    Mmap,
+    /// Streaming I/O into page-aligned buffers owned by the capture.
+    UserPtr,
+    /// `read()` into buffers owned by the capture, for drivers without
+    /// streaming I/O.
+    Read,
 }
 
-impl Capture {
-    fn new(device: V4l2Device) -> Capture {
+/// Memory management applied to buffer mappings, to avoid page faults
+/// while capturing on systems under memory pressure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MapOptions {
+    /// Locks the buffers into RAM with `mlock`. Subject to
+    /// `RLIMIT_MEMLOCK`.
+    pub lock: bool,
+    /// Excludes the buffers from core dumps with `MADV_DONTDUMP`.
+    pub dont_dump: bool,
+    /// Faults the buffers in ahead of use with `MADV_WILLNEED`.
+    pub will_need: bool,
+}
+
+/// Lifecycle of a `Capture`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamState {
+    /// No buffers are prepared.
+    Unprepared,
+    /// Buffers are prepared, but not streaming.
+    Prepared,
+    /// Between `start` and `stop`.
+    Streaming,
+}
+
+/// Owner of a prepared buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BufferState {
+    /// Owned by the capture, to be queued by `start`.
+    Idle,
+    /// Queued to the driver.
+    Queued,
+    /// Dequeued and held by the application.
+    Held,
+}
+
+/// The memory of a prepared buffer.
+///
+/// In read mode frames are read into buffers the application does not
+/// hold, while other buffers are shared with frames, so the mapping is
+/// written through an `UnsafeCell` rather than a shared reference.
+pub(crate) struct BufferMemory(UnsafeCell<MmapMut>);
+
+// Only written through `slice_mut`, whose callers hold the only reference
+// to the memory.
+unsafe impl Sync for BufferMemory {}
+
+impl BufferMemory {
+    fn new(mmap: MmapMut) -> BufferMemory {
+        BufferMemory(UnsafeCell::new(mmap))
+    }
+
+    fn get_mut(&mut self) -> &mut MmapMut {
+        self.0.get_mut()
+    }
+
+    /// Returns the memory for writing.
+    ///
+    /// # Safety
+    ///
+    /// No other reference to the memory may exist while the slice is
+    /// alive, i.e. the buffer is neither queued nor held by a frame.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slice_mut(&self) -> &mut [u8] {
+        (&mut *self.0.get()).deref_mut()
+    }
+}
+
+impl Deref for BufferMemory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { (&*self.0.get()).deref() }
+    }
+}
+
+/// Returns true for errors of drivers that do not implement an ioctl, or
+/// not for the control or queue asked for.
+fn is_unsupported(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::ENOTTY) | Some(libc::EINVAL))
+}
+
+fn not_streaming() -> io::Error {
+    io::Error::other("capture is not streaming")
+}
+
+/// A capture device with its negotiated format and buffers.
+///
+/// The device is a `V4l2Device`. Streaming goes through an interface
+/// internal to this crate, so `map_device` can wrap it, e.g. in a
+/// `FaultyDevice` with the `testing` feature; configuring the device needs
+/// the `V4l2Device` itself.
+///
+/// # Thread safety
+///
+/// A capture is `Send` but not `Sync`: it tracks buffer ownership without
+/// locking, so it is driven from one thread at a time. Frames meant for
+/// other threads are dequeued with `dequeue_owned_frame`; dropping them
+/// there queues the buffer again. Calls that change the format or
+/// reallocate buffers take `&mut self`, so they cannot happen while a
+/// borrowed `Frame` is alive.
+pub struct Capture<D: DeviceOps = V4l2Device> {
+    /* Taken when the capture is handed off or mapped, so that dropping it
+     * releases nothing */
+    device: Option<D>,
+    path: String,
+    format: v4l2_pix_format,
+    io: IoMethod,
+    buffers: Vec<Arc<BufferMemory>>,
+    dmabufs: Vec<DmabufBuffer>,
+    state: Cell<StreamState>,
+    recycler: Arc<Recycler>,
+    buffer_states: RefCell<Vec<BufferState>>,
+    map_options: MapOptions,
+    /* Queued buffers in the order they are read into, in read mode */
+    free: RefCell<VecDeque<usize>>,
+    sequence: Cell<u32>,
+    every_nth: usize,
+    /* Frames still to requeue before the next one is delivered */
+    skip: Cell<usize>,
+    latest_only: bool,
+    stats: Cell<CaptureStats>,
+    memory_limit: Option<usize>,
+    stall_timeout: Option<Duration>,
+    #[cfg(feature = "sunxi-vfe")]
+    subchannel: Option<v4l2_pix_format>,
+}
+
+impl<D: DeviceOps> Capture<D> {
+    pub(crate) fn new(device: D, path: &str, format: v4l2_pix_format, io: IoMethod) -> Capture<D> {
         Capture {
-            device,
+            device: Some(device),
+            path: path.to_string(),
+            format,
+            io,
             buffers: Vec::new(),
+            dmabufs: Vec::new(),
+            state: Cell::new(StreamState::Unprepared),
+            recycler: Arc::default(),
+            buffer_states: RefCell::new(Vec::new()),
+            map_options: MapOptions::default(),
+            free: RefCell::new(VecDeque::new()),
+            sequence: Cell::new(0),
+            every_nth: 1,
+            skip: Cell::new(0),
+            latest_only: false,
+            stats: Cell::new(CaptureStats::default()),
+            memory_limit: None,
+            stall_timeout: None,
+            #[cfg(feature = "sunxi-vfe")]
+            subchannel: None,
+        }
+    }
+
+    /// Replaces the device with `f(device)`, e.g. to wrap it, keeping the
+    /// format, buffers and stream state.
+    pub fn map_device<E: DeviceOps, F: FnOnce(D) -> E>(mut self, f: F) -> Capture<E> {
+        // Taking the device keeps drop from releasing the buffers.
+        let device = self.device.take().map(f);
+        let capture = Capture {
+            device,
+            path: mem::take(&mut self.path),
+            format: self.format,
+            io: self.io,
+            buffers: mem::take(&mut self.buffers),
+            dmabufs: mem::take(&mut self.dmabufs),
+            state: self.state.clone(),
+            recycler: mem::take(&mut self.recycler),
+            buffer_states: mem::take(&mut self.buffer_states),
+            map_options: self.map_options,
+            free: mem::take(&mut self.free),
+            sequence: self.sequence.clone(),
+            every_nth: self.every_nth,
+            skip: self.skip.clone(),
+            latest_only: self.latest_only,
+            stats: self.stats.clone(),
+            memory_limit: self.memory_limit,
+            stall_timeout: self.stall_timeout,
+            #[cfg(feature = "sunxi-vfe")]
+            subchannel: self.subchannel,
+        };
+        if capture.is_streaming() {
+            capture.share_queue();
+        }
+        capture
+    }
+
+    fn dev(&self) -> &D {
+        self.device
+            .as_ref()
+            .expect("device is only taken when the capture is consumed")
+    }
+
+    /// Returns the path or `DeviceId` the device was opened with.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns how frames are transferred from the driver.
+    pub fn io_method(&self) -> IoMethod {
+        self.io
+    }
+
+    /// Returns the number of prepared buffers.
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Delivers only every `n`th frame, e.g. for a slow consumer of a fast
+    /// camera. The frames in between are requeued as soon as they are
+    /// dequeued, keeping the queue drained; their sequence numbers are
+    /// skipped. The next frame is delivered, and `n` of 1, the default,
+    /// delivers all frames.
+    pub fn deliver_every_nth(&mut self, n: usize) {
+        self.every_nth = n.max(1);
+        self.skip.set(0);
+    }
+
+    /// Delivers only the newest frame: after each dequeue the device is
+    /// polled without waiting, and frames that arrived in the meantime
+    /// replace the dequeued one, which is requeued. Consumers that fall
+    /// behind then see the current picture instead of working off a
+    /// backlog.
+    pub fn deliver_latest_only(&mut self, enable: bool) {
+        self.latest_only = enable;
+    }
+
+    /// Makes dequeues fail with `TimedOut` if no frame arrives within
+    /// `timeout`, as some drivers silently stop delivering frames, e.g.
+    /// after an underrun. Stalls are counted in `stats`. `None`, the
+    /// default, waits forever.
+    pub fn set_stall_timeout(&mut self, timeout: Option<Duration>) {
+        self.stall_timeout = timeout;
+    }
+
+    /// Takes over the delivery, buffer and stall settings of `other`, e.g.
+    /// when replacing it with a reopened capture.
+    pub(crate) fn copy_settings<E: DeviceOps>(&mut self, other: &Capture<E>) {
+        self.map_options = other.map_options;
+        self.every_nth = other.every_nth;
+        self.skip.set(0);
+        self.latest_only = other.latest_only;
+        self.memory_limit = other.memory_limit;
+        self.stall_timeout = other.stall_timeout;
+    }
+
+    /// Releases the buffers and closes the device. Only the settings of a
+    /// closed capture may still be used, e.g. by `copy_settings`.
+    pub(crate) fn close(&mut self) {
+        if self.device.is_some() {
+            self.unprepare();
+            self.device = None;
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.device.is_none()
+    }
+
+    /// Returns the frame counters and the measured latency.
+    pub fn stats(&self) -> CaptureStats {
+        self.stats.get()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.set(CaptureStats::default());
+    }
+
+    fn memory(&self) -> v4l2_memory {
+        match self.io {
+            IoMethod::UserPtr => v4l2_memory::V4L2_MEMORY_USERPTR,
+            _ => v4l2_memory::V4L2_MEMORY_MMAP,
         }
     }
 
     /// Return current pixel format of capture device.
     pub fn pix_format(&self) -> io::Result<v4l2_pix_format> {
-        self.device.capture_format()
+        self.dev().capture_format()
+    }
+
+    /// Returns the pixel format negotiated when the capture was opened.
+    pub fn format(&self) -> &v4l2_pix_format {
+        &self.format
+    }
+
+    /// Returns the memory held by the prepared buffers.
+    pub fn buffer_usage(&self) -> BufferUsage {
+        let mapping = match self.io {
+            IoMethod::Mmap => BufferMapping::Driver,
+            _ => BufferMapping::Process,
+        };
+        let mut usage = BufferUsage::single_planar(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            mapping,
+            self.buffers.iter().map(|buffer| buffer.len()),
+        );
+        usage.locked = self.map_options.lock && !self.buffers.is_empty();
+        usage
+    }
+
+    /// Caps the memory `prepare_mmapped` may allocate, in bytes. Fewer
+    /// buffers than requested are prepared if needed, and preparing fails
+    /// with `OutOfMemory` if not even the driver's minimum fits.
+    pub fn set_buffer_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
     }
 
+    /// Returns the number of buffers of `size` bytes, up to `count`, that
+    /// fit into the memory limit.
+    fn limit_count(&self, count: usize, size: usize) -> io::Result<usize> {
+        match self.memory_limit {
+            Some(limit) if limit < size.max(1) => Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                "buffer memory limit is below one frame",
+            )),
+            Some(limit) => Ok(count.min(limit / size.max(1))),
+            None => Ok(count),
+        }
+    }
+
+    /// Prepares `count` buffers. In user pointer mode these are
+    /// `UserPtrBuffer`s, and in read mode anonymous mappings of `sizeimage`
+    /// bytes. If mapping the buffers fails, none are left prepared.
     pub fn prepare_mmapped(&mut self, count: usize) -> io::Result<()> {
-        // Request buffers
-        let n = self.device.request_buffers(
+        if self.is_streaming() {
+            return Err(io::Error::other("cannot prepare buffers while streaming"));
+        }
+
+        let mut size = self.format.sizeimage as usize;
+        if self.io == IoMethod::UserPtr {
+            size = size.div_ceil(userptr::page_size()) * userptr::page_size();
+        }
+        let count = self.limit_count(count, size)?;
+        let count = match self.io {
+            IoMethod::Read => count,
+            _ => self.dev().request_buffers(
+                v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                self.memory(),
+                count,
+            )?,
+        };
+
+        self.dmabufs.clear();
+        self.buffers.clear();
+        if let Err(e) = self.map_requested(count, size) {
+            self.unprepare();
+            return Err(e);
+        }
+        self.set_prepared();
+        Ok(())
+    }
+
+    /// Maps the `count` buffers of `size` bytes `prepare_mmapped` requested.
+    fn map_requested(&mut self, count: usize, size: usize) -> io::Result<()> {
+        if self.io == IoMethod::Mmap {
+            self.buffers = self
+                .dev()
+                .map_buffers(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)?
+                .into_iter()
+                .map(|mmap| Arc::new(BufferMemory::new(mmap)))
+                .collect();
+            if self.buffers.len() != count {
+                return Err(io::Error::other("failed to map all buffers"));
+            }
+            // Drivers may allocate more or larger buffers than asked for.
+            if let Some(limit) = self.memory_limit {
+                if self.buffer_usage().total() > limit {
+                    return Err(io::Error::new(
+                        io::ErrorKind::OutOfMemory,
+                        "driver buffers exceed the buffer memory limit",
+                    ));
+                }
+            }
+        } else {
+            for _ in 0..count {
+                let mmap = match self.io {
+                    IoMethod::UserPtr => userptr::UserPtrBuffer::new_mapping(size)?,
+                    _ => MmapMut::map_anon(size)?,
+                };
+                self.buffers.push(Arc::new(BufferMemory::new(mmap)));
+            }
+        }
+        self.apply_map_options()
+    }
+
+    fn set_prepared(&self) {
+        self.recycler.next_generation();
+        *self.buffer_states.borrow_mut() = vec![BufferState::Idle; self.buffers.len()];
+        self.state.set(if self.buffers.is_empty() {
+            StreamState::Unprepared
+        } else {
+            StreamState::Prepared
+        });
+    }
+
+    /// Sets how buffers are mapped by the next `prepare_mmapped`.
+    pub fn set_map_options(&mut self, options: MapOptions) {
+        self.map_options = options;
+    }
+
+    fn apply_map_options(&self) -> io::Result<()> {
+        let options = &self.map_options;
+        for buffer in &self.buffers {
+            let addr = buffer.as_ptr() as *mut libc::c_void;
+            let len = buffer.len();
+
+            if options.dont_dump {
+                cvt(unsafe { libc::madvise(addr, len, libc::MADV_DONTDUMP) })?;
+            }
+            if options.will_need {
+                cvt(unsafe { libc::madvise(addr, len, libc::MADV_WILLNEED) })?;
+            }
+            // Unlocked again by munmap.
+            if options.lock {
+                cvt(unsafe { libc::mlock(addr, len) })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps the buffers and releases them in the driver, stopping the
+    /// capture first if needed.
+    pub fn unprepare(&mut self) {
+        if self.is_streaming() {
+            let _ = self.stop();
+        }
+        self.dmabufs.clear();
+        self.buffers.clear();
+        self.set_prepared();
+        if self.io == IoMethod::Read {
+            return;
+        }
+        let _ = self.dev().request_buffers(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            self.memory(),
+            0,
+        );
+    }
+
+    /// Queues all buffers the application does not hold and starts
+    /// streaming.
+    pub fn start(&self) -> io::Result<()> {
+        match self.state.get() {
+            StreamState::Unprepared => return Err(io::Error::other("no buffers prepared")),
+            StreamState::Streaming => return Err(io::Error::other("capture is already streaming")),
+            StreamState::Prepared => {}
+        }
+
+        self.recycle()?;
+        if let Err(e) = self.queue_idle_and_stream_on() {
+            // Take back what was queued, so the capture is prepared as
+            // before and can be started again.
+            if self.io != IoMethod::Read {
+                let _ = self
+                    .dev()
+                    .stream_off(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE);
+            }
+            self.reclaim_queued();
+            return Err(e);
+        }
+        self.restart_sequence();
+        self.state.set(StreamState::Streaming);
+        self.share_queue();
+        Ok(())
+    }
+
+    /// Lets dropped owned frames queue their buffers themselves, if the
+    /// device can be shared with their threads.
+    fn share_queue(&self) {
+        if self.io != IoMethod::Read {
+            self.recycler.set_handle(self.dev().queue_handle());
+        }
+    }
+
+    fn queue_idle_and_stream_on(&self) -> io::Result<()> {
+        let idle: Vec<usize> = self
+            .buffer_states
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &state)| state == BufferState::Idle)
+            .map(|(index, _)| index)
+            .collect();
+        for index in idle {
+            self.queue(index)?;
+        }
+
+        // In read mode, the driver starts capturing on the first read().
+        if self.io != IoMethod::Read {
+            self.dev()
+                .stream_on(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)?;
+        }
+        Ok(())
+    }
+
+    /// Marks the buffers queued to the driver idle again, after it
+    /// returned them all on `STREAMOFF`.
+    fn reclaim_queued(&self) {
+        self.free.borrow_mut().clear();
+        for state in self.buffer_states.borrow_mut().iter_mut() {
+            if *state == BufferState::Queued {
+                *state = BufferState::Idle;
+            }
+        }
+    }
+
+    /// Stops streaming. Queued buffers return to the capture; buffers the
+    /// application holds stay valid until returned.
+    pub fn stop(&self) -> io::Result<()> {
+        if !self.is_streaming() {
+            return Ok(());
+        }
+
+        self.recycler.set_handle(None);
+        if self.io != IoMethod::Read {
+            self.dev()
+                .stream_off(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)?;
+        }
+        self.reclaim_queued();
+        self.restart_sequence();
+        self.state.set(StreamState::Prepared);
+        // Buffers that dropped frames queued came back with the others.
+        self.recycle()
+    }
+
+    /// Forgets the expected sequence number, as drivers start counting
+    /// from 0 on `STREAMON`.
+    fn restart_sequence(&self) {
+        let mut stats = self.stats.get();
+        stats.restart_sequence();
+        self.stats.set(stats);
+    }
+
+    /// Stops and starts streaming again without unmapping or requesting
+    /// buffers, to recover quickly from a stalled driver. Frames queued to
+    /// the driver are discarded; buffers the application holds stay valid
+    /// until returned.
+    pub fn restart(&self) -> io::Result<()> {
+        if !self.is_streaming() {
+            return Err(not_streaming());
+        }
+        self.stop()?;
+
+        self.skip.set(0);
+        self.start()
+    }
+
+    pub fn state(&self) -> StreamState {
+        self.state.get()
+    }
+
+    /// Returns true between `start` and `stop`.
+    pub fn is_streaming(&self) -> bool {
+        self.state.get() == StreamState::Streaming
+    }
+
+    /// Queues buffer `index`, which must not be queued already.
+    fn queue(&self, index: usize) -> io::Result<()> {
+        if self.io == IoMethod::Read {
+            self.free.borrow_mut().push_back(index);
+        } else {
+            let mut buf =
+                v4l2_buffer::new(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE, self.memory());
+            buf.index = index as u32;
+            if self.io == IoMethod::UserPtr {
+                let buffer = &self.buffers[index];
+                buf.m.userptr = buffer.as_ptr() as libc::c_ulong;
+                buf.length = buffer.len() as u32;
+            }
+            self.dev().queue_buffer(&buf)?;
+        }
+
+        self.buffer_states.borrow_mut()[index] = BufferState::Queued;
+        Ok(())
+    }
+
+    fn dequeue(&self) -> io::Result<v4l2_buffer> {
+        if !self.is_streaming() {
+            return Err(not_streaming());
+        }
+        self.recycle()?;
+
+        loop {
+            self.wait_frame()?;
+            let mut buf = self.dequeue_next()?;
+            let skip = self.skip.get();
+            if skip > 0 {
+                self.skip.set(skip - 1);
+                self.skip_frame(&buf)?;
+                continue;
+            }
+            if self.latest_only {
+                // Each buffer is replaced at most once, so a device filling
+                // buffers as fast as they are requeued cannot stall this.
+                let mut replaceable = self.buffers.len().saturating_sub(1);
+                while replaceable > 0 && self.dev().frame_ready()? {
+                    replaceable -= 1;
+                    let newer = match self.dequeue_next() {
+                        Ok(newer) => newer,
+                        Err(e) => {
+                            // Still queued in the books, so a failed
+                            // requeue is retried by the next `start`.
+                            let _ = self.queue(buf.index as usize);
+                            return Err(e);
+                        }
+                    };
+                    self.skip_frame(&buf)?;
+                    buf = newer;
+                }
+            }
+            self.skip.set(self.every_nth - 1);
+            self.buffer_states.borrow_mut()[buf.index as usize] = BufferState::Held;
+            self.record(&buf, true);
+            return Ok(buf);
+        }
+    }
+
+    /// Waits for the next frame up to the stall timeout.
+    fn wait_frame(&self) -> io::Result<()> {
+        let timeout = match self.stall_timeout {
+            Some(timeout) => timeout,
+            None => return Ok(()),
+        };
+        if self.dev().wait_frame(timeout)? {
+            return Ok(());
+        }
+
+        let mut stats = self.stats.get();
+        stats.stalls += 1;
+        self.stats.set(stats);
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "no frame within the stall timeout",
+        ))
+    }
+
+    fn dequeue_next(&self) -> io::Result<v4l2_buffer> {
+        match self.io {
+            IoMethod::Read => self.read_frame(),
+            _ => {
+                let buf = self
+                    .dev()
+                    .dequeue_buffer(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE, self.memory())?;
+                // A dropped frame may have queued it since the last look.
+                self.note_requeued();
+                Ok(buf)
+            }
+        }
+    }
+
+    /// Requeues a dequeued frame without delivering it.
+    fn skip_frame(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        self.record(buf, false);
+        self.queue(buf.index as usize)
+    }
+
+    fn record(&self, buf: &v4l2_buffer, delivered: bool) {
+        let mut stats = self.stats.get();
+        stats.record(buf, delivered, stats::monotonic_now());
+        self.stats.set(stats);
+    }
+
+    /// Reads a frame into a free buffer and describes it like a dequeued
+    /// driver buffer, with a monotonic timestamp and a sequence number.
+    fn read_frame(&self) -> io::Result<v4l2_buffer> {
+        let index = self
+            .free
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| io::Error::other("no free buffer to read into"))?;
+        let buffer = &self.buffers[index];
+
+        // Free buffers are neither queued nor held, so nothing else refers
+        // to the memory.
+        let data = unsafe { buffer.slice_mut() };
+        let n = loop {
+            match self.dev().read(data) {
+                Ok(n) => break n as u32,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.free.borrow_mut().push_front(index);
+                    return Err(e);
+                }
+            }
+        };
+
+        let now = stats::monotonic_now();
+
+        let mut buf = v4l2_buffer::new(
             v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
             v4l2_memory::V4L2_MEMORY_MMAP,
-            count,
+        );
+        buf.index = index as u32;
+        buf.bytesused = n;
+        buf.length = buffer.len() as u32;
+        buf.field = self.format.field;
+        buf.flags = V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC;
+        buf.timestamp = libc::timeval {
+            tv_sec: now.as_secs() as libc::time_t,
+            tv_usec: now.subsec_micros() as libc::suseconds_t,
+        };
+        buf.sequence = self.sequence.get();
+        self.sequence.set(buf.sequence.wrapping_add(1));
+        Ok(buf)
+    }
+
+    /// Dequeues a frame into `buf` and returns its valid data, without
+    /// allocating or copying frame data. Dequeuing this way and giving the
+    /// buffers back with `return_frame(buf)` allocates nothing once
+    /// streaming, so `buf` can be reused for every frame.
+    pub fn dequeue_into(&self, buf: &mut v4l2_buffer) -> io::Result<&[u8]> {
+        *buf = self.dequeue()?;
+        let data = &self.buffers[buf.index as usize][..];
+        Ok(&data[..(buf.bytesused as usize).min(data.len())])
+    }
+
+    pub fn take_frame(&mut self) -> io::Result<(v4l2_buffer, &mut MmapMut)> {
+        let buf = self.dequeue()?;
+
+        // Owned frames of the buffer are gone once it was requeued.
+        let mmap = Arc::get_mut(&mut self.buffers[buf.index as usize])
+            .ok_or_else(|| io::Error::other("buffer is still shared"))?
+            .get_mut();
+
+        Ok((buf, mmap))
+    }
+
+    /// Dequeues a frame as a read-only `Frame` view.
+    ///
+    /// Unlike `take_frame`, this borrows the capture immutably, so the frame
+    /// can be returned with `return_frame(frame.buffer())` while it is alive.
+    pub fn dequeue_frame(&self) -> io::Result<Frame<'_>> {
+        let buf = self.dequeue()?;
+
+        let data = &self.buffers[buf.index as usize][..];
+        let dmabuf = self.dmabufs.get(buf.index as usize);
+
+        Ok(Frame::new(buf, data, self.format).with_dmabuf(dmabuf))
+    }
+
+    /// Returns the angle the VFE rotates frames by. `format` already
+    /// describes the rotated frames.
+    #[cfg(feature = "sunxi-vfe")]
+    pub fn rotation(&self) -> u32 {
+        self.format.rot_angle
+    }
+
+    /// Returns the format of the VFE sub channel, if one was configured
+    /// with `Builder::subchannel`.
+    #[cfg(feature = "sunxi-vfe")]
+    pub fn subchannel_format(&self) -> Option<&v4l2_pix_format> {
+        self.subchannel.as_ref()
+    }
+
+    /// Dequeues a buffer holding frames of both VFE channels, and returns
+    /// the main and the sub channel frame. Both share one buffer, which is
+    /// given back with `return_frame` on either of them.
+    #[cfg(feature = "sunxi-vfe")]
+    pub fn dequeue_dual_frame(&self) -> io::Result<(Frame<'_>, Frame<'_>)> {
+        let sub_format = self
+            .subchannel
+            .ok_or_else(|| io::Error::other("no sub channel configured"))?;
+        let buf = self.dequeue()?;
+
+        let data = &self.buffers[buf.index as usize][..];
+        let offset = subchannel_offset(&self.format);
+        let sub_len = sub_format.sizeimage as usize;
+        if offset + sub_len > data.len() {
+            self.release(buf.index as usize)?;
+            return Err(io::Error::other("buffer does not hold the sub channel"));
+        }
+
+        let mut main_buf = buf.clone();
+        main_buf.bytesused = self.format.sizeimage.min(buf.bytesused);
+        let mut sub_buf = buf;
+        sub_buf.bytesused = sub_format.sizeimage;
+
+        Ok((
+            Frame::new(main_buf, &data[..offset], self.format),
+            Frame::new(sub_buf, &data[offset..offset + sub_len], sub_format),
+        ))
+    }
+
+    /// Dequeues a frame that owns a reference to its buffer, so that it
+    /// can be kept alongside other frames or sent to another thread.
+    ///
+    /// While streaming, the buffer is queued again by the thread that
+    /// drops the frame. Devices wrapped with `map_device` may not support
+    /// that; their buffers are queued the next time a frame is dequeued
+    /// or the capture started. Owned frames must be dropped before the
+    /// buffers are prepared again.
+    pub fn dequeue_owned_frame(&self) -> io::Result<OwnedFrame> {
+        let buf = self.dequeue()?;
+        let data = self.buffers[buf.index as usize].clone();
+
+        Ok(OwnedFrame::new(
+            buf,
+            data,
+            self.format,
+            self.recycler.clone(),
+        ))
+    }
+
+    /// Takes note of the buffers dropped owned frames queued themselves.
+    /// After `STREAMOFF`, the driver has returned them.
+    fn note_requeued(&self) {
+        let state = if self.is_streaming() {
+            BufferState::Queued
+        } else {
+            BufferState::Idle
+        };
+        let mut states = self.buffer_states.borrow_mut();
+        for index in self.recycler.take_queued() {
+            states[index] = state;
+        }
+    }
+
+    /// Gives back the buffers of dropped owned frames.
+    fn recycle(&self) -> io::Result<()> {
+        self.note_requeued();
+        let mut result = Ok(());
+        for index in self.recycler.take_returned() {
+            result = result.and(self.release(index));
+        }
+        result
+    }
+
+    /// Gives a dequeued buffer back. It is queued again right away while
+    /// streaming, and by the next `start` otherwise.
+    pub fn return_frame(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        self.release(buf.index as usize)
+    }
+
+    /// Gives back dequeued buffer `index`, like `return_frame`, for callers
+    /// that only kept the index of the frame.
+    pub fn requeue(&self, index: usize) -> io::Result<()> {
+        self.release(index)
+    }
+
+    pub(crate) fn release(&self, index: usize) -> io::Result<()> {
+        let state = self.buffer_states.borrow().get(index).cloned();
+        match state {
+            Some(BufferState::Held) if self.is_streaming() => self.queue(index),
+            Some(BufferState::Held) => {
+                self.buffer_states.borrow_mut()[index] = BufferState::Idle;
+                Ok(())
+            }
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer is not held by the application",
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no such buffer",
+            )),
+        }
+    }
+
+    /// Grabs a single frame and writes it to an image file, whose format is
+    /// chosen by the extension of `path` (e.g. `.png` or `.jpg`).
+    ///
+    /// If no buffers are prepared, the capture is started for a single frame
+    /// and stopped again, and it is left without buffers also if that
+    /// fails. Otherwise the capture must already be started.
+    #[cfg(feature = "image")]
+    pub fn snapshot<P: AsRef<std::path::Path>>(&mut self, path: P) -> io::Result<()> {
+        if self.buffers.is_empty() {
+            self.prepare_mmapped(2)?;
+            let result = self.start().and_then(|()| self.snapshot(path));
+            let stopped = if self.is_streaming() {
+                self.stop()
+            } else {
+                Ok(())
+            };
+            self.unprepare();
+            return result.and(stopped);
+        }
+
+        let frame = self.dequeue_frame()?;
+        let image = match frame.pixel_format() {
+            V4L2_PIX_FMT_MJPEG | V4L2_PIX_FMT_JPEG => {
+                let jpeg = crate::mjpeg::fix_huffman_tables(frame.data());
+                image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+                    .map(|img| img.to_rgb8())
+                    .map_err(io::Error::other)
+            }
+            _ => crate::convert::to_rgb24(&frame).map(|rgb| {
+                image::RgbImage::from_raw(frame.width(), frame.height(), rgb)
+                    .expect("converted buffer matches frame size")
+            }),
+        };
+        self.return_frame(frame.buffer())?;
+
+        image?.save(path).map_err(io::Error::other)
+    }
+
+    /// Returns the area of the sensor frames are cropped to.
+    pub fn crop(&self) -> io::Result<v4l2_rect> {
+        self.dev().selection(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            V4L2_SEL_TGT_CROP,
+        )
+    }
+
+    /// Returns the area `set_crop` can select from.
+    pub fn crop_bounds(&self) -> io::Result<v4l2_rect> {
+        self.dev().selection(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            V4L2_SEL_TGT_CROP_BOUNDS,
+        )
+    }
+
+    /// Crops frames to `rect`, e.g. to pan digitally, and returns the
+    /// rectangle the driver applied after rounding it to what the hardware
+    /// supports.
+    ///
+    /// Drivers that crop without changing the buffer size allow this while
+    /// streaming; others fail with `EBUSY`. The format is read back
+    /// afterwards, as drivers without a scaler shrink the frames to the
+    /// crop.
+    pub fn set_crop(&mut self, rect: &v4l2_rect) -> io::Result<v4l2_rect> {
+        let applied = self.dev().set_selection(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            V4L2_SEL_TGT_CROP,
+            0,
+            rect,
         )?;
+        self.format = self.dev().capture_format()?;
+        Ok(applied)
+    }
 
-        self.buffers.clear();
+    /// Zooms digitally by `factor`, at least 1, into the default crop of
+    /// the sensor, centered on `center` in fractions of its width and
+    /// height. Returns the crop the driver applied.
+    ///
+    /// Drivers with a scaler compose the crop onto the whole frame, so the
+    /// frame size stays the same. Drivers that only crop, or cannot change
+    /// the scaling while streaming, deliver smaller frames instead, as
+    /// `format` tells.
+    pub fn set_zoom(&mut self, factor: f32, center: (f32, f32)) -> io::Result<v4l2_rect> {
+        if factor.is_nan() || factor < 1.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zoom factor is below 1",
+            ));
+        }
+        let buf_type = v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let full = self.dev().selection(buf_type, V4L2_SEL_TGT_CROP_DEFAULT)?;
+        let frame = v4l2_rect {
+            left: 0,
+            top: 0,
+            width: self.format.width as i32,
+            height: self.format.height as i32,
+        };
+
+        let applied = self.set_crop(&zoom_rect(&full, factor, center))?;
+        match self
+            .dev()
+            .set_selection(buf_type, V4L2_SEL_TGT_COMPOSE, 0, &frame)
+        {
+            Ok(_) => self.format = self.dev().capture_format()?,
+            // The driver cannot compose, or not while streaming, so the
+            // frames shrink to the crop.
+            Err(ref e)
+                if e.raw_os_error() == Some(libc::EINVAL)
+                    || e.raw_os_error() == Some(libc::EBUSY) =>
+            {
+                self.format = self.dev().capture_format()?
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(applied)
+    }
+
+    /// Stops streaming, releases the buffers and sets the format `apply`
+    /// returns, then prepares as many buffers as before and restarts
+    /// streaming if it was running.
+    ///
+    /// Errors up to preparing leave the capture without buffers.
+    fn switch_format<F>(&mut self, apply: F) -> io::Result<v4l2_pix_format>
+    where
+        F: FnOnce(&D) -> io::Result<v4l2_pix_format>,
+    {
+        let was_streaming = self.is_streaming();
+        let count = self.buffers.len();
+
+        if was_streaming {
+            self.stop()?;
+        }
+        self.unprepare();
+
+        self.format = apply(self.dev())?;
+        if count > 0 {
+            self.prepare_mmapped(count)?;
+        }
+        if was_streaming {
+            self.start()?;
+        }
+        Ok(self.format)
+    }
+}
+
+impl<D: DeviceOps + AsFd> Capture<D> {
+    /// Hands the device over `socket` to another process, which rebuilds
+    /// the capture with `Capture::receive`. Only prepared mmap captures
+    /// that are not streaming can be handed over.
+    ///
+    /// Both processes share the driver state, so the capture is consumed
+    /// without releasing its buffers. It is given back if it could not be
+    /// sent.
+    #[allow(clippy::result_large_err)]
+    pub fn send_to(mut self, socket: &UnixStream) -> Result<(), (Capture<D>, io::Error)> {
+        if self.io != IoMethod::Mmap {
+            let err = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only mmap captures can be handed over",
+            );
+            return Err((self, err));
+        }
+        if self.state() != StreamState::Prepared {
+            let err = io::Error::other("capture must be prepared and stopped");
+            return Err((self, err));
+        }
+
+        let mut data = (self.buffers.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(self.path.as_bytes());
+        if let Err(err) = handoff::send_fd(socket, self.dev().as_fd(), &data) {
+            return Err((self, err));
+        }
+        // The receiver owns the buffers now.
+        self.device = None;
+        Ok(())
+    }
+}
+
+impl Capture {
+    /// Returns the underlying device, e.g. to subscribe to events.
+    pub fn device(&self) -> &V4l2Device {
+        self.dev()
+    }
 
-        let f = unsafe { File::from_raw_fd(self.device.as_raw_fd()) };
+    /// Returns the current frame interval of the device.
+    pub fn time_per_frame(&self) -> io::Result<v4l2_fract> {
+        self.dev().capture_parm().map(|parm| parm.timeperframe)
+    }
+
+    /// Changes the frame interval to `num/den` seconds and returns the
+    /// interval the driver chose.
+    ///
+    /// The change is applied while streaming if the driver allows it.
+    /// Otherwise streaming is stopped, the interval set and streaming
+    /// restarted with all buffers requeued, also if the driver refused the
+    /// interval. If restarting fails, its error is returned and the
+    /// capture is left stopped with its buffers prepared, as after a
+    /// failed `start`, so `start` can be tried again.
+    pub fn set_frame_rate(&mut self, num: u32, den: u32) -> io::Result<v4l2_fract> {
+        let mut parm = self.dev().capture_parm()?;
+        if parm.capability & V4L2_CAP_TIMEPERFRAME == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "device does not support setting the frame interval",
+            ));
+        }
+        parm.timeperframe = v4l2_fract {
+            numerator: num,
+            denominator: den,
+        };
+
+        match self.dev().set_capture_parm(&parm) {
+            Err(ref e) if e.raw_os_error() == Some(libc::EBUSY) && self.is_streaming() => {
+                self.stop()?;
+                let result = self.dev().set_capture_parm(&parm);
+                self.start()?;
+                result.map(|parm| parm.timeperframe)
+            }
+            result => result.map(|parm| parm.timeperframe),
+        }
+    }
+
+    /// Returns true if the JPEG quality of the frames can be set, with
+    /// `V4L2_CID_JPEG_COMPRESSION_QUALITY` or the older `VIDIOC_S_JPEGCOMP`.
+    pub fn supports_jpeg_quality(&self) -> bool {
+        self.dev()
+            .query_control(V4L2_CID_JPEG_COMPRESSION_QUALITY)
+            .is_ok()
+            || self.dev().jpeg_compression().is_ok()
+    }
+
+    /// Sets the JPEG quality of MJPEG and JPEG frames, from 1 to 100, and
+    /// returns the quality the driver applied. Fails with `Unsupported` on
+    /// devices that do not support it.
+    pub fn set_jpeg_quality(&self, quality: u32) -> io::Result<u32> {
+        if !(1..=100).contains(&quality) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "JPEG quality is not within 1 to 100",
+            ));
+        }
+
+        if let Ok(qctrl) = self.dev().query_control(V4L2_CID_JPEG_COMPRESSION_QUALITY) {
+            let value = ControlInfo::from_raw(&qctrl, None).clamp(quality as i32);
+            self.dev()
+                .set_control(V4L2_CID_JPEG_COMPRESSION_QUALITY, value)?;
+            return self
+                .dev()
+                .control(V4L2_CID_JPEG_COMPRESSION_QUALITY)
+                .map(|value| value as u32);
+        }
+
+        let mut jpeg = match self.dev().jpeg_compression() {
+            Ok(jpeg) => jpeg,
+            Err(ref e) if is_unsupported(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "device does not support setting the JPEG quality",
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        jpeg.quality = quality as i32;
+        self.dev().set_jpeg_compression(&jpeg)?;
+        self.dev()
+            .jpeg_compression()
+            .map(|jpeg| jpeg.quality as u32)
+    }
 
-        for buf in self.device.buffers(
+    /// Exports the prepared buffers as dmabufs, together with the layout
+    /// metadata GPU APIs need to import them.
+    ///
+    /// The buffers are exported once and kept until the next
+    /// `prepare_mmapped` or `unprepare`.
+    pub fn export_dmabufs(&mut self) -> io::Result<&[DmabufBuffer]> {
+        if self.io != IoMethod::Mmap {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only mmap buffers can be exported",
+            ));
+        }
+        if self.dmabufs.is_empty() {
+            // Collected apart, so a failed export leaves nothing half done
+            // and the next call exports all buffers again.
+            let mut dmabufs = Vec::with_capacity(self.buffers.len());
+            for (index, mmap) in self.buffers.iter().enumerate() {
+                let fd = self.dev().export_buffer(
+                    v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                    index,
+                    0,
+                )?;
+                dmabufs.push(DmabufBuffer::new(
+                    index as u32,
+                    fd,
+                    mmap.len() as u32,
+                    &self.format,
+                ));
+            }
+            self.dmabufs = dmabufs;
+        }
+        Ok(&self.dmabufs)
+    }
+
+    /// Frees the buffers in the driver and hands over their exported
+    /// dmabufs, which stay valid, so consumers such as GPU APIs can outlive
+    /// the capture session. The capture is stopped first if needed, and
+    /// buffers can be prepared again afterwards.
+    ///
+    /// Only queues with `V4L2_BUF_CAP_SUPPORTS_ORPHANED_BUFS` free buffers
+    /// still in use. Others fail with `Unsupported` before anything is
+    /// stopped or freed.
+    pub fn orphan_dmabufs(&mut self) -> io::Result<Vec<DmabufBuffer>> {
+        if self.dmabufs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no buffers exported",
+            ));
+        }
+        let caps = match self
+            .dev()
+            .peek_buffer_capabilities(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)
+        {
+            Ok(caps) => caps,
+            // Drivers without CREATE_BUFS do not orphan buffers either.
+            Err(ref e) if is_unsupported(e) => 0,
+            Err(e) => return Err(e),
+        };
+        if caps & V4L2_BUF_CAP_SUPPORTS_ORPHANED_BUFS == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "driver cannot orphan buffers in use",
+            ));
+        }
+        if self.is_streaming() {
+            self.stop()?;
+        }
+
+        match self.dev().request_buffers(
             v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
             v4l2_memory::V4L2_MEMORY_MMAP,
+            0,
         ) {
-            let mmap = unsafe {
-                MmapOptions::new()
-                    .len(buf.length as usize)
-                    .offset(buf.m.offset as u64)
-                    .map_mut(&f)
-            };
-            if let Ok(mmap) = mmap {
-                self.buffers.push(mmap);
+            Ok(_) => {}
+            Err(ref e) if e.raw_os_error() == Some(libc::EBUSY) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "driver cannot orphan buffers in use",
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+
+        let dmabufs = mem::take(&mut self.dmabufs);
+        self.buffers.clear();
+        self.set_prepared();
+        Ok(dmabufs)
+    }
+
+    /// Returns the status of the selected input, to tell "no signal" from
+    /// a frozen picture.
+    pub fn input_status(&self) -> io::Result<InputStatus> {
+        input::input_status(self.dev())
+    }
+
+    /// Returns the current configuration of the device, including the values
+    /// of all writable controls, so it can be restored later with
+    /// `Builder::from_config`.
+    ///
+    /// Drivers without streaming parameters leave the capture mode and
+    /// frame interval at their defaults, and controls the driver cannot
+    /// read back are left out.
+    pub fn current_config(&self) -> io::Result<CaptureConfig> {
+        let fmt = self.dev().capture_format()?;
+        let (capture_mode, time_per_frame) = match self.dev().capture_parm() {
+            Ok(parm) => (
+                parm.capturemode,
+                (parm.timeperframe.numerator, parm.timeperframe.denominator),
+            ),
+            Err(ref e) if is_unsupported(e) => (0, CaptureConfig::default().time_per_frame),
+            Err(e) => return Err(e),
+        };
+        let input = self.dev().input().ok();
+
+        let controls = self.control_values()?;
+
+        Ok(CaptureConfig {
+            device: self.path.clone(),
+            input,
+            width: fmt.width,
+            height: fmt.height,
+            pixel_format: fmt.pixelformat,
+            field: fmt.field,
+            capture_mode,
+            time_per_frame,
+            controls,
+            buffer_count: self.buffers.len(),
+        })
+    }
+
+    /// Returns the values of all controls that can be restored, skipping
+    /// those the driver cannot read.
+    fn control_values(&self) -> io::Result<Vec<(u32, i32)>> {
+        let mut values = Vec::new();
+        for qctrl in self.dev().controls().filter(config::is_restorable) {
+            match self.dev().control(qctrl.id) {
+                Ok(value) => values.push((qctrl.id, value)),
+                Err(ref e) if is_unsupported(e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Switches to a new format in one call: stops streaming, releases the
+    /// buffers, sets the format, prepares as many buffers as before and
+    /// restarts streaming if it was running.
+    ///
+    /// Control values are restored afterwards, for drivers that reset them
+    /// on a format change. Returns the format chosen by the driver.
+    ///
+    /// If setting the format or preparing the buffers fails, the capture is
+    /// left stopped without buffers, and `prepare_mmapped` and `start` must
+    /// be called again. If only restarting fails, the buffers stay
+    /// prepared, as after a failed `start`.
+    pub fn reconfigure(&mut self, format: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+        let controls = self.control_values()?;
+        self.switch_format(|device| {
+            let format = device.set_capture_format(format)?;
+            for (id, value) in controls {
+                // Controls may have become inactive or read-only with the
+                // new format; keep the driver's value for those.
+                let _ = device.set_control(id, value);
+            }
+            Ok(format)
+        })
+    }
+
+    /// Adapts the capture to a changed source, e.g. after an
+    /// `Event::SourceChange`.
+    ///
+    /// For inputs with digital video timings the new timings are detected
+    /// first. If there is no usable signal (yet), the capture is left as it
+    /// is and an error of kind `NotConnected` is returned, so the call can
+    /// be repeated on the next source change.
+    ///
+    /// Otherwise streaming is stopped, the buffers released and the
+    /// detected timings applied. The format the driver now reports is then
+    /// set, keeping the pixel format, the buffers prepared again and
+    /// streaming resumed if it was running. Returns the new format.
+    ///
+    /// If applying the timings or the format, or preparing the buffers
+    /// fails, the capture is left stopped without buffers, and
+    /// `prepare_mmapped` and `start` must be called again.
+    pub fn renegotiate(&mut self) -> io::Result<v4l2_pix_format> {
+        let timings = match self.dev().query_dv_timings() {
+            Ok(timings) => Some(timings),
+            // Not a DV input, e.g. a decoder or an analog input.
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOTTY) => None,
+            Err(e) => match e.raw_os_error() {
+                Some(libc::ENOLINK) | Some(libc::ENOLCK) | Some(libc::ENODATA)
+                | Some(libc::ERANGE) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        format!("no usable signal: {}", e),
+                    ))
+                }
+                _ => return Err(e),
+            },
+        };
+
+        let pixelformat = self.format.pixelformat;
+        self.switch_format(|device| {
+            if let Some(timings) = timings {
+                device.set_dv_timings(&timings)?;
+            }
+
+            let mut format = device.capture_format()?;
+            format.pixelformat = pixelformat;
+            format.bytesperline = 0;
+            format.sizeimage = 0;
+            device.set_capture_format(&format)
+        })
+    }
+
+    /// Renegotiates the format if `event` reports a resolution change, see
+    /// `renegotiate`. Returns the new format in that case.
+    pub fn handle_source_change(&mut self, event: &Event) -> io::Result<Option<v4l2_pix_format>> {
+        match *event {
+            Event::SourceChange { changes } if changes & V4L2_EVENT_SRC_CH_RESOLUTION != 0 => {
+                self.renegotiate().map(Some)
             }
+            _ => Ok(None),
         }
-        mem::forget(f);
+    }
 
-        if self.buffers.len() != n {}
+    /// Rebuilds a capture handed over with `send_to`. The format is read
+    /// back from the device and its buffers are mapped again.
+    pub fn receive(socket: &UnixStream) -> io::Result<Capture> {
+        let (fd, data) = handoff::recv_fd(socket)?;
+        if data.len() < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed capture handoff",
+            ));
+        }
+        let count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let path = String::from_utf8_lossy(&data[4..]).into_owned();
 
-        Ok(())
+        let device = V4l2Device::from(fd);
+        let format = device.capture_format()?;
+        let mut capture = Capture::new(device, &path, format, IoMethod::Mmap);
+        capture.buffers = capture
+            .dev()
+            .map_buffers(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)?
+            .into_iter()
+            .map(|mmap| Arc::new(BufferMemory::new(mmap)))
+            .collect();
+        if capture.buffers.len() != count {
+            return Err(io::Error::other("failed to map all buffers"));
+        }
+        capture.set_prepared();
+        Ok(capture)
     }
 
-    pub fn unprepare(&mut self) {
-        self.buffers.clear();
+    /// Wraps a device configured elsewhere, e.g. by `input::TvSetup`, in
+    /// its current format and without buffers.
+    pub fn from_device(device: V4l2Device, path: &str) -> io::Result<Capture> {
+        let format = device.capture_format()?;
+        let io = probe_io_method(&device)?;
+        Ok(Capture::new(device, path, format, io))
     }
 
-    pub fn start(&self) -> io::Result<()> {
-        let mut buf: v4l2_buffer = unsafe { mem::zeroed() };
-
-        buf.typ = v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE;
-        buf.memory = v4l2_memory::V4L2_MEMORY_MMAP;
+    pub fn with_default<'a>() -> Builder<'a> {
+        Builder::default()
+    }
 
-        // Queue buffers
-        for i in 0..self.buffers.len() {
-            buf.index = i as u32;
+    pub fn with_device<'a>(path: &'a str) -> Builder<'a> {
+        Builder::with_device(path)
+    }
+}
 
-            self.device.queue_buffer(&buf)?;
+/// Stops streaming and releases the buffers before the device is closed,
+/// which some drivers, like the sunxi VFE, do not handle themselves.
+impl<D: DeviceOps> Drop for Capture<D> {
+    fn drop(&mut self) {
+        if self.device.is_some() {
+            self.unprepare();
         }
-
-        self.device
-            .stream_on(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)
     }
+}
 
-    pub fn stop(&self) -> io::Result<()> {
-        self.device
-            .stream_off(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)
+impl AsRawFd for Capture {
+    fn as_raw_fd(&self) -> RawFd {
+        self.dev().as_raw_fd()
     }
+}
 
-    pub fn take_frame(&mut self) -> io::Result<(v4l2_buffer, &mut MmapMut)> {
-        let buf = self.device.dequeue_buffer(
-            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
-            v4l2_memory::V4L2_MEMORY_MMAP,
-        )?;
+/// Picks the first I/O method the driver supports, in the order mmap, user
+/// pointer, `read()`. Streaming methods are looked up in the buffer
+/// capabilities of the queue, or, on kernels that do not report them,
+/// probed by requesting zero buffers, which drivers reject for unsupported
+/// memory types.
+fn probe_io_method(video: &V4l2Device) -> io::Result<IoMethod> {
+    if video.supports_streaming()? {
+        let buf_type = v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        let caps = video.buffer_capabilities(buf_type).unwrap_or(0);
+        for &(io, memory, cap) in &[
+            (
+                IoMethod::Mmap,
+                v4l2_memory::V4L2_MEMORY_MMAP,
+                V4L2_BUF_CAP_SUPPORTS_MMAP,
+            ),
+            (
+                IoMethod::UserPtr,
+                v4l2_memory::V4L2_MEMORY_USERPTR,
+                V4L2_BUF_CAP_SUPPORTS_USERPTR,
+            ),
+        ] {
+            let supported = if caps != 0 {
+                caps & cap != 0
+            } else {
+                video.request_buffers(buf_type, memory, 0).is_ok()
+            };
+            if supported {
+                return Ok(io);
+            }
+        }
+    }
+    if video.supports_readwrite()? {
+        return Ok(IoMethod::Read);
+    }
 
-        let mmap = &mut self.buffers[buf.index as usize];
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "device supports no capture I/O method",
+    ))
+}
 
-        Ok((buf, mmap))
+/// Returns the part of `full` that is `factor` times smaller, centered on
+/// `center` in fractions of `full` as far as it stays inside. Sizes are
+/// kept even for subsampled chroma.
+fn zoom_rect(full: &v4l2_rect, factor: f32, center: (f32, f32)) -> v4l2_rect {
+    let width = ((full.width as f32 / factor) as i32 & !1).max(2);
+    let height = ((full.height as f32 / factor) as i32 & !1).max(2);
+    let left = full.left + (center.0 * full.width as f32) as i32 - width / 2;
+    let top = full.top + (center.1 * full.height as f32) as i32 - height / 2;
+    v4l2_rect {
+        left: left.min(full.left + full.width - width).max(full.left),
+        top: top.min(full.top + full.height - height).max(full.top),
+        width,
+        height,
     }
+}
 
-    pub fn return_frame(&self, buf: &v4l2_buffer) -> io::Result<()> {
-        self.device.queue_buffer(buf)
+/// Returns the format frames are delivered in when the VFE rotates them:
+/// quarter turns swap width and height, and the stride follows the new
+/// width. Formats the driver already reports rotated are kept.
+#[cfg(feature = "sunxi-vfe")]
+fn rotated_format(requested: &v4l2_pix_format, format: &v4l2_pix_format) -> v4l2_pix_format {
+    let mut rotated = *format;
+    let quarter_turn = format.rot_angle == 90 || format.rot_angle == 270;
+    let swapped = format.width != format.height
+        && format.width == requested.height
+        && format.height == requested.width;
+    if quarter_turn && !swapped && format.width > 0 {
+        rotated.width = format.height;
+        rotated.height = format.width;
+        rotated.bytesperline = format.bytesperline / format.width * rotated.width;
     }
+    rotated
+}
 
-    pub fn with_default<'a>() -> Builder<'a> {
-        Builder::default()
-    }
+/// Offset of the sub channel frame in a VFE buffer: it follows the main
+/// frame, starting on a page boundary.
+#[cfg(feature = "sunxi-vfe")]
+fn subchannel_offset(main: &v4l2_pix_format) -> usize {
+    (main.sizeimage as usize).div_ceil(4096) * 4096
+}
 
-    pub fn with_device<'a>(path: &'a str) -> Builder<'a> {
-        Builder::with_device(path)
+/// Fills in the size of sub channel frames for drivers that leave it 0,
+/// from the line length the driver chose.
+#[cfg(feature = "sunxi-vfe")]
+fn fill_subchannel_size(sub: &mut v4l2_pix_format) -> io::Result<()> {
+    if sub.sizeimage != 0 {
+        return Ok(());
     }
+    let (height, stride) = (sub.height as usize, sub.bytesperline as usize);
+    let len = match sub.pixelformat {
+        V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => crate::repack::nv12_len(height, stride),
+        V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => crate::repack::i420_len(height, stride),
+        _ => stride * height,
+    };
+    if len == 0 || len > u32::MAX as usize {
+        return Err(io::Error::other(
+            "driver reported no size for the sub channel frames",
+        ));
+    }
+    sub.sizeimage = len as u32;
+    Ok(())
 }
 
 pub struct Builder<'a> {
@@ -114,12 +1556,23 @@ pub struct Builder<'a> {
     input: Option<i32>,
     capturemode: u32,
     timeperframe: v4l2_fract,
-    format: v4l2_pix_format,
+    pub(crate) format: v4l2_pix_format,
+    controls: Vec<(u32, i32)>,
+    buffer_count: Option<usize>,
+    read_buffers: Option<u32>,
+    io_method: Option<IoMethod>,
+    map_options: MapOptions,
+    low_latency: bool,
+    max_buffer_memory: Option<usize>,
+    stall_timeout: Option<Duration>,
+    open_options: Option<OpenOptions>,
     #[cfg(feature = "sunxi-vfe")]
-    _subch: Option<v4l2_pix_format>,
+    subchannel: Option<v4l2_pix_format>,
 }
 
 impl<'a> Builder<'a> {
+    /// Opens the device at `path`, which may also be a formatted
+    /// `DeviceId`.
     pub fn with_device(path: &'a str) -> Self {
         #[cfg(feature = "sunxi-vfe")]
         use std::ptr;
@@ -156,11 +1609,35 @@ impl<'a> Builder<'a> {
                 rot_angle: 0,
                 subchannel: ptr::null_mut(),
             },
+            controls: Vec::new(),
+            buffer_count: None,
+            read_buffers: None,
+            io_method: None,
+            map_options: MapOptions::default(),
+            low_latency: false,
+            max_buffer_memory: None,
+            stall_timeout: None,
+            open_options: None,
             #[cfg(feature = "sunxi-vfe")]
-            _subch: None,
+            subchannel: None,
         }
     }
 
+    /// Creates a builder that reproduces a saved `CaptureConfig`.
+    pub fn from_config(config: &'a CaptureConfig) -> Self {
+        let mut builder = Builder::with_device(&config.device)
+            .video_size(config.width, config.height)
+            .pixel_format(config.pixel_format)
+            .time_per_frame(config.time_per_frame.0, config.time_per_frame.1)
+            .buffers(config.buffer_count);
+
+        builder.input = config.input;
+        builder.capturemode = config.capture_mode;
+        builder.format.field = config.field;
+        builder.controls = config.controls.clone();
+        builder
+    }
+
     pub fn device(mut self, path: &'a str) -> Self {
         self.path = path;
         self
@@ -198,8 +1675,128 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Sets a control value to be applied when the device is opened.
+    pub fn control(mut self, id: u32, value: i32) -> Self {
+        self.controls.push((id, value));
+        self
+    }
+
+    /// Prepares `count` mmap buffers on open, also with `low_latency`.
+    pub fn buffers(mut self, count: usize) -> Self {
+        self.buffer_count = Some(count);
+        self
+    }
+
+    /// Sets the number of buffers the driver uses internally, which some
+    /// drivers use to size their queues.
+    pub fn read_buffers(mut self, count: u32) -> Self {
+        self.read_buffers = Some(count);
+        self
+    }
+
+    /// Forces an I/O method instead of probing for the best one the driver
+    /// supports.
+    pub fn io_method(mut self, io: IoMethod) -> Self {
+        self.io_method = Some(io);
+        self
+    }
+
+    /// Sets how the buffers prepared on open are mapped.
+    pub fn map_options(mut self, options: MapOptions) -> Self {
+        self.map_options = options;
+        self
+    }
+
+    /// Caps the memory of the buffers at `bytes`, preparing fewer buffers
+    /// than asked for if needed. See `Capture::set_buffer_memory_limit`.
+    pub fn max_buffer_memory(mut self, bytes: usize) -> Self {
+        self.max_buffer_memory = Some(bytes);
+        self
+    }
+
+    /// Fails dequeues if no frame arrives within `timeout`. See
+    /// `Capture::set_stall_timeout`.
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Opens the device with `options` instead of for blocking reading and
+    /// writing. With `nonblocking`, dequeues fail with `WouldBlock` when no
+    /// frame is ready.
+    pub fn open_options(mut self, options: OpenOptions) -> Self {
+        self.open_options = Some(options);
+        self
+    }
+
+    /// Tunes the capture for the shortest delay from sensor to
+    /// application, at the cost of dropping frames when the application
+    /// falls behind:
+    ///
+    /// - unless set with `buffers`, only as many buffers as the driver
+    ///   needs to stream, plus one held by the application, are prepared,
+    ///   so frames cannot wait long in the queue;
+    /// - frames are delivered with `Capture::deliver_latest_only`;
+    /// - the default capture mode is used, and cameras may not lower the
+    ///   frame rate to lengthen the exposure (`V4L2_CID_EXPOSURE_AUTO_PRIORITY`).
+    ///
+    /// The effect can be measured with `Capture::stats`, whose latency runs
+    /// from the driver timestamp to the dequeue. A consumer that falls
+    /// behind otherwise works off every queued frame, so with `n` buffers
+    /// its `max_latency` grows towards `n - 1` frame intervals, while with
+    /// this preset it stays within about one interval plus the processing
+    /// time, and the frames it could not keep up with are counted as
+    /// `skipped`:
+    ///
+    /// ```no_run
+    /// # fn run() -> std::io::Result<()> {
+    /// let capture = v4l2::Capture::with_device("/dev/video0").low_latency().open()?;
+    /// capture.start()?;
+    /// for _ in 0..300 {
+    ///     let frame = capture.dequeue_frame()?;
+    ///     // Process the frame.
+    ///     capture.return_frame(frame.buffer())?;
+    /// }
+    /// let stats = capture.stats();
+    /// println!(
+    ///     "mean {:?}, max {:?}, {} skipped",
+    ///     stats.mean_latency(),
+    ///     stats.max_latency,
+    ///     stats.skipped
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn low_latency(mut self) -> Self {
+        self.low_latency = true;
+        self.capturemode = 0;
+        self
+    }
+
+    /// Opens the device without preparing buffers, as a capture whose
+    /// stream state is checked at compile time.
+    pub fn open_configured(mut self) -> io::Result<TypedCapture<Configured>> {
+        self.buffer_count = Some(0);
+        Ok(TypedCapture::new(self.open()?))
+    }
+
     pub fn open(self) -> io::Result<Capture> {
-        let video = V4l2Device::open(self.path)?;
+        #[cfg(feature = "sunxi-vfe")]
+        match self.format.rot_angle {
+            0 | 90 | 180 | 270 => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "rotation must be 0, 90, 180 or 270 degrees",
+                ))
+            }
+        }
+
+        let id = DeviceId::parse(self.path);
+        let video = match self.open_options {
+            Some(ref options) => id.open_with(options)?,
+            None => id.open()?,
+        };
 
         // Ensure pixel format supported for safety.
         // VFE driver crashes if pixel format is not specified.
@@ -208,10 +1805,7 @@ impl<'a> Builder<'a> {
             .find(|fmtdesc| fmtdesc.pixelformat == self.format.pixelformat)
             .is_none()
         {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "unsupported pixel format",
-            ));
+            return Err(io::Error::other("unsupported pixel format"));
         }
 
         if let Some(input) = self.input {
@@ -222,6 +1816,9 @@ impl<'a> Builder<'a> {
         println!("expected read bufs {}", param.readbuffers);
 
         param.capturemode = self.capturemode;
+        if let Some(count) = self.read_buffers {
+            param.readbuffers = count;
+        }
 
         if (param.capability & V4L2_CAP_TIMEPERFRAME) != 0 {
             param.timeperframe = self.timeperframe;
@@ -234,9 +1831,58 @@ impl<'a> Builder<'a> {
             param.timeperframe.numerator, param.timeperframe.denominator
         );
 
-        let _pixfmt = video.set_capture_format(&self.format)?;
+        #[cfg(not(feature = "sunxi-vfe"))]
+        let pixfmt = video.set_capture_format(&self.format)?;
+        #[cfg(feature = "sunxi-vfe")]
+        let (pixfmt, subchannel) = {
+            let mut format = self.format;
+            let mut subchannel = self.subchannel;
+            if let Some(ref mut sub) = subchannel {
+                format.subchannel = sub;
+            }
+            let mut pixfmt = video.set_capture_format(&format)?;
+            pixfmt.subchannel = std::ptr::null_mut();
+            if let Some(ref mut sub) = subchannel {
+                fill_subchannel_size(sub)?;
+            }
+            (rotated_format(&self.format, &pixfmt), subchannel)
+        };
+
+        for &(id, value) in &self.controls {
+            video.set_control(id, value)?;
+        }
+        if self.low_latency && video.query_control(V4L2_CID_EXPOSURE_AUTO_PRIORITY).is_ok() {
+            video.set_control(V4L2_CID_EXPOSURE_AUTO_PRIORITY, 0)?;
+        }
+        let buffer_count = match self.buffer_count {
+            Some(count) => count,
+            // Drivers that do not tell their minimum stream with two.
+            None if self.low_latency => video
+                .control(V4L2_CID_MIN_BUFFERS_FOR_CAPTURE)
+                .map(|min| min.max(1) as usize + 1)
+                .unwrap_or(2),
+            None => 0,
+        };
+
+        let io = match self.io_method {
+            Some(io) => io,
+            None => probe_io_method(&video)?,
+        };
 
-        Ok(Capture::new(video))
+        let mut capture = Capture::new(video, self.path, pixfmt, io);
+        capture.set_map_options(self.map_options);
+        capture.deliver_latest_only(self.low_latency);
+        capture.set_buffer_memory_limit(self.max_buffer_memory);
+        capture.set_stall_timeout(self.stall_timeout);
+        #[cfg(feature = "sunxi-vfe")]
+        {
+            capture.subchannel = subchannel;
+        }
+        if buffer_count > 0 {
+            capture.prepare_mmapped(buffer_count)?;
+        }
+
+        Ok(capture)
     }
 }
 
@@ -257,10 +1903,28 @@ impl<'a> Builder<'a> {
         self
     }
 
+    /// Rotates frames in the VFE by `degree`, one of 0, 90, 180 or 270.
+    /// Other angles make `open` fail.
     pub fn rotate(&mut self, degree: u32) -> &mut Self {
         self.format.rot_angle = degree;
         self
     }
+
+    /// Captures a second, smaller stream on the VFE sub channel, e.g. a
+    /// preview next to a full resolution main stream. Both frames arrive
+    /// in one buffer, see `Capture::dequeue_dual_frame`.
+    pub fn subchannel(mut self, width: u32, height: u32, pixelformat: u32) -> Self {
+        let mut format = self.format;
+        format.width = width;
+        format.height = height;
+        format.pixelformat = pixelformat;
+        format.bytesperline = 0;
+        format.sizeimage = 0;
+        format.rot_angle = 0;
+        format.subchannel = std::ptr::null_mut();
+        self.subchannel = Some(format);
+        self
+    }
 }
 
 impl<'a> Default for Builder<'a> {
@@ -268,3 +1932,621 @@ impl<'a> Default for Builder<'a> {
         Builder::with_device("/dev/video0")
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::os::unix::io::BorrowedFd;
+    use std::rc::Rc;
+    use std::sync::Mutex;
+    use std::thread;
+
+    use crate::sys::QueueHandle;
+
+    /// An in-memory device that hands out queued buffers in order, failing
+    /// dequeues with the errnos in `errors` first.
+    pub(crate) struct FakeDevice {
+        format: Cell<v4l2_pix_format>,
+        count: Cell<usize>,
+        /* Shared with queue handles */
+        queued: Arc<Mutex<VecDeque<u32>>>,
+        streaming: Cell<bool>,
+        sequence: Cell<u32>,
+        errors: RefCell<VecDeque<i32>>,
+        /* Errno of the next STREAMON, if it is to fail */
+        stream_on_error: Cell<Option<i32>>,
+        /* Errno of COMPOSE selections, if they are to fail */
+        compose_error: Cell<Option<i32>>,
+        /* Calls freeing the buffers, shared to be read after a drop */
+        releases: Rc<Cell<usize>>,
+        /* Stands in for the device node when handing off */
+        node: File,
+        /* Set when the device is closed */
+        pub(crate) dropped: Rc<Cell<bool>>,
+    }
+
+    impl Drop for FakeDevice {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    impl AsFd for FakeDevice {
+        fn as_fd(&self) -> BorrowedFd<'_> {
+            self.node.as_fd()
+        }
+    }
+
+    impl FakeDevice {
+        pub(crate) fn new() -> FakeDevice {
+            let mut format = Builder::with_device("")
+                .video_size(4, 2)
+                .pixel_format(V4L2_PIX_FMT_GREY)
+                .format;
+            format.bytesperline = 4;
+            format.sizeimage = 8;
+            FakeDevice {
+                format: Cell::new(format),
+                count: Cell::new(0),
+                queued: Arc::default(),
+                streaming: Cell::new(false),
+                sequence: Cell::new(0),
+                errors: RefCell::new(VecDeque::new()),
+                stream_on_error: Cell::new(None),
+                compose_error: Cell::new(None),
+                releases: Rc::new(Cell::new(0)),
+                node: File::open("/dev/null").unwrap(),
+                dropped: Rc::new(Cell::new(false)),
+            }
+        }
+
+        pub(crate) fn into_capture(self) -> Capture<FakeDevice> {
+            let format = self.format.get();
+            Capture::new(self, "fake", format, IoMethod::Mmap)
+        }
+    }
+
+    fn queue_index(
+        queued: &Mutex<VecDeque<u32>>,
+        count: usize,
+        buf: &v4l2_buffer,
+    ) -> io::Result<()> {
+        let mut queued = queued.lock().unwrap();
+        if buf.index as usize >= count || queued.contains(&buf.index) {
+            return Err(errno(libc::EINVAL));
+        }
+        queued.push_back(buf.index);
+        Ok(())
+    }
+
+    fn errno(code: i32) -> io::Error {
+        io::Error::from_raw_os_error(code)
+    }
+
+    impl DeviceOps for FakeDevice {
+        fn capability(&self) -> io::Result<v4l2_capability> {
+            let mut cap: v4l2_capability = unsafe { mem::zeroed() };
+            cap.device_caps = V4L2_CAP_VIDEO_CAPTURE | V4L2_CAP_STREAMING;
+            cap.capabilities = cap.device_caps;
+            Ok(cap)
+        }
+
+        fn capture_format(&self) -> io::Result<v4l2_pix_format> {
+            Ok(self.format.get())
+        }
+
+        fn set_capture_format(&self, fmt: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+            if self.count.get() > 0 {
+                return Err(errno(libc::EBUSY));
+            }
+            let mut format = self.format.get();
+            format.width = fmt.width;
+            format.height = fmt.height;
+            self.format.set(format);
+            Ok(format)
+        }
+
+        fn request_buffers(
+            &self,
+            _buf_type: v4l2_buf_type,
+            _memory: v4l2_memory,
+            count: usize,
+        ) -> io::Result<usize> {
+            if self.streaming.get() {
+                return Err(errno(libc::EBUSY));
+            }
+            if count == 0 {
+                self.releases.set(self.releases.get() + 1);
+            }
+            self.count.set(count);
+            self.queued.lock().unwrap().clear();
+            Ok(count)
+        }
+
+        fn map_buffers(&self, _buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>> {
+            (0..self.count.get())
+                .map(|_| MmapMut::map_anon(self.format.get().sizeimage as usize))
+                .collect()
+        }
+
+        fn queue_buffer(&self, buf: &v4l2_buffer) -> io::Result<()> {
+            queue_index(&self.queued, self.count.get(), buf)
+        }
+
+        fn queue_handle(&self) -> Option<QueueHandle> {
+            let queued = self.queued.clone();
+            let count = self.count.get();
+            Some(Box::new(move |buf| queue_index(&queued, count, buf)))
+        }
+
+        fn dequeue_buffer(
+            &self,
+            buf_type: v4l2_buf_type,
+            memory: v4l2_memory,
+        ) -> io::Result<v4l2_buffer> {
+            if !self.streaming.get() {
+                return Err(errno(libc::EINVAL));
+            }
+            match self.errors.borrow_mut().pop_front() {
+                Some(0) | None => {}
+                Some(code) => return Err(errno(code)),
+            }
+            let index = self
+                .queued
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| errno(libc::EAGAIN))?;
+
+            let mut buf = v4l2_buffer::new(buf_type, memory);
+            buf.index = index;
+            buf.bytesused = self.format.get().sizeimage;
+            buf.field = v4l2_field::V4L2_FIELD_NONE;
+            buf.sequence = self.sequence.get();
+            self.sequence.set(buf.sequence + 1);
+            Ok(buf)
+        }
+
+        fn frame_ready(&self) -> io::Result<bool> {
+            Ok(self.streaming.get() && !self.queued.lock().unwrap().is_empty())
+        }
+
+        fn wait_frame(&self, _timeout: Duration) -> io::Result<bool> {
+            self.frame_ready()
+        }
+
+        fn stream_on(&self, _buf_type: v4l2_buf_type) -> io::Result<()> {
+            if let Some(code) = self.stream_on_error.take() {
+                return Err(errno(code));
+            }
+            self.streaming.set(true);
+            self.sequence.set(0);
+            Ok(())
+        }
+
+        fn stream_off(&self, _buf_type: v4l2_buf_type) -> io::Result<()> {
+            self.streaming.set(false);
+            self.queued.lock().unwrap().clear();
+            Ok(())
+        }
+
+        fn read(&self, _data: &mut [u8]) -> io::Result<usize> {
+            Err(errno(libc::EINVAL))
+        }
+
+        /* A 4x2 sensor that crops by shrinking the frames, and scales
+         * them back up when composing. */
+        fn selection(&self, _buf_type: v4l2_buf_type, target: u32) -> io::Result<v4l2_rect> {
+            match target {
+                V4L2_SEL_TGT_CROP_DEFAULT => Ok(v4l2_rect {
+                    left: 0,
+                    top: 0,
+                    width: 4,
+                    height: 2,
+                }),
+                _ => Err(errno(libc::EINVAL)),
+            }
+        }
+
+        fn set_selection(
+            &self,
+            _buf_type: v4l2_buf_type,
+            target: u32,
+            _flags: u32,
+            rect: &v4l2_rect,
+        ) -> io::Result<v4l2_rect> {
+            match (target, self.compose_error.get()) {
+                (V4L2_SEL_TGT_CROP, _) | (V4L2_SEL_TGT_COMPOSE, None) => {}
+                (V4L2_SEL_TGT_COMPOSE, Some(code)) => return Err(errno(code)),
+                _ => return Err(errno(libc::EINVAL)),
+            }
+            let mut format = self.format.get();
+            format.width = rect.width as u32;
+            format.height = rect.height as u32;
+            self.format.set(format);
+            Ok(*rect)
+        }
+    }
+
+    fn fake_capture() -> Capture<FakeDevice> {
+        FakeDevice::new().into_capture()
+    }
+
+    #[test]
+    fn delivers_frames_in_queue_order() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        for &(index, sequence) in &[(0, 0), (1, 1), (0, 2)] {
+            let frame = capture.dequeue_frame().unwrap();
+            assert_eq!((frame.buffer().index, frame.sequence()), (index, sequence));
+            assert_eq!(frame.data().len(), 8);
+            capture.return_frame(frame.buffer()).unwrap();
+        }
+    }
+
+    #[test]
+    fn requeues_owned_frames_on_drop() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        let frame = capture.dequeue_owned_frame().unwrap();
+        thread::spawn(move || drop(frame)).join().unwrap();
+        assert_eq!(*capture.dev().queued.lock().unwrap(), [1, 0]);
+
+        // Queued by a dropped frame, then returned by STREAMOFF.
+        assert_eq!(capture.dequeue_frame().unwrap().buffer().index, 1);
+        drop(capture.dequeue_owned_frame().unwrap());
+        capture.stop().unwrap();
+        capture.start().unwrap();
+        assert_eq!(capture.dev().queued.lock().unwrap().len(), 1);
+
+        // Dropped while stopped, so queued by the next start.
+        let frame = capture.dequeue_owned_frame().unwrap();
+        capture.stop().unwrap();
+        drop(frame);
+        assert!(capture.dev().queued.lock().unwrap().is_empty());
+        capture.start().unwrap();
+        assert_eq!(capture.dev().queued.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn survives_dequeue_errors() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        capture.dev().errors.borrow_mut().push_back(libc::EIO);
+        let err = capture.dequeue_frame().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+
+        // Both buffers held: the driver has nothing to hand out.
+        let first = capture.dequeue_owned_frame().unwrap();
+        let second = capture.dequeue_frame().unwrap();
+        let err = capture.dequeue_frame().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        // Dropped owned frames queue their buffer right away.
+        capture.return_frame(second.buffer()).unwrap();
+        drop(first);
+        assert_eq!(capture.dequeue_frame().unwrap().buffer().index, 1);
+        assert_eq!(capture.dequeue_frame().unwrap().buffer().index, 0);
+
+        capture.stop().unwrap();
+        assert!(capture.dequeue_frame().is_err());
+    }
+
+    #[test]
+    fn dequeues_into_caller_buffer() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        let mut buf = v4l2_buffer::new(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            v4l2_memory::V4L2_MEMORY_MMAP,
+        );
+        for &(index, sequence) in &[(0, 0), (1, 1), (0, 2)] {
+            assert_eq!(capture.dequeue_into(&mut buf).unwrap().len(), 8);
+            assert_eq!((buf.index, buf.sequence), (index, sequence));
+            capture.return_frame(&buf).unwrap();
+        }
+    }
+
+    #[test]
+    fn requeues_by_index() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        let index = capture.dequeue_frame().unwrap().buffer().index as usize;
+        capture.requeue(index).unwrap();
+        assert_eq!(*capture.dev().queued.lock().unwrap(), [1, 0]);
+
+        // Only dequeued buffers can be given back.
+        let err = capture.requeue(1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = capture.requeue(2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn restarts_without_held_buffers() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        let held = capture.dequeue_frame().unwrap().buffer().clone();
+        capture.restart().unwrap();
+        assert!(capture.is_streaming());
+        assert_eq!(capture.dequeue_frame().unwrap().buffer().index, 1);
+        capture.return_frame(&held).unwrap();
+        assert_eq!(capture.stats().dropped, 0);
+
+        capture.stop().unwrap();
+        capture.start().unwrap();
+        capture.dequeue_frame().unwrap();
+        assert_eq!(capture.stats().dropped, 0);
+    }
+
+    #[test]
+    fn hands_off_without_releasing_buffers() {
+        let (sender, receiver) = UnixStream::pair().unwrap();
+
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+        let releases = capture.dev().releases.clone();
+        let (capture, err) = capture.send_to(&sender).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert!(capture.is_streaming());
+
+        capture.stop().unwrap();
+        assert!(capture.send_to(&sender).is_ok());
+        assert_eq!(releases.get(), 0);
+        let (_fd, data) = handoff::recv_fd(&receiver).unwrap();
+        assert_eq!(data[..4], 2u32.to_le_bytes());
+        assert_eq!(&data[4..], b"fake");
+
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        let releases = capture.dev().releases.clone();
+        drop(capture);
+        assert_eq!(releases.get(), 1);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn releases_buffers_that_fail_to_map() {
+        use crate::faulty::FaultyDevice;
+
+        let mut capture =
+            fake_capture().map_device(|device| FaultyDevice::new(device).no_memory(1.0));
+        let err = capture.prepare_mmapped(2).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOMEM));
+        assert_eq!(capture.buffer_count(), 0);
+        assert_eq!(capture.dev().inner().count.get(), 0);
+        assert_eq!(capture.dev().inner().releases.get(), 1);
+        assert!(capture.start().is_err());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn switching_formats_leaves_no_buffers_on_errors() {
+        use crate::faulty::FaultyDevice;
+
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+        let mut capture = capture.map_device(|device| FaultyDevice::new(device).no_memory(1.0));
+
+        let mut format = *capture.format();
+        format.width = 2;
+        let err = capture
+            .switch_format(|device| device.set_capture_format(&format))
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOMEM));
+        assert_eq!(capture.format().width, 2);
+        assert_eq!(capture.state(), StreamState::Unprepared);
+        assert_eq!(capture.buffer_count(), 0);
+        assert_eq!(capture.dev().inner().count.get(), 0);
+        assert!(capture.start().is_err());
+    }
+
+    #[test]
+    fn switching_formats_leaves_no_buffers_when_applying_fails() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        let err = capture
+            .switch_format(|_| Err(errno(libc::ENOLINK)))
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOLINK));
+        assert_eq!(capture.state(), StreamState::Unprepared);
+        assert_eq!(capture.dev().releases.get(), 1);
+
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+        assert!(capture.dequeue_frame().is_ok());
+    }
+
+    #[test]
+    fn times_out_on_stalls() {
+        let mut capture = fake_capture();
+        capture.set_stall_timeout(Some(Duration::from_millis(10)));
+        capture.prepare_mmapped(1).unwrap();
+        capture.start().unwrap();
+
+        let frame = capture.dequeue_frame().unwrap();
+        let err = capture.dequeue_frame().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(capture.stats().stalls, 1);
+        capture.return_frame(frame.buffer()).unwrap();
+        assert!(capture.dequeue_frame().is_ok());
+    }
+
+    #[test]
+    fn delivers_every_nth_frame() {
+        let mut capture = fake_capture();
+        capture.deliver_every_nth(3);
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        for &sequence in &[0, 3, 6] {
+            let frame = capture.dequeue_frame().unwrap();
+            assert_eq!(frame.sequence(), sequence);
+            capture.return_frame(frame.buffer()).unwrap();
+        }
+    }
+
+    #[test]
+    fn delivers_latest_frame() {
+        let mut capture = fake_capture();
+        capture.deliver_latest_only(true);
+        capture.prepare_mmapped(3).unwrap();
+        capture.start().unwrap();
+
+        let frame = capture.dequeue_frame().unwrap();
+        assert_eq!(frame.sequence(), 2);
+        let stats = capture.stats();
+        assert_eq!((stats.delivered, stats.skipped, stats.dropped), (1, 2, 0));
+    }
+
+    #[test]
+    fn rolls_back_failed_start() {
+        let mut capture = fake_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.dev().stream_on_error.set(Some(libc::EIO));
+        assert!(capture.start().is_err());
+        assert!(!capture.is_streaming());
+        assert!(capture.dev().queued.lock().unwrap().is_empty());
+
+        capture.start().unwrap();
+        for _ in 0..2 {
+            capture.dequeue_frame().unwrap();
+        }
+    }
+
+    #[test]
+    fn requeues_latest_frame_on_error() {
+        let mut capture = fake_capture();
+        capture.deliver_latest_only(true);
+        capture.prepare_mmapped(3).unwrap();
+        capture.start().unwrap();
+
+        capture.dev().errors.borrow_mut().extend([0, libc::EIO]);
+        let err = capture.dequeue_frame().err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+        capture.deliver_latest_only(false);
+        for _ in 0..3 {
+            capture.dequeue_frame().unwrap();
+        }
+    }
+
+    #[test]
+    fn caps_buffer_memory() {
+        let mut capture = fake_capture();
+        capture.set_buffer_memory_limit(Some(20));
+        capture.prepare_mmapped(4).unwrap();
+        let usage = capture.buffer_usage();
+        assert_eq!((usage.buffer_count(), usage.total()), (2, 16));
+        assert_eq!(usage.mapping, BufferMapping::Driver);
+
+        capture.set_buffer_memory_limit(Some(4));
+        let err = capture.prepare_mmapped(4).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+    }
+
+    #[test]
+    fn zoom_reads_back_the_format() {
+        let mut capture = fake_capture();
+        let crop = capture.set_zoom(2.0, (0.5, 0.5)).unwrap();
+        assert_eq!(crop.width, 2);
+        assert_eq!((capture.format().width, capture.format().height), (4, 2));
+
+        // Without composing, the frames shrink to the crop.
+        for &code in &[libc::EINVAL, libc::EBUSY] {
+            capture.dev().compose_error.set(Some(code));
+            let crop = capture.set_zoom(2.0, (0.5, 0.5)).unwrap();
+            let size = (crop.width as u32, crop.height as u32);
+            assert_eq!((capture.format().width, capture.format().height), size);
+        }
+
+        capture.dev().compose_error.set(Some(libc::EIO));
+        let err = capture.set_zoom(2.0, (0.5, 0.5)).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EIO));
+    }
+
+    #[test]
+    fn zoom_stays_inside_the_sensor() {
+        let full = v4l2_rect {
+            left: 0,
+            top: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let centered = zoom_rect(&full, 2.0, (0.5, 0.5));
+        assert_eq!(
+            (centered.left, centered.top, centered.width, centered.height),
+            (480, 270, 960, 540)
+        );
+        let corner = zoom_rect(&full, 2.0, (1.0, 0.0));
+        assert_eq!((corner.left, corner.top), (960, 0));
+        assert_eq!(zoom_rect(&full, 1.0, (0.2, 0.8)), full);
+    }
+
+    #[test]
+    fn opens_with_given_options() {
+        // `/dev/null` opens, but is no video device; these options fail
+        // before opening.
+        let options = *OpenOptions::new().read(false).write(false);
+        let err = Builder::with_device("/dev/null")
+            .open_options(options)
+            .open()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "sunxi-vfe")]
+    #[test]
+    fn quarter_turn_swaps_dimensions() {
+        let mut requested = Builder::with_device("").video_size(640, 480).format;
+        requested.rot_angle = 90;
+        let mut format = requested;
+        format.bytesperline = 1280;
+
+        let rotated = rotated_format(&requested, &format);
+        assert_eq!((rotated.width, rotated.height), (480, 640));
+        assert_eq!(rotated.bytesperline, 960);
+
+        // Already rotated by the driver.
+        assert_eq!(rotated_format(&requested, &rotated).width, 480);
+    }
+
+    #[cfg(feature = "sunxi-vfe")]
+    #[test]
+    fn fills_in_subchannel_size() {
+        let mut sub = Builder::with_device("")
+            .subchannel(320, 240, V4L2_PIX_FMT_NV12)
+            .subchannel
+            .unwrap();
+        sub.bytesperline = 320;
+        fill_subchannel_size(&mut sub).unwrap();
+        assert_eq!(sub.sizeimage, 320 * 360);
+
+        sub.pixelformat = V4L2_PIX_FMT_YUYV;
+        sub.bytesperline = 640;
+        sub.sizeimage = 0;
+        fill_subchannel_size(&mut sub).unwrap();
+        assert_eq!(sub.sizeimage, 640 * 240);
+
+        sub.bytesperline = 0;
+        sub.sizeimage = 0;
+        assert!(fill_subchannel_size(&mut sub).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use crate::capture::Capture;
+use crate::frame::Frame;
+use crate::sys::{cvt, poll_millis};
+
+/// A set of started captures multiplexed over a single epoll instance.
+///
+/// Frames are returned together with the id of the capture they come from
+/// as they arrive, so many cameras can be served from one thread. Each frame
+/// must be given back with `return_frame`.
+pub struct CaptureSet<K> {
+    epoll: OwnedFd,
+    captures: Vec<Option<(K, Capture)>>,
+    ready: RefCell<VecDeque<usize>>,
+}
+
+impl<K: PartialEq> CaptureSet<K> {
+    pub fn new() -> io::Result<CaptureSet<K>> {
+        let fd = cvt(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        Ok(CaptureSet {
+            epoll: unsafe { OwnedFd::from_raw_fd(fd) },
+            captures: Vec::new(),
+            ready: RefCell::new(VecDeque::new()),
+        })
+    }
+
+    fn slot(&self, id: &K) -> Option<usize> {
+        self.captures
+            .iter()
+            .position(|c| c.as_ref().is_some_and(|(k, _)| k == id))
+    }
+
+    /// Adds a capture under `id`, replacing any capture with the same id.
+    ///
+    /// The new capture is registered with epoll before the old one is
+    /// taken out, so the set is unchanged if either step fails.
+    pub fn add(&mut self, id: K, capture: Capture) -> io::Result<Option<Capture>> {
+        let existing = self.slot(&id);
+        let slot = existing
+            .or_else(|| self.captures.iter().position(Option::is_none))
+            .unwrap_or(self.captures.len());
+
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: slot as u64,
+        };
+        self.ctl(libc::EPOLL_CTL_ADD, &capture, &mut event)?;
+
+        let old = match existing {
+            Some(slot) => {
+                let (_, old) = self.captures[slot].as_ref().unwrap();
+                if let Err(e) = self.ctl(libc::EPOLL_CTL_DEL, old, ptr::null_mut()) {
+                    let _ = self.ctl(libc::EPOLL_CTL_DEL, &capture, ptr::null_mut());
+                    return Err(e);
+                }
+                // Readiness reported for the old capture is stale.
+                self.ready.borrow_mut().retain(|&s| s != slot);
+                self.captures[slot]
+                    .replace((id, capture))
+                    .map(|(_, old)| old)
+            }
+            None if slot == self.captures.len() => {
+                self.captures.push(Some((id, capture)));
+                None
+            }
+            None => {
+                self.captures[slot] = Some((id, capture));
+                None
+            }
+        };
+        Ok(old)
+    }
+
+    fn ctl(
+        &self,
+        op: libc::c_int,
+        capture: &Capture,
+        event: *mut libc::epoll_event,
+    ) -> io::Result<()> {
+        cvt(unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), op, capture.as_raw_fd(), event) })
+            .map(|_| ())
+    }
+
+    /// Removes the capture registered under `id` and returns it.
+    pub fn remove(&mut self, id: &K) -> io::Result<Option<Capture>> {
+        let slot = match self.slot(id) {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        let (_, capture) = self.captures[slot].take().unwrap();
+        self.ready.borrow_mut().retain(|&s| s != slot);
+
+        self.ctl(libc::EPOLL_CTL_DEL, &capture, ptr::null_mut())?;
+        Ok(Some(capture))
+    }
+
+    pub fn get(&self, id: &K) -> Option<&Capture> {
+        self.slot(id)
+            .and_then(|slot| self.captures[slot].as_ref())
+            .map(|(_, capture)| capture)
+    }
+
+    pub fn get_mut(&mut self, id: &K) -> Option<&mut Capture> {
+        let slot = self.slot(id)?;
+        self.captures[slot].as_mut().map(|(_, capture)| capture)
+    }
+
+    pub fn len(&self) -> usize {
+        self.captures.iter().filter(|c| c.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Capture)> {
+        self.captures
+            .iter()
+            .filter_map(|c| c.as_ref().map(|(k, capture)| (k, capture)))
+    }
+
+    fn wait(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let timeout = poll_millis(timeout);
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; 16];
+
+        let n = loop {
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    events.len() as i32,
+                    timeout,
+                )
+            };
+            match cvt(n) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result? as usize,
+            }
+        };
+
+        let mut ready = self.ready.borrow_mut();
+        ready.extend(events[..n].iter().map(|e| e.u64 as usize));
+        Ok(())
+    }
+
+    /// Waits for the next frame of any capture.
+    ///
+    /// Returns `None` only if no frame arrived within `timeout`, or waits
+    /// forever if `timeout` is `None`.
+    pub fn next_frame(&self, timeout: Option<Duration>) -> io::Result<Option<(&K, Frame<'_>)>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            if self.ready.borrow().is_empty() {
+                let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+                self.wait(remaining)?;
+            }
+
+            let slot = match self.ready.borrow_mut().pop_front() {
+                Some(slot) => slot,
+                None => return Ok(None),
+            };
+            if let Some((id, capture)) = self.captures.get(slot).and_then(Option::as_ref) {
+                return capture.dequeue_frame().map(|frame| Some((id, frame)));
+            }
+            // The capture was removed since it became ready; wait on.
+        }
+    }
+
+    /// Gives a frame back to the capture it came from.
+    pub fn return_frame(&self, id: &K, frame: &Frame) -> io::Result<()> {
+        match self.get(id) {
+            Some(capture) => capture.return_frame(frame.buffer()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no capture with this id",
+            )),
+        }
+    }
+}
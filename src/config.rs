@@ -0,0 +1,67 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::sys::uapi::*;
+
+/// Complete description of a capture setup, suitable for saving and
+/// restoring a camera configuration across restarts.
+///
+/// Build a `Capture` from it with `Builder::from_config`, and obtain the
+/// configuration of a running capture with `Capture::current_config`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CaptureConfig {
+    /// Device node path, e.g. `/dev/video0`, or a `DeviceId`, which
+    /// survives the devices being enumerated in a different order.
+    pub device: String,
+    pub input: Option<i32>,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: u32,
+    pub field: v4l2_field,
+    pub capture_mode: u32,
+    /// Frame interval as `(numerator, denominator)`.
+    pub time_per_frame: (u32, u32),
+    /// Control values as `(id, value)` pairs, applied in order.
+    pub controls: Vec<(u32, i32)>,
+    /// Number of mmap buffers to prepare on open, or 0 to skip.
+    pub buffer_count: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> CaptureConfig {
+        CaptureConfig {
+            device: "/dev/video0".to_string(),
+            input: None,
+            width: 0,
+            height: 0,
+            pixel_format: 0,
+            field: v4l2_field::V4L2_FIELD_ANY,
+            capture_mode: 0,
+            time_per_frame: (1, 30),
+            controls: Vec::new(),
+            buffer_count: 0,
+        }
+    }
+}
+
+/// Returns true if the control's current value should be part of a saved
+/// configuration.
+pub(crate) fn is_restorable(qctrl: &v4l2_queryctrl) -> bool {
+    const SKIP: u32 = V4L2_CTRL_FLAG_DISABLED
+        | V4L2_CTRL_FLAG_READ_ONLY
+        | V4L2_CTRL_FLAG_INACTIVE
+        | V4L2_CTRL_FLAG_WRITE_ONLY;
+
+    if qctrl.flags & SKIP != 0 {
+        return false;
+    }
+
+    matches!(
+        qctrl.typ,
+        V4L2_CTRL_TYPE_INTEGER
+            | V4L2_CTRL_TYPE_BOOLEAN
+            | V4L2_CTRL_TYPE_MENU
+            | V4L2_CTRL_TYPE_INTEGER_MENU
+    )
+}
@@ -0,0 +1,577 @@
+//! Pixel format conversion to RGB.
+//!
+//! YUYV, NV12, NV21 and I420/YV12 frames are converted to RGB24 or RGBA
+//! using BT.601 or BT.709 coefficients in limited or full range, normally
+//! chosen from the colorspace of the negotiated format. Odd widths and
+//! heights are supported, the last column and row then use the chroma
+//! samples of the pixel before.
+//!
+//! Eight pixels at a time are converted with AVX2 on x86_64 CPUs that have
+//! it, with the same fixed point arithmetic as the scalar fallback, so both
+//! give the same output.
+
+use std::io;
+
+use crate::frame::Frame;
+use crate::sys::uapi::*;
+
+/// YCbCr to RGB matrix coefficients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Matrix {
+    Bt601,
+    Bt709,
+}
+
+/// Quantization range of the Y and CbCr samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    /// Y in 16..=235, CbCr in 16..=240.
+    Limited,
+    /// All components in 0..=255.
+    Full,
+}
+
+/// Color encoding of YCbCr frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpec {
+    pub matrix: Matrix,
+    pub range: Range,
+}
+
+impl ColorSpec {
+    pub const BT601: ColorSpec = ColorSpec {
+        matrix: Matrix::Bt601,
+        range: Range::Limited,
+    };
+
+    pub const BT709: ColorSpec = ColorSpec {
+        matrix: Matrix::Bt709,
+        range: Range::Limited,
+    };
+
+    pub const JPEG: ColorSpec = ColorSpec {
+        matrix: Matrix::Bt601,
+        range: Range::Full,
+    };
+
+    /// Returns the default encoding V4L2 defines for the colorspace.
+    pub fn from_colorspace(colorspace: v4l2_colorspace) -> ColorSpec {
+        match colorspace {
+            v4l2_colorspace::V4L2_COLORSPACE_REC709
+            | v4l2_colorspace::V4L2_COLORSPACE_SMPTE240M => ColorSpec::BT709,
+            v4l2_colorspace::V4L2_COLORSPACE_JPEG => ColorSpec::JPEG,
+            _ => ColorSpec::BT601,
+        }
+    }
+
+    pub fn from_format(format: &v4l2_pix_format) -> ColorSpec {
+        ColorSpec::from_colorspace(format.colorspace)
+    }
+}
+
+/// Layout of the converted pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RgbLayout {
+    Rgb24,
+    /// RGB with an opaque alpha byte.
+    Rgba,
+}
+
+impl RgbLayout {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            RgbLayout::Rgb24 => 3,
+            RgbLayout::Rgba => 4,
+        }
+    }
+}
+
+/// Fixed point (16.16) conversion coefficients.
+#[derive(Clone, Copy)]
+struct Coeffs {
+    y_off: i32,
+    y: i32,
+    rv: i32,
+    gu: i32,
+    gv: i32,
+    bu: i32,
+}
+
+impl Coeffs {
+    fn new(spec: ColorSpec) -> Coeffs {
+        let (kr, kb) = match spec.matrix {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+        };
+        let kg = 1.0 - kr - kb;
+        let (y_off, y_scale, c_scale) = match spec.range {
+            Range::Limited => (16, 255.0 / 219.0, 255.0 / 224.0),
+            Range::Full => (0, 1.0, 1.0),
+        };
+        let fix = |v: f64| (v * 65536.0).round() as i32;
+
+        Coeffs {
+            y_off,
+            y: fix(y_scale),
+            rv: fix(2.0 * (1.0 - kr) * c_scale),
+            gu: fix(2.0 * (1.0 - kb) * kb / kg * c_scale),
+            gv: fix(2.0 * (1.0 - kr) * kr / kg * c_scale),
+            bu: fix(2.0 * (1.0 - kb) * c_scale),
+        }
+    }
+
+    #[inline(always)]
+    fn rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let y = (y as i32 - self.y_off) * self.y + 32768;
+        let u = u as i32 - 128;
+        let v = v as i32 - 128;
+
+        [
+            ((y + self.rv * v) >> 16).clamp(0, 255) as u8,
+            ((y - self.gu * u - self.gv * v) >> 16).clamp(0, 255) as u8,
+            ((y + self.bu * u) >> 16).clamp(0, 255) as u8,
+        ]
+    }
+}
+
+/// Returns the minimum buffer length and line stride for a frame, or `None`
+/// if the pixel format is not supported.
+fn frame_layout(
+    pixelformat: u32,
+    width: usize,
+    height: usize,
+    stride: usize,
+) -> Option<(usize, usize)> {
+    let ch = height.div_ceil(2);
+    match pixelformat {
+        V4L2_PIX_FMT_YUYV => {
+            let stride = stride.max(width.div_ceil(2) * 4);
+            Some((stride * height, stride))
+        }
+        V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => {
+            let stride = stride.max(width.div_ceil(2) * 2);
+            Some((stride * (height + ch), stride))
+        }
+        V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => {
+            let stride = stride.max(width);
+            Some((stride * height + stride.div_ceil(2) * ch * 2, stride))
+        }
+        _ => None,
+    }
+}
+
+#[inline(always)]
+fn put(out: &mut [u8], rgb: [u8; 3]) {
+    out[..3].copy_from_slice(&rgb);
+    if out.len() == 4 {
+        out[3] = 0xff;
+    }
+}
+
+/// Converts eight pixels, given as their Y, Cb and Cr samples.
+type Kernel = fn(&Coeffs, &[u8; 8], &[u8; 8], &[u8; 8]) -> [[u8; 3]; 8];
+
+fn rgb8_scalar(c: &Coeffs, y: &[u8; 8], u: &[u8; 8], v: &[u8; 8]) -> [[u8; 3]; 8] {
+    let mut rgb = [[0; 3]; 8];
+    for (i, px) in rgb.iter_mut().enumerate() {
+        *px = c.rgb(y[i], u[i], v[i]);
+    }
+    rgb
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn rgb8_avx2_unchecked(c: &Coeffs, y: &[u8; 8], u: &[u8; 8], v: &[u8; 8]) -> [[u8; 3]; 8] {
+    use std::arch::x86_64::*;
+
+    let y = _mm256_cvtepu8_epi32(_mm_loadl_epi64(y.as_ptr() as *const __m128i));
+    let u = _mm256_cvtepu8_epi32(_mm_loadl_epi64(u.as_ptr() as *const __m128i));
+    let v = _mm256_cvtepu8_epi32(_mm_loadl_epi64(v.as_ptr() as *const __m128i));
+    let u = _mm256_sub_epi32(u, _mm256_set1_epi32(128));
+    let v = _mm256_sub_epi32(v, _mm256_set1_epi32(128));
+    let y = _mm256_sub_epi32(y, _mm256_set1_epi32(c.y_off));
+    let y = _mm256_add_epi32(
+        _mm256_mullo_epi32(y, _mm256_set1_epi32(c.y)),
+        _mm256_set1_epi32(32768),
+    );
+
+    let r = _mm256_add_epi32(y, _mm256_mullo_epi32(v, _mm256_set1_epi32(c.rv)));
+    let g = _mm256_sub_epi32(y, _mm256_mullo_epi32(u, _mm256_set1_epi32(c.gu)));
+    let g = _mm256_sub_epi32(g, _mm256_mullo_epi32(v, _mm256_set1_epi32(c.gv)));
+    let b = _mm256_add_epi32(y, _mm256_mullo_epi32(u, _mm256_set1_epi32(c.bu)));
+
+    let (zero, max) = (_mm256_setzero_si256(), _mm256_set1_epi32(255));
+    let mut channels = [[0i32; 8]; 3];
+    for (channel, x) in channels.iter_mut().zip(&[r, g, b]) {
+        let x = _mm256_min_epi32(_mm256_max_epi32(_mm256_srai_epi32(*x, 16), zero), max);
+        _mm256_storeu_si256(channel.as_mut_ptr() as *mut __m256i, x);
+    }
+
+    let mut rgb = [[0; 3]; 8];
+    for (i, px) in rgb.iter_mut().enumerate() {
+        *px = [
+            channels[0][i] as u8,
+            channels[1][i] as u8,
+            channels[2][i] as u8,
+        ];
+    }
+    rgb
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rgb8_avx2(c: &Coeffs, y: &[u8; 8], u: &[u8; 8], v: &[u8; 8]) -> [[u8; 3]; 8] {
+    // Only returned by `kernel` once AVX2 was detected.
+    unsafe { rgb8_avx2_unchecked(c, y, u, v) }
+}
+
+/// Returns the fastest kernel the CPU supports.
+fn kernel() -> Kernel {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return rgb8_avx2;
+        }
+    }
+    rgb8_scalar
+}
+
+/// Converts a line of pixels, whose samples `sample` returns by column,
+/// eight pixels at a time with `kernel`.
+#[inline(always)]
+fn convert_line<F: Fn(usize) -> (u8, u8, u8)>(
+    out: &mut [u8],
+    bpp: usize,
+    c: &Coeffs,
+    kernel: Kernel,
+    sample: F,
+) {
+    let blocks = out.len() / (bpp * 8);
+    let (head, tail) = out.split_at_mut(blocks * bpp * 8);
+    for (block, px) in head.chunks_exact_mut(bpp * 8).enumerate() {
+        let (mut y, mut u, mut v) = ([0; 8], [0; 8], [0; 8]);
+        for i in 0..8 {
+            let (ys, us, vs) = sample(block * 8 + i);
+            y[i] = ys;
+            u[i] = us;
+            v[i] = vs;
+        }
+        for (rgb, px) in kernel(c, &y, &u, &v).iter().zip(px.chunks_exact_mut(bpp)) {
+            put(px, *rgb);
+        }
+    }
+    for (i, px) in tail.chunks_exact_mut(bpp).enumerate() {
+        let (y, u, v) = sample(blocks * 8 + i);
+        put(px, c.rgb(y, u, v));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn convert_impl(
+    src: &[u8],
+    pixelformat: u32,
+    width: usize,
+    height: usize,
+    stride: usize,
+    c: Coeffs,
+    bpp: usize,
+    dst: &mut [u8],
+) {
+    let ch = height.div_ceil(2);
+    let kernel = kernel();
+
+    for (row, out) in dst.chunks_exact_mut(width * bpp).take(height).enumerate() {
+        match pixelformat {
+            V4L2_PIX_FMT_YUYV => {
+                // The last macropixel of odd widths has only its first half
+                // converted.
+                let line = &src[row * stride..row * stride + width.div_ceil(2) * 4];
+                convert_line(out, bpp, &c, kernel, |col| {
+                    let yuyv = &line[col / 2 * 4..];
+                    (yuyv[(col & 1) * 2], yuyv[1], yuyv[3])
+                });
+            }
+            V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => {
+                let (ui, vi) = if pixelformat == V4L2_PIX_FMT_NV12 {
+                    (0, 1)
+                } else {
+                    (1, 0)
+                };
+                let y_line = &src[row * stride..row * stride + width];
+                let uv_off = stride * height + (row / 2) * stride;
+                let uv_line = &src[uv_off..uv_off + width.div_ceil(2) * 2];
+                convert_line(out, bpp, &c, kernel, |col| {
+                    let uv = &uv_line[col & !1..];
+                    (y_line[col], uv[ui], uv[vi])
+                });
+            }
+            _ => {
+                let cstride = stride.div_ceil(2);
+                let (mut u_off, mut v_off) = (stride * height, stride * height + cstride * ch);
+                if pixelformat == V4L2_PIX_FMT_YVU420 {
+                    std::mem::swap(&mut u_off, &mut v_off);
+                }
+                let y_line = &src[row * stride..row * stride + width];
+                let u_line = &src[u_off + (row / 2) * cstride..];
+                let v_line = &src[v_off + (row / 2) * cstride..];
+                convert_line(out, bpp, &c, kernel, |col| {
+                    (y_line[col], u_line[col / 2], v_line[col / 2])
+                });
+            }
+        }
+    }
+}
+
+/// Converts a YCbCr frame to RGB.
+///
+/// `stride` is the length of a luma (or packed) line in bytes, as given by
+/// `bytesperline`; chroma planes of I420/YV12 use half of it, rounded up.
+/// `dst` must hold `width * height * layout.bytes_per_pixel()` bytes.
+#[allow(clippy::too_many_arguments)]
+pub fn convert_to_rgb(
+    src: &[u8],
+    pixelformat: u32,
+    width: usize,
+    height: usize,
+    stride: usize,
+    spec: ColorSpec,
+    layout: RgbLayout,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame is empty",
+        ));
+    }
+    let (len, stride) = frame_layout(pixelformat, width, height, stride)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported pixel format"))?;
+    if src.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "frame data is too short",
+        ));
+    }
+    let bpp = layout.bytes_per_pixel();
+    if dst.len() < width * height * bpp {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "destination buffer is too small",
+        ));
+    }
+
+    let c = Coeffs::new(spec);
+    convert_impl(src, pixelformat, width, height, stride, c, bpp, dst);
+    Ok(())
+}
+
+/// Converts packed YUYV (4:2:2) with BT.601 limited range to RGB24.
+///
+/// `stride` is the length of a source line in bytes.
+pub fn yuyv_to_rgb24(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    convert_to_rgb(
+        src,
+        V4L2_PIX_FMT_YUYV,
+        width,
+        height,
+        stride,
+        ColorSpec::BT601,
+        RgbLayout::Rgb24,
+        dst,
+    )
+}
+
+/// Converts NV12 (Y plane followed by interleaved CbCr) with BT.601 limited
+/// range to RGB24.
+pub fn nv12_to_rgb24(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    convert_to_rgb(
+        src,
+        V4L2_PIX_FMT_NV12,
+        width,
+        height,
+        stride,
+        ColorSpec::BT601,
+        RgbLayout::Rgb24,
+        dst,
+    )
+}
+
+/// Converts NV21 (Y plane followed by interleaved CrCb) with BT.601 limited
+/// range to RGB24.
+pub fn nv21_to_rgb24(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    convert_to_rgb(
+        src,
+        V4L2_PIX_FMT_NV21,
+        width,
+        height,
+        stride,
+        ColorSpec::BT601,
+        RgbLayout::Rgb24,
+        dst,
+    )
+}
+
+fn frame_to(frame: &Frame, layout: RgbLayout) -> io::Result<Vec<u8>> {
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let mut rgb = vec![0; width * height * layout.bytes_per_pixel()];
+
+    convert_to_rgb(
+        frame.data(),
+        frame.pixel_format(),
+        width,
+        height,
+        frame.bytes_per_line() as usize,
+        ColorSpec::from_format(frame.format()),
+        layout,
+        &mut rgb,
+    )?;
+    Ok(rgb)
+}
+
+/// Converts an uncompressed frame to a tightly packed RGB24 image, using the
+/// colorspace of its format.
+pub fn to_rgb24(frame: &Frame) -> io::Result<Vec<u8>> {
+    frame_to(frame, RgbLayout::Rgb24)
+}
+
+/// Converts an uncompressed frame to a tightly packed RGBA image, using the
+/// colorspace of its format.
+pub fn to_rgba(frame: &Frame) -> io::Result<Vec<u8>> {
+    frame_to(frame, RgbLayout::Rgba)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yuyv_black_and_white() {
+        let src = [16, 128, 235, 128];
+        let mut dst = [0; 6];
+        yuyv_to_rgb24(&src, 2, 1, 4, &mut dst).unwrap();
+        assert_eq!(dst, [0, 0, 0, 255, 255, 255]);
+    }
+
+    #[test]
+    fn converts_odd_widths() {
+        // Black, white and black again, the last pixel without a partner.
+        let yuyv = [16, 128, 235, 128, 16, 128, 16, 128];
+        let mut dst = [0xaa; 9];
+        yuyv_to_rgb24(&yuyv, 3, 1, 8, &mut dst).unwrap();
+        assert_eq!(dst, [0, 0, 0, 255, 255, 255, 0, 0, 0]);
+
+        // Lines are padded to the two CbCr pairs of the chroma row.
+        let nv12 = [16, 235, 16, 0, 128, 128, 128, 128];
+        let mut dst = [0xaa; 9];
+        assert!(nv12_to_rgb24(&nv12[..7], 3, 1, 3, &mut dst).is_err());
+        nv12_to_rgb24(&nv12, 3, 1, 3, &mut dst).unwrap();
+        assert_eq!(dst, [0, 0, 0, 255, 255, 255, 0, 0, 0]);
+
+        let i420 = [16, 235, 16, 128, 128, 128, 128];
+        let mut dst = [0xaa; 9];
+        convert_to_rgb(
+            &i420,
+            V4L2_PIX_FMT_YUV420,
+            3,
+            1,
+            3,
+            ColorSpec::BT601,
+            RgbLayout::Rgb24,
+            &mut dst,
+        )
+        .unwrap();
+        assert_eq!(dst, [0, 0, 0, 255, 255, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn converts_blocks_and_tail() {
+        // Eleven pixels: a block of eight, then three one at a time.
+        let y: Vec<u8> = (0..12).map(|i| 16 + i * 20).collect();
+        let yuyv: Vec<u8> = y.chunks(2).flat_map(|y| [y[0], 90, y[1], 200]).collect();
+        let mut dst = [0; 33];
+        yuyv_to_rgb24(&yuyv, 11, 1, 24, &mut dst).unwrap();
+
+        let c = Coeffs::new(ColorSpec::BT601);
+        for (px, &y) in dst.chunks(3).zip(&y) {
+            assert_eq!(px, c.rgb(y, 90, 200));
+        }
+    }
+
+    #[test]
+    fn rejects_empty_frames() {
+        let mut dst = [0; 6];
+        let err = yuyv_to_rgb24(&[16, 128, 235, 128], 0, 1, 4, &mut dst).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = yuyv_to_rgb24(&[16, 128, 235, 128], 2, 0, 4, &mut dst).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        let specs = [
+            ColorSpec::BT601,
+            ColorSpec::BT709,
+            ColorSpec::JPEG,
+            ColorSpec {
+                matrix: Matrix::Bt709,
+                range: Range::Full,
+            },
+        ];
+        for &spec in &specs {
+            let c = Coeffs::new(spec);
+            for u in (0..=255).step_by(5) {
+                for v in (0..=255).step_by(5) {
+                    for y in (0..256).step_by(8) {
+                        let y: [u8; 8] = std::array::from_fn(|i| (y + i) as u8);
+                        let (u, v) = ([u as u8; 8], [v as u8; 8]);
+                        assert_eq!(rgb8_avx2(&c, &y, &u, &v), rgb8_scalar(&c, &y, &u, &v));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_range_bt709_red() {
+        // Y'CbCr of pure red in BT.709 full range.
+        let src = [54, 99, 54, 255];
+        let mut dst = [0; 8];
+        convert_to_rgb(
+            &src,
+            V4L2_PIX_FMT_YUYV,
+            2,
+            1,
+            4,
+            ColorSpec {
+                matrix: Matrix::Bt709,
+                range: Range::Full,
+            },
+            RgbLayout::Rgba,
+            &mut dst,
+        )
+        .unwrap();
+        assert!(dst[0] >= 253 && dst[1] <= 2 && dst[2] <= 2 && dst[3] == 255);
+    }
+}
@@ -0,0 +1,290 @@
+//! Software demosaicing of raw Bayer captures.
+//!
+//! Sensors exposed through a raw CSI receiver deliver one color sample per
+//! pixel. This module reconstructs RGB24 images from 8-bit and 10-bit
+//! (unpacked or MIPI packed) Bayer data so that such sensors can be
+//! previewed without an ISP.
+
+use std::io;
+
+use crate::frame::Frame;
+use crate::repack;
+use crate::sys::uapi::*;
+
+const R: u8 = 0;
+const G: u8 = 1;
+const B: u8 = 2;
+
+/// Demosaicing algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Averages the nearest samples of each color. Fast, but softens edges
+    /// and produces color fringes.
+    Bilinear,
+    /// Variable number of gradients: only neighbours in directions with low
+    /// gradients contribute, which preserves edges at a higher cost.
+    Vng,
+}
+
+/// Color filter array order, named after the first 2x2 block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Bggr,
+    Gbrg,
+    Grbg,
+    Rggb,
+}
+
+impl Pattern {
+    /// Returns the pattern and bit depth of a Bayer pixel format.
+    pub fn from_fourcc(pixelformat: u32) -> Option<(Pattern, u32)> {
+        match pixelformat {
+            V4L2_PIX_FMT_SBGGR8 => Some((Pattern::Bggr, 8)),
+            V4L2_PIX_FMT_SGBRG8 => Some((Pattern::Gbrg, 8)),
+            V4L2_PIX_FMT_SGRBG8 => Some((Pattern::Grbg, 8)),
+            V4L2_PIX_FMT_SRGGB8 => Some((Pattern::Rggb, 8)),
+            V4L2_PIX_FMT_SBGGR10 | V4L2_PIX_FMT_SBGGR10P => Some((Pattern::Bggr, 10)),
+            _ => None,
+        }
+    }
+
+    fn cells(self) -> [[u8; 2]; 2] {
+        match self {
+            Pattern::Bggr => [[B, G], [G, R]],
+            Pattern::Gbrg => [[G, B], [R, G]],
+            Pattern::Grbg => [[G, R], [B, G]],
+            Pattern::Rggb => [[R, G], [G, B]],
+        }
+    }
+}
+
+struct Mosaic {
+    data: Vec<u16>,
+    width: usize,
+    height: usize,
+    cells: [[u8; 2]; 2],
+}
+
+impl Mosaic {
+    /// Mirrors out of range coordinates, keeping the CFA parity.
+    #[inline]
+    fn reflect(i: isize, n: usize) -> usize {
+        let n = n as isize;
+        let i = if i < 0 { -i } else { i };
+        let i = if i >= n { 2 * (n - 1) - i } else { i };
+        i.clamp(0, n - 1) as usize
+    }
+
+    #[inline]
+    fn at(&self, y: isize, x: isize) -> u16 {
+        let y = Mosaic::reflect(y, self.height);
+        let x = Mosaic::reflect(x, self.width);
+        self.data[y * self.width + x]
+    }
+
+    #[inline]
+    fn color(&self, y: isize, x: isize) -> u8 {
+        self.cells[(y & 1) as usize][(x & 1) as usize]
+    }
+
+    fn bilinear(&self) -> Vec<[u16; 3]> {
+        let mut out = vec![[0; 3]; self.width * self.height];
+
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                let mut sum = [0u32; 3];
+                let mut count = [0u32; 3];
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let c = self.color(y + dy, x + dx) as usize;
+                        sum[c] += self.at(y + dy, x + dx) as u32;
+                        count[c] += 1;
+                    }
+                }
+
+                let px = &mut out[y as usize * self.width + x as usize];
+                for c in 0..3 {
+                    px[c] = (sum[c] / count[c].max(1)) as u16;
+                }
+                px[self.color(y, x) as usize] = self.at(y, x);
+            }
+        }
+
+        out
+    }
+
+    fn vng(&self) -> Vec<[u16; 3]> {
+        const DIRS: [(isize, isize); 8] = [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (-1, 1),
+            (1, -1),
+            (1, 1),
+        ];
+
+        let base = self.bilinear();
+        let mut out = base.clone();
+        let (w, h) = (self.width as isize, self.height as isize);
+
+        for y in 0..h {
+            for x in 0..w {
+                let center = self.at(y, x) as i32;
+                let mut grads = [0i32; 8];
+                for (g, &(dy, dx)) in grads.iter_mut().zip(DIRS.iter()) {
+                    let fwd = self.at(y + dy, x + dx) as i32;
+                    let back = self.at(y - dy, x - dx) as i32;
+                    let far = self.at(y + 2 * dy, x + 2 * dx) as i32;
+                    *g = (fwd - back).abs() + (far - center).abs();
+                }
+
+                let min = *grads.iter().min().unwrap_or(&0);
+                let max = *grads.iter().max().unwrap_or(&0);
+                let threshold = min + min / 2 + (max - min) / 2;
+
+                let own = self.color(y, x) as usize;
+                let mut sum = [0i32; 3];
+                let mut n = 0;
+                for (&g, &(dy, dx)) in grads.iter().zip(DIRS.iter()) {
+                    if g > threshold {
+                        continue;
+                    }
+                    let ny = Mosaic::reflect(y + dy, self.height);
+                    let nx = Mosaic::reflect(x + dx, self.width);
+                    let px = base[ny * self.width + nx];
+                    for c in 0..3 {
+                        sum[c] += px[c] as i32;
+                    }
+                    n += 1;
+                }
+                if n == 0 {
+                    continue;
+                }
+
+                let max_value = (1i32 << 16) - 1;
+                let px = &mut out[y as usize * self.width + x as usize];
+                for c in 0..3 {
+                    if c != own {
+                        px[c] = (center + (sum[c] - sum[own]) / n).clamp(0, max_value) as u16;
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// Demosaics a Bayer frame to tightly packed RGB24.
+///
+/// `stride` is the line length of `src` in bytes. 10-bit input is scaled
+/// down to 8 bits.
+#[allow(clippy::too_many_arguments)]
+pub fn debayer(
+    src: &[u8],
+    pixelformat: u32,
+    width: usize,
+    height: usize,
+    stride: usize,
+    method: Method,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    let (pattern, depth) = Pattern::from_fourcc(pixelformat)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a Bayer pixel format"))?;
+    if dst.len() < width * height * 3 || width < 2 || height < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid frame or destination size",
+        ));
+    }
+
+    let mut data = vec![0u16; width * height];
+    match pixelformat {
+        V4L2_PIX_FMT_SBGGR10P => repack::unpack_10bit(src, stride, width, height, &mut data)?,
+        V4L2_PIX_FMT_SBGGR10 => {
+            let stride = stride.max(width * 2);
+            if src.len() < stride * (height - 1) + width * 2 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "frame data is too short",
+                ));
+            }
+            for (line, out) in src.chunks(stride).zip(data.chunks_mut(width)) {
+                for (px, le) in out.iter_mut().zip(line.chunks_exact(2)) {
+                    *px = u16::from_le_bytes([le[0], le[1]]) & 0x3ff;
+                }
+            }
+        }
+        _ => {
+            let stride = stride.max(width);
+            if src.len() < stride * (height - 1) + width {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "frame data is too short",
+                ));
+            }
+            for (line, out) in src.chunks(stride).zip(data.chunks_mut(width)) {
+                for (px, &v) in out.iter_mut().zip(line) {
+                    *px = v as u16;
+                }
+            }
+        }
+    }
+
+    let mosaic = Mosaic {
+        data,
+        width,
+        height,
+        cells: pattern.cells(),
+    };
+    let rgb = match method {
+        Method::Bilinear => mosaic.bilinear(),
+        Method::Vng => mosaic.vng(),
+    };
+
+    let shift = depth - 8;
+    let max = (1u16 << depth) - 1;
+    for (px, out) in rgb.iter().zip(dst.chunks_exact_mut(3)) {
+        for c in 0..3 {
+            out[c] = (px[c].min(max) >> shift) as u8;
+        }
+    }
+
+    Ok(())
+}
+
+/// Demosaics a Bayer frame to a tightly packed RGB24 image.
+pub fn debayer_frame(frame: &Frame, method: Method) -> io::Result<Vec<u8>> {
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let mut rgb = vec![0; width * height * 3];
+
+    debayer(
+        frame.data(),
+        frame.pixel_format(),
+        width,
+        height,
+        frame.bytes_per_line() as usize,
+        method,
+        &mut rgb,
+    )?;
+    Ok(rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_field_stays_flat() {
+        // A gray scene: every sample has the same value regardless of color.
+        let src = [100u8; 16];
+        for &method in &[Method::Bilinear, Method::Vng] {
+            let mut rgb = [0; 48];
+            debayer(&src, V4L2_PIX_FMT_SBGGR8, 4, 4, 4, method, &mut rgb).unwrap();
+            assert!(rgb.iter().all(|&v| v == 100));
+        }
+    }
+}
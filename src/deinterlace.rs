@@ -0,0 +1,174 @@
+//! Software deinterlacing for `V4L2_FIELD_INTERLACED` and
+//! `V4L2_FIELD_ALTERNATE` captures.
+//!
+//! The routines work on a single plane of bytes (a luma plane, a packed YUYV
+//! image or an 8-bit gray image) described by its line length and stride;
+//! planar formats are processed one plane at a time.
+
+use std::io;
+
+use crate::sys::uapi::*;
+
+/// Which field of an interlaced frame to keep or weigh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    /// Even lines (0, 2, 4, ...).
+    Top,
+    /// Odd lines (1, 3, 5, ...).
+    Bottom,
+}
+
+impl Parity {
+    /// Returns the field that is transmitted first for an interlaced field
+    /// order, or the parity of a single field.
+    pub fn from_field(field: v4l2_field) -> Option<Parity> {
+        match field {
+            v4l2_field::V4L2_FIELD_TOP
+            | v4l2_field::V4L2_FIELD_INTERLACED
+            | v4l2_field::V4L2_FIELD_INTERLACED_TB
+            | v4l2_field::V4L2_FIELD_SEQ_TB => Some(Parity::Top),
+            v4l2_field::V4L2_FIELD_BOTTOM
+            | v4l2_field::V4L2_FIELD_INTERLACED_BT
+            | v4l2_field::V4L2_FIELD_SEQ_BT => Some(Parity::Bottom),
+            _ => None,
+        }
+    }
+
+    fn first_line(self) -> usize {
+        match self {
+            Parity::Top => 0,
+            Parity::Bottom => 1,
+        }
+    }
+}
+
+fn check(src_len: usize, stride: usize, line_len: usize, lines: usize) -> io::Result<()> {
+    if lines == 0 || stride < line_len || src_len < stride * (lines - 1) + line_len {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "plane is too short",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn average(a: &[u8], b: &[u8], out: &mut [u8]) {
+    for ((o, &a), &b) in out.iter_mut().zip(a).zip(b) {
+        *o = ((a as u16 + b as u16 + 1) >> 1) as u8;
+    }
+}
+
+/// Bob deinterlacing of an interleaved frame: keeps the lines of one field
+/// and interpolates the lines of the other from their neighbours.
+///
+/// `dst` receives `height` lines of `line_len` bytes, tightly packed.
+pub fn bob(
+    src: &[u8],
+    line_len: usize,
+    height: usize,
+    stride: usize,
+    keep: Parity,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    check(src.len(), stride, line_len, height)?;
+    check(dst.len(), line_len, line_len, height)?;
+
+    let line = |y: usize| &src[y * stride..y * stride + line_len];
+    let first = keep.first_line();
+
+    for (y, out) in dst.chunks_exact_mut(line_len).take(height).enumerate() {
+        if y % 2 == first % 2 {
+            out.copy_from_slice(line(y));
+        } else {
+            let above = if y > first { Some(y - 1) } else { None };
+            let below = if y + 1 < height { Some(y + 1) } else { None };
+            match (above, below) {
+                (Some(a), Some(b)) => average(line(a), line(b), out),
+                (Some(n), None) | (None, Some(n)) => out.copy_from_slice(line(n)),
+                (None, None) => out.copy_from_slice(line(y)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Linear blend deinterlacing of an interleaved frame: every output line is
+/// a (1, 2, 1) weighted mix of itself and its neighbours, which removes
+/// combing at the cost of vertical resolution.
+pub fn blend(
+    src: &[u8],
+    line_len: usize,
+    height: usize,
+    stride: usize,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    check(src.len(), stride, line_len, height)?;
+    check(dst.len(), line_len, line_len, height)?;
+
+    let line = |y: usize| &src[y * stride..y * stride + line_len];
+
+    for (y, out) in dst.chunks_exact_mut(line_len).take(height).enumerate() {
+        let above = line(y.saturating_sub(1));
+        let below = line((y + 1).min(height - 1));
+        for (i, o) in out.iter_mut().enumerate() {
+            let sum = above[i] as u16 + 2 * line(y)[i] as u16 + below[i] as u16;
+            *o = ((sum + 2) >> 2) as u8;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bob deinterlacing of a single field from a `V4L2_FIELD_ALTERNATE`
+/// capture: the field of `field_height` lines is line-doubled to a full
+/// frame, with its lines placed according to `parity`.
+///
+/// `dst` receives `2 * field_height` lines of `line_len` bytes.
+pub fn bob_field(
+    src: &[u8],
+    line_len: usize,
+    field_height: usize,
+    stride: usize,
+    parity: Parity,
+    dst: &mut [u8],
+) -> io::Result<()> {
+    check(src.len(), stride, line_len, field_height)?;
+    check(dst.len(), line_len, line_len, field_height * 2)?;
+
+    let line = |y: usize| &src[y * stride..y * stride + line_len];
+    let first = parity.first_line();
+
+    for (y, out) in dst
+        .chunks_exact_mut(line_len)
+        .take(field_height * 2)
+        .enumerate()
+    {
+        if y % 2 == first {
+            out.copy_from_slice(line(y / 2));
+        } else {
+            // Missing lines lie between field lines (y - 1) / 2 and (y + 1) / 2.
+            let (a, b) = match parity {
+                Parity::Top => (y / 2, (y / 2 + 1).min(field_height - 1)),
+                Parity::Bottom => ((y / 2).saturating_sub(1), y / 2),
+            };
+            average(line(a), line(b), out);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bob_interpolates_dropped_field() {
+        let src = [10, 99, 30, 99];
+        let mut dst = [0; 4];
+        bob(&src, 1, 4, 1, Parity::Top, &mut dst).unwrap();
+        assert_eq!(dst, [10, 20, 30, 30]);
+    }
+}
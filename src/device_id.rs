@@ -0,0 +1,179 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::sys::{OpenOptions, V4l2Device};
+
+const BY_ID: &str = "/dev/v4l/by-id";
+const BY_PATH: &str = "/dev/v4l/by-path";
+const BUS_INFO_PREFIX: &str = "bus-info:";
+
+/// A name of a device that, unlike `/dev/videoN`, does not change when
+/// devices are enumerated in a different order, e.g. after a reboot.
+///
+/// Ids format as paths that can be opened directly, except bus infos,
+/// which format as `bus-info:<bus_info>`. `DeviceId::parse` reads them
+/// back, and `Builder::with_device` accepts them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceId {
+    /// A symlink in `/dev/v4l/by-id`, naming the device by vendor, model
+    /// and serial number, so it follows the device from port to port.
+    ById(PathBuf),
+    /// A symlink in `/dev/v4l/by-path`, naming the port the device is
+    /// attached to.
+    ByPath(PathBuf),
+    /// The bus info reported by the driver, e.g. `usb-0000:00:14.0-2`.
+    BusInfo(String),
+    /// A device node, which is not stable.
+    Node(PathBuf),
+}
+
+/// Returns the N of a `videoN` node name.
+fn node_number(name: &str) -> Option<u32> {
+    name.strip_prefix("video")?.parse().ok()
+}
+
+/// Returns the `/dev/videoN` nodes, ordered by N.
+fn video_nodes() -> io::Result<Vec<PathBuf>> {
+    let mut nodes: Vec<(u32, PathBuf)> = fs::read_dir("/dev")?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let number = node_number(entry.file_name().to_str()?)?;
+            Some((number, entry.path()))
+        })
+        .collect();
+    nodes.sort();
+    Ok(nodes.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Returns the symlink in `dir` that points to `node`, if any.
+fn find_link(dir: &str, node: &Path) -> Option<PathBuf> {
+    let mut links: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|link| fs::canonicalize(link).is_ok_and(|target| target == node))
+        .collect();
+    links.sort();
+    links.into_iter().next()
+}
+
+impl DeviceId {
+    /// Returns the most stable id of the device at `path`: its by-id link,
+    /// else its by-path link, else its bus info.
+    pub fn of<P: AsRef<Path>>(path: P) -> io::Result<DeviceId> {
+        let node = fs::canonicalize(path.as_ref())?;
+        if let Some(link) = find_link(BY_ID, &node) {
+            return Ok(DeviceId::ById(link));
+        }
+        if let Some(link) = find_link(BY_PATH, &node) {
+            return Ok(DeviceId::ByPath(link));
+        }
+
+        let device = OpenOptions::new().write(false).open(&node)?;
+        let bus_info = device.bus_info()?;
+        if bus_info.is_empty() {
+            return Ok(DeviceId::Node(node));
+        }
+        Ok(DeviceId::BusInfo(bus_info))
+    }
+
+    /// Reads an id formatted by `Display`. Anything else is taken as a
+    /// path.
+    pub fn parse(s: &str) -> DeviceId {
+        if let Some(bus_info) = s.strip_prefix(BUS_INFO_PREFIX) {
+            return DeviceId::BusInfo(bus_info.to_string());
+        }
+        let path = PathBuf::from(s);
+        if path.parent() == Some(Path::new(BY_ID)) {
+            DeviceId::ById(path)
+        } else if path.parent() == Some(Path::new(BY_PATH)) {
+            DeviceId::ByPath(path)
+        } else {
+            DeviceId::Node(path)
+        }
+    }
+
+    /// Returns the node the device currently has.
+    ///
+    /// Drivers may report the same bus info for several nodes, e.g. for
+    /// video and metadata capture. Of those, the first node that captures
+    /// video is returned.
+    pub fn resolve(&self) -> io::Result<PathBuf> {
+        let bus_info = match *self {
+            DeviceId::ById(ref link) | DeviceId::ByPath(ref link) => return fs::canonicalize(link),
+            DeviceId::Node(ref node) => return Ok(node.clone()),
+            DeviceId::BusInfo(ref bus_info) => bus_info,
+        };
+
+        let mut first = None;
+        for node in video_nodes()? {
+            let device = match OpenOptions::new().write(false).open(&node) {
+                Ok(device) => device,
+                Err(_) => continue,
+            };
+            if device.bus_info().ok().as_ref() != Some(bus_info) {
+                continue;
+            }
+            // A node that cannot be queried does not stop the search.
+            match device.supports_capture() {
+                Ok(true) => return Ok(node),
+                Ok(false) => {}
+                Err(_) => continue,
+            }
+            first.get_or_insert(node);
+        }
+        first.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no device with bus info {}", bus_info),
+            )
+        })
+    }
+
+    /// Opens the device for reading and writing.
+    pub fn open(&self) -> io::Result<V4l2Device> {
+        V4l2Device::open(self.resolve()?)
+    }
+
+    /// Opens the device with `options`.
+    pub fn open_with(&self, options: &OpenOptions) -> io::Result<V4l2Device> {
+        options.open(self.resolve()?)
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DeviceId::ById(ref path) | DeviceId::ByPath(ref path) | DeviceId::Node(ref path) => {
+                write!(f, "{}", path.display())
+            }
+            DeviceId::BusInfo(ref bus_info) => write!(f, "{}{}", BUS_INFO_PREFIX, bus_info),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_formatted_ids() {
+        let ids = [
+            DeviceId::ById(PathBuf::from(
+                "/dev/v4l/by-id/usb-046d_HD_Pro_Webcam_C920-video-index0",
+            )),
+            DeviceId::ByPath(PathBuf::from(
+                "/dev/v4l/by-path/pci-0000:00:14.0-usb-0:2:1.0-video-index0",
+            )),
+            DeviceId::BusInfo("usb-0000:00:14.0-2".to_string()),
+            DeviceId::Node(PathBuf::from("/dev/video0")),
+        ];
+        for id in &ids {
+            assert_eq!(DeviceId::parse(&id.to_string()), *id);
+        }
+        assert_eq!(node_number("video12"), Some(12));
+        assert_eq!(node_number("video-index0"), None);
+    }
+}
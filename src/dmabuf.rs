@@ -0,0 +1,227 @@
+//! Dmabuf export of capture buffers for GPU APIs.
+//!
+//! A `DmabufBuffer` carries an exported buffer together with the DRM format,
+//! modifier and plane layout that `VK_EXT_external_memory_dma_buf` with
+//! `VK_EXT_image_drm_format_modifier` (or wgpu-hal on Vulkan) needs to
+//! import it as an image without copying.
+
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+
+use crate::drm;
+use crate::sys::uapi::*;
+
+/// Buffers written by V4L2 drivers through mmap are linear.
+pub const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+
+/// Layout of one plane of a buffer, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneLayout {
+    pub offset: u32,
+    pub pitch: u32,
+    pub size: u32,
+}
+
+/// Returns the layout of the planes of a single-planar V4L2 buffer, in DRM
+/// plane order.
+pub fn plane_layouts(format: &v4l2_pix_format) -> Vec<PlaneLayout> {
+    let stride = format.bytesperline;
+    let luma = stride * format.height;
+    let chroma_lines = format.height.div_ceil(2);
+    let plane = |offset, pitch, size| PlaneLayout {
+        offset,
+        pitch,
+        size,
+    };
+
+    match format.pixelformat {
+        V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => vec![
+            plane(0, stride, luma),
+            plane(luma, stride, stride * chroma_lines),
+        ],
+        V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => {
+            let chroma = stride / 2 * chroma_lines;
+            vec![
+                plane(0, stride, luma),
+                plane(luma, stride / 2, chroma),
+                plane(luma + chroma, stride / 2, chroma),
+            ]
+        }
+        _ => vec![plane(0, stride, luma)],
+    }
+}
+
+/// Returns the `VkFormat` value matching a V4L2 pixel format, for formats
+/// with a single-plane or multi-planar Vulkan equivalent.
+pub fn vk_format(pixelformat: u32) -> Option<u32> {
+    match pixelformat {
+        V4L2_PIX_FMT_GREY => Some(9),   /* VK_FORMAT_R8_UNORM */
+        V4L2_PIX_FMT_RGB24 => Some(23), /* VK_FORMAT_R8G8B8_UNORM */
+        V4L2_PIX_FMT_BGR24 => Some(30), /* VK_FORMAT_B8G8R8_UNORM */
+        V4L2_PIX_FMT_ABGR32 | V4L2_PIX_FMT_XBGR32 => Some(44), /* VK_FORMAT_B8G8R8A8_UNORM */
+        V4L2_PIX_FMT_YUYV => Some(1000156000), /* VK_FORMAT_G8B8G8R8_422_UNORM */
+        V4L2_PIX_FMT_YUV420 => Some(1000156002), /* VK_FORMAT_G8_B8_R8_3PLANE_420_UNORM */
+        V4L2_PIX_FMT_NV12 => Some(1000156003), /* VK_FORMAT_G8_B8R8_2PLANE_420_UNORM */
+        _ => None,
+    }
+}
+
+/// A capture buffer exported as a dmabuf.
+#[derive(Debug)]
+pub struct DmabufBuffer {
+    /// Index of the V4L2 buffer.
+    pub index: u32,
+    pub fd: OwnedFd,
+    /// Size of the whole buffer.
+    pub size: u32,
+    pub width: u32,
+    pub height: u32,
+    /// DRM fourcc of the data, or 0 if there is no DRM equivalent.
+    pub drm_format: u32,
+    pub modifier: u64,
+    pub planes: Vec<PlaneLayout>,
+}
+
+impl DmabufBuffer {
+    pub(crate) fn new(index: u32, fd: OwnedFd, size: u32, format: &v4l2_pix_format) -> Self {
+        DmabufBuffer {
+            index,
+            fd,
+            size,
+            width: format.width,
+            height: format.height,
+            drm_format: drm::drm_format(format.pixelformat).unwrap_or(0),
+            modifier: DRM_FORMAT_MOD_LINEAR,
+            planes: plane_layouts(format),
+        }
+    }
+}
+
+impl AsRawFd for DmabufBuffer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// One plane of a dmabuf, as passed to `EGL_EXT_image_dma_buf_import`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmabufPlaneDescriptor {
+    pub fd: RawFd,
+    pub offset: u32,
+    pub pitch: u32,
+    pub modifier: u64,
+}
+
+impl DmabufBuffer {
+    /// Returns the per-plane descriptors of the buffer. All planes share
+    /// the fd of the buffer.
+    pub fn plane_descriptors(&self) -> Vec<DmabufPlaneDescriptor> {
+        self.planes
+            .iter()
+            .map(|plane| DmabufPlaneDescriptor {
+                fd: self.fd.as_raw_fd(),
+                offset: plane.offset,
+                pitch: plane.pitch,
+                modifier: self.modifier,
+            })
+            .collect()
+    }
+
+    /// Returns the `EGL_NONE` terminated attribute list for
+    /// `eglCreateImageKHR(..., EGL_LINUX_DMA_BUF_EXT, ...)`.
+    pub fn egl_image_attribs(&self) -> Vec<i32> {
+        egl_image_attribs(
+            self.width,
+            self.height,
+            self.drm_format,
+            &self.plane_descriptors(),
+        )
+    }
+}
+
+const EGL_NONE: i32 = 0x3038;
+const EGL_WIDTH: i32 = 0x3057;
+const EGL_HEIGHT: i32 = 0x3056;
+const EGL_LINUX_DRM_FOURCC_EXT: i32 = 0x3271;
+/* FD, OFFSET and PITCH of planes 0 to 2 */
+const EGL_DMA_BUF_PLANE_ATTRIBS: [[i32; 3]; 3] = [
+    [0x3272, 0x3273, 0x3274],
+    [0x3275, 0x3276, 0x3277],
+    [0x3278, 0x3279, 0x327a],
+];
+/* MODIFIER_LO and MODIFIER_HI of planes 0 to 2 */
+const EGL_DMA_BUF_PLANE_MODIFIER_ATTRIBS: [[i32; 2]; 3] =
+    [[0x3443, 0x3444], [0x3445, 0x3446], [0x3447, 0x3448]];
+
+/// Builds the `EGL_NONE` terminated attribute list of an EGLImage importing
+/// `planes`. Modifiers are only passed if they are not linear.
+pub fn egl_image_attribs(
+    width: u32,
+    height: u32,
+    drm_format: u32,
+    planes: &[DmabufPlaneDescriptor],
+) -> Vec<i32> {
+    let mut attribs = vec![
+        EGL_WIDTH,
+        width as i32,
+        EGL_HEIGHT,
+        height as i32,
+        EGL_LINUX_DRM_FOURCC_EXT,
+        drm_format as i32,
+    ];
+
+    for (plane, (names, modifier_names)) in planes.iter().zip(
+        EGL_DMA_BUF_PLANE_ATTRIBS
+            .iter()
+            .zip(EGL_DMA_BUF_PLANE_MODIFIER_ATTRIBS.iter()),
+    ) {
+        attribs.extend_from_slice(&[
+            names[0],
+            plane.fd,
+            names[1],
+            plane.offset as i32,
+            names[2],
+            plane.pitch as i32,
+        ]);
+        if plane.modifier != DRM_FORMAT_MOD_LINEAR {
+            attribs.extend_from_slice(&[
+                modifier_names[0],
+                plane.modifier as u32 as i32,
+                modifier_names[1],
+                (plane.modifier >> 32) as u32 as i32,
+            ]);
+        }
+    }
+
+    attribs.push(EGL_NONE);
+    attribs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nv12_egl_attribs() {
+        let planes = [
+            DmabufPlaneDescriptor {
+                fd: 7,
+                offset: 0,
+                pitch: 640,
+                modifier: DRM_FORMAT_MOD_LINEAR,
+            },
+            DmabufPlaneDescriptor {
+                fd: 7,
+                offset: 640 * 480,
+                pitch: 640,
+                modifier: DRM_FORMAT_MOD_LINEAR,
+            },
+        ];
+        let attribs = egl_image_attribs(640, 480, drm::DRM_FORMAT_NV12, &planes);
+        assert_eq!(attribs.len(), 6 + 2 * 6 + 1);
+        assert_eq!(
+            &attribs[12..18],
+            &[0x3275, 7, 0x3276, 640 * 480, 0x3277, 640]
+        );
+        assert_eq!(attribs.last(), Some(&EGL_NONE));
+    }
+}
@@ -0,0 +1,176 @@
+//! Zero-copy display of captured buffers with DRM/KMS.
+//!
+//! Buffers exported with `Capture::export_dmabufs` are imported into a DRM
+//! device as PRIME buffers and wrapped in KMS framebuffers, which can then
+//! be scanned out by a plane or CRTC without copying the frame.
+
+#![allow(non_camel_case_types)]
+
+use std::io;
+use std::marker::PhantomData;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use nix::sys::ioctl::ioctl_num_type;
+
+use crate::dmabuf;
+use crate::sys::cvt;
+use crate::sys::uapi::*;
+
+macro_rules! drm_fourcc {
+    ( $a:expr, $b:expr, $c:expr, $d:expr ) => {
+        ($a as u32) | (($b as u32) << 8) | (($c as u32) << 16) | (($d as u32) << 24)
+    };
+}
+
+pub const DRM_FORMAT_R8: u32 = drm_fourcc!('R', '8', ' ', ' ');
+pub const DRM_FORMAT_RGB888: u32 = drm_fourcc!('R', 'G', '2', '4');
+pub const DRM_FORMAT_BGR888: u32 = drm_fourcc!('B', 'G', '2', '4');
+pub const DRM_FORMAT_XRGB8888: u32 = drm_fourcc!('X', 'R', '2', '4');
+pub const DRM_FORMAT_ARGB8888: u32 = drm_fourcc!('A', 'R', '2', '4');
+pub const DRM_FORMAT_YUYV: u32 = drm_fourcc!('Y', 'U', 'Y', 'V');
+pub const DRM_FORMAT_NV12: u32 = drm_fourcc!('N', 'V', '1', '2');
+pub const DRM_FORMAT_NV21: u32 = drm_fourcc!('N', 'V', '2', '1');
+pub const DRM_FORMAT_YUV420: u32 = drm_fourcc!('Y', 'U', '1', '2');
+pub const DRM_FORMAT_YVU420: u32 = drm_fourcc!('Y', 'V', '1', '2');
+
+#[repr(C)]
+struct drm_prime_handle {
+    handle: u32,
+    flags: u32,
+    fd: i32,
+}
+
+#[repr(C)]
+struct drm_gem_close {
+    handle: u32,
+    pad: u32,
+}
+
+#[repr(C)]
+struct drm_mode_fb_cmd2 {
+    fb_id: u32,
+    width: u32,
+    height: u32,
+    pixel_format: u32,
+    flags: u32,
+    handles: [u32; 4],
+    pitches: [u32; 4],
+    offsets: [u32; 4],
+    modifier: [u64; 4],
+}
+
+const DRM_IOCTL_GEM_CLOSE: ioctl_num_type =
+    request_code_write!(b'd', 0x09, std::mem::size_of::<drm_gem_close>());
+const DRM_IOCTL_PRIME_FD_TO_HANDLE: ioctl_num_type =
+    request_code_readwrite!(b'd', 0x2e, std::mem::size_of::<drm_prime_handle>());
+const DRM_IOCTL_MODE_RMFB: ioctl_num_type =
+    request_code_readwrite!(b'd', 0xaf, std::mem::size_of::<u32>());
+const DRM_IOCTL_MODE_ADDFB2: ioctl_num_type =
+    request_code_readwrite!(b'd', 0xb8, std::mem::size_of::<drm_mode_fb_cmd2>());
+
+/// Returns the DRM format with the same memory layout as a V4L2 pixel
+/// format.
+///
+/// DRM formats are named after little-endian words, so V4L2 `RGB3`
+/// (R, G, B bytes) is `DRM_FORMAT_BGR888`.
+pub fn drm_format(pixelformat: u32) -> Option<u32> {
+    match pixelformat {
+        V4L2_PIX_FMT_GREY => Some(DRM_FORMAT_R8),
+        V4L2_PIX_FMT_RGB24 => Some(DRM_FORMAT_BGR888),
+        V4L2_PIX_FMT_BGR24 => Some(DRM_FORMAT_RGB888),
+        V4L2_PIX_FMT_XBGR32 => Some(DRM_FORMAT_XRGB8888),
+        V4L2_PIX_FMT_ABGR32 => Some(DRM_FORMAT_ARGB8888),
+        V4L2_PIX_FMT_YUYV => Some(DRM_FORMAT_YUYV),
+        V4L2_PIX_FMT_NV12 => Some(DRM_FORMAT_NV12),
+        V4L2_PIX_FMT_NV21 => Some(DRM_FORMAT_NV21),
+        V4L2_PIX_FMT_YUV420 => Some(DRM_FORMAT_YUV420),
+        V4L2_PIX_FMT_YVU420 => Some(DRM_FORMAT_YVU420),
+        _ => None,
+    }
+}
+
+/// A KMS framebuffer backed by an imported dmabuf.
+///
+/// The framebuffer and the imported GEM handle are released on drop.
+#[derive(Debug)]
+pub struct Framebuffer<'a> {
+    drm: RawFd,
+    fb_id: u32,
+    handle: u32,
+    _drm: PhantomData<&'a ()>,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// Imports `dmabuf`, holding a frame in `format`, into the DRM device
+    /// `drm` and creates a framebuffer for it.
+    pub fn import<D: AsRawFd, B: AsRawFd>(
+        drm: &'a D,
+        dmabuf: &B,
+        format: &v4l2_pix_format,
+    ) -> io::Result<Framebuffer<'a>> {
+        let pixel_format = drm_format(format.pixelformat)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no matching DRM format"))?;
+        let drm = drm.as_raw_fd();
+
+        let mut prime = drm_prime_handle {
+            handle: 0,
+            flags: 0,
+            fd: dmabuf.as_raw_fd(),
+        };
+        unsafe { cvt(libc::ioctl(drm, DRM_IOCTL_PRIME_FD_TO_HANDLE, &mut prime))? };
+
+        let mut handles = [0; 4];
+        let mut pitches = [0; 4];
+        let mut offsets = [0; 4];
+        for (i, plane) in dmabuf::plane_layouts(format).iter().enumerate() {
+            handles[i] = prime.handle;
+            pitches[i] = plane.pitch;
+            offsets[i] = plane.offset;
+        }
+
+        let mut cmd = drm_mode_fb_cmd2 {
+            fb_id: 0,
+            width: format.width,
+            height: format.height,
+            pixel_format,
+            flags: 0,
+            handles,
+            pitches,
+            offsets,
+            modifier: [0; 4],
+        };
+        // Closes the GEM handle on error.
+        let mut fb = Framebuffer {
+            drm,
+            fb_id: 0,
+            handle: prime.handle,
+            _drm: PhantomData,
+        };
+        unsafe { cvt(libc::ioctl(drm, DRM_IOCTL_MODE_ADDFB2, &mut cmd))? };
+
+        fb.fb_id = cmd.fb_id;
+        Ok(fb)
+    }
+
+    /// Returns the framebuffer id, to be used with `drmModeSetPlane` or an
+    /// atomic commit.
+    pub fn id(&self) -> u32 {
+        self.fb_id
+    }
+}
+
+impl<'a> Drop for Framebuffer<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            if self.fb_id != 0 {
+                let mut fb_id = self.fb_id;
+                libc::ioctl(self.drm, DRM_IOCTL_MODE_RMFB, &mut fb_id);
+            }
+            let close = drm_gem_close {
+                handle: self.handle,
+                pad: 0,
+            };
+            libc::ioctl(self.drm, DRM_IOCTL_GEM_CLOSE, &close);
+        }
+    }
+}
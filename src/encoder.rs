@@ -0,0 +1,419 @@
+use std::convert::TryInto;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::m2m::M2m;
+use crate::sys::uapi::*;
+use crate::sys::ControlInfo;
+
+/// Declares an enum of menu entries with conversions from and to the
+/// values of the menu control.
+macro_rules! menu_enum {
+    ($(#[$attr:meta])* $name:ident { $($variant:ident = $raw:ident,)* }) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl $name {
+            pub fn raw(self) -> i32 {
+                match self {
+                    $($name::$variant => $raw,)*
+                }
+            }
+
+            pub fn from_raw(raw: i32) -> Option<$name> {
+                match raw {
+                    $($raw => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+menu_enum! {
+    /// Values of `V4L2_CID_MPEG_VIDEO_BITRATE_MODE`.
+    RateControl {
+        Vbr = V4L2_MPEG_VIDEO_BITRATE_MODE_VBR,
+        Cbr = V4L2_MPEG_VIDEO_BITRATE_MODE_CBR,
+        ConstantQuality = V4L2_MPEG_VIDEO_BITRATE_MODE_CQ,
+    }
+}
+
+menu_enum! {
+    /// Values of `V4L2_CID_MPEG_VIDEO_H264_PROFILE`.
+    H264Profile {
+        Baseline = V4L2_MPEG_VIDEO_H264_PROFILE_BASELINE,
+        ConstrainedBaseline = V4L2_MPEG_VIDEO_H264_PROFILE_CONSTRAINED_BASELINE,
+        Main = V4L2_MPEG_VIDEO_H264_PROFILE_MAIN,
+        Extended = V4L2_MPEG_VIDEO_H264_PROFILE_EXTENDED,
+        High = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH,
+        High10 = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_10,
+        High422 = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_422,
+        High444Predictive = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_444_PREDICTIVE,
+        High10Intra = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_10_INTRA,
+        High422Intra = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_422_INTRA,
+        High444Intra = V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_444_INTRA,
+        Cavlc444Intra = V4L2_MPEG_VIDEO_H264_PROFILE_CAVLC_444_INTRA,
+        ScalableBaseline = V4L2_MPEG_VIDEO_H264_PROFILE_SCALABLE_BASELINE,
+        ScalableHigh = V4L2_MPEG_VIDEO_H264_PROFILE_SCALABLE_HIGH,
+        ScalableHighIntra = V4L2_MPEG_VIDEO_H264_PROFILE_SCALABLE_HIGH_INTRA,
+        StereoHigh = V4L2_MPEG_VIDEO_H264_PROFILE_STEREO_HIGH,
+        MultiviewHigh = V4L2_MPEG_VIDEO_H264_PROFILE_MULTIVIEW_HIGH,
+        ConstrainedHigh = V4L2_MPEG_VIDEO_H264_PROFILE_CONSTRAINED_HIGH,
+    }
+}
+
+menu_enum! {
+    /// Values of `V4L2_CID_MPEG_VIDEO_H264_LEVEL`.
+    H264Level {
+        L1_0 = V4L2_MPEG_VIDEO_H264_LEVEL_1_0,
+        L1b = V4L2_MPEG_VIDEO_H264_LEVEL_1B,
+        L1_1 = V4L2_MPEG_VIDEO_H264_LEVEL_1_1,
+        L1_2 = V4L2_MPEG_VIDEO_H264_LEVEL_1_2,
+        L1_3 = V4L2_MPEG_VIDEO_H264_LEVEL_1_3,
+        L2_0 = V4L2_MPEG_VIDEO_H264_LEVEL_2_0,
+        L2_1 = V4L2_MPEG_VIDEO_H264_LEVEL_2_1,
+        L2_2 = V4L2_MPEG_VIDEO_H264_LEVEL_2_2,
+        L3_0 = V4L2_MPEG_VIDEO_H264_LEVEL_3_0,
+        L3_1 = V4L2_MPEG_VIDEO_H264_LEVEL_3_1,
+        L3_2 = V4L2_MPEG_VIDEO_H264_LEVEL_3_2,
+        L4_0 = V4L2_MPEG_VIDEO_H264_LEVEL_4_0,
+        L4_1 = V4L2_MPEG_VIDEO_H264_LEVEL_4_1,
+        L4_2 = V4L2_MPEG_VIDEO_H264_LEVEL_4_2,
+        L5_0 = V4L2_MPEG_VIDEO_H264_LEVEL_5_0,
+        L5_1 = V4L2_MPEG_VIDEO_H264_LEVEL_5_1,
+        L5_2 = V4L2_MPEG_VIDEO_H264_LEVEL_5_2,
+        L6_0 = V4L2_MPEG_VIDEO_H264_LEVEL_6_0,
+        L6_1 = V4L2_MPEG_VIDEO_H264_LEVEL_6_1,
+        L6_2 = V4L2_MPEG_VIDEO_H264_LEVEL_6_2,
+    }
+}
+
+menu_enum! {
+    /// Values of `V4L2_CID_MPEG_VIDEO_VP8_PROFILE` and
+    /// `V4L2_CID_MPEG_VIDEO_VP9_PROFILE`.
+    VpxProfile {
+        P0 = V4L2_MPEG_VIDEO_VPX_PROFILE_0,
+        P1 = V4L2_MPEG_VIDEO_VPX_PROFILE_1,
+        P2 = V4L2_MPEG_VIDEO_VPX_PROFILE_2,
+        P3 = V4L2_MPEG_VIDEO_VPX_PROFILE_3,
+    }
+}
+
+menu_enum! {
+    /// Values of `V4L2_CID_MPEG_VIDEO_VPX_NUM_PARTITIONS`, the number of
+    /// VP8 token partitions, which can be decoded in parallel.
+    VpxPartitions {
+        One = V4L2_CID_MPEG_VIDEO_VPX_1_PARTITION,
+        Two = V4L2_CID_MPEG_VIDEO_VPX_2_PARTITIONS,
+        Four = V4L2_CID_MPEG_VIDEO_VPX_4_PARTITIONS,
+        Eight = V4L2_CID_MPEG_VIDEO_VPX_8_PARTITIONS,
+    }
+}
+
+/// Which frame a VPx encoder keeps as golden frame, a long-term reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoldenFrame {
+    /// The frame before the previous one.
+    PreviousButOne,
+    /// Every this many frames.
+    Period(u32),
+}
+
+/// How frames are split into slices, which can be sent and decoded on
+/// their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceMode {
+    /// One slice per frame.
+    Single,
+    /// Slices of at most this many macroblocks.
+    MaxMacroblocks(u32),
+    /// Slices of at most this many bytes, e.g. to fit network packets.
+    MaxBytes(u32),
+}
+
+/// A stateful hardware encoder, such as the H.264 encoder of the Raspberry
+/// Pi or the Hantro encoders of Rockchip boards.
+///
+/// An encoder is a memory-to-memory device turning raw frames into a
+/// compressed stream. `Encoder` sets it up as an `M2m` and adds the codec
+/// controls of the MPEG control class.
+pub struct Encoder {
+    m2m: M2m,
+}
+
+impl Encoder {
+    /// Opens the encoder at `path`, compressing frames in `input` format
+    /// to `codec`, e.g. `V4L2_PIX_FMT_H264`. The driver picks the size of
+    /// the compressed buffers.
+    pub fn open(path: &str, input: &v4l2_pix_format, codec: u32) -> io::Result<Encoder> {
+        let mut output = *input;
+        output.pixelformat = codec;
+        output.bytesperline = 0;
+        output.sizeimage = 0;
+        M2m::open(path, input, &output).map(|m2m| Encoder { m2m })
+    }
+
+    /// Returns the device, for buffer handling and streaming.
+    pub fn m2m(&self) -> &M2m {
+        &self.m2m
+    }
+
+    pub fn m2m_mut(&mut self) -> &mut M2m {
+        &mut self.m2m
+    }
+
+    /// Returns the device, e.g. to build a `Pipeline` with.
+    pub fn into_m2m(self) -> M2m {
+        self.m2m
+    }
+
+    /// Makes the next encoded frame a keyframe, an IDR frame for H.264,
+    /// e.g. when a client joins a stream.
+    pub fn request_keyframe(&self) -> io::Result<()> {
+        self.m2m
+            .device()
+            .set_control(V4L2_CID_MPEG_VIDEO_FORCE_KEY_FRAME, 0)
+    }
+
+    /// Sets the average bitrate in bits per second.
+    pub fn set_bitrate(&self, bits_per_second: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_BITRATE, bits_per_second)
+    }
+
+    /// Sets the bitrate that variable bitrate encoding may peak at, in
+    /// bits per second.
+    pub fn set_peak_bitrate(&self, bits_per_second: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_BITRATE_PEAK, bits_per_second)
+    }
+
+    /// Sets the number of frames from one keyframe to the next.
+    pub fn set_gop_size(&self, frames: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_GOP_SIZE, frames)
+    }
+
+    /// Sets the number of B-frames between reference frames.
+    pub fn set_b_frames(&self, count: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_B_FRAMES, count)
+    }
+
+    /// Sets the rate control mode, or the closest one the driver offers:
+    /// constant quality falls back to variable bitrate, and variable and
+    /// constant bitrate to each other. Returns the mode set.
+    pub fn set_rate_control(&self, mode: RateControl) -> io::Result<RateControl> {
+        let candidates: &[RateControl] = match mode {
+            RateControl::ConstantQuality => &[
+                RateControl::ConstantQuality,
+                RateControl::Vbr,
+                RateControl::Cbr,
+            ],
+            RateControl::Vbr => &[RateControl::Vbr, RateControl::Cbr],
+            RateControl::Cbr => &[RateControl::Cbr, RateControl::Vbr],
+        };
+
+        let device = self.m2m.device();
+        // Some encoders ignore the mode unless frame level rate control
+        // is on.
+        if device
+            .query_control(V4L2_CID_MPEG_VIDEO_FRAME_RC_ENABLE)
+            .is_ok()
+        {
+            device.set_control(V4L2_CID_MPEG_VIDEO_FRAME_RC_ENABLE, 1)?;
+        }
+        for &candidate in candidates {
+            match self.set_menu(V4L2_CID_MPEG_VIDEO_BITRATE_MODE, candidate.raw()) {
+                Ok(()) => return Ok(candidate),
+                Err(ref e) if e.kind() == io::ErrorKind::Unsupported => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "driver offers no suitable rate control mode",
+        ))
+    }
+
+    pub fn rate_control(&self) -> io::Result<RateControl> {
+        self.menu(V4L2_CID_MPEG_VIDEO_BITRATE_MODE, RateControl::from_raw)
+    }
+
+    /// Sets the quality of constant quality encoding, from 1 for the
+    /// smallest to 100 for the best frames.
+    pub fn set_constant_quality(&self, quality: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_CONSTANT_QUALITY, quality)
+    }
+
+    /// Sets the number of frames from one H.264 IDR frame to the next.
+    pub fn set_idr_period(&self, frames: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_H264_I_PERIOD, frames)
+    }
+
+    /// Refreshes the picture with intra coded macroblocks spread over
+    /// `frames` frames, instead of with keyframes whose size causes
+    /// latency spikes. 0 turns intra refresh off.
+    pub fn set_intra_refresh_period(&self, frames: u32) -> io::Result<()> {
+        self.set(V4L2_CID_MPEG_VIDEO_INTRA_REFRESH_PERIOD, frames)
+    }
+
+    /// Sets how frames are split into slices.
+    pub fn set_slice_mode(&self, mode: SliceMode) -> io::Result<()> {
+        let (raw, limit) = match mode {
+            SliceMode::Single => (V4L2_MPEG_VIDEO_MULTI_SLICE_MODE_SINGLE, None),
+            SliceMode::MaxMacroblocks(count) => (
+                V4L2_MPEG_VIDEO_MULTI_SLICE_MODE_MAX_MB,
+                Some((V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MAX_MB, count)),
+            ),
+            SliceMode::MaxBytes(bytes) => (
+                V4L2_MPEG_VIDEO_MULTI_SLICE_MODE_MAX_BYTES,
+                Some((V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MAX_BYTES, bytes)),
+            ),
+        };
+        // Checked first, so that a rejected limit leaves the mode as it
+        // was.
+        let limit = match limit {
+            Some((id, value)) => Some((id, self.checked(id, value)?)),
+            None => None,
+        };
+        self.set_menu(V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MODE, raw)?;
+        match limit {
+            Some((id, value)) => self.m2m.device().set_control(id, value),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the VP8 profile. Fails with `Unsupported` if the driver does
+    /// not offer it.
+    pub fn set_vp8_profile(&self, profile: VpxProfile) -> io::Result<()> {
+        self.set_menu(V4L2_CID_MPEG_VIDEO_VP8_PROFILE, profile.raw())
+    }
+
+    /// Sets the VP9 profile. Fails with `Unsupported` if the driver does
+    /// not offer it.
+    pub fn set_vp9_profile(&self, profile: VpxProfile) -> io::Result<()> {
+        self.set_menu(V4L2_CID_MPEG_VIDEO_VP9_PROFILE, profile.raw())
+    }
+
+    pub fn set_vpx_partitions(&self, partitions: VpxPartitions) -> io::Result<()> {
+        self.set_menu(V4L2_CID_MPEG_VIDEO_VPX_NUM_PARTITIONS, partitions.raw())
+    }
+
+    /// Sets the strength and sharpness of the VPx loop filter.
+    pub fn set_vpx_filter(&self, level: u32, sharpness: u32) -> io::Result<()> {
+        // Both checked first, so that a rejected sharpness leaves the level
+        // as it was.
+        let level = self.checked(V4L2_CID_MPEG_VIDEO_VPX_FILTER_LEVEL, level)?;
+        let sharpness = self.checked(V4L2_CID_MPEG_VIDEO_VPX_FILTER_SHARPNESS, sharpness)?;
+        let device = self.m2m.device();
+        device.set_control(V4L2_CID_MPEG_VIDEO_VPX_FILTER_LEVEL, level)?;
+        device.set_control(V4L2_CID_MPEG_VIDEO_VPX_FILTER_SHARPNESS, sharpness)
+    }
+
+    pub fn set_golden_frame(&self, golden: GoldenFrame) -> io::Result<()> {
+        match golden {
+            GoldenFrame::PreviousButOne => self.set_menu(
+                V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_SEL,
+                V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_USE_PREV,
+            ),
+            GoldenFrame::Period(frames) => {
+                // Checked first, so that a rejected period leaves the
+                // selection as it was.
+                let id = V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_REF_PERIOD;
+                let frames = self.checked(id, frames)?;
+                self.set_menu(
+                    V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_SEL,
+                    V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_USE_REF_PERIOD,
+                )?;
+                self.m2m.device().set_control(id, frames)
+            }
+        }
+    }
+
+    /// Sets the H.264 profile. Fails with `Unsupported` if the driver does
+    /// not offer it.
+    pub fn set_h264_profile(&self, profile: H264Profile) -> io::Result<()> {
+        self.set_menu(V4L2_CID_MPEG_VIDEO_H264_PROFILE, profile.raw())
+    }
+
+    pub fn h264_profile(&self) -> io::Result<H264Profile> {
+        self.menu(V4L2_CID_MPEG_VIDEO_H264_PROFILE, H264Profile::from_raw)
+    }
+
+    /// Sets the H.264 level. Fails with `Unsupported` if the driver does
+    /// not offer it.
+    pub fn set_h264_level(&self, level: H264Level) -> io::Result<()> {
+        self.set_menu(V4L2_CID_MPEG_VIDEO_H264_LEVEL, level.raw())
+    }
+
+    pub fn h264_level(&self) -> io::Result<H264Level> {
+        self.menu(V4L2_CID_MPEG_VIDEO_H264_LEVEL, H264Level::from_raw)
+    }
+
+    /// Sets menu control `id` to entry `index`, after checking that the
+    /// driver offers it: drivers implement different subsets of the menus.
+    fn set_menu(&self, id: u32, index: i32) -> io::Result<()> {
+        let device = self.m2m.device();
+        match device.query_menu(id, index as u32) {
+            Ok(_) => device.set_control(id, index),
+            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "driver does not offer this menu entry",
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn menu<T>(&self, id: u32, from_raw: fn(i32) -> Option<T>) -> io::Result<T> {
+        let raw = self.m2m.device().control(id)?;
+        from_raw(raw).ok_or_else(|| io::Error::other("unknown menu entry"))
+    }
+
+    /// Sets control `id` after checking `value` against the range the
+    /// driver reports, so out of range values fail instead of being
+    /// clamped silently.
+    fn set<T: TryInto<i32>>(&self, id: u32, value: T) -> io::Result<()> {
+        let value = self.checked(id, value)?;
+        self.m2m.device().set_control(id, value)
+    }
+
+    /// Returns `value` as the value of control `id`, if it is in the range
+    /// the driver reports.
+    fn checked<T: TryInto<i32>>(&self, id: u32, value: T) -> io::Result<i32> {
+        let value = value.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "value is out of the control range",
+            )
+        })?;
+        let device = self.m2m.device();
+        ControlInfo::from_raw(&device.query_control(id)?, None).validate(value)?;
+        Ok(value)
+    }
+}
+
+impl AsRawFd for Encoder {
+    fn as_raw_fd(&self) -> RawFd {
+        self.m2m.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_menu_entries() {
+        assert_eq!(H264Profile::High.raw(), 4);
+        assert_eq!(
+            H264Profile::from_raw(1),
+            Some(H264Profile::ConstrainedBaseline)
+        );
+        assert_eq!(
+            H264Level::from_raw(H264Level::L4_1.raw()),
+            Some(H264Level::L4_1)
+        );
+        assert_eq!(H264Level::from_raw(20), None);
+    }
+}
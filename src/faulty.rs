@@ -0,0 +1,462 @@
+//! Fault injection, to test recovery logic without misbehaving hardware.
+//!
+//! A `FaultyDevice` wraps a device and fails its calls the way drivers do
+//! in the field: dequeues that would block or are interrupted by signals,
+//! devices that disappear when unplugged, buffers that cannot be mapped,
+//! and frames the driver marks as corrupt. Faults are drawn from a seeded generator, so a failing run can
+//! be replayed.
+//!
+//! Without hardware, a `MemoryDevice` streams blank frames instead.
+//!
+//! ```no_run
+//! # use v4l2::faulty::FaultyDevice;
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0")
+//!     .buffers(4)
+//!     .open()?
+//!     .map_device(|device| FaultyDevice::new(device).again(0.1).corrupt_frames(0.01));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+use memmap::MmapMut;
+
+use crate::capture::{Builder, Capture, IoMethod};
+use crate::sys::uapi::*;
+use crate::sys::{DeviceOps, V4l2Device};
+
+const DEFAULT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A device whose calls fail at configurable rates.
+///
+/// Rates are probabilities between 0 and 1 per call. `EAGAIN` and `EINTR`
+/// are injected into dequeues and reads, before the wrapped device is
+/// called, so no frame is lost. Once `ENODEV` is injected, the device
+/// stays gone and every call fails with it. Corrupt frames are dequeued
+/// with `V4L2_BUF_FLAG_ERROR` set.
+pub struct FaultyDevice<D = V4l2Device> {
+    inner: D,
+    again: f64,
+    interrupted: f64,
+    no_device: f64,
+    no_memory: f64,
+    corrupt: f64,
+    state: Cell<u64>,
+    gone: Cell<bool>,
+}
+
+impl<D: DeviceOps> FaultyDevice<D> {
+    /// Wraps `inner` without injecting any faults.
+    pub fn new(inner: D) -> FaultyDevice<D> {
+        FaultyDevice {
+            inner,
+            again: 0.0,
+            interrupted: 0.0,
+            no_device: 0.0,
+            no_memory: 0.0,
+            corrupt: 0.0,
+            state: Cell::new(DEFAULT_SEED),
+            gone: Cell::new(false),
+        }
+    }
+
+    /// Fails dequeues and reads with `EAGAIN` at `rate`.
+    pub fn again(mut self, rate: f64) -> Self {
+        self.again = rate;
+        self
+    }
+
+    /// Fails dequeues and reads with `EINTR` at `rate`.
+    pub fn interrupted(mut self, rate: f64) -> Self {
+        self.interrupted = rate;
+        self
+    }
+
+    /// Makes the device disappear at `rate` per call.
+    pub fn no_device(mut self, rate: f64) -> Self {
+        self.no_device = rate;
+        self
+    }
+
+    /// Fails mapping buffers with `ENOMEM` at `rate`.
+    pub fn no_memory(mut self, rate: f64) -> Self {
+        self.no_memory = rate;
+        self
+    }
+
+    /// Flags dequeued frames as corrupt at `rate`.
+    pub fn corrupt_frames(mut self, rate: f64) -> Self {
+        self.corrupt = rate;
+        self
+    }
+
+    /// Seeds the generator faults are drawn from.
+    pub fn seed(self, seed: u64) -> Self {
+        // Xorshift gets stuck at zero.
+        self.state.set(if seed == 0 { DEFAULT_SEED } else { seed });
+        self
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Returns true once `ENODEV` was injected.
+    pub fn is_gone(&self) -> bool {
+        self.gone.get()
+    }
+
+    /// Returns true with probability `rate`.
+    fn roll(&self, rate: f64) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+        // xorshift64*
+        let mut x = self.state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state.set(x);
+        let sample = (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64;
+        sample < rate
+    }
+
+    /// Fails with `ENODEV` if the device is, or now goes, away.
+    fn check_present(&self) -> io::Result<()> {
+        if self.gone.get() || self.roll(self.no_device) {
+            self.gone.set(true);
+            return Err(io::Error::from_raw_os_error(libc::ENODEV));
+        }
+        Ok(())
+    }
+
+    /// Injects the faults of a call that may block.
+    fn check_blocking(&self) -> io::Result<()> {
+        self.check_present()?;
+        if self.roll(self.again) {
+            return Err(io::Error::from_raw_os_error(libc::EAGAIN));
+        }
+        if self.roll(self.interrupted) {
+            return Err(io::Error::from_raw_os_error(libc::EINTR));
+        }
+        Ok(())
+    }
+}
+
+impl<D: DeviceOps> DeviceOps for FaultyDevice<D> {
+    fn capability(&self) -> io::Result<v4l2_capability> {
+        self.check_present()?;
+        self.inner.capability()
+    }
+
+    fn capture_format(&self) -> io::Result<v4l2_pix_format> {
+        self.check_present()?;
+        self.inner.capture_format()
+    }
+
+    fn set_capture_format(&self, fmt: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+        self.check_present()?;
+        self.inner.set_capture_format(fmt)
+    }
+
+    fn request_buffers(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+        count: usize,
+    ) -> io::Result<usize> {
+        self.check_present()?;
+        self.inner.request_buffers(buf_type, memory, count)
+    }
+
+    fn map_buffers(&self, buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>> {
+        self.check_present()?;
+        if self.roll(self.no_memory) {
+            return Err(io::Error::from_raw_os_error(libc::ENOMEM));
+        }
+        self.inner.map_buffers(buf_type)
+    }
+
+    fn queue_buffer(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        self.check_present()?;
+        self.inner.queue_buffer(buf)
+    }
+
+    fn dequeue_buffer(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+    ) -> io::Result<v4l2_buffer> {
+        self.check_blocking()?;
+        let mut buf = self.inner.dequeue_buffer(buf_type, memory)?;
+        if self.roll(self.corrupt) {
+            buf.flags |= V4L2_BUF_FLAG_ERROR;
+        }
+        Ok(buf)
+    }
+
+    fn stream_on(&self, buf_type: v4l2_buf_type) -> io::Result<()> {
+        self.check_present()?;
+        self.inner.stream_on(buf_type)
+    }
+
+    fn stream_off(&self, buf_type: v4l2_buf_type) -> io::Result<()> {
+        self.check_present()?;
+        self.inner.stream_off(buf_type)
+    }
+
+    fn read(&self, data: &mut [u8]) -> io::Result<usize> {
+        self.check_blocking()?;
+        self.inner.read(data)
+    }
+
+    fn frame_ready(&self) -> io::Result<bool> {
+        self.check_present()?;
+        self.inner.frame_ready()
+    }
+
+    fn wait_frame(&self, timeout: Duration) -> io::Result<bool> {
+        self.check_present()?;
+        self.inner.wait_frame(timeout)
+    }
+}
+
+/// An in-memory capture device, streaming blank frames from the buffers
+/// queued to it in order.
+pub struct MemoryDevice {
+    format: v4l2_pix_format,
+    count: Cell<usize>,
+    queued: RefCell<VecDeque<u32>>,
+    streaming: Cell<bool>,
+    sequence: Cell<u32>,
+}
+
+impl MemoryDevice {
+    /// Creates a device with `width` by `height` greyscale frames.
+    pub fn new(width: u32, height: u32) -> MemoryDevice {
+        let mut format = Builder::with_device("")
+            .video_size(width, height)
+            .pixel_format(V4L2_PIX_FMT_GREY)
+            .format;
+        format.field = v4l2_field::V4L2_FIELD_NONE;
+        format.bytesperline = width;
+        format.sizeimage = width * height;
+        MemoryDevice {
+            format,
+            count: Cell::new(0),
+            queued: RefCell::new(VecDeque::new()),
+            streaming: Cell::new(false),
+            sequence: Cell::new(0),
+        }
+    }
+
+    /// Returns a capture from this device, without buffers prepared.
+    pub fn into_capture(self) -> Capture<MemoryDevice> {
+        let format = self.format;
+        Capture::new(self, "memory", format, IoMethod::Mmap)
+    }
+}
+
+impl DeviceOps for MemoryDevice {
+    fn capability(&self) -> io::Result<v4l2_capability> {
+        let mut cap: v4l2_capability = unsafe { std::mem::zeroed() };
+        cap.device_caps = V4L2_CAP_VIDEO_CAPTURE | V4L2_CAP_STREAMING;
+        cap.capabilities = cap.device_caps;
+        Ok(cap)
+    }
+
+    fn capture_format(&self) -> io::Result<v4l2_pix_format> {
+        Ok(self.format)
+    }
+
+    fn set_capture_format(&self, _fmt: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+        // The format is fixed, as drivers may do.
+        Ok(self.format)
+    }
+
+    fn request_buffers(
+        &self,
+        _buf_type: v4l2_buf_type,
+        _memory: v4l2_memory,
+        count: usize,
+    ) -> io::Result<usize> {
+        if self.streaming.get() {
+            return Err(io::Error::from_raw_os_error(libc::EBUSY));
+        }
+        self.count.set(count);
+        // Queuing never allocates.
+        *self.queued.borrow_mut() = VecDeque::with_capacity(count);
+        Ok(count)
+    }
+
+    fn map_buffers(&self, _buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>> {
+        (0..self.count.get())
+            .map(|_| MmapMut::map_anon(self.format.sizeimage as usize))
+            .collect()
+    }
+
+    fn queue_buffer(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        let mut queued = self.queued.borrow_mut();
+        if buf.index as usize >= self.count.get() || queued.contains(&buf.index) {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        queued.push_back(buf.index);
+        Ok(())
+    }
+
+    fn dequeue_buffer(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+    ) -> io::Result<v4l2_buffer> {
+        if !self.streaming.get() {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        let index = self
+            .queued
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EAGAIN))?;
+
+        let mut buf = v4l2_buffer::new(buf_type, memory);
+        buf.index = index;
+        buf.bytesused = self.format.sizeimage;
+        buf.field = v4l2_field::V4L2_FIELD_NONE;
+        buf.sequence = self.sequence.get();
+        self.sequence.set(buf.sequence.wrapping_add(1));
+        Ok(buf)
+    }
+
+    fn stream_on(&self, _buf_type: v4l2_buf_type) -> io::Result<()> {
+        self.streaming.set(true);
+        self.sequence.set(0);
+        Ok(())
+    }
+
+    fn stream_off(&self, _buf_type: v4l2_buf_type) -> io::Result<()> {
+        self.streaming.set(false);
+        self.queued.borrow_mut().clear();
+        Ok(())
+    }
+
+    fn read(&self, _data: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::from_raw_os_error(libc::EINVAL))
+    }
+
+    fn frame_ready(&self) -> io::Result<bool> {
+        Ok(self.streaming.get() && !self.queued.borrow().is_empty())
+    }
+
+    fn wait_frame(&self, _timeout: Duration) -> io::Result<bool> {
+        self.frame_ready()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A device that always has a frame ready.
+    struct Ready;
+
+    impl DeviceOps for Ready {
+        fn capability(&self) -> io::Result<v4l2_capability> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn capture_format(&self) -> io::Result<v4l2_pix_format> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn set_capture_format(&self, _fmt: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn request_buffers(
+            &self,
+            _buf_type: v4l2_buf_type,
+            _memory: v4l2_memory,
+            count: usize,
+        ) -> io::Result<usize> {
+            Ok(count)
+        }
+
+        fn map_buffers(&self, _buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>> {
+            Ok(Vec::new())
+        }
+
+        fn queue_buffer(&self, _buf: &v4l2_buffer) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn dequeue_buffer(
+            &self,
+            buf_type: v4l2_buf_type,
+            memory: v4l2_memory,
+        ) -> io::Result<v4l2_buffer> {
+            Ok(v4l2_buffer::new(buf_type, memory))
+        }
+
+        fn stream_on(&self, _buf_type: v4l2_buf_type) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn stream_off(&self, _buf_type: v4l2_buf_type) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn read(&self, data: &mut [u8]) -> io::Result<usize> {
+            Ok(data.len())
+        }
+    }
+
+    fn dequeue<D: DeviceOps>(device: &D) -> io::Result<v4l2_buffer> {
+        device.dequeue_buffer(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            v4l2_memory::V4L2_MEMORY_MMAP,
+        )
+    }
+
+    #[test]
+    fn injects_faults_at_rate() {
+        let device = FaultyDevice::new(Ready)
+            .seed(1)
+            .again(0.25)
+            .corrupt_frames(0.5);
+        let (mut again, mut corrupt) = (0, 0);
+        for _ in 0..4000 {
+            match dequeue(&device) {
+                Ok(buf) if buf.flags & V4L2_BUF_FLAG_ERROR != 0 => corrupt += 1,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => again += 1,
+                Err(e) => panic!("unexpected error {}", e),
+            }
+        }
+        assert!((900..1100).contains(&again), "{} EAGAIN", again);
+        assert!((1350..1650).contains(&corrupt), "{} corrupt", corrupt);
+    }
+
+    #[test]
+    fn device_stays_gone() {
+        let device = FaultyDevice::new(Ready).no_device(1.0);
+        let err = dequeue(&device).err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::ENODEV));
+        assert!(device.is_gone());
+
+        let device = FaultyDevice {
+            no_device: 0.0,
+            ..device
+        };
+        let err = device.queue_buffer(&dequeue(&Ready).unwrap()).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENODEV));
+    }
+}
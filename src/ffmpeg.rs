@@ -0,0 +1,188 @@
+//! Conversions between captured frames and `ffmpeg_next::frame::Video`.
+//!
+//! Frame data is copied plane by plane, honouring the strides of both
+//! sides. Presentation timestamps are the V4L2 buffer timestamps in
+//! microseconds, see `time_base`.
+
+use std::io;
+
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::frame::Video;
+use ffmpeg_next::Rational;
+
+use crate::frame::Frame;
+use crate::sys::uapi::*;
+
+/// Returns the FFmpeg pixel format matching a V4L2 pixel format.
+pub fn pixel_from_fourcc(pixelformat: u32) -> Option<Pixel> {
+    match pixelformat {
+        V4L2_PIX_FMT_YUYV => Some(Pixel::YUYV422),
+        V4L2_PIX_FMT_NV12 => Some(Pixel::NV12),
+        V4L2_PIX_FMT_NV21 => Some(Pixel::NV21),
+        V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => Some(Pixel::YUV420P),
+        V4L2_PIX_FMT_GREY => Some(Pixel::GRAY8),
+        V4L2_PIX_FMT_RGB24 => Some(Pixel::RGB24),
+        V4L2_PIX_FMT_BGR24 => Some(Pixel::BGR24),
+        V4L2_PIX_FMT_ABGR32 => Some(Pixel::BGRA),
+        V4L2_PIX_FMT_XBGR32 => Some(Pixel::BGRZ),
+        V4L2_PIX_FMT_SBGGR8 => Some(Pixel::BAYER_BGGR8),
+        V4L2_PIX_FMT_SGBRG8 => Some(Pixel::BAYER_GBRG8),
+        V4L2_PIX_FMT_SGRBG8 => Some(Pixel::BAYER_GRBG8),
+        V4L2_PIX_FMT_SRGGB8 => Some(Pixel::BAYER_RGGB8),
+        _ => None,
+    }
+}
+
+/// Returns the V4L2 pixel format matching an FFmpeg pixel format.
+pub fn fourcc_from_pixel(pixel: Pixel) -> Option<u32> {
+    match pixel {
+        Pixel::YUYV422 => Some(V4L2_PIX_FMT_YUYV),
+        Pixel::NV12 => Some(V4L2_PIX_FMT_NV12),
+        Pixel::NV21 => Some(V4L2_PIX_FMT_NV21),
+        Pixel::YUV420P => Some(V4L2_PIX_FMT_YUV420),
+        Pixel::GRAY8 => Some(V4L2_PIX_FMT_GREY),
+        Pixel::RGB24 => Some(V4L2_PIX_FMT_RGB24),
+        Pixel::BGR24 => Some(V4L2_PIX_FMT_BGR24),
+        Pixel::BGRA => Some(V4L2_PIX_FMT_ABGR32),
+        Pixel::BGRZ => Some(V4L2_PIX_FMT_XBGR32),
+        Pixel::BAYER_BGGR8 => Some(V4L2_PIX_FMT_SBGGR8),
+        Pixel::BAYER_GBRG8 => Some(V4L2_PIX_FMT_SGBRG8),
+        Pixel::BAYER_GRBG8 => Some(V4L2_PIX_FMT_SGRBG8),
+        Pixel::BAYER_RGGB8 => Some(V4L2_PIX_FMT_SRGGB8),
+        _ => None,
+    }
+}
+
+/// Time base of the timestamps set by `to_video`.
+pub fn time_base() -> Rational {
+    Rational::new(1, 1_000_000)
+}
+
+/// A plane of a single-planar V4L2 buffer.
+struct Plane {
+    offset: usize,
+    stride: usize,
+    line_len: usize,
+    lines: usize,
+}
+
+/// Describes the planes of a V4L2 buffer in FFmpeg plane order.
+fn planes(pixelformat: u32, width: usize, height: usize, stride: usize) -> Option<Vec<Plane>> {
+    let plane = |offset, stride, line_len, lines| Plane {
+        offset,
+        stride,
+        line_len,
+        lines,
+    };
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+
+    let planes = match pixelformat {
+        V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => {
+            let stride = stride.max(width);
+            vec![
+                plane(0, stride, width, height),
+                plane(stride * height, stride, cw * 2, ch),
+            ]
+        }
+        V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => {
+            let stride = stride.max(width);
+            let first = plane(stride * height, stride / 2, cw, ch);
+            let second = plane(stride * height + stride / 2 * ch, stride / 2, cw, ch);
+            let luma = plane(0, stride, width, height);
+            if pixelformat == V4L2_PIX_FMT_YUV420 {
+                vec![luma, first, second]
+            } else {
+                vec![luma, second, first]
+            }
+        }
+        _ => {
+            let bpp = match pixelformat {
+                V4L2_PIX_FMT_YUYV => 2,
+                V4L2_PIX_FMT_RGB24 | V4L2_PIX_FMT_BGR24 => 3,
+                V4L2_PIX_FMT_ABGR32 | V4L2_PIX_FMT_XBGR32 => 4,
+                _ => {
+                    pixel_from_fourcc(pixelformat)?;
+                    1
+                }
+            };
+            vec![plane(0, stride.max(width * bpp), width * bpp, height)]
+        }
+    };
+
+    Some(planes)
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "unsupported pixel format")
+}
+
+fn too_short() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "buffer is too short")
+}
+
+/// Copies a frame into a newly allocated FFmpeg video frame.
+///
+/// The pts is the V4L2 timestamp in `time_base` units.
+pub fn to_video(frame: &Frame) -> io::Result<Video> {
+    let pixel = pixel_from_fourcc(frame.pixel_format()).ok_or_else(unsupported)?;
+    let width = frame.width() as usize;
+    let height = frame.height() as usize;
+    let planes = planes(
+        frame.pixel_format(),
+        width,
+        height,
+        frame.bytes_per_line() as usize,
+    )
+    .ok_or_else(unsupported)?;
+
+    let src = frame.data();
+    let mut video = Video::new(pixel, frame.width(), frame.height());
+    for (i, p) in planes.iter().enumerate() {
+        if p.lines > 0 && src.len() < p.offset + p.stride * (p.lines - 1) + p.line_len {
+            return Err(too_short());
+        }
+        let dst_stride = video.stride(i);
+        let dst = video.data_mut(i);
+        for y in 0..p.lines {
+            let s = p.offset + y * p.stride;
+            dst[y * dst_stride..y * dst_stride + p.line_len]
+                .copy_from_slice(&src[s..s + p.line_len]);
+        }
+    }
+
+    video.set_pts(Some(frame.timestamp().as_micros() as i64));
+    Ok(video)
+}
+
+/// Copies an FFmpeg video frame into a V4L2 buffer laid out with the given
+/// line stride, e.g. to queue it on an output device.
+///
+/// Returns the V4L2 pixel format of the data and the number of bytes
+/// written.
+pub fn copy_from_video(video: &Video, stride: usize, dst: &mut [u8]) -> io::Result<(u32, usize)> {
+    let pixelformat = fourcc_from_pixel(video.format()).ok_or_else(unsupported)?;
+    let width = video.width() as usize;
+    let height = video.height() as usize;
+    let planes = planes(pixelformat, width, height, stride).ok_or_else(unsupported)?;
+
+    let mut used = 0;
+    for (i, p) in planes.iter().enumerate() {
+        if p.lines == 0 {
+            continue;
+        }
+        let end = p.offset + p.stride * (p.lines - 1) + p.line_len;
+        if dst.len() < end {
+            return Err(too_short());
+        }
+        let src_stride = video.stride(i);
+        let src = video.data(i);
+        for y in 0..p.lines {
+            let d = p.offset + y * p.stride;
+            dst[d..d + p.line_len]
+                .copy_from_slice(&src[y * src_stride..y * src_stride + p.line_len]);
+        }
+        used = used.max((p.offset + p.stride * p.lines).min(dst.len()));
+    }
+
+    Ok((pixelformat, used))
+}
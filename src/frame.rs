@@ -0,0 +1,434 @@
+#[cfg(any(feature = "jpeg", feature = "ndarray", feature = "opencv"))]
+use std::io;
+#[cfg(feature = "opencv")]
+use std::marker::PhantomData;
+use std::mem;
+#[cfg(feature = "opencv")]
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::capture::BufferMemory;
+use crate::dmabuf::{DmabufBuffer, DmabufPlaneDescriptor};
+use crate::sys::uapi::*;
+use crate::sys::QueueHandle;
+
+/// Returns the number of bytes per pixel of a single plane, packed pixel
+/// format, or `None` for planar and compressed formats.
+#[cfg(any(feature = "ndarray", feature = "opencv"))]
+pub(crate) fn packed_channels(pixelformat: u32) -> Option<usize> {
+    match pixelformat {
+        V4L2_PIX_FMT_GREY | V4L2_PIX_FMT_SBGGR8 | V4L2_PIX_FMT_SGBRG8 | V4L2_PIX_FMT_SGRBG8
+        | V4L2_PIX_FMT_SRGGB8 => Some(1),
+        V4L2_PIX_FMT_YUYV => Some(2),
+        V4L2_PIX_FMT_RGB24 | V4L2_PIX_FMT_BGR24 => Some(3),
+        V4L2_PIX_FMT_ABGR32 | V4L2_PIX_FMT_XBGR32 => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns the driver timestamp of `buf`.
+pub(crate) fn buffer_timestamp(buf: &v4l2_buffer) -> Duration {
+    let tv = &buf.timestamp;
+    Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+/// A dequeued frame borrowed from the buffers of a `Capture`, together with
+/// the format it was captured in.
+///
+/// The buffer must be given back with `Capture::return_frame(frame.buffer())`
+/// once the data is no longer needed.
+pub struct Frame<'a> {
+    buffer: v4l2_buffer,
+    data: &'a [u8],
+    format: v4l2_pix_format,
+    dmabuf: Option<&'a DmabufBuffer>,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(buffer: v4l2_buffer, data: &'a [u8], format: v4l2_pix_format) -> Frame<'a> {
+        Frame {
+            buffer,
+            data,
+            format,
+            dmabuf: None,
+        }
+    }
+
+    pub(crate) fn with_dmabuf(mut self, dmabuf: Option<&'a DmabufBuffer>) -> Frame<'a> {
+        self.dmabuf = dmabuf;
+        self
+    }
+
+    /// Returns the valid part of the buffer, as reported by `bytesused`.
+    pub fn data(&self) -> &'a [u8] {
+        let len = (self.buffer.bytesused as usize).min(self.data.len());
+        &self.data[..len]
+    }
+
+    pub fn buffer(&self) -> &v4l2_buffer {
+        &self.buffer
+    }
+
+    pub fn format(&self) -> &v4l2_pix_format {
+        &self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.format.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.format.height
+    }
+
+    pub fn pixel_format(&self) -> u32 {
+        self.format.pixelformat
+    }
+
+    pub fn bytes_per_line(&self) -> u32 {
+        self.format.bytesperline
+    }
+
+    /// Returns the index of the buffer holding the frame.
+    pub fn index(&self) -> usize {
+        self.buffer.index as usize
+    }
+
+    pub fn sequence(&self) -> u32 {
+        self.buffer.sequence
+    }
+
+    /// Returns the `V4L2_BUF_FLAG_*` flags of the buffer.
+    pub fn flags(&self) -> u32 {
+        self.buffer.flags
+    }
+
+    /// Returns the driver timestamp of the frame.
+    pub fn timestamp(&self) -> Duration {
+        buffer_timestamp(&self.buffer)
+    }
+
+    /// Returns the timecode of the frame, if the driver set one.
+    pub fn timecode(&self) -> Option<&v4l2_timecode> {
+        if self.buffer.flags & V4L2_BUF_FLAG_TIMECODE != 0 {
+            Some(&self.buffer.timecode)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the dmabuf planes of the frame, in the shape
+    /// `EGL_EXT_image_dma_buf_import` expects, if the buffers of the capture
+    /// were exported with `Capture::export_dmabufs`.
+    pub fn as_dmabuf_plane_descriptors(&self) -> Option<Vec<DmabufPlaneDescriptor>> {
+        self.dmabuf.map(DmabufBuffer::plane_descriptors)
+    }
+
+    /// Returns the exported dmabuf holding the frame, see
+    /// `as_dmabuf_plane_descriptors`.
+    pub fn dmabuf(&self) -> Option<&'a DmabufBuffer> {
+        self.dmabuf
+    }
+
+    /// Returns a zero-copy height x width x channels view of a packed pixel
+    /// frame, honouring the line stride.
+    ///
+    /// Channels are bytes in memory order, e.g. Y0/U and Y1/V pairs for
+    /// YUYV or B, G, R for BGR24.
+    #[cfg(feature = "ndarray")]
+    pub fn as_array(&self) -> io::Result<ndarray::ArrayView3<'a, u8>> {
+        use ndarray::ShapeBuilder;
+
+        let channels = packed_channels(self.pixel_format()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "not a packed pixel format")
+        })?;
+        let width = self.width() as usize;
+        let height = self.height() as usize;
+        let stride = (self.bytes_per_line() as usize).max(width * channels);
+
+        let data = self.data();
+        let len = if height == 0 {
+            0
+        } else {
+            stride * (height - 1) + width * channels
+        };
+        if data.len() < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "frame data is too short",
+            ));
+        }
+
+        let shape = (height, width, channels).strides((stride, channels, 1));
+        ndarray::ArrayView3::from_shape(shape, &data[..len])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Returns an OpenCV `Mat` header over the frame data, without copying.
+    ///
+    /// Packed formats map to `CV_8UC1`..`CV_8UC4` matrices. NV12, NV21,
+    /// YUV420 and YVU420 frames map to a single channel matrix of
+    /// `height * 3 / 2` rows, the layout `imgproc::cvt_color` expects for
+    /// those formats. The matrix must not be written to.
+    #[cfg(feature = "opencv")]
+    pub fn as_mat(&self) -> io::Result<MatRef<'a>> {
+        use opencv::core::{Mat, CV_8UC1, CV_8UC2, CV_8UC3, CV_8UC4};
+
+        let width = self.width() as usize;
+        let height = self.height() as usize;
+        let (rows, cols, typ, bpp) = match self.pixel_format() {
+            V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 | V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => {
+                (height * 3 / 2, width, CV_8UC1, 1)
+            }
+            fmt => {
+                let channels = packed_channels(fmt).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "unsupported pixel format")
+                })?;
+                let typ = [CV_8UC1, CV_8UC2, CV_8UC3, CV_8UC4][channels - 1];
+                (height, width, typ, channels)
+            }
+        };
+        let step = (self.bytes_per_line() as usize).max(cols * bpp);
+
+        let data = self.data();
+        if rows == 0 || cols == 0 || data.len() < step * (rows - 1) + cols * bpp {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "frame data is too short",
+            ));
+        }
+
+        // The header borrows `data` for 'a, which `MatRef` carries along.
+        let mat = unsafe {
+            Mat::new_rows_cols_with_data_unsafe(
+                rows as i32,
+                cols as i32,
+                typ,
+                data.as_ptr() as *mut _,
+                step,
+            )
+        }
+        .map_err(io::Error::other)?;
+
+        Ok(MatRef {
+            mat,
+            _data: PhantomData,
+        })
+    }
+
+    /// Copies the frame into an owned OpenCV `Mat`, see `as_mat`.
+    #[cfg(feature = "opencv")]
+    pub fn to_mat(&self) -> io::Result<opencv::core::Mat> {
+        use opencv::prelude::*;
+
+        self.as_mat()?.try_clone().map_err(io::Error::other)
+    }
+
+    /// Decodes the frame to tightly packed RGB24 pixels.
+    ///
+    /// MJPEG/JPEG frames are decoded with a pure Rust decoder, after inserting
+    /// the standard Huffman tables if they are missing. Other formats are
+    /// converted with `convert::to_rgb24`.
+    #[cfg(feature = "jpeg")]
+    pub fn decode_rgb(&self) -> io::Result<Vec<u8>> {
+        use jpeg_decoder::{Decoder, PixelFormat};
+
+        match self.pixel_format() {
+            V4L2_PIX_FMT_MJPEG | V4L2_PIX_FMT_JPEG => {
+                let jpeg = crate::mjpeg::fix_huffman_tables(self.data());
+                let mut decoder = Decoder::new(&jpeg[..]);
+                let pixels = decoder
+                    .decode()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+                match decoder.info().map(|info| info.pixel_format) {
+                    Some(PixelFormat::RGB24) => Ok(pixels),
+                    Some(PixelFormat::L8) => Ok(pixels.iter().flat_map(|&l| [l, l, l]).collect()),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unsupported JPEG color type",
+                    )),
+                }
+            }
+            _ => crate::convert::to_rgb24(self),
+        }
+    }
+}
+
+/// An OpenCV `Mat` borrowing the data of a `Frame`.
+#[cfg(feature = "opencv")]
+pub struct MatRef<'a> {
+    mat: opencv::core::Mat,
+    _data: PhantomData<&'a [u8]>,
+}
+
+#[cfg(feature = "opencv")]
+impl<'a> Deref for MatRef<'a> {
+    type Target = opencv::core::Mat;
+
+    fn deref(&self) -> &opencv::core::Mat {
+        &self.mat
+    }
+}
+
+/// Hands the buffers of dropped owned frames back to their capture.
+///
+/// While the capture streams from a device with a `QueueHandle`, the
+/// thread dropping a frame queues its buffer and the capture only takes
+/// note. Otherwise the capture queues the buffer itself.
+#[derive(Default)]
+pub(crate) struct Recycler(Mutex<Recycled>);
+
+#[derive(Default)]
+struct Recycled {
+    /* Bumped whenever buffers are prepared, to drop stale owned frames */
+    generation: u32,
+    /* Set while streaming */
+    handle: Option<QueueHandle>,
+    /* Buffers queued by dropped frames */
+    queued: Vec<usize>,
+    /* Buffers the capture still has to queue */
+    returned: Vec<usize>,
+}
+
+impl Recycler {
+    fn lock(&self) -> MutexGuard<'_, Recycled> {
+        // The lists stay consistent even if a queue handle panicked.
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn generation(&self) -> u32 {
+        self.lock().generation
+    }
+
+    /// Forgets the buffers of frames dequeued so far, for newly prepared
+    /// buffers.
+    pub(crate) fn next_generation(&self) {
+        let mut recycled = self.lock();
+        recycled.generation = recycled.generation.wrapping_add(1);
+        recycled.handle = None;
+        recycled.queued.clear();
+        recycled.returned.clear();
+    }
+
+    /// Sets the handle buffers are queued with, `None` while not
+    /// streaming.
+    pub(crate) fn set_handle(&self, handle: Option<QueueHandle>) {
+        self.lock().handle = handle;
+    }
+
+    pub(crate) fn take_queued(&self) -> Vec<usize> {
+        mem::take(&mut self.lock().queued)
+    }
+
+    pub(crate) fn take_returned(&self) -> Vec<usize> {
+        mem::take(&mut self.lock().returned)
+    }
+
+    fn recycle(&self, generation: u32, buffer: &v4l2_buffer) {
+        let mut recycled = self.lock();
+        if recycled.generation != generation {
+            return;
+        }
+
+        let mut buf = v4l2_buffer::new(buffer.typ, buffer.memory);
+        buf.index = buffer.index;
+        buf.m = buffer.m;
+        buf.length = buffer.length;
+        // Queued under the lock, so that a capture dequeuing the buffer
+        // again learns of it first.
+        let queued = match recycled.handle {
+            Some(ref queue) => queue(&buf).is_ok(),
+            None => false,
+        };
+        let index = buffer.index as usize;
+        if queued {
+            recycled.queued.push(index);
+        } else {
+            recycled.returned.push(index);
+        }
+    }
+}
+
+/// A dequeued frame that shares ownership of its buffer, see
+/// `Capture::dequeue_owned_frame`.
+///
+/// The buffer is queued again when the frame is dropped.
+pub struct OwnedFrame {
+    buffer: v4l2_buffer,
+    data: Option<Arc<BufferMemory>>,
+    format: v4l2_pix_format,
+    generation: u32,
+    recycler: Arc<Recycler>,
+}
+
+// The raw pointers in `v4l2_buffer` and `v4l2_pix_format` are never
+// dereferenced, and the mapping is only written after the frame is gone.
+unsafe impl Send for OwnedFrame {}
+
+impl OwnedFrame {
+    pub(crate) fn new(
+        buffer: v4l2_buffer,
+        data: Arc<BufferMemory>,
+        format: v4l2_pix_format,
+        recycler: Arc<Recycler>,
+    ) -> OwnedFrame {
+        OwnedFrame {
+            buffer,
+            data: Some(data),
+            format,
+            generation: recycler.generation(),
+            recycler,
+        }
+    }
+
+    /// Returns a borrowed view, for the accessors and conversions of
+    /// `Frame`.
+    pub fn as_frame(&self) -> Frame<'_> {
+        let data = self.data.as_ref().expect("data is only taken on drop");
+        Frame::new(self.buffer.clone(), &data[..], self.format)
+    }
+
+    /// Returns the valid part of the buffer, as reported by `bytesused`.
+    pub fn data(&self) -> &[u8] {
+        let data = self.data.as_ref().expect("data is only taken on drop");
+        let len = (self.buffer.bytesused as usize).min(data.len());
+        &data[..len]
+    }
+
+    pub fn buffer(&self) -> &v4l2_buffer {
+        &self.buffer
+    }
+
+    pub fn format(&self) -> &v4l2_pix_format {
+        &self.format
+    }
+
+    /// Returns the index of the buffer holding the frame.
+    pub fn index(&self) -> usize {
+        self.buffer.index as usize
+    }
+
+    pub fn sequence(&self) -> u32 {
+        self.buffer.sequence
+    }
+
+    /// Returns the `V4L2_BUF_FLAG_*` flags of the buffer.
+    pub fn flags(&self) -> u32 {
+        self.buffer.flags
+    }
+
+    pub fn timestamp(&self) -> Duration {
+        buffer_timestamp(&self.buffer)
+    }
+}
+
+impl Drop for OwnedFrame {
+    fn drop(&mut self) {
+        // Drops the mapping first, so that the capture holds the only
+        // reference again when the buffer is queued.
+        self.data = None;
+        self.recycler.recycle(self.generation, &self.buffer);
+    }
+}
@@ -0,0 +1,127 @@
+//! GStreamer interop: caps and `VideoInfo` for negotiated formats, and
+//! frames wrapped as `gst::Buffer`s for `appsrc` based pipelines.
+
+use std::io;
+
+use gstreamer as gst;
+use gstreamer_video as gst_video;
+
+use crate::frame::Frame;
+use crate::sys::uapi::*;
+
+/// Returns the GStreamer raw video format matching a V4L2 pixel format.
+pub fn video_format(pixelformat: u32) -> Option<gst_video::VideoFormat> {
+    use gst_video::VideoFormat;
+
+    match pixelformat {
+        V4L2_PIX_FMT_YUYV => Some(VideoFormat::Yuy2),
+        V4L2_PIX_FMT_NV12 => Some(VideoFormat::Nv12),
+        V4L2_PIX_FMT_NV21 => Some(VideoFormat::Nv21),
+        V4L2_PIX_FMT_YUV420 => Some(VideoFormat::I420),
+        V4L2_PIX_FMT_YVU420 => Some(VideoFormat::Yv12),
+        V4L2_PIX_FMT_GREY => Some(VideoFormat::Gray8),
+        V4L2_PIX_FMT_RGB24 => Some(VideoFormat::Rgb),
+        V4L2_PIX_FMT_BGR24 => Some(VideoFormat::Bgr),
+        V4L2_PIX_FMT_ABGR32 => Some(VideoFormat::Bgra),
+        V4L2_PIX_FMT_XBGR32 => Some(VideoFormat::Bgrx),
+        _ => None,
+    }
+}
+
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "unsupported pixel format")
+}
+
+/// Returns the plane strides and offsets of a single-planar V4L2 buffer.
+fn layout(format: &v4l2_pix_format) -> (Vec<i32>, Vec<usize>) {
+    let height = format.height as usize;
+    let stride = format.bytesperline as usize;
+    let chroma_lines = height.div_ceil(2);
+
+    match format.pixelformat {
+        V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => (vec![stride as i32; 2], vec![0, stride * height]),
+        V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 => {
+            let chroma = stride / 2;
+            (
+                vec![stride as i32, chroma as i32, chroma as i32],
+                vec![0, stride * height, stride * height + chroma * chroma_lines],
+            )
+        }
+        _ => (vec![stride as i32], vec![0]),
+    }
+}
+
+/// Builds the `VideoInfo` of a negotiated raw format, including the driver
+/// strides and plane offsets.
+pub fn video_info(
+    format: &v4l2_pix_format,
+    time_per_frame: v4l2_fract,
+) -> io::Result<gst_video::VideoInfo> {
+    let video_format = video_format(format.pixelformat).ok_or_else(unsupported)?;
+    let (strides, offsets) = layout(format);
+
+    let mut builder = gst_video::VideoInfo::builder(video_format, format.width, format.height)
+        .fps(fps(time_per_frame));
+    if format.bytesperline != 0 {
+        builder = builder.stride(&strides).offset(&offsets);
+    }
+    match format.field {
+        v4l2_field::V4L2_FIELD_INTERLACED
+        | v4l2_field::V4L2_FIELD_INTERLACED_TB
+        | v4l2_field::V4L2_FIELD_INTERLACED_BT => {
+            builder = builder.interlace_mode(gst_video::VideoInterlaceMode::Interleaved);
+        }
+        _ => {}
+    }
+
+    builder.build().map_err(io::Error::other)
+}
+
+fn fps(time_per_frame: v4l2_fract) -> gst::Fraction {
+    if time_per_frame.numerator == 0 {
+        gst::Fraction::new(0, 1)
+    } else {
+        gst::Fraction::new(
+            time_per_frame.denominator as i32,
+            time_per_frame.numerator as i32,
+        )
+    }
+}
+
+/// Returns the caps of a negotiated format: `video/x-raw` for raw formats,
+/// `image/jpeg` for MJPEG/JPEG and byte-stream `video/x-h264` for H.264.
+pub fn caps(format: &v4l2_pix_format, time_per_frame: v4l2_fract) -> io::Result<gst::Caps> {
+    let compressed = |media_type| {
+        gst::Caps::builder(media_type)
+            .field("width", format.width as i32)
+            .field("height", format.height as i32)
+            .field("framerate", fps(time_per_frame))
+    };
+
+    match format.pixelformat {
+        V4L2_PIX_FMT_MJPEG | V4L2_PIX_FMT_JPEG => Ok(compressed("image/jpeg").build()),
+        V4L2_PIX_FMT_H264 => Ok(compressed("video/x-h264")
+            .field("stream-format", "byte-stream")
+            .field("alignment", "au")
+            .build()),
+        _ => video_info(format, time_per_frame)?
+            .to_caps()
+            .map_err(io::Error::other),
+    }
+}
+
+/// Copies a frame into a `gst::Buffer`, so that the V4L2 buffer can be
+/// requeued right away.
+///
+/// The pts is the V4L2 timestamp and the offset the frame sequence number.
+pub fn to_buffer(frame: &Frame) -> gst::Buffer {
+    let mut buffer = gst::Buffer::from_mut_slice(frame.data().to_vec());
+    {
+        let buffer = buffer.get_mut().unwrap();
+        buffer.set_pts(gst::ClockTime::from_useconds(
+            frame.timestamp().as_micros() as u64
+        ));
+        buffer.set_offset(frame.sequence() as u64);
+    }
+    buffer
+}
@@ -0,0 +1,144 @@
+//! Passing device handles between processes.
+//!
+//! A privileged daemon can open and prepare a device, then hand the open
+//! file to a sandboxed process over a Unix socket with `SCM_RIGHTS`. The
+//! receiver shares the driver state of the sender's handle, including the
+//! requested buffers, so it only needs to map them again.
+
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::ptr;
+
+/* Upper bound for the payload of a handoff message */
+const MAX_PAYLOAD: usize = 64 * 1024;
+
+/// Sends `fd` over `socket`, together with `data`.
+pub fn send_fd(socket: &UnixStream, fd: BorrowedFd<'_>, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "handoff payload is too large",
+        ));
+    }
+
+    // The length prefix carries the descriptor; the rest may be split up
+    // by the stream.
+    let mut message = (data.len() as u32).to_le_bytes().to_vec();
+    message.extend_from_slice(data);
+
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<libc::c_int>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut iov = libc::iovec {
+        iov_base: message.as_mut_ptr() as *mut libc::c_void,
+        iov_len: message.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::c_int>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, fd.as_raw_fd());
+    }
+
+    let sent = loop {
+        match unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            n => break n as usize,
+        }
+    };
+
+    (&*socket).write_all(&message[sent..])
+}
+
+/// Receives a descriptor and its data sent with `send_fd`.
+pub fn recv_fd(socket: &UnixStream) -> io::Result<(OwnedFd, Vec<u8>)> {
+    let mut header = [0u8; 4];
+    let space = unsafe { libc::CMSG_SPACE(mem::size_of::<libc::c_int>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut iov = libc::iovec {
+        iov_base: header.as_mut_ptr() as *mut libc::c_void,
+        iov_len: header.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+
+    let received = loop {
+        match unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => break n as usize,
+        }
+    };
+
+    let mut fd = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let raw = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                fd = Some(OwnedFd::from_raw_fd(raw));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::other("handoff descriptor was truncated"));
+    }
+    let fd = fd.ok_or_else(|| io::Error::other("no descriptor in handoff message"))?;
+
+    (&*socket).read_exact(&mut header[received..])?;
+    let len = u32::from_le_bytes(header) as usize;
+    if len > MAX_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "handoff payload is too large",
+        ));
+    }
+    let mut data = vec![0u8; len];
+    (&*socket).read_exact(&mut data)?;
+
+    Ok((fd, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::os::unix::io::AsFd;
+
+    #[test]
+    fn fd_round_trip() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let (reader, writer) = UnixStream::pair().unwrap();
+
+        send_fd(&a, writer.as_fd(), b"payload").unwrap();
+        let (fd, data) = recv_fd(&b).unwrap();
+        assert_eq!(data, b"payload");
+
+        File::from(fd).write_all(b"x").unwrap();
+        let mut byte = [0u8; 1];
+        (&reader).read_exact(&mut byte).unwrap();
+        assert_eq!(&byte, b"x");
+    }
+}
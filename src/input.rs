@@ -0,0 +1,407 @@
+//! Input selection for grabbers with several analog or HDMI inputs.
+
+use std::fmt;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::capture::Capture;
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+/// How often the input status is polled while waiting for a signal.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Status bits of inputs without a usable signal.
+const NO_LOCK: u32 = V4L2_IN_ST_NO_POWER
+    | V4L2_IN_ST_NO_SIGNAL
+    | V4L2_IN_ST_NO_H_LOCK
+    | V4L2_IN_ST_NO_V_LOCK
+    | V4L2_IN_ST_NO_SYNC
+    | V4L2_IN_ST_NO_CARRIER;
+
+/// Status of a video input, as reported by `VIDIOC_ENUMINPUT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputStatus(u32);
+
+impl InputStatus {
+    /// Wraps the `V4L2_IN_ST_*` bits `status`.
+    pub fn from_raw(status: u32) -> InputStatus {
+        InputStatus(status)
+    }
+
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if the input has a signal the device is locked to.
+    pub fn has_signal(self) -> bool {
+        self.0 & NO_LOCK == 0
+    }
+
+    /// Returns true if the attached device is off.
+    pub fn no_power(self) -> bool {
+        self.0 & V4L2_IN_ST_NO_POWER != 0
+    }
+
+    pub fn no_signal(self) -> bool {
+        self.0 & V4L2_IN_ST_NO_SIGNAL != 0
+    }
+
+    /// Returns true if the device is not locked to the horizontal or
+    /// vertical sync of an analog, or the sync of a digital signal.
+    pub fn no_sync(self) -> bool {
+        self.0 & (V4L2_IN_ST_NO_H_LOCK | V4L2_IN_ST_NO_V_LOCK | V4L2_IN_ST_NO_SYNC) != 0
+    }
+
+    /// Returns true if no video standard was detected.
+    pub fn no_standard(self) -> bool {
+        self.0 & V4L2_IN_ST_NO_STD_LOCK != 0
+    }
+
+    pub fn no_color(self) -> bool {
+        self.0 & (V4L2_IN_ST_NO_COLOR | V4L2_IN_ST_COLOR_KILL) != 0
+    }
+
+    pub fn no_carrier(self) -> bool {
+        self.0 & V4L2_IN_ST_NO_CARRIER != 0
+    }
+}
+
+/// Formats the problems of the input, e.g. "no signal, no sync", or "ok".
+impl fmt::Display for InputStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems = [
+            (self.no_power(), "no power"),
+            (self.no_signal(), "no signal"),
+            (self.no_sync(), "no sync"),
+            (self.no_carrier(), "no carrier"),
+            (self.no_standard(), "no standard"),
+            (self.no_color(), "no color"),
+        ];
+        let mut first = true;
+        for &(_, problem) in problems.iter().filter(|&&(set, _)| set) {
+            if !first {
+                f.write_str(", ")?;
+            }
+            f.write_str(problem)?;
+            first = false;
+        }
+        if first {
+            f.write_str("ok")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the status of the selected input of `device`.
+pub fn input_status(device: &V4l2Device) -> io::Result<InputStatus> {
+    let index = device.input()?;
+    let input = device.enum_input(index as u32)?;
+    Ok(InputStatus::from_raw(input.status))
+}
+
+/// Waits up to `timeout` for the status of input `index` to report a
+/// locked signal, and returns whether it did. The input must be selected,
+/// as most drivers only track the status of the current input.
+pub fn wait_for_signal(device: &V4l2Device, index: u32, timeout: Duration) -> io::Result<bool> {
+    let start = Instant::now();
+    loop {
+        if InputStatus::from_raw(device.enum_input(index)?.status).has_signal() {
+            return Ok(true);
+        }
+        if start.elapsed() >= timeout {
+            return Ok(false);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Returns true if `std` tells 525 line (NTSC, PAL-M) and 625 line (PAL,
+/// SECAM) signals apart.
+fn is_detected(std: v4l2_std_id) -> bool {
+    std != V4L2_STD_UNKNOWN && (std & !V4L2_STD_525_60 == 0 || std & !V4L2_STD_625_50 == 0)
+}
+
+/// Returns true if `std` names 525-line, 60 field standards only. Masks
+/// such as `V4L2_STD_ALL`, which drivers report while the standard is
+/// ambiguous, are taken as 625/50.
+fn is_525_60(std: v4l2_std_id) -> bool {
+    std != 0 && std & !V4L2_STD_525_60 == 0
+}
+
+/// Waits up to `timeout` for the driver to detect the video standard of
+/// the current input, sets it and returns it. Fails with `TimedOut` if
+/// the standard was not detected in time.
+pub fn detect_standard(device: &V4l2Device, timeout: Duration) -> io::Result<v4l2_std_id> {
+    let start = Instant::now();
+    loop {
+        match device.query_standard() {
+            Ok(std) if is_detected(std) => {
+                device.set_standard(std)?;
+                return device.standard();
+            }
+            Ok(_) => {}
+            Err(ref e) if e.raw_os_error() == Some(libc::ENODATA) => {}
+            Err(e) => return Err(e),
+        }
+        if start.elapsed() >= timeout {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no video standard detected",
+            ));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Selects each input of `device` in turn and waits up to `timeout` for a
+/// signal on it. Returns the index of the first input with a signal,
+/// which stays selected, or `None` if no input has one. In that case, and
+/// on errors, the input selected before is restored.
+pub fn probe_inputs(device: &V4l2Device, timeout: Duration) -> io::Result<Option<u32>> {
+    let restore = RestoreInput {
+        device,
+        previous: Some(device.input()?),
+    };
+    let indices: Vec<u32> = device.inputs().map(|input| input.index).collect();
+
+    for index in indices {
+        device.set_input(index as i32)?;
+        if wait_for_signal(device, index, timeout)? {
+            restore.keep();
+            return Ok(Some(index));
+        }
+    }
+
+    restore.restore()?;
+    Ok(None)
+}
+
+/// Selects the input that was selected before when dropped, unless told
+/// to keep the current one.
+struct RestoreInput<'a> {
+    device: &'a V4l2Device,
+    previous: Option<i32>,
+}
+
+impl<'a> RestoreInput<'a> {
+    fn keep(mut self) {
+        self.previous = None;
+    }
+
+    fn restore(mut self) -> io::Result<()> {
+        match self.previous.take() {
+            Some(previous) => self.device.set_input(previous),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Drop for RestoreInput<'a> {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            let _ = self.device.set_input(previous);
+        }
+    }
+}
+
+/// Adds the setup `stage` that failed to `err`.
+fn stage_error(stage: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", stage, err))
+}
+
+/// Setup of an analog capture card, in the order drivers require: input,
+/// video standard, format, then streaming parameters.
+pub struct TvSetup {
+    input: Option<u32>,
+    standard: Option<v4l2_std_id>,
+    pixelformat: u32,
+    size: Option<(u32, u32)>,
+    timeout: Duration,
+}
+
+/// The configuration `TvSetup` applied.
+#[derive(Clone, Copy)]
+pub struct TvConfig {
+    pub input: u32,
+    pub standard: v4l2_std_id,
+    pub format: v4l2_pix_format,
+    pub time_per_frame: v4l2_fract,
+}
+
+impl TvSetup {
+    /// Captures full interlaced frames in `pixelformat` from the first
+    /// input with a signal, in the standard the driver detects.
+    pub fn new(pixelformat: u32) -> TvSetup {
+        TvSetup {
+            input: None,
+            standard: None,
+            pixelformat,
+            size: None,
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Uses input `index` instead of probing the inputs.
+    pub fn input(mut self, index: u32) -> Self {
+        self.input = Some(index);
+        self
+    }
+
+    /// Sets `std` instead of detecting the standard.
+    pub fn standard(mut self, std: v4l2_std_id) -> Self {
+        self.standard = Some(std);
+        self
+    }
+
+    /// Scales frames to `width` x `height`, instead of the 720 x 480 or
+    /// 720 x 576 of the standard.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// Sets how long to wait for a signal on each input and for the
+    /// standard to be detected.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Configures `device`. Errors tell the stage that failed.
+    pub fn apply(&self, device: &V4l2Device) -> io::Result<TvConfig> {
+        let input = self
+            .select_input(device)
+            .map_err(|e| stage_error("selecting input", e))?;
+        let standard = self
+            .set_standard(device, input)
+            .map_err(|e| stage_error("setting video standard", e))?;
+        let is_525_60 = is_525_60(standard);
+
+        let mut format = device
+            .capture_format()
+            .map_err(|e| stage_error("getting format", e))?;
+        let (width, height) = self
+            .size
+            .unwrap_or((720, if is_525_60 { 480 } else { 576 }));
+        format.width = width;
+        format.height = height;
+        format.pixelformat = self.pixelformat;
+        format.field = v4l2_field::V4L2_FIELD_INTERLACED;
+        let format = device
+            .set_capture_format(&format)
+            .map_err(|e| stage_error("setting format", e))?;
+
+        let frame_period = if is_525_60 {
+            v4l2_fract {
+                numerator: 1001,
+                denominator: 30000,
+            }
+        } else {
+            v4l2_fract {
+                numerator: 1,
+                denominator: 25,
+            }
+        };
+        let set_parm = || {
+            let mut parm = device.capture_parm()?;
+            if parm.capability & V4L2_CAP_TIMEPERFRAME != 0 {
+                parm.timeperframe = frame_period;
+            }
+            parm.capturemode = 0;
+            device.set_capture_parm(&parm)
+        };
+        // Many analog grabbers have a fixed frame rate and no G/S_PARM.
+        let time_per_frame = match set_parm() {
+            Ok(parm) => parm.timeperframe,
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOTTY) => frame_period,
+            Err(e) => return Err(stage_error("setting streaming parameters", e)),
+        };
+
+        Ok(TvConfig {
+            input,
+            standard,
+            format,
+            time_per_frame,
+        })
+    }
+
+    /// Opens the device at `path`, configures it and returns it as a
+    /// capture without buffers.
+    pub fn open(&self, path: &str) -> io::Result<Capture> {
+        let device = V4l2Device::open(path)?;
+        self.apply(&device)?;
+        Capture::from_device(device, path)
+    }
+
+    fn select_input(&self, device: &V4l2Device) -> io::Result<u32> {
+        let index = match self.input {
+            Some(index) => {
+                device.set_input(index as i32)?;
+                if !wait_for_signal(device, index, self.timeout)? {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no signal on input {}", index),
+                    ));
+                }
+                index
+            }
+            None => probe_inputs(device, self.timeout)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no input has a signal"))?,
+        };
+
+        if device.enum_input(index)?.capabilities & V4L2_IN_CAP_STD == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("input {} does not support video standards", index),
+            ));
+        }
+        Ok(index)
+    }
+
+    fn set_standard(&self, device: &V4l2Device, input: u32) -> io::Result<v4l2_std_id> {
+        match self.standard {
+            Some(std) => {
+                if device.enum_input(input)?.std & std == 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "input does not support the standard",
+                    ));
+                }
+                device.set_standard(std)?;
+                device.standard()
+            }
+            None => detect_standard(device, self.timeout),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_input_status() {
+        let ok = InputStatus::from_raw(V4L2_IN_ST_MACROVISION);
+        assert!(ok.has_signal());
+        assert_eq!(ok.to_string(), "ok");
+
+        let lost = InputStatus::from_raw(V4L2_IN_ST_NO_SIGNAL | V4L2_IN_ST_NO_H_LOCK);
+        assert!(!lost.has_signal());
+        assert_eq!(lost.to_string(), "no signal, no sync");
+    }
+
+    #[test]
+    fn tells_line_standards_apart() {
+        assert!(is_detected(V4L2_STD_PAL_BG));
+        assert!(is_detected(V4L2_STD_NTSC_M));
+        assert!(!is_detected(V4L2_STD_UNKNOWN));
+        assert!(!is_detected(V4L2_STD_ALL));
+
+        assert!(is_525_60(V4L2_STD_NTSC_M | V4L2_STD_PAL_M));
+        assert!(!is_525_60(V4L2_STD_PAL_BG));
+        assert!(!is_525_60(V4L2_STD_ALL));
+        assert!(!is_525_60(V4L2_STD_UNKNOWN));
+    }
+}
@@ -3,14 +3,111 @@ extern crate nix;
 
 pub mod sys {
     mod device;
+    mod event;
+    mod frame_size;
+    mod info;
+    pub mod ioctl;
+    mod ops;
+    mod request;
+    pub mod stateless;
     pub mod uapi;
+    mod window;
 
-    pub use self::device::V4l2Device;
+    #[cfg(feature = "async")]
+    pub use self::device::AsyncEvents;
+    pub(crate) use self::device::{cvt, poll_events, poll_millis};
+    pub use self::device::{Events, OpenOptions, V4l2Device};
+    pub use self::event::Event;
+    pub use self::frame_size::{FrameInterval, FrameSize};
+    pub use self::info::{ControlInfo, DeviceInfo, FormatDesc, FrameSizeDesc, Probe, QueueFormats};
+    pub(crate) use self::ops::{DeviceOps, QueueHandle};
+    pub use self::request::MediaRequest;
+    pub use self::window::{bitmap_len, RawWindow, Window};
 }
 
+pub mod sink {
+    mod h264;
+    mod recorder;
+    mod y4m;
+
+    pub use self::h264::H264Writer;
+    pub use self::recorder::PreRollRecorder;
+    pub use self::y4m::Y4mWriter;
+}
+
+#[cfg(feature = "bcm2835-isp")]
+pub mod bcm2835_isp;
+pub mod bracket;
+pub mod buffer_pool;
 mod capture;
+mod capture_set;
+mod config;
+pub mod convert;
+#[cfg(feature = "debayer")]
+pub mod debayer;
+pub mod deinterlace;
+mod device_id;
+pub mod dmabuf;
+pub mod drm;
+mod encoder;
+#[cfg(feature = "testing")]
+pub mod faulty;
+#[cfg(feature = "ffmpeg")]
+pub mod ffmpeg;
+mod frame;
+#[cfg(feature = "gstreamer")]
+pub mod gst;
+pub mod handoff;
+pub mod input;
+mod m2m;
+pub mod media;
+pub mod mjpeg;
+mod output;
+mod pipeline;
+pub mod recovery;
+pub mod repack;
+pub mod scale;
+mod stats;
+#[cfg(feature = "sunxi-vfe")]
+pub mod sunxi;
+pub mod typestate;
+pub mod userptr;
+mod virtual_camera;
+pub mod white_balance;
 
-pub use self::capture::Capture;
+pub use self::capture::{Builder, Capture, IoMethod, MapOptions, StreamState};
+pub use self::capture_set::CaptureSet;
+pub use self::config::CaptureConfig;
+pub use self::device_id::DeviceId;
+pub use self::encoder::{
+    Encoder, GoldenFrame, H264Level, H264Profile, RateControl, SliceMode, VpxPartitions, VpxProfile,
+};
+#[cfg(feature = "opencv")]
+pub use self::frame::MatRef;
+pub use self::frame::{Frame, OwnedFrame};
+pub use self::m2m::M2m;
+pub use self::output::Output;
+pub use self::pipeline::Pipeline;
+pub use self::stats::{BufferMapping, BufferUsage, CaptureStats};
+pub use self::virtual_camera::VirtualCamera;
+
+// Thread-safety guarantees of the public types, checked at compile time.
+#[allow(dead_code)]
+fn assert_thread_safety() {
+    fn send<T: Send>() {}
+    fn send_sync<T: Send + Sync>() {}
+
+    send_sync::<sys::V4l2Device>();
+    send_sync::<userptr::UserPtrBuffer>();
+    send::<Capture>();
+    send::<OwnedFrame>();
+    send::<Output>();
+    send::<M2m>();
+    send::<Encoder>();
+    send::<Pipeline>();
+    send::<VirtualCamera>();
+    send::<buffer_pool::BufferPool<'static>>();
+}
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use memmap::MmapMut;
+
+use crate::frame::Frame;
+use crate::output::output_buffer;
+use crate::stats::{BufferMapping, BufferUsage};
+use crate::sys::uapi::*;
+use crate::sys::{poll_events, V4l2Device};
+
+const INPUT: v4l2_buf_type = v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT;
+const RESULT: v4l2_buf_type = v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE;
+const MMAP: v4l2_memory = v4l2_memory::V4L2_MEMORY_MMAP;
+
+fn map_buffers(
+    device: &V4l2Device,
+    buf_type: v4l2_buf_type,
+    count: usize,
+) -> io::Result<Vec<MmapMut>> {
+    let buffers = device.map_buffers(buf_type)?;
+    if buffers.len() != count {
+        return Err(io::Error::other("failed to map all buffers"));
+    }
+    Ok(buffers)
+}
+
+/// A memory-to-memory device, such as a scaler, color converter or codec.
+///
+/// Frames are queued as input on the output queue of the device and the
+/// results dequeued from its capture queue. Input buffers are either mmap
+/// buffers filled by copying, or imported dmabufs.
+///
+/// Devices that copy timestamps (`copies_timestamps`) stamp each result
+/// with the timestamp of the input it was made from. Codecs may reorder or
+/// split frames, so inputs queued with `queue_input_tagged` are matched to
+/// their results by `result_tag`.
+pub struct M2m {
+    device: V4l2Device,
+    input_format: v4l2_pix_format,
+    output_format: v4l2_pix_format,
+    input_memory: v4l2_memory,
+    input_count: usize,
+    input_buffers: Vec<MmapMut>,
+    /* Mmap input buffers not queued to the driver */
+    input_free: VecDeque<usize>,
+    output_buffers: Vec<MmapMut>,
+    streaming: bool,
+}
+
+impl M2m {
+    /// Opens the device at `path`, converting frames in `input` format to
+    /// `output` format. The formats the driver chose are available with
+    /// `input_format` and `output_format`.
+    pub fn open(path: &str, input: &v4l2_pix_format, output: &v4l2_pix_format) -> io::Result<M2m> {
+        let device = V4l2Device::open(path)?;
+        let input_format = device.set_output_format(input)?;
+        let output_format = device.set_capture_format(output)?;
+
+        Ok(M2m {
+            device,
+            input_format,
+            output_format,
+            input_memory: MMAP,
+            input_count: 0,
+            input_buffers: Vec::new(),
+            input_free: VecDeque::new(),
+            output_buffers: Vec::new(),
+            streaming: false,
+        })
+    }
+
+    /// Returns the underlying device, e.g. to set controls.
+    pub fn device(&self) -> &V4l2Device {
+        &self.device
+    }
+
+    pub fn input_format(&self) -> &v4l2_pix_format {
+        &self.input_format
+    }
+
+    pub fn output_format(&self) -> &v4l2_pix_format {
+        &self.output_format
+    }
+
+    /// Returns true if input is passed as dmabufs.
+    pub fn imports_dmabufs(&self) -> bool {
+        self.input_memory == v4l2_memory::V4L2_MEMORY_DMABUF
+    }
+
+    /// Returns the number of input buffers or dmabuf slots the driver
+    /// granted, which may be fewer than requested.
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    /// Prepares `input_count` mmap input buffers and `output_count` result
+    /// buffers.
+    pub fn prepare_mmapped(&mut self, input_count: usize, output_count: usize) -> io::Result<()> {
+        self.prepare(MMAP, input_count, output_count)
+    }
+
+    /// Prepares `input_count` input slots for dmabufs, fed with
+    /// `queue_input_dmabuf`, and `output_count` result buffers.
+    pub fn prepare_dmabuf_input(
+        &mut self,
+        input_count: usize,
+        output_count: usize,
+    ) -> io::Result<()> {
+        self.prepare(v4l2_memory::V4L2_MEMORY_DMABUF, input_count, output_count)
+    }
+
+    fn prepare(
+        &mut self,
+        input_memory: v4l2_memory,
+        input_count: usize,
+        output_count: usize,
+    ) -> io::Result<()> {
+        self.unprepare();
+
+        self.input_count = self
+            .device
+            .request_buffers(INPUT, input_memory, input_count)?;
+        self.input_memory = input_memory;
+        if input_memory == MMAP {
+            self.input_buffers = map_buffers(&self.device, INPUT, self.input_count)?;
+        }
+
+        let n = self.device.request_buffers(RESULT, MMAP, output_count)?;
+        self.output_buffers = map_buffers(&self.device, RESULT, n)?;
+        Ok(())
+    }
+
+    /// Returns the memory held by the input buffers. Imported dmabufs are
+    /// listed with size 0.
+    pub fn input_buffer_usage(&self) -> BufferUsage {
+        if self.imports_dmabufs() {
+            return BufferUsage::single_planar(
+                INPUT,
+                BufferMapping::Imported,
+                std::iter::repeat_n(0, self.input_count),
+            );
+        }
+        BufferUsage::single_planar(
+            INPUT,
+            BufferMapping::Driver,
+            self.input_buffers.iter().map(|buffer| buffer.len()),
+        )
+    }
+
+    /// Returns the memory held by the result buffers.
+    pub fn output_buffer_usage(&self) -> BufferUsage {
+        BufferUsage::single_planar(
+            RESULT,
+            BufferMapping::Driver,
+            self.output_buffers.iter().map(|buffer| buffer.len()),
+        )
+    }
+
+    /// Unmaps the buffers and releases them in the driver.
+    pub fn unprepare(&mut self) {
+        self.input_buffers.clear();
+        self.input_free.clear();
+        self.output_buffers.clear();
+        let _ = self.device.request_buffers(INPUT, self.input_memory, 0);
+        let _ = self.device.request_buffers(RESULT, MMAP, 0);
+        self.input_count = 0;
+    }
+
+    /// Queues all result buffers and starts both queues.
+    pub fn start(&mut self) -> io::Result<()> {
+        let mut buf = v4l2_buffer::new(RESULT, MMAP);
+        for i in 0..self.output_buffers.len() {
+            buf.index = i as u32;
+            self.device.queue_buffer(&buf)?;
+        }
+
+        self.device.stream_on(INPUT)?;
+        self.device.stream_on(RESULT)?;
+        self.input_free = (0..self.input_buffers.len()).collect();
+        self.streaming = true;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.device.stream_off(INPUT)?;
+        self.device.stream_off(RESULT)?;
+        self.streaming = false;
+        Ok(())
+    }
+
+    /// Returns true between `start` and `stop`.
+    pub fn is_streaming(&self) -> bool {
+        self.streaming
+    }
+
+    /// Copies `data` into a free mmap input buffer and queues it. Blocks
+    /// until the device has consumed an input if all are queued.
+    pub fn queue_input(&mut self, data: &[u8], timestamp: Duration) -> io::Result<()> {
+        if self.input_memory != MMAP {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "input buffers are dmabuf imports",
+            ));
+        }
+
+        while poll_events(self.device.as_raw_fd(), libc::POLLOUT, Some(Instant::now()))?
+            & libc::POLLOUT
+            != 0
+        {
+            self.dequeue_input()?;
+        }
+        if self.input_free.is_empty() {
+            self.dequeue_input()?;
+        }
+        let index = self
+            .input_free
+            .pop_front()
+            .ok_or_else(|| io::Error::other("no input buffers prepared"))?;
+
+        let buffer = &mut self.input_buffers[index];
+        if data.len() > buffer.len() {
+            self.input_free.push_front(index);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame does not fit into an input buffer",
+            ));
+        }
+        buffer[..data.len()].copy_from_slice(data);
+
+        let buf = output_buffer(
+            INPUT,
+            MMAP,
+            index,
+            &self.input_format,
+            data.len() as u32,
+            timestamp,
+        );
+        self.device.queue_buffer(&buf)
+    }
+
+    /// Queues `data` like `queue_input`, stamped with `tag` for matching
+    /// the results made from it with `result_tag`.
+    pub fn queue_input_tagged(&mut self, data: &[u8], tag: u64) -> io::Result<()> {
+        self.queue_input(data, Duration::from_micros(tag))
+    }
+
+    /// Returns true if results carry the timestamp of their input
+    /// (`V4L2_BUF_FLAG_TIMESTAMP_COPY`). Buffers must be prepared.
+    pub fn copies_timestamps(&self) -> io::Result<bool> {
+        let buf = self.device.buffer(RESULT, MMAP, 0)?;
+        Ok(buf.flags & V4L2_BUF_FLAG_TIMESTAMP_MASK == V4L2_BUF_FLAG_TIMESTAMP_COPY)
+    }
+
+    /// Returns the timestamp of a result in microseconds. On a device
+    /// copying timestamps, this is the tag of the input the result was made
+    /// from when that was queued with `queue_input_tagged`; otherwise it is
+    /// just the capture time and not a tag.
+    pub fn result_tag(buf: &v4l2_buffer) -> u64 {
+        buf.timestamp.tv_sec as u64 * 1_000_000 + buf.timestamp.tv_usec as u64
+    }
+
+    /// Queues the dmabuf `fd`, holding `bytes_used` bytes of a `length`
+    /// bytes buffer, into input slot `index`. The dmabuf must not be
+    /// written until `dequeue_input` returns the slot.
+    pub fn queue_input_dmabuf(
+        &mut self,
+        index: usize,
+        fd: RawFd,
+        length: u32,
+        bytes_used: u32,
+        timestamp: Duration,
+    ) -> io::Result<()> {
+        if !self.imports_dmabufs() || index >= self.input_count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no such dmabuf input slot",
+            ));
+        }
+
+        let mut buf = output_buffer(
+            INPUT,
+            v4l2_memory::V4L2_MEMORY_DMABUF,
+            index,
+            &self.input_format,
+            bytes_used,
+            timestamp,
+        );
+        buf.m.fd = fd;
+        buf.length = length;
+        self.device.queue_buffer(&buf)
+    }
+
+    /// Dequeues an input buffer the device is done with, blocking until
+    /// there is one.
+    pub fn dequeue_input(&mut self) -> io::Result<v4l2_buffer> {
+        let buf = self.device.dequeue_buffer(INPUT, self.input_memory)?;
+        if self.input_memory == MMAP {
+            self.input_free.push_back(buf.index as usize);
+        }
+        Ok(buf)
+    }
+
+    /// Dequeues a result, blocking until there is one. It must be given
+    /// back with `return_output(frame.buffer())`.
+    pub fn dequeue_output(&self) -> io::Result<Frame<'_>> {
+        let buf = self.device.dequeue_buffer(RESULT, MMAP)?;
+        let data = &self.output_buffers[buf.index as usize][..];
+
+        Ok(Frame::new(buf, data, self.output_format))
+    }
+
+    pub fn return_output(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        self.device.queue_buffer(buf)
+    }
+}
+
+impl AsRawFd for M2m {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    fn tagged(tag: u64) -> v4l2_buffer {
+        // Zero is not a colorspace, so set one before assuming init.
+        let format = unsafe {
+            let mut format = mem::MaybeUninit::<v4l2_pix_format>::zeroed();
+            (*format.as_mut_ptr()).colorspace = v4l2_colorspace::V4L2_COLORSPACE_JPEG;
+            format.assume_init()
+        };
+        output_buffer(INPUT, MMAP, 0, &format, 0, Duration::from_micros(tag))
+    }
+
+    #[test]
+    fn result_tags_round_trip() {
+        for &tag in &[0, 1, 999_999, 1_000_000, 1_234_567_890, 1 << 40] {
+            assert_eq!(M2m::result_tag(&tagged(tag)), tag);
+        }
+    }
+
+    #[test]
+    fn result_tag_of_untagged_result_is_its_timestamp() {
+        let mut buf = v4l2_buffer::new(RESULT, MMAP);
+        buf.timestamp = libc::timeval {
+            tv_sec: 12,
+            tv_usec: 345,
+        };
+        assert_eq!(M2m::result_tag(&buf), 12_000_345);
+    }
+}
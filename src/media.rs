@@ -0,0 +1,184 @@
+//! Media controller topology, to find the devices that belong together
+//! with a video node, like the sound card of a USB grabber.
+
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+/// Returns the interfaces of the media graph of the media device `fd`.
+pub fn interfaces<F: AsRawFd>(fd: &F) -> io::Result<Vec<media_v2_interface>> {
+    loop {
+        let mut topology: media_v2_topology = unsafe { mem::zeroed() };
+        if unsafe { libc::ioctl(fd.as_raw_fd(), MEDIA_IOC_G_TOPOLOGY, &mut topology) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let version = topology.topology_version;
+
+        let mut interfaces: Vec<media_v2_interface> =
+            vec![unsafe { mem::zeroed() }; topology.num_interfaces as usize];
+        topology.ptr_interfaces = interfaces.as_mut_ptr() as u64;
+        if unsafe { libc::ioctl(fd.as_raw_fd(), MEDIA_IOC_G_TOPOLOGY, &mut topology) } < 0 {
+            let err = io::Error::last_os_error();
+            // Interfaces were added in between and do not fit.
+            if err.raw_os_error() == Some(libc::ENOSPC) {
+                continue;
+            }
+            return Err(err);
+        }
+        // The graph changed in between, so try again.
+        if topology.topology_version == version {
+            interfaces.truncate(topology.num_interfaces as usize);
+            return Ok(interfaces);
+        }
+    }
+}
+
+/// An ALSA PCM capture device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlsaPcm {
+    pub card: u32,
+    pub device: u32,
+    /// The device node, e.g. `/dev/snd/pcmC1D0c`.
+    pub path: PathBuf,
+}
+
+impl AlsaPcm {
+    /// Returns the ALSA name of the device, e.g. `hw:1,0`.
+    pub fn hw_name(&self) -> String {
+        format!("hw:{},{}", self.card, self.device)
+    }
+}
+
+/// Parses the name of a PCM capture node, e.g. `pcmC1D0c`.
+fn parse_pcm_capture(name: &str) -> Option<(u32, u32)> {
+    let name = name.strip_prefix("pcmC")?.strip_suffix('c')?;
+    let (card, device) = name.split_once('D')?;
+    Some((card.parse().ok()?, device.parse().ok()?))
+}
+
+fn pcm_from_name(name: &str) -> Option<AlsaPcm> {
+    let (card, device) = parse_pcm_capture(name)?;
+    Some(AlsaPcm {
+        card,
+        device,
+        path: Path::new("/dev/snd").join(name),
+    })
+}
+
+/// Returns the sysfs name of character device `major`:`minor`.
+fn char_device_name(major: u32, minor: u32) -> Option<String> {
+    let uevent = fs::read_to_string(format!("/sys/dev/char/{}:{}/uevent", major, minor)).ok()?;
+    let devname = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("DEVNAME="))?;
+    Some(devname.rsplit('/').next()?.to_string())
+}
+
+/// Returns the major and minor number of the node `device` was opened
+/// from.
+fn device_number(device: &V4l2Device) -> io::Result<(u32, u32)> {
+    let rdev = fs::metadata(format!("/proc/self/fd/{}", device.as_raw_fd()))?.rdev();
+    Ok((libc::major(rdev), libc::minor(rdev)))
+}
+
+/// Returns the PCM capture devices in the media graph that holds the
+/// video node `devnode`.
+fn graph_pcms(devnode: (u32, u32)) -> io::Result<Vec<AlsaPcm>> {
+    for entry in fs::read_dir("/dev")?.filter_map(|entry| entry.ok()) {
+        let is_media = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with("media"));
+        if !is_media {
+            continue;
+        }
+        let interfaces = match fs::File::open(entry.path()).and_then(|file| interfaces(&file)) {
+            Ok(interfaces) => interfaces,
+            Err(_) => continue,
+        };
+        let node =
+            |intf: &media_v2_interface| unsafe { (intf.u.devnode.major, intf.u.devnode.minor) };
+
+        if interfaces
+            .iter()
+            .any(|intf| intf.intf_type == MEDIA_INTF_T_V4L_VIDEO && node(intf) == devnode)
+        {
+            return Ok(interfaces
+                .iter()
+                .filter(|intf| intf.intf_type == MEDIA_INTF_T_ALSA_PCM_CAPTURE)
+                .filter_map(|intf| {
+                    let (major, minor) = node(intf);
+                    pcm_from_name(&char_device_name(major, minor)?)
+                })
+                .collect());
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Returns the PCM capture devices of the USB device the video node
+/// `devnode` belongs to, from sysfs.
+fn usb_pcms(devnode: (u32, u32)) -> io::Result<Vec<AlsaPcm>> {
+    let mut dir = fs::canonicalize(format!("/sys/dev/char/{}:{}/device", devnode.0, devnode.1))?;
+    // The USB device is the ancestor with a vendor ID, above the interface
+    // the video function is bound to.
+    while !dir.join("idVendor").exists() {
+        if !dir.pop() {
+            return Ok(Vec::new());
+        }
+    }
+
+    let mut pcms = Vec::new();
+    for interface in fs::read_dir(&dir)?.filter_map(|entry| entry.ok()) {
+        let sound = interface.path().join("sound");
+        let cards = match fs::read_dir(&sound) {
+            Ok(cards) => cards,
+            Err(_) => continue,
+        };
+        for card in cards.filter_map(|entry| entry.ok()) {
+            for node in fs::read_dir(card.path())?.filter_map(|entry| entry.ok()) {
+                if let Some(pcm) = node.file_name().to_str().and_then(pcm_from_name) {
+                    pcms.push(pcm);
+                }
+            }
+        }
+    }
+    pcms.sort_by_key(|pcm| (pcm.card, pcm.device));
+    Ok(pcms)
+}
+
+/// Returns the ALSA capture devices that record the audio belonging to the
+/// video of `device`.
+///
+/// They are looked up in the media graph of the device, and, for drivers
+/// that do not add audio to it like most USB grabbers, among the functions
+/// of the same USB device.
+pub fn companion_alsa(device: &V4l2Device) -> io::Result<Vec<AlsaPcm>> {
+    let devnode = device_number(device)?;
+    let pcms = graph_pcms(devnode)?;
+    if !pcms.is_empty() {
+        return Ok(pcms);
+    }
+    usb_pcms(devnode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pcm_nodes() {
+        assert_eq!(parse_pcm_capture("pcmC1D0c"), Some((1, 0)));
+        assert_eq!(parse_pcm_capture("pcmC12D3c"), Some((12, 3)));
+        assert_eq!(parse_pcm_capture("pcmC1D0p"), None);
+        assert_eq!(parse_pcm_capture("controlC1"), None);
+        assert_eq!(mem::size_of::<media_v2_topology>(), 72);
+        assert_eq!(mem::size_of::<media_v2_interface>(), 112);
+    }
+}
@@ -0,0 +1,180 @@
+//! Helpers for Motion-JPEG frames.
+//!
+//! Many UVC cameras omit the Huffman tables (DHT segment) from MJPEG frames
+//! and rely on the decoder to use the standard ones from the JPEG
+//! specification. Most image decoders reject such frames, so
+//! `fix_huffman_tables` inserts the standard tables to make each frame a
+//! standalone JPEG.
+
+use std::borrow::Cow;
+
+const SOI: u8 = 0xd8;
+const DHT: u8 = 0xc4;
+const SOS: u8 = 0xda;
+
+/* JPEG specification, Annex K.3 */
+const DC_LUMINANCE_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_CHROMINANCE_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMINANCE_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_LUMINANCE_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+const AC_CHROMINANCE_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMINANCE_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Scans the marker segments of a JPEG image up to the start of scan.
+///
+/// Returns whether a DHT segment was seen and the offset of the SOS marker.
+fn scan(data: &[u8]) -> Option<(bool, usize)> {
+    if data.len() < 4 || data[0] != 0xff || data[1] != SOI {
+        return None;
+    }
+
+    let mut has_dht = false;
+    let mut i = 2;
+    while i + 1 < data.len() {
+        if data[i] != 0xff {
+            return None;
+        }
+        let marker = data[i + 1];
+        match marker {
+            0xff => i += 1, /* fill byte */
+            SOS => return Some((has_dht, i)),
+            0x01 | 0xd0..=0xd7 => i += 2,
+            _ => {
+                if marker == DHT {
+                    has_dht = true;
+                }
+                if i + 3 >= data.len() {
+                    return None;
+                }
+                let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+                i += 2 + len;
+            }
+        }
+    }
+
+    None
+}
+
+fn write_dht(out: &mut Vec<u8>) {
+    let tables: [(u8, &[u8], &[u8]); 4] = [
+        (0x00, &DC_LUMINANCE_BITS, &DC_VALUES),
+        (0x10, &AC_LUMINANCE_BITS, &AC_LUMINANCE_VALUES),
+        (0x01, &DC_CHROMINANCE_BITS, &DC_VALUES),
+        (0x11, &AC_CHROMINANCE_BITS, &AC_CHROMINANCE_VALUES),
+    ];
+
+    let len = 2 + tables
+        .iter()
+        .map(|(_, bits, values)| 1 + bits.len() + values.len())
+        .sum::<usize>();
+
+    out.extend_from_slice(&[0xff, DHT]);
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    for (class_id, bits, values) in tables.iter() {
+        out.push(*class_id);
+        out.extend_from_slice(bits);
+        out.extend_from_slice(values);
+    }
+}
+
+/// Returns true if `data` is a JPEG image that defines its own Huffman
+/// tables.
+pub fn has_huffman_tables(data: &[u8]) -> bool {
+    matches!(scan(data), Some((true, _)))
+}
+
+/// Returns `data` as a standalone JPEG image, inserting the standard
+/// Huffman tables in front of the scan if the frame lacks them.
+///
+/// Data that does not parse as JPEG is returned unchanged.
+pub fn fix_huffman_tables(data: &[u8]) -> Cow<'_, [u8]> {
+    match scan(data) {
+        Some((false, sos)) => {
+            let mut out = Vec::with_capacity(data.len() + 420);
+            out.extend_from_slice(&data[..sos]);
+            write_dht(&mut out);
+            out.extend_from_slice(&data[sos..]);
+            Cow::Owned(out)
+        }
+        _ => Cow::Borrowed(data),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* SOI, an APP0 segment, then SOS with one byte of scan data */
+    const FRAME: [u8; 20] = [
+        0xff, SOI, 0xff, 0xe0, 0x00, 0x04, 0x4a, 0x46, 0xff, SOS, 0x00, 0x08, 0x01, 0x01, 0x00,
+        0x00, 0x3f, 0x00, 0x12, 0xff,
+    ];
+
+    #[test]
+    fn inserts_tables_before_the_scan() {
+        assert!(!has_huffman_tables(&FRAME));
+        let fixed = fix_huffman_tables(&FRAME);
+        assert!(matches!(fixed, Cow::Owned(_)));
+        assert!(has_huffman_tables(&fixed));
+
+        let mut dht = Vec::new();
+        write_dht(&mut dht);
+        assert_eq!(fixed.len(), FRAME.len() + dht.len());
+        assert_eq!(fixed[..8], FRAME[..8]);
+        assert_eq!(fixed[8..8 + dht.len()], dht[..]);
+        assert_eq!(fixed[8 + dht.len()..], FRAME[8..]);
+        let len = u16::from_be_bytes([dht[2], dht[3]]) as usize;
+        assert_eq!(len + 2, dht.len());
+    }
+
+    #[test]
+    fn keeps_frames_with_tables() {
+        let fixed = fix_huffman_tables(&FRAME).into_owned();
+        assert!(matches!(fix_huffman_tables(&fixed), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn keeps_invalid_data() {
+        let garbage = [0x12, 0x34, 0xff, SOI, 0xff, SOS];
+        for len in 0..FRAME.len() - 10 {
+            assert!(matches!(
+                fix_huffman_tables(&FRAME[..len]),
+                Cow::Borrowed(_)
+            ));
+        }
+        assert!(matches!(fix_huffman_tables(&garbage), Cow::Borrowed(_)));
+
+        // A segment length running past the end of the data.
+        let mut long = FRAME;
+        long[5] = 0xff;
+        assert!(matches!(fix_huffman_tables(&long), Cow::Borrowed(_)));
+        assert!(!has_huffman_tables(&long));
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+use memmap::MmapMut;
+
+use crate::stats::{BufferMapping, BufferUsage};
+use crate::sys::uapi::*;
+use crate::sys::{poll_events, V4l2Device};
+
+const OUTPUT: v4l2_buf_type = v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT;
+const MMAP: v4l2_memory = v4l2_memory::V4L2_MEMORY_MMAP;
+
+/// Describes a filled output buffer to be queued. Output buffers must
+/// carry a field order, so `V4L2_FIELD_ANY` is sent as progressive.
+pub(crate) fn output_buffer(
+    buf_type: v4l2_buf_type,
+    memory: v4l2_memory,
+    index: usize,
+    format: &v4l2_pix_format,
+    bytes_used: u32,
+    timestamp: Duration,
+) -> v4l2_buffer {
+    let mut buf = v4l2_buffer::new(buf_type, memory);
+    buf.index = index as u32;
+    buf.bytesused = bytes_used;
+    buf.field = match format.field {
+        v4l2_field::V4L2_FIELD_ANY => v4l2_field::V4L2_FIELD_NONE,
+        field => field,
+    };
+    buf.timestamp = libc::timeval {
+        tv_sec: timestamp.as_secs() as libc::time_t,
+        tv_usec: timestamp.subsec_micros() as libc::suseconds_t,
+    };
+    buf
+}
+
+/// A video output device, fed one frame at a time.
+///
+/// Frames are either written with `write_frame`, or pushed through mmap
+/// buffers: fill the buffer returned by `get_writable_buffer` and queue it
+/// with `submit`. Buffers the driver has displayed are reclaimed as new
+/// ones are needed.
+pub struct Output {
+    device: V4l2Device,
+    format: v4l2_pix_format,
+    buffers: Vec<MmapMut>,
+    /* Buffers owned by the application, in the order they are handed out */
+    free: VecDeque<usize>,
+    current: Option<usize>,
+    streaming: bool,
+}
+
+impl Output {
+    /// Opens the output device at `path` and sets its format. The format
+    /// the driver chose is available with `format`.
+    pub fn open(path: &str, format: &v4l2_pix_format) -> io::Result<Output> {
+        Output::from_device(V4l2Device::open(path)?, format)
+    }
+
+    /// Sets the format of an already opened output device.
+    pub fn from_device(device: V4l2Device, format: &v4l2_pix_format) -> io::Result<Output> {
+        let format = device.set_output_format(format)?;
+
+        Ok(Output {
+            device,
+            format,
+            buffers: Vec::new(),
+            free: VecDeque::new(),
+            current: None,
+            streaming: false,
+        })
+    }
+
+    /// Returns the underlying device.
+    pub fn device(&self) -> &V4l2Device {
+        &self.device
+    }
+
+    /// Returns the format negotiated when the output was opened.
+    pub fn format(&self) -> &v4l2_pix_format {
+        &self.format
+    }
+
+    /// Writes a whole frame with `write()`. The driver consumes one frame
+    /// per call.
+    pub fn write_frame(&self, data: &[u8]) -> io::Result<()> {
+        let n = loop {
+            let n = unsafe {
+                libc::write(
+                    self.device.as_raw_fd(),
+                    data.as_ptr() as *const libc::c_void,
+                    data.len(),
+                )
+            };
+            if n >= 0 {
+                break n as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+
+        if n != data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "driver accepted a partial frame",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Requests and maps `count` output buffers, replacing any prepared
+    /// before. Must not be called while streaming.
+    pub fn prepare_mmapped(&mut self, count: usize) -> io::Result<()> {
+        self.unprepare();
+        let n = self.device.request_buffers(OUTPUT, MMAP, count)?;
+
+        self.buffers = self.device.map_buffers(OUTPUT)?;
+        if self.buffers.len() != n {
+            return Err(io::Error::other("failed to map all buffers"));
+        }
+        self.free = (0..n).collect();
+        Ok(())
+    }
+
+    /// Returns the memory held by the prepared buffers.
+    pub fn buffer_usage(&self) -> BufferUsage {
+        BufferUsage::single_planar(
+            OUTPUT,
+            BufferMapping::Driver,
+            self.buffers.iter().map(|buffer| buffer.len()),
+        )
+    }
+
+    /// Unmaps the buffers and releases them in the driver.
+    pub fn unprepare(&mut self) {
+        self.buffers.clear();
+        self.free.clear();
+        self.current = None;
+        let _ = self.device.request_buffers(OUTPUT, MMAP, 0);
+    }
+
+    /// Returns a buffer to fill with the next frame.
+    ///
+    /// The same buffer is returned until it is queued with `submit`. If all
+    /// buffers are queued, this blocks until the driver has displayed one.
+    pub fn get_writable_buffer(&mut self) -> io::Result<&mut [u8]> {
+        if self.current.is_none() {
+            self.reclaim()?;
+            if self.free.is_empty() {
+                if self.buffers.is_empty() {
+                    return Err(io::Error::other("no buffers prepared"));
+                }
+                let buf = self.device.dequeue_buffer(OUTPUT, MMAP)?;
+                self.free.push_back(buf.index as usize);
+            }
+            self.current = self.free.pop_front();
+        }
+
+        let index = self.current.expect("a buffer was taken above");
+        Ok(&mut self.buffers[index][..])
+    }
+
+    /// Queues the buffer returned by `get_writable_buffer`, holding
+    /// `bytes_used` bytes of frame data, and starts streaming on the first
+    /// call.
+    ///
+    /// If streaming cannot be started, the frame is dropped and the output
+    /// is left stopped with all buffers free, as after `stop`.
+    pub fn submit(&mut self, bytes_used: u32, timestamp: Duration) -> io::Result<()> {
+        self.submit_with(bytes_used, timestamp, None)
+    }
+
+    /// Queues the buffer like `submit`, carrying `timecode` for drivers
+    /// that pass timecodes on, e.g. to SDI outputs.
+    pub fn submit_with_timecode(
+        &mut self,
+        bytes_used: u32,
+        timestamp: Duration,
+        timecode: &v4l2_timecode,
+    ) -> io::Result<()> {
+        self.submit_with(bytes_used, timestamp, Some(timecode))
+    }
+
+    fn submit_with(
+        &mut self,
+        bytes_used: u32,
+        timestamp: Duration,
+        timecode: Option<&v4l2_timecode>,
+    ) -> io::Result<()> {
+        let index = self
+            .current
+            .ok_or_else(|| io::Error::other("no buffer taken with get_writable_buffer"))?;
+
+        let mut buf = output_buffer(OUTPUT, MMAP, index, &self.format, bytes_used, timestamp);
+        if let Some(timecode) = timecode {
+            buf.timecode = *timecode;
+            buf.flags |= V4L2_BUF_FLAG_TIMECODE;
+        }
+        self.device.queue_buffer(&buf)?;
+        self.current = None;
+
+        if !self.streaming {
+            if let Err(e) = self.device.stream_on(OUTPUT) {
+                // STREAMOFF takes back the buffers queued so far.
+                let _ = self.device.stream_off(OUTPUT);
+                self.free = (0..self.buffers.len()).collect();
+                return Err(e);
+            }
+            self.streaming = true;
+        }
+        Ok(())
+    }
+
+    /// Stops streaming. All buffers are given back to the application.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.device.stream_off(OUTPUT)?;
+        self.streaming = false;
+        self.current = None;
+        self.free = (0..self.buffers.len()).collect();
+        Ok(())
+    }
+
+    /// Dequeues the buffers the driver is done with, without blocking.
+    fn reclaim(&mut self) -> io::Result<()> {
+        if !self.streaming {
+            return Ok(());
+        }
+
+        while poll_events(self.device.as_raw_fd(), libc::POLLOUT, Some(Instant::now()))?
+            & libc::POLLOUT
+            != 0
+        {
+            let buf = self.device.dequeue_buffer(OUTPUT, MMAP)?;
+            self.free.push_back(buf.index as usize);
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Output {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device.as_raw_fd()
+    }
+}
@@ -0,0 +1,187 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::Instant;
+
+use crate::capture::Capture;
+use crate::frame::Frame;
+use crate::m2m::M2m;
+use crate::output::Output;
+use crate::sys::poll_events;
+
+/* Buffers on each queue the pipeline allocates itself */
+const BUFFER_COUNT: usize = 4;
+
+/// Moves frames from a capture device through a memory-to-memory device,
+/// and optionally on to an output device.
+///
+/// Captured buffers are handed to the M2M device as dmabufs when the
+/// capture can export them and the M2M device can import them, and are
+/// copied otherwise. Results are copied into the output.
+pub struct Pipeline {
+    capture: Capture,
+    m2m: M2m,
+    output: Option<Output>,
+    /* Capture buffers lent to the M2M device, by index */
+    held: Vec<bool>,
+}
+
+impl Pipeline {
+    /// Wires up `capture`, which must have buffers prepared, `m2m`, whose
+    /// input format must match the capture format, and `output`, whose
+    /// format must match the M2M output format. Buffers of the M2M device
+    /// and the output are prepared here, and the capture and M2M device are
+    /// started.
+    pub fn new(mut capture: Capture, mut m2m: M2m, output: Option<Output>) -> io::Result<Pipeline> {
+        let count = capture.buffer_count();
+        if count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "capture has no buffers prepared",
+            ));
+        }
+
+        // Capture buffers are lent by index, so the M2M device needs a
+        // slot for each of them.
+        let zero_copy = capture.export_dmabufs().is_ok()
+            && m2m.prepare_dmabuf_input(count, BUFFER_COUNT).is_ok()
+            && m2m.input_count() >= count;
+        if !zero_copy {
+            m2m.prepare_mmapped(BUFFER_COUNT, BUFFER_COUNT)?;
+        }
+
+        let mut output = output;
+        if let Some(ref mut output) = output {
+            output.prepare_mmapped(BUFFER_COUNT)?;
+        }
+
+        capture.start()?;
+        m2m.start()?;
+
+        Ok(Pipeline {
+            capture,
+            m2m,
+            output,
+            held: vec![false; if zero_copy { count } else { 0 }],
+        })
+    }
+
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    pub fn m2m(&self) -> &M2m {
+        &self.m2m
+    }
+
+    pub fn output(&self) -> Option<&Output> {
+        self.output.as_ref()
+    }
+
+    /// Returns true if captured frames reach the M2M device as dmabufs.
+    pub fn is_zero_copy(&self) -> bool {
+        self.m2m.imports_dmabufs()
+    }
+
+    /// Captures one frame and feeds it to the M2M device, then passes every
+    /// result that is ready to `sink` and to the output. Returns the number
+    /// of results.
+    ///
+    /// Devices that buffer several inputs, like encoders, may produce no
+    /// result for a frame and several for a later one. A result larger
+    /// than an output buffer fails with `InvalidData` after `sink` saw it.
+    pub fn process<F>(&mut self, mut sink: F) -> io::Result<usize>
+    where
+        F: FnMut(&Frame) -> io::Result<()>,
+    {
+        let frame = self.capture.dequeue_frame()?;
+        match frame.dmabuf() {
+            Some(dmabuf) if self.m2m.imports_dmabufs() => {
+                let buf = frame.buffer();
+                let queued = self.m2m.queue_input_dmabuf(
+                    buf.index as usize,
+                    dmabuf.as_raw_fd(),
+                    dmabuf.size,
+                    buf.bytesused,
+                    frame.timestamp(),
+                );
+                if let Err(e) = queued {
+                    // The M2M device did not take the buffer, so capture
+                    // into it again.
+                    let _ = self.capture.return_frame(buf);
+                    return Err(e);
+                }
+                self.held[buf.index as usize] = true;
+            }
+            _ => {
+                let queued = self.m2m.queue_input(frame.data(), frame.timestamp());
+                self.capture.return_frame(frame.buffer())?;
+                queued?;
+            }
+        }
+
+        let mut results = 0;
+        loop {
+            // Keep at least one capture buffer queued, so the next capture
+            // does not wait forever.
+            let held = self.held.iter().filter(|&&held| held).count();
+            let deadline = if held > 0 && held + 1 >= self.held.len() {
+                None
+            } else {
+                Some(Instant::now())
+            };
+            let ready = poll_events(self.m2m.as_raw_fd(), libc::POLLIN | libc::POLLOUT, deadline)?;
+
+            if ready & libc::POLLOUT != 0 {
+                let index = self.m2m.dequeue_input()?.index as usize;
+                if let Some(held) = self.held.get_mut(index).filter(|held| **held) {
+                    *held = false;
+                    self.capture.release(index)?;
+                }
+            }
+            if ready & libc::POLLIN != 0 {
+                let result = self.m2m.dequeue_output()?;
+                let output = &mut self.output;
+                let emitted = sink(&result).and_then(|_| match *output {
+                    Some(ref mut output) => {
+                        let data = result.data();
+                        let buffer = output.get_writable_buffer()?;
+                        if data.len() > buffer.len() {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "result does not fit into an output buffer",
+                            ));
+                        }
+                        buffer[..data.len()].copy_from_slice(data);
+                        output.submit(data.len() as u32, result.timestamp())
+                    }
+                    None => Ok(()),
+                });
+                self.m2m.return_output(result.buffer())?;
+                emitted?;
+                results += 1;
+            }
+            if ready & (libc::POLLIN | libc::POLLOUT) == 0 {
+                return Ok(results);
+            }
+        }
+    }
+
+    /// Like `process`, for pipelines whose results only go to the output.
+    pub fn step(&mut self) -> io::Result<usize> {
+        self.process(|_| Ok(()))
+    }
+
+    /// Stops all devices. Capture buffers lent to the M2M device are given
+    /// back when the capture is started again.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.capture.stop()?;
+        self.m2m.stop()?;
+        for held in self.held.iter_mut() {
+            *held = false;
+        }
+        if let Some(ref mut output) = self.output {
+            output.stop()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+//! Unattended capture that recovers from stalled and wedged devices.
+//!
+//! A `Supervisor` dequeues frames with a stall timeout. When no frame
+//! arrives in time or the driver reports an I/O error, it first restarts
+//! streaming, which is enough for most drivers that stop delivering
+//! buffers after an underrun, and then escalates to reopening the device,
+//! for USB cameras that only recover once the node is closed.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use v4l2::recovery::{RecoveryPolicy, Supervisor};
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0").buffers(4).open()?;
+//! let mut supervisor = Supervisor::new(capture, Duration::from_secs(2), RecoveryPolicy::default())?;
+//! loop {
+//!     let frame = supervisor.dequeue_owned_frame()?;
+//!     // ...
+//! }
+//! # }
+//! ```
+
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+use crate::capture::{Builder, Capture, IoMethod};
+use crate::config::CaptureConfig;
+use crate::device_id::DeviceId;
+use crate::frame::OwnedFrame;
+use crate::sys::DeviceOps;
+
+/// How a `Supervisor` escalates after failed dequeues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryPolicy {
+    /// Consecutive failures recovered by restarting streaming, before the
+    /// device is reopened.
+    pub restarts: u32,
+    /// Reopens of the device after that, before the error is returned.
+    /// The next dequeue then starts over with restarts.
+    pub reopens: u32,
+    /// Time to wait before each reopen, for USB devices to re-enumerate.
+    pub reopen_delay: Duration,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> RecoveryPolicy {
+        RecoveryPolicy {
+            restarts: 2,
+            reopens: 3,
+            reopen_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What to do about a failed dequeue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Restart,
+    Reopen,
+    Fail,
+}
+
+/// Returns true for errors of stalled, wedged or unplugged devices, as
+/// opposed to misuse of the capture. Reopening a device that has not
+/// reappeared yet fails with `NotFound`.
+fn is_recoverable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::NotFound
+    ) || matches!(err.raw_os_error(), Some(libc::EIO) | Some(libc::ENODEV))
+}
+
+/// Returns the action for `err`, after `failures` consecutive failures
+/// were recovered already.
+fn next_action(policy: &RecoveryPolicy, failures: u32, err: &io::Error) -> Action {
+    if !is_recoverable(err) {
+        return Action::Fail;
+    }
+    // Restarting a device that is gone is pointless.
+    let unplugged = err.raw_os_error() == Some(libc::ENODEV);
+    if failures < policy.restarts && !unplugged {
+        Action::Restart
+    } else if failures < policy.restarts.saturating_add(policy.reopens) {
+        Action::Reopen
+    } else {
+        Action::Fail
+    }
+}
+
+/// Returns a configuration of the format of `capture` only, for drivers
+/// whose full configuration cannot be read.
+fn format_config(capture: &Capture) -> CaptureConfig {
+    let format = capture.format();
+    let time_per_frame = capture
+        .time_per_frame()
+        .map(|t| (t.numerator, t.denominator))
+        .unwrap_or(CaptureConfig::default().time_per_frame);
+    CaptureConfig {
+        device: capture.path().to_string(),
+        width: format.width,
+        height: format.height,
+        pixel_format: format.pixelformat,
+        field: format.field,
+        time_per_frame,
+        ..CaptureConfig::default()
+    }
+}
+
+/// Closes `capture`, waits `delay` and replaces it with the capture `open`
+/// returns for its I/O method, streaming with the same settings and
+/// `buffer_count` buffers. If that fails, `capture` stays closed.
+fn reopen_capture<D, F>(
+    capture: &mut Capture<D>,
+    buffer_count: usize,
+    delay: Duration,
+    open: F,
+) -> io::Result<()>
+where
+    D: DeviceOps,
+    F: FnOnce(IoMethod) -> io::Result<Capture<D>>,
+{
+    // USB devices only recover once their node is closed, and drivers may
+    // refuse to open or allocate buffers twice.
+    capture.close();
+    thread::sleep(delay);
+
+    let mut reopened = open(capture.io_method())?;
+    reopened.copy_settings(capture);
+    reopened.prepare_mmapped(buffer_count)?;
+    reopened.start()?;
+    *capture = reopened;
+    Ok(())
+}
+
+/// A streaming `Capture` that restarts or reopens its device when frames
+/// stop arriving, following a `RecoveryPolicy`.
+pub struct Supervisor {
+    capture: Capture,
+    config: CaptureConfig,
+    policy: RecoveryPolicy,
+    buffer_count: usize,
+    failures: u32,
+    restarts: u64,
+    reopens: u64,
+}
+
+impl Supervisor {
+    /// Supervises `capture`, which is started if it is not streaming yet.
+    /// Dequeues fail when no frame arrives within `stall_timeout`.
+    ///
+    /// The configuration of the device, including its controls, is saved
+    /// to reproduce it on reopening, or only its format if the driver
+    /// cannot report more. The device is reopened by its `DeviceId`, so a
+    /// USB camera is found again under a different node.
+    pub fn new(
+        mut capture: Capture,
+        stall_timeout: Duration,
+        policy: RecoveryPolicy,
+    ) -> io::Result<Supervisor> {
+        let mut config = capture
+            .current_config()
+            .unwrap_or_else(|_| format_config(&capture));
+        // Without stable names, the node is the best guess.
+        config.device = DeviceId::of(capture.path())
+            .map(|id| id.to_string())
+            .unwrap_or(config.device);
+        let buffer_count = capture.buffer_count();
+        capture.set_stall_timeout(Some(stall_timeout));
+        if !capture.is_streaming() {
+            capture.start()?;
+        }
+        Ok(Supervisor {
+            capture,
+            config,
+            policy,
+            buffer_count,
+            failures: 0,
+            restarts: 0,
+            reopens: 0,
+        })
+    }
+
+    /// Dequeues a frame, recovering from stalls on the way. Errors that
+    /// cannot be recovered, or persist after the policy is exhausted, are
+    /// returned.
+    pub fn dequeue_owned_frame(&mut self) -> io::Result<OwnedFrame> {
+        loop {
+            let err = if self.capture.is_closed() {
+                io::Error::from_raw_os_error(libc::ENODEV)
+            } else {
+                match self.capture.dequeue_owned_frame() {
+                    Ok(frame) => {
+                        self.failures = 0;
+                        return Ok(frame);
+                    }
+                    Err(err) => err,
+                }
+            };
+            self.recover(err)?;
+        }
+    }
+
+    /// Recovers from the failed dequeue `err`, escalating while restarts
+    /// or reopens fail, or returns the last error if the policy says to
+    /// give up.
+    fn recover(&mut self, err: io::Error) -> io::Result<()> {
+        let mut last = None;
+        loop {
+            // A failed reopen may have turned a stall into an unplug.
+            let action = next_action(&self.policy, self.failures, last.as_ref().unwrap_or(&err));
+            self.failures += 1;
+            let result = match action {
+                Action::Restart => {
+                    self.restarts += 1;
+                    self.capture.restart()
+                }
+                Action::Reopen => {
+                    self.reopens += 1;
+                    self.reopen()
+                }
+                Action::Fail => {
+                    self.failures = 0;
+                    return Err(last.unwrap_or(err));
+                }
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => last = Some(e),
+            }
+        }
+    }
+
+    /// Closes the device and opens it again with the saved configuration,
+    /// at the node its `DeviceId` currently resolves to.
+    fn reopen(&mut self) -> io::Result<()> {
+        let config = &self.config;
+        reopen_capture(
+            &mut self.capture,
+            self.buffer_count,
+            self.policy.reopen_delay,
+            |io| Builder::from_config(config).io_method(io).buffers(0).open(),
+        )
+    }
+
+    /// Returns the supervised capture. Check `is_closed` before using its
+    /// device.
+    pub fn capture(&self) -> &Capture {
+        &self.capture
+    }
+
+    /// Returns true while the device is closed because reopening it
+    /// failed. The next dequeue tries again.
+    pub fn is_closed(&self) -> bool {
+        self.capture.is_closed()
+    }
+
+    /// Returns the number of times streaming was restarted.
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    /// Returns the number of times the device was reopened.
+    pub fn reopens(&self) -> u64 {
+        self.reopens
+    }
+
+    pub fn into_inner(self) -> Capture {
+        self.capture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::tests::FakeDevice;
+
+    #[test]
+    fn escalates_from_restart_to_reopen() {
+        let policy = RecoveryPolicy {
+            restarts: 2,
+            reopens: 1,
+            reopen_delay: Duration::ZERO,
+        };
+        let stall = io::Error::from(io::ErrorKind::TimedOut);
+        let actions: Vec<Action> = (0..4).map(|n| next_action(&policy, n, &stall)).collect();
+        assert_eq!(
+            actions,
+            [
+                Action::Restart,
+                Action::Restart,
+                Action::Reopen,
+                Action::Fail
+            ]
+        );
+
+        let unplugged = io::Error::from_raw_os_error(libc::ENODEV);
+        assert_eq!(next_action(&policy, 0, &unplugged), Action::Reopen);
+        let missing = io::Error::from_raw_os_error(libc::ENOENT);
+        assert_eq!(next_action(&policy, 2, &missing), Action::Reopen);
+        let misuse = io::Error::from(io::ErrorKind::InvalidInput);
+        assert_eq!(next_action(&policy, 0, &misuse), Action::Fail);
+    }
+
+    #[test]
+    fn closes_the_device_before_reopening() {
+        let device = FakeDevice::new();
+        let old = device.dropped.clone();
+        let mut capture = device.into_capture();
+        capture.prepare_mmapped(2).unwrap();
+        capture.start().unwrap();
+
+        reopen_capture(&mut capture, 2, Duration::ZERO, |_| {
+            assert!(old.get());
+            Ok(FakeDevice::new().into_capture())
+        })
+        .unwrap();
+        assert!(capture.is_streaming());
+        assert_eq!(capture.buffer_count(), 2);
+
+        // A failed open leaves it closed.
+        let err = reopen_capture(&mut capture, 2, Duration::ZERO, |_| {
+            Err(io::Error::from_raw_os_error(libc::ENOENT))
+        })
+        .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+        assert!(capture.is_closed());
+    }
+}
@@ -0,0 +1,375 @@
+//! Copy and repack routines between common raw layouts.
+//!
+//! Sources and destinations are described by their line stride in bytes so
+//! that padded driver buffers can be fed to encoders that expect a specific,
+//! possibly differently padded, layout. For planar 4:2:0 (I420) buffers the
+//! chroma planes use half of the luma stride, rounded up for odd strides.
+
+use std::io;
+
+fn too_short() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "buffer is too short")
+}
+
+fn check(len: usize, needed: usize) -> io::Result<()> {
+    if len < needed {
+        Err(too_short())
+    } else {
+        Ok(())
+    }
+}
+
+fn check_size(width: usize, height: usize) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame is empty",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the size of an I420 buffer with the given luma stride.
+pub fn i420_len(height: usize, stride: usize) -> usize {
+    stride * height + chroma_stride(stride) * height.div_ceil(2) * 2
+}
+
+/// Returns the stride of the chroma planes of an I420 buffer.
+fn chroma_stride(stride: usize) -> usize {
+    stride.div_ceil(2)
+}
+
+/// Returns the size of an NV12/NV21 buffer with the given stride.
+pub fn nv12_len(height: usize, stride: usize) -> usize {
+    stride * (height + height.div_ceil(2))
+}
+
+fn copy_plane(
+    src: &[u8],
+    src_stride: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+    width: usize,
+    height: usize,
+) {
+    for (s, d) in src
+        .chunks(src_stride)
+        .zip(dst.chunks_mut(dst_stride))
+        .take(height)
+    {
+        d[..width].copy_from_slice(&s[..width]);
+    }
+}
+
+/// Splits an I420 buffer into its Y, U and V planes.
+fn i420_planes(buf: &mut [u8], height: usize, stride: usize) -> (&mut [u8], &mut [u8], &mut [u8]) {
+    let chroma = chroma_stride(stride) * height.div_ceil(2);
+    let (y, uv) = buf.split_at_mut(stride * height);
+    let (u, v) = uv.split_at_mut(chroma);
+    (y, u, v)
+}
+
+/// Converts packed YUYV (4:2:2) to I420, averaging the chroma of each pair
+/// of lines. Lines of odd widths end in half a macropixel.
+pub fn yuyv_to_i420(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> io::Result<()> {
+    check_size(width, height)?;
+    let line_len = width.div_ceil(2) * 4;
+    let src_stride = src_stride.max(line_len);
+    let dst_stride = dst_stride.max(width);
+    check(src.len(), src_stride * height)?;
+    check(dst.len(), i420_len(height, dst_stride))?;
+
+    let cstride = chroma_stride(dst_stride);
+    let (y, u, v) = i420_planes(dst, height, dst_stride);
+
+    for row in 0..height {
+        let line = &src[row * src_stride..row * src_stride + line_len];
+        let y_line = &mut y[row * dst_stride..row * dst_stride + width];
+        for (yy, pair) in y_line.iter_mut().zip(line.chunks_exact(2)) {
+            *yy = pair[0];
+        }
+
+        if row % 2 == 0 {
+            let next = if row + 1 < height { row + 1 } else { row };
+            let below = &src[next * src_stride..next * src_stride + line_len];
+            let c = (row / 2) * cstride;
+            for (i, (a, b)) in line.chunks_exact(4).zip(below.chunks_exact(4)).enumerate() {
+                u[c + i] = (a[1] as u16 + b[1] as u16).div_ceil(2) as u8;
+                v[c + i] = (a[3] as u16 + b[3] as u16).div_ceil(2) as u8;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn semiplanar_to_i420(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+    swap_uv: bool,
+) -> io::Result<()> {
+    check_size(width, height)?;
+    // CbCr lines hold whole pairs, also for odd widths.
+    let src_stride = src_stride.max(width.div_ceil(2) * 2);
+    let dst_stride = dst_stride.max(width);
+    check(src.len(), nv12_len(height, src_stride))?;
+    check(dst.len(), i420_len(height, dst_stride))?;
+
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+    let cstride = chroma_stride(dst_stride);
+    let (luma, chroma) = src.split_at(src_stride * height);
+    let (y, mut u, mut v) = i420_planes(dst, height, dst_stride);
+    if swap_uv {
+        std::mem::swap(&mut u, &mut v);
+    }
+
+    copy_plane(luma, src_stride, y, dst_stride, width, height);
+    for row in 0..ch {
+        let uv = &chroma[row * src_stride..row * src_stride + cw * 2];
+        for (i, pair) in uv.chunks_exact(2).enumerate() {
+            u[row * cstride + i] = pair[0];
+            v[row * cstride + i] = pair[1];
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts NV12 (interleaved CbCr) to I420.
+pub fn nv12_to_i420(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> io::Result<()> {
+    semiplanar_to_i420(src, src_stride, width, height, dst, dst_stride, false)
+}
+
+/// Converts NV21 (interleaved CrCb) to I420.
+pub fn nv21_to_i420(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> io::Result<()> {
+    semiplanar_to_i420(src, src_stride, width, height, dst, dst_stride, true)
+}
+
+/// Converts I420 to NV12.
+pub fn i420_to_nv12(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> io::Result<()> {
+    check_size(width, height)?;
+    let src_stride = src_stride.max(width);
+    let dst_stride = dst_stride.max(width.div_ceil(2) * 2);
+    check(src.len(), i420_len(height, src_stride))?;
+    check(dst.len(), nv12_len(height, dst_stride))?;
+
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+    let cstride = chroma_stride(src_stride);
+    let (luma, chroma) = src.split_at(src_stride * height);
+    let (u, v) = chroma.split_at(cstride * ch);
+    let (y, uv) = dst.split_at_mut(dst_stride * height);
+
+    copy_plane(luma, src_stride, y, dst_stride, width, height);
+    for row in 0..ch {
+        let out = &mut uv[row * dst_stride..row * dst_stride + cw * 2];
+        for (i, pair) in out.chunks_exact_mut(2).enumerate() {
+            pair[0] = u[row * cstride + i];
+            pair[1] = v[row * cstride + i];
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies an I420 buffer to a destination with a different stride.
+pub fn copy_i420(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> io::Result<()> {
+    check_size(width, height)?;
+    let src_stride = src_stride.max(width);
+    let dst_stride = dst_stride.max(width);
+    check(src.len(), i420_len(height, src_stride))?;
+    check(dst.len(), i420_len(height, dst_stride))?;
+
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+    let (sy, schroma) = src.split_at(src_stride * height);
+    let (scstride, dcstride) = (chroma_stride(src_stride), chroma_stride(dst_stride));
+    let (su, sv) = schroma.split_at(scstride * ch);
+    let (dy, du, dv) = i420_planes(dst, height, dst_stride);
+
+    copy_plane(sy, src_stride, dy, dst_stride, width, height);
+    copy_plane(su, scstride, du, dcstride, cw, ch);
+    copy_plane(sv, scstride, dv, dcstride, cw, ch);
+
+    Ok(())
+}
+
+/// Unpacks MIPI CSI-2 packed 10-bit samples (e.g. `V4L2_PIX_FMT_SBGGR10P`,
+/// four samples in five bytes) into 16-bit samples.
+///
+/// `dst` receives `width * height` samples, tightly packed.
+pub fn unpack_10bit(
+    src: &[u8],
+    src_stride: usize,
+    width: usize,
+    height: usize,
+    dst: &mut [u16],
+) -> io::Result<()> {
+    check_size(width, height)?;
+    let line_len = width.div_ceil(4) * 5;
+    let src_stride = src_stride.max(line_len);
+    check(src.len(), src_stride * (height - 1) + line_len)?;
+    check(dst.len(), width * height)?;
+
+    for (line, out) in src
+        .chunks(src_stride)
+        .zip(dst.chunks_mut(width))
+        .take(height)
+    {
+        for (i, px) in out.iter_mut().enumerate() {
+            let group = &line[(i / 4) * 5..];
+            let lsb = (group[4] >> ((i % 4) * 2)) & 0x3;
+            *px = ((group[i % 4] as u16) << 2) | lsb as u16;
+        }
+    }
+
+    Ok(())
+}
+
+/// Packs 16-bit samples holding 10-bit values into the MIPI CSI-2 packed
+/// 10-bit layout, the inverse of `unpack_10bit`.
+pub fn pack_10bit(
+    src: &[u16],
+    width: usize,
+    height: usize,
+    dst: &mut [u8],
+    dst_stride: usize,
+) -> io::Result<()> {
+    check_size(width, height)?;
+    let line_len = width.div_ceil(4) * 5;
+    let dst_stride = dst_stride.max(line_len);
+    check(src.len(), width * height)?;
+    check(dst.len(), dst_stride * (height - 1) + line_len)?;
+
+    for (line, out) in src
+        .chunks(width)
+        .zip(dst.chunks_mut(dst_stride))
+        .take(height)
+    {
+        for (samples, group) in line.chunks(4).zip(out.chunks_mut(5)) {
+            group[4] = 0;
+            for (i, &s) in samples.iter().enumerate() {
+                group[i] = (s >> 2) as u8;
+                group[4] |= ((s & 0x3) as u8) << (i * 2);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nv12_i420_round_trip() {
+        let nv12 = [1, 2, 3, 4, 5, 6, 7, 8, 10, 20, 11, 21];
+        let mut i420 = [0; 12];
+        nv12_to_i420(&nv12, 4, 4, 2, &mut i420, 4).unwrap();
+        assert_eq!(i420, [1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 20, 21]);
+
+        let mut back = [0; 12];
+        i420_to_nv12(&i420, 4, 4, 2, &mut back, 4).unwrap();
+        assert_eq!(back, nv12);
+    }
+
+    #[test]
+    fn sizes_odd_chroma_planes() {
+        // A 3x3 frame has 2x2 chroma samples per plane.
+        assert_eq!(i420_len(3, 3), 9 + 4 + 4);
+        let src: Vec<u8> = (0..17).collect();
+        let mut dst = [0; 17];
+        copy_i420(&src, 3, 3, 3, &mut dst, 3).unwrap();
+        assert_eq!(dst[..], src[..]);
+        assert!(copy_i420(&src[..16], 3, 3, 3, &mut dst, 3).is_err());
+    }
+
+    #[test]
+    fn converts_odd_width_yuyv() {
+        // Two lines of a macropixel and a half.
+        let yuyv = [1, 10, 2, 20, 3, 30, 0, 40, 4, 12, 5, 22, 6, 32, 0, 42];
+        let mut i420 = [0xaa; 6 + 2 + 2];
+        yuyv_to_i420(&yuyv, 8, 3, 2, &mut i420, 3).unwrap();
+        assert_eq!(i420, [1, 2, 3, 4, 5, 6, 11, 31, 21, 41]);
+    }
+
+    #[test]
+    fn converts_odd_width_nv12() {
+        let nv12 = [1, 2, 3, 0, 10, 20, 11, 21];
+        let mut i420 = [0; 3 + 2 + 2];
+        nv12_to_i420(&nv12, 4, 3, 1, &mut i420, 3).unwrap();
+        assert_eq!(i420, [1, 2, 3, 10, 11, 20, 21]);
+
+        let mut back = [0; 8];
+        i420_to_nv12(&i420, 3, 3, 1, &mut back, 0).unwrap();
+        assert_eq!(back, nv12);
+    }
+
+    #[test]
+    fn rejects_empty_frames() {
+        let mut dst = [0; 16];
+        let mut samples = [0; 16];
+        let results = [
+            yuyv_to_i420(&[0; 16], 0, 0, 2, &mut dst, 0),
+            nv12_to_i420(&[0; 16], 4, 4, 0, &mut dst, 4),
+            i420_to_nv12(&[0; 16], 0, 0, 2, &mut dst, 0),
+            copy_i420(&[0; 16], 0, 2, 0, &mut dst, 0),
+            unpack_10bit(&[0; 16], 0, 0, 2, &mut samples),
+            pack_10bit(&[0; 16], 0, 2, &mut dst, 0),
+        ];
+        for result in results.iter() {
+            let err = result.as_ref().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn ten_bit_round_trip() {
+        let samples = [0x3ff, 0x001, 0x200, 0x155, 0x0aa];
+        let mut packed = [0; 10];
+        pack_10bit(&samples, 5, 1, &mut packed, 0).unwrap();
+
+        let mut unpacked = [0; 5];
+        unpack_10bit(&packed, 0, 5, 1, &mut unpacked).unwrap();
+        assert_eq!(unpacked, samples);
+    }
+}
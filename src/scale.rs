@@ -0,0 +1,340 @@
+//! Stride-aware crop and scale for packed images (gray, RGB24, RGBA, ...).
+//!
+//! Images are described by `ImageView`/`ImageViewMut`, which may point into
+//! padded buffers. Cropping is zero-copy: it only narrows the view.
+
+use std::io;
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn required_len(width: usize, height: usize, stride: usize, bpp: usize) -> usize {
+    if height == 0 {
+        0
+    } else {
+        stride * (height - 1) + width * bpp
+    }
+}
+
+/// Checks that a rectangle starts inside an image of `image_width` by
+/// `image_height` pixels and does not extend past it.
+fn check_rect(
+    image_width: usize,
+    image_height: usize,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> io::Result<()> {
+    let fits = |start: usize, len: usize, end: usize| {
+        start < end && start.checked_add(len).is_some_and(|e| e <= end)
+    };
+    if fits(x, width, image_width) && fits(y, height, image_height) {
+        Ok(())
+    } else {
+        Err(invalid("crop rectangle is out of bounds"))
+    }
+}
+
+/// A read-only packed image.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageView<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    bpp: usize,
+}
+
+impl<'a> ImageView<'a> {
+    /// Creates a view of `height` lines of `width` pixels of `bpp` bytes,
+    /// `stride` bytes apart.
+    pub fn new(
+        data: &'a [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        bpp: usize,
+    ) -> io::Result<ImageView<'a>> {
+        if bpp == 0 || stride < width * bpp || data.len() < required_len(width, height, stride, bpp)
+        {
+            return Err(invalid("image buffer is too short"));
+        }
+        Ok(ImageView {
+            data,
+            width,
+            height,
+            stride,
+            bpp,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    fn pixel(&self, x: usize, y: usize) -> &'a [u8] {
+        let i = y * self.stride + x * self.bpp;
+        &self.data[i..i + self.bpp]
+    }
+
+    /// Returns the view of a rectangle of this image, without copying.
+    pub fn crop(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<ImageView<'a>> {
+        check_rect(self.width, self.height, x, y, width, height)?;
+        let start = y * self.stride + x * self.bpp;
+        let len = required_len(width, height, self.stride, self.bpp);
+        ImageView::new(
+            &self.data[start..start + len],
+            width,
+            height,
+            self.stride,
+            self.bpp,
+        )
+    }
+}
+
+/// A writable packed image.
+#[derive(Debug)]
+pub struct ImageViewMut<'a> {
+    data: &'a mut [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    bpp: usize,
+}
+
+impl<'a> ImageViewMut<'a> {
+    pub fn new(
+        data: &'a mut [u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+        bpp: usize,
+    ) -> io::Result<ImageViewMut<'a>> {
+        if bpp == 0 || stride < width * bpp || data.len() < required_len(width, height, stride, bpp)
+        {
+            return Err(invalid("image buffer is too short"));
+        }
+        Ok(ImageViewMut {
+            data,
+            width,
+            height,
+            stride,
+            bpp,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns a writable view of a rectangle of this image.
+    pub fn crop(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> io::Result<ImageViewMut<'_>> {
+        check_rect(self.width, self.height, x, y, width, height)?;
+        let start = y * self.stride + x * self.bpp;
+        let len = required_len(width, height, self.stride, self.bpp);
+        ImageViewMut::new(
+            &mut self.data[start..start + len],
+            width,
+            height,
+            self.stride,
+            self.bpp,
+        )
+    }
+
+    /// Fills every pixel with `pixel`, which must be `bpp` bytes long.
+    pub fn fill(&mut self, pixel: &[u8]) {
+        let (width, bpp) = (self.width, self.bpp);
+        // Empty images may have a zero stride.
+        if width == 0 || self.height == 0 {
+            return;
+        }
+        for line in self.data.chunks_mut(self.stride).take(self.height) {
+            for px in line[..width * bpp].chunks_exact_mut(bpp) {
+                px.copy_from_slice(&pixel[..bpp]);
+            }
+        }
+    }
+
+    #[inline]
+    fn line(&mut self, y: usize) -> &mut [u8] {
+        let start = y * self.stride;
+        &mut self.data[start..start + self.width * self.bpp]
+    }
+}
+
+/// Resampling filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+/// Maps destination coordinates to 16.16 fixed point source coordinates,
+/// aligning pixel centers.
+fn source_positions(src: usize, dst: usize) -> Vec<i64> {
+    let scale = ((src as i64) << 16) / dst as i64;
+    (0..dst as i64)
+        .map(|d| (d * scale + scale / 2 - (1 << 15)).clamp(0, ((src as i64) - 1) << 16))
+        .collect()
+}
+
+fn check_scale(src: &ImageView, dst: &ImageViewMut) -> io::Result<()> {
+    if src.bpp != dst.bpp {
+        return Err(invalid("pixel sizes differ"));
+    }
+    if src.width == 0 || src.height == 0 {
+        return Err(invalid("source image is empty"));
+    }
+    if dst.width == 0 || dst.height == 0 {
+        return Err(invalid("destination image is empty"));
+    }
+    Ok(())
+}
+
+/// Scales `src` to the size of `dst`.
+pub fn scale(src: &ImageView, dst: &mut ImageViewMut, filter: Filter) -> io::Result<()> {
+    check_scale(src, dst)?;
+
+    let bpp = src.bpp;
+    let xs = source_positions(src.width, dst.width);
+    let ys = source_positions(src.height, dst.height);
+
+    for (dy, &sy) in ys.iter().enumerate() {
+        let out = dst.line(dy);
+        match filter {
+            Filter::Nearest => {
+                let y = ((sy + (1 << 15)) >> 16) as usize;
+                for (px, &sx) in out.chunks_exact_mut(bpp).zip(&xs) {
+                    let x = ((sx + (1 << 15)) >> 16) as usize;
+                    px.copy_from_slice(src.pixel(x.min(src.width - 1), y.min(src.height - 1)));
+                }
+            }
+            Filter::Bilinear => {
+                let y0 = (sy >> 16) as usize;
+                let y1 = (y0 + 1).min(src.height - 1);
+                let fy = (sy & 0xffff) as u32;
+                for (px, &sx) in out.chunks_exact_mut(bpp).zip(&xs) {
+                    let x0 = (sx >> 16) as usize;
+                    let x1 = (x0 + 1).min(src.width - 1);
+                    let fx = (sx & 0xffff) as u32;
+
+                    let (a, b) = (src.pixel(x0, y0), src.pixel(x1, y0));
+                    let (c, d) = (src.pixel(x0, y1), src.pixel(x1, y1));
+                    for i in 0..bpp {
+                        let top = a[i] as u32 * (0x10000 - fx) + b[i] as u32 * fx;
+                        let bottom = c[i] as u32 * (0x10000 - fx) + d[i] as u32 * fx;
+                        let v = ((top >> 8) * (0x10000 - fy) + (bottom >> 8) * fy) >> 24;
+                        px[i] = v as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the largest size with the aspect ratio of `width`x`height` that
+/// fits in `max_width`x`max_height`.
+pub fn fit(width: usize, height: usize, max_width: usize, max_height: usize) -> (usize, usize) {
+    if width == 0 || height == 0 {
+        return (0, 0);
+    }
+    if width * max_height > height * max_width {
+        (max_width, (height * max_width / width).max(1))
+    } else {
+        ((width * max_height / height).max(1), max_height)
+    }
+}
+
+/// Scales `src` to fit `dst` keeping its aspect ratio, centers it and fills
+/// the remaining bars with `fill`.
+pub fn letterbox(
+    src: &ImageView,
+    dst: &mut ImageViewMut,
+    filter: Filter,
+    fill: &[u8],
+) -> io::Result<()> {
+    check_scale(src, dst)?;
+    if fill.len() < dst.bpp {
+        return Err(invalid("fill pixel is too short"));
+    }
+    let (w, h) = fit(src.width, src.height, dst.width, dst.height);
+    let (x, y) = ((dst.width - w) / 2, (dst.height - h) / 2);
+
+    dst.fill(fill);
+    scale(src, &mut dst.crop(x, y, w, h)?, filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_and_downscale() {
+        #[rustfmt::skip]
+        let data = [
+            0, 0, 10, 10, 99,
+            0, 0, 10, 10, 99,
+            20, 20, 30, 30, 99,
+            20, 20, 30, 30, 99,
+        ];
+        let src = ImageView::new(&data, 4, 4, 5, 1).unwrap();
+        let mut out = [0; 4];
+        let mut dst = ImageViewMut::new(&mut out, 2, 2, 2, 1).unwrap();
+        scale(&src, &mut dst, Filter::Bilinear).unwrap();
+        assert_eq!(out, [0, 10, 20, 30]);
+
+        let cropped = src.crop(2, 2, 2, 2).unwrap();
+        assert_eq!(cropped.pixel(1, 1), &[30]);
+        assert!(src.crop(0, 4, 1, 0).is_err());
+        assert!(src.crop(3, 0, 2, 1).is_err());
+        assert!(src.crop(usize::MAX, 0, 2, 1).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_images() {
+        let data = [1, 2, 3, 4];
+        let src = ImageView::new(&data, 2, 2, 2, 1).unwrap();
+        let mut out = [7; 4];
+
+        for &(width, height, stride) in &[(0, 2, 0), (2, 0, 2), (0, 0, 0)] {
+            let mut dst = ImageViewMut::new(&mut out, width, height, stride, 1).unwrap();
+            dst.fill(&[9]);
+            let err = scale(&src, &mut dst, Filter::Nearest).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let err = letterbox(&src, &mut dst, Filter::Nearest, &[9]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert_eq!(out, [7; 4]);
+
+        let empty = ImageView::new(&data, 0, 2, 0, 1).unwrap();
+        let mut dst = ImageViewMut::new(&mut out, 2, 2, 2, 1).unwrap();
+        let err = letterbox(&empty, &mut dst, Filter::Nearest, &[9]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(out, [7; 4]);
+    }
+}
@@ -0,0 +1,202 @@
+use std::io::{self, Write};
+
+use crate::capture::Capture;
+use crate::frame::Frame;
+use crate::sys::uapi::*;
+
+const START_CODE: [u8; 4] = [0, 0, 0, 1];
+
+const NAL_IDR: u8 = 5;
+const NAL_SPS: u8 = 7;
+const NAL_PPS: u8 = 8;
+
+/// Splits an Annex-B byte stream into NAL units without start codes.
+fn annexb_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut nals = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+
+    while i + 3 <= data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(s) = start {
+                // A four byte start code leaves a zero behind the previous NAL.
+                let end = if i > s && data[i - 1] == 0 { i - 1 } else { i };
+                nals.push(&data[s..end]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        nals.push(&data[s..]);
+    }
+
+    nals.retain(|nal| !nal.is_empty());
+    nals
+}
+
+/// Splits AVC1 data with 4-byte big-endian length prefixes into NAL units.
+fn avc_nals(mut data: &[u8]) -> io::Result<Vec<&[u8]>> {
+    let mut nals = Vec::new();
+
+    while data.len() >= 4 {
+        let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if data.len() - 4 < len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated NAL unit",
+            ));
+        }
+        if len > 0 {
+            nals.push(&data[4..4 + len]);
+        }
+        data = &data[4 + len..];
+    }
+
+    Ok(nals)
+}
+
+fn nal_type(nal: &[u8]) -> u8 {
+    nal[0] & 0x1f
+}
+
+/// Writes H.264 bitstream frames as a playable Annex-B elementary stream
+/// (`.h264`).
+///
+/// Both `V4L2_PIX_FMT_H264` (start codes) and `V4L2_PIX_FMT_H264_NO_SC`
+/// (length prefixed) input is accepted. Frames are dropped until the first
+/// IDR frame, and the most recent SPS/PPS are prepended to it if the encoder
+/// did not repeat them.
+pub struct H264Writer<W: Write> {
+    writer: W,
+    length_prefixed: bool,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    started: bool,
+}
+
+impl<W: Write> H264Writer<W> {
+    pub fn new(writer: W, pixelformat: u32) -> io::Result<Self> {
+        let length_prefixed = match pixelformat {
+            V4L2_PIX_FMT_H264 => false,
+            V4L2_PIX_FMT_H264_NO_SC => true,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "pixel format is not H.264",
+                ))
+            }
+        };
+
+        Ok(H264Writer {
+            writer,
+            length_prefixed,
+            sps: None,
+            pps: None,
+            started: false,
+        })
+    }
+
+    pub fn from_capture(writer: W, capture: &Capture) -> io::Result<Self> {
+        H264Writer::new(writer, capture.format().pixelformat)
+    }
+
+    /// Provides out-of-band parameter sets (NAL units without start codes),
+    /// as needed for AVC1 encoders that do not emit them in the stream.
+    pub fn set_parameter_sets(&mut self, sps: &[u8], pps: &[u8]) {
+        self.sps = Some(sps.to_vec());
+        self.pps = Some(pps.to_vec());
+    }
+
+    /// Returns true once the first IDR frame has been written.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write_data(frame.data())
+    }
+
+    /// Writes one frame of encoded data.
+    pub fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        let nals = if self.length_prefixed {
+            avc_nals(data)?
+        } else {
+            annexb_nals(data)
+        };
+
+        let mut has_sps = false;
+        let mut has_pps = false;
+        let mut has_idr = false;
+        for nal in &nals {
+            match nal_type(nal) {
+                NAL_SPS => {
+                    has_sps = true;
+                    self.sps = Some(nal.to_vec());
+                }
+                NAL_PPS => {
+                    has_pps = true;
+                    self.pps = Some(nal.to_vec());
+                }
+                NAL_IDR => has_idr = true,
+                _ => {}
+            }
+        }
+
+        if !self.started {
+            if !has_idr || self.sps.is_none() || self.pps.is_none() {
+                return Ok(());
+            }
+            self.started = true;
+        }
+
+        if has_idr {
+            if let (false, Some(sps)) = (has_sps, &self.sps) {
+                self.writer.write_all(&START_CODE)?;
+                self.writer.write_all(sps)?;
+            }
+            if let (false, Some(pps)) = (has_pps, &self.pps) {
+                self.writer.write_all(&START_CODE)?;
+                self.writer.write_all(pps)?;
+            }
+        }
+
+        for nal in nals {
+            self.writer.write_all(&START_CODE)?;
+            self.writer.write_all(nal)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prepends_parameter_sets_to_idr() {
+        let mut w = H264Writer::new(Vec::new(), V4L2_PIX_FMT_H264).unwrap();
+
+        w.write_data(&[0, 0, 0, 1, 0x67, 1, 0, 0, 1, 0x68, 2])
+            .unwrap();
+        w.write_data(&[0, 0, 1, 0x41, 3]).unwrap();
+        assert!(!w.is_started());
+
+        w.write_data(&[0, 0, 0, 1, 0x65, 4, 0]).unwrap();
+        assert_eq!(
+            w.into_inner(),
+            [0, 0, 0, 1, 0x67, 1, 0, 0, 0, 1, 0x68, 2, 0, 0, 0, 1, 0x65, 4, 0]
+        );
+    }
+}
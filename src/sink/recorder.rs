@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::frame::Frame;
+
+/// Raw frame recorder that keeps the last few seconds of frames in memory
+/// until it is triggered, as in dashcams and security cameras.
+///
+/// While idle, pushed frames are copied into a ring buffer covering
+/// `pre_roll` of stream time. `start` writes the buffered frames to the
+/// output and every frame pushed afterwards goes straight to it, until
+/// `stop` is called.
+pub struct PreRollRecorder<W: Write> {
+    pre_roll: Duration,
+    frames: VecDeque<(Duration, Vec<u8>)>,
+    spare: Vec<Vec<u8>>,
+    output: Option<W>,
+}
+
+impl<W: Write> PreRollRecorder<W> {
+    pub fn new(pre_roll: Duration) -> Self {
+        PreRollRecorder {
+            pre_roll,
+            frames: VecDeque::new(),
+            spare: Vec::new(),
+            output: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Returns the number of frames currently held in the pre-roll buffer.
+    pub fn buffered_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn push_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.push(frame.timestamp(), frame.data())
+    }
+
+    /// Records one frame of raw data captured at `timestamp`.
+    pub fn push(&mut self, timestamp: Duration, data: &[u8]) -> io::Result<()> {
+        if let Some(output) = self.output.as_mut() {
+            return output.write_all(data);
+        }
+
+        while let Some(&(oldest, _)) = self.frames.front() {
+            if timestamp.saturating_sub(oldest) <= self.pre_roll {
+                break;
+            }
+            if let Some((_, buf)) = self.frames.pop_front() {
+                self.spare.push(buf);
+            }
+        }
+
+        let mut buf = self.spare.pop().unwrap_or_default();
+        buf.clear();
+        buf.extend_from_slice(data);
+        self.frames.push_back((timestamp, buf));
+
+        Ok(())
+    }
+
+    /// Writes the pre-roll to `output` and keeps recording to it.
+    ///
+    /// If writing fails, `output` is dropped and the recorder keeps
+    /// buffering with the whole pre-roll, so it can be started again.
+    pub fn start(&mut self, mut output: W) -> io::Result<()> {
+        for (_, buf) in &self.frames {
+            output.write_all(buf)?;
+        }
+        self.spare.extend(self.frames.drain(..).map(|(_, buf)| buf));
+        self.output = Some(output);
+        Ok(())
+    }
+
+    /// Stops recording and returns the output, going back to buffering.
+    pub fn stop(&mut self) -> io::Result<Option<W>> {
+        if let Some(output) = self.output.as_mut() {
+            output.flush()?;
+        }
+        Ok(self.output.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_pre_roll() {
+        let mut rec = PreRollRecorder::new(Duration::from_secs(1));
+        for i in 0..5u8 {
+            rec.push(Duration::from_millis(i as u64 * 500), &[i])
+                .unwrap();
+        }
+        assert_eq!(rec.buffered_frames(), 3);
+
+        rec.start(Vec::new()).unwrap();
+        rec.push(Duration::from_millis(2500), &[5]).unwrap();
+        assert_eq!(rec.stop().unwrap(), Some(vec![2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn keeps_pre_roll_on_failed_start() {
+        let mut rec = PreRollRecorder::new(Duration::from_secs(1));
+        for i in 0..3u8 {
+            rec.push(Duration::from_millis(i as u64 * 500), &[i])
+                .unwrap();
+        }
+
+        let mut full = [0u8; 2];
+        assert!(rec.start(&mut full[..]).is_err());
+        assert!(!rec.is_recording());
+        assert_eq!(rec.buffered_frames(), 3);
+    }
+}
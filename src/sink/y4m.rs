@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+
+use crate::capture::Capture;
+use crate::frame::Frame;
+use crate::repack;
+use crate::sys::uapi::*;
+
+/// Writes frames as a YUV4MPEG2 stream, e.g. for piping into x264 or ffmpeg.
+///
+/// Planar 4:2:0 formats are written as-is; NV12/NV21 are written as I420 and
+/// YUYV as planar 4:2:2.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    stride: usize,
+    pixelformat: u32,
+    scratch: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Creates a writer for frames of the given format and writes the stream
+    /// header.
+    pub fn new(writer: W, format: &v4l2_pix_format, timeperframe: v4l2_fract) -> io::Result<Self> {
+        let colorspace = match format.pixelformat {
+            V4L2_PIX_FMT_YUV420 | V4L2_PIX_FMT_YVU420 | V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => {
+                "420jpeg"
+            }
+            V4L2_PIX_FMT_YUYV => "422",
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "pixel format not supported by y4m",
+                ))
+            }
+        };
+
+        let interlace = match format.field {
+            v4l2_field::V4L2_FIELD_INTERLACED_TB => 't',
+            v4l2_field::V4L2_FIELD_INTERLACED_BT => 'b',
+            v4l2_field::V4L2_FIELD_ANY | v4l2_field::V4L2_FIELD_NONE => 'p',
+            _ => '?',
+        };
+
+        let width = format.width as usize;
+        // Lines hold whole macropixels, also for odd widths.
+        let min_stride = match format.pixelformat {
+            V4L2_PIX_FMT_YUYV => width.div_ceil(2) * 4,
+            V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => width.div_ceil(2) * 2,
+            _ => width,
+        };
+        let stride = match format.bytesperline as usize {
+            0 => min_stride,
+            stride if stride < min_stride => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "bytesperline is shorter than a line",
+                ))
+            }
+            stride => stride,
+        };
+
+        let mut y4m = Y4mWriter {
+            writer,
+            width,
+            height: format.height as usize,
+            stride,
+            pixelformat: format.pixelformat,
+            scratch: Vec::new(),
+        };
+
+        writeln!(
+            y4m.writer,
+            "YUV4MPEG2 W{} H{} F{}:{} I{} A1:1 C{}",
+            format.width,
+            format.height,
+            timeperframe.denominator,
+            timeperframe.numerator.max(1),
+            interlace,
+            colorspace
+        )?;
+
+        Ok(y4m)
+    }
+
+    /// Creates a writer using the negotiated format and frame rate of
+    /// `capture`.
+    pub fn from_capture(writer: W, capture: &Capture) -> io::Result<Self> {
+        let timeperframe = capture.time_per_frame()?;
+        Y4mWriter::new(writer, capture.format(), timeperframe)
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write_data(frame.data())
+    }
+
+    /// Writes one frame of raw data in the format given at construction.
+    pub fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        let (w, h, stride) = (self.width, self.height, self.stride);
+        let ch = h.div_ceil(2);
+
+        let needed = match self.pixelformat {
+            V4L2_PIX_FMT_YUYV => stride * h,
+            V4L2_PIX_FMT_NV12 | V4L2_PIX_FMT_NV21 => stride * (h + ch),
+            _ => repack::i420_len(h, stride),
+        };
+        if data.len() < needed {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "frame data is too short",
+            ));
+        }
+
+        self.scratch.clear();
+        let out = &mut self.scratch;
+
+        match self.pixelformat {
+            V4L2_PIX_FMT_YUYV => {
+                for plane in 0..3 {
+                    for row in data.chunks(stride).take(h) {
+                        let pixels = row[..w.div_ceil(2) * 4].chunks_exact(4);
+                        match plane {
+                            0 => {
+                                pixels.for_each(|p| out.extend_from_slice(&[p[0], p[2]]));
+                                // Odd widths end in half a macropixel.
+                                if w % 2 == 1 {
+                                    out.pop();
+                                }
+                            }
+                            1 => pixels.for_each(|p| out.push(p[1])),
+                            _ => pixels.for_each(|p| out.push(p[3])),
+                        }
+                    }
+                }
+            }
+            V4L2_PIX_FMT_NV12 => {
+                out.resize(repack::i420_len(h, w), 0);
+                repack::nv12_to_i420(data, stride, w, h, out, w)?;
+            }
+            V4L2_PIX_FMT_NV21 => {
+                out.resize(repack::i420_len(h, w), 0);
+                repack::nv21_to_i420(data, stride, w, h, out, w)?;
+            }
+            _ => {
+                out.resize(repack::i420_len(h, w), 0);
+                repack::copy_i420(data, stride, w, h, out, w)?;
+                if self.pixelformat == V4L2_PIX_FMT_YVU420 {
+                    let (u, v) = out[w * h..].split_at_mut(w.div_ceil(2) * ch);
+                    u.swap_with_slice(v);
+                }
+            }
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.scratch)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_odd_width_yuyv() {
+        let mut y4m = Y4mWriter {
+            writer: Vec::new(),
+            width: 3,
+            height: 1,
+            stride: 8,
+            pixelformat: V4L2_PIX_FMT_YUYV,
+            scratch: Vec::new(),
+        };
+        assert!(y4m.write_data(&[1, 2, 3, 4, 5, 6, 7]).is_err());
+        y4m.write_data(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        assert_eq!(y4m.into_inner(), b"FRAME\n\x01\x03\x05\x02\x06\x04\x08");
+    }
+}
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use crate::frame::buffer_timestamp;
+use crate::sys::uapi::*;
+
+/// Counters of a `Capture` since it was opened or the counters reset, see
+/// `Capture::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureStats {
+    /// Frames handed to the application.
+    pub delivered: u64,
+    /// Frames requeued without being handed out, by `deliver_every_nth`
+    /// or `deliver_latest_only`.
+    pub skipped: u64,
+    /// Frames the driver dropped, as told by gaps in the sequence numbers.
+    pub dropped: u64,
+    /// Dequeues that gave up after the stall timeout, see
+    /// `Capture::set_stall_timeout`.
+    pub stalls: u64,
+    /// Shortest time from the driver timestamp of a delivered frame to its
+    /// dequeue.
+    pub min_latency: Duration,
+    pub max_latency: Duration,
+    total_latency: Duration,
+    /// Delivered frames with monotonic timestamps, which latencies are
+    /// measured over.
+    pub latency_samples: u64,
+    next_sequence: Option<u32>,
+}
+
+impl CaptureStats {
+    /// Returns the mean time from the driver timestamp of a delivered
+    /// frame to its dequeue, the latency the driver and its buffer queue
+    /// add.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.latency_samples == 0 {
+            return None;
+        }
+        let mean = self.total_latency.as_nanos() / u128::from(self.latency_samples);
+        Some(Duration::from_nanos(mean as u64))
+    }
+
+    /// Forgets the expected sequence number, which drivers reset when
+    /// streaming restarts.
+    pub(crate) fn restart_sequence(&mut self) {
+        self.next_sequence = None;
+    }
+
+    /// Counts dequeued buffer `buf`, which was delivered if `delivered`,
+    /// at time `now` of the monotonic clock.
+    pub(crate) fn record(&mut self, buf: &v4l2_buffer, delivered: bool, now: Duration) {
+        if let Some(expected) = self.next_sequence {
+            // Sequence numbers that go backwards were reset by the driver,
+            // so no frames were lost.
+            let gap = buf.sequence.wrapping_sub(expected);
+            if gap < 1 << 31 {
+                self.dropped += u64::from(gap);
+            }
+        }
+        self.next_sequence = Some(buf.sequence.wrapping_add(1));
+
+        if !delivered {
+            self.skipped += 1;
+            return;
+        }
+        self.delivered += 1;
+
+        if buf.flags & V4L2_BUF_FLAG_TIMESTAMP_MASK != V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC {
+            return;
+        }
+        let latency = now.checked_sub(buffer_timestamp(buf)).unwrap_or_default();
+        if self.latency_samples == 0 || latency < self.min_latency {
+            self.min_latency = latency;
+        }
+        self.max_latency = self.max_latency.max(latency);
+        self.total_latency += latency;
+        self.latency_samples += 1;
+    }
+}
+
+/// Where the memory of buffers comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferMapping {
+    /// Driver buffers mapped into the process.
+    Driver,
+    /// Anonymous memory of the process, passed to the driver as user
+    /// pointers or read into.
+    Process,
+    /// Dmabufs imported from elsewhere, whose memory is accounted to their
+    /// exporter.
+    Imported,
+}
+
+/// Memory held by the buffers of one queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferUsage {
+    pub buf_type: v4l2_buf_type,
+    pub mapping: BufferMapping,
+    /// Bytes of each plane, per buffer. Sizes of imported buffers are not
+    /// known and reported as 0.
+    pub planes: Vec<Vec<usize>>,
+    /// True if the buffers are locked into RAM.
+    pub locked: bool,
+}
+
+impl BufferUsage {
+    /// Describes buffers of one plane each, of `sizes` bytes.
+    pub(crate) fn single_planar<I: IntoIterator<Item = usize>>(
+        buf_type: v4l2_buf_type,
+        mapping: BufferMapping,
+        sizes: I,
+    ) -> BufferUsage {
+        BufferUsage {
+            buf_type,
+            mapping,
+            planes: sizes.into_iter().map(|size| vec![size]).collect(),
+            locked: false,
+        }
+    }
+
+    pub fn buffer_count(&self) -> usize {
+        self.planes.len()
+    }
+
+    /// Returns the bytes held by all buffers.
+    pub fn total(&self) -> usize {
+        self.planes.iter().flatten().sum()
+    }
+}
+
+/// Returns the time of the monotonic clock, which drivers timestamp
+/// buffers with.
+pub(crate) fn monotonic_now() -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_drops_and_latency() {
+        let mut stats = CaptureStats::default();
+        let mut buf = v4l2_buffer::new(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            v4l2_memory::V4L2_MEMORY_MMAP,
+        );
+        buf.flags = V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC;
+        for &(sequence, sec, delivered) in &[(4, 1, true), (5, 2, false), (8, 3, true)] {
+            buf.sequence = sequence;
+            buf.timestamp.tv_sec = sec;
+            stats.record(
+                &buf,
+                delivered,
+                Duration::from_millis(sec as u64 * 1000 + 30),
+            );
+        }
+
+        assert_eq!((stats.delivered, stats.skipped, stats.dropped), (2, 1, 2));
+        assert_eq!(stats.mean_latency(), Some(Duration::from_millis(30)));
+        assert_eq!(stats.max_latency, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn resyncs_on_sequence_reset() {
+        let mut stats = CaptureStats::default();
+        let mut buf = v4l2_buffer::new(
+            v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            v4l2_memory::V4L2_MEMORY_MMAP,
+        );
+        for &sequence in &[100, 101, 0, 1, 3] {
+            buf.sequence = sequence;
+            stats.record(&buf, true, Duration::ZERO);
+        }
+        assert_eq!(stats.dropped, 1);
+
+        buf.sequence = u32::MAX;
+        stats.record(&buf, true, Duration::ZERO);
+        buf.sequence = 1;
+        stats.record(&buf, true, Duration::ZERO);
+        assert_eq!(stats.dropped, 2);
+    }
+}
@@ -0,0 +1,125 @@
+//! ISP controls of the Allwinner VFE driver.
+//!
+//! The BSP kernel adds private controls for white balance gains and the
+//! flash LED, and private ioctls to read the statistics the ISP gathers
+//! for its 3A algorithms. Exposure and flips use the standard controls,
+//! which the VFE applies in the ISP rather than in the sensor.
+
+use std::io;
+
+use crate::sys::ioctl::IoctlRequest;
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+/// Mode of the flash LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashMode {
+    Off,
+    On,
+    Torch,
+    Auto,
+    RedEye,
+}
+
+impl FlashMode {
+    fn value(self) -> i32 {
+        match self {
+            FlashMode::Off => V4L2_FLASH_MODE_NONE,
+            FlashMode::On => V4L2_FLASH_MODE_ON,
+            FlashMode::Torch => V4L2_FLASH_MODE_TORCH,
+            FlashMode::Auto => V4L2_FLASH_MODE_AUTO,
+            FlashMode::RedEye => V4L2_FLASH_MODE_RED_EYE,
+        }
+    }
+}
+
+/// The ISP of a VFE capture device.
+pub struct Isp<'a> {
+    device: &'a V4l2Device,
+}
+
+impl<'a> Isp<'a> {
+    pub fn new(device: &'a V4l2Device) -> Isp<'a> {
+        Isp { device }
+    }
+
+    pub fn set_auto_exposure(&self, enable: bool) -> io::Result<()> {
+        let mode = if enable {
+            V4L2_EXPOSURE_AUTO
+        } else {
+            V4L2_EXPOSURE_MANUAL
+        };
+        self.device.set_control(V4L2_CID_EXPOSURE_AUTO, mode)
+    }
+
+    /// Sets the exposure time in 100 µs units, with auto exposure off.
+    pub fn set_exposure(&self, value: i32) -> io::Result<()> {
+        self.device.set_control(V4L2_CID_EXPOSURE_ABSOLUTE, value)
+    }
+
+    /// Sets the auto exposure target bias, in the driver's EV steps.
+    pub fn set_exposure_bias(&self, value: i32) -> io::Result<()> {
+        self.device.set_control(V4L2_CID_AUTO_EXPOSURE_BIAS, value)
+    }
+
+    pub fn set_auto_white_balance(&self, enable: bool) -> io::Result<()> {
+        self.device
+            .set_control(V4L2_CID_AUTO_WHITE_BALANCE, enable as i32)
+    }
+
+    /// Sets the white balance gains, with auto white balance off.
+    pub fn set_white_balance_gains(&self, red: i32, green: i32, blue: i32) -> io::Result<()> {
+        self.device.set_control(V4L2_CID_R_GAIN, red)?;
+        self.device.set_control(V4L2_CID_G_GAIN, green)?;
+        self.device.set_control(V4L2_CID_B_GAIN, blue)
+    }
+
+    pub fn white_balance_gains(&self) -> io::Result<(i32, i32, i32)> {
+        Ok((
+            self.device.control(V4L2_CID_R_GAIN)?,
+            self.device.control(V4L2_CID_G_GAIN)?,
+            self.device.control(V4L2_CID_B_GAIN)?,
+        ))
+    }
+
+    pub fn set_flash_mode(&self, mode: FlashMode) -> io::Result<()> {
+        self.device
+            .set_control(V4L2_CID_FLASH_LED_MODE_V1, mode.value())
+    }
+
+    pub fn set_hflip(&self, enable: bool) -> io::Result<()> {
+        self.device.set_control(V4L2_CID_HFLIP, enable as i32)
+    }
+
+    pub fn set_vflip(&self, enable: bool) -> io::Result<()> {
+        self.device.set_control(V4L2_CID_VFLIP, enable as i32)
+    }
+
+    /// Copies the latest auto exposure statistics into `buf`.
+    pub fn ae_stats(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.stat_request(VIDIOC_ISP_AE_STAT_REQ, buf)
+    }
+
+    /// Copies the latest luminance histogram into `buf`.
+    pub fn histogram(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.stat_request(VIDIOC_ISP_HIST_STAT_REQ, buf)
+    }
+
+    /// Copies the latest auto focus statistics into `buf`.
+    pub fn af_stats(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.stat_request(VIDIOC_ISP_AF_STAT_REQ, buf)
+    }
+
+    /// Copies the current gamma table into `buf`.
+    pub fn gamma_table(&self, buf: &mut [u8]) -> io::Result<()> {
+        self.stat_request(VIDIOC_ISP_GAMMA_REQ, buf)
+    }
+
+    fn stat_request(&self, request: IoctlRequest, buf: &mut [u8]) -> io::Result<()> {
+        let mut stat = isp_stat_buf {
+            buf: buf.as_mut_ptr() as *mut libc::c_void,
+            buf_size: buf.len() as u32,
+        };
+        unsafe { self.device.ioctl(request, &mut stat) }.map(|_| ())
+    }
+}
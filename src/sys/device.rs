@@ -1,15 +1,25 @@
 use std::ffi::CString;
+use std::fs::File;
 use std::io;
 use std::mem;
 use std::os::unix::ffi::OsStrExt;
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
 use libc;
+use memmap::{MmapMut, MmapOptions};
 
+use super::event::Event;
+use super::frame_size::{FrameInterval, FrameSize};
+use super::info::{self, ControlInfo, DeviceInfo, Probe, QueueFormats};
+use super::ioctl::IoctlRequest;
+use super::request::MediaRequest;
+use super::stateless;
 use super::uapi::*;
+use super::window::{RawWindow, Window};
 
-fn cvt(i: libc::c_int) -> io::Result<libc::c_int> {
+pub(crate) fn cvt(i: libc::c_int) -> io::Result<libc::c_int> {
     if i == -1 {
         Err(io::Error::last_os_error())
     } else {
@@ -17,41 +27,290 @@ fn cvt(i: libc::c_int) -> io::Result<libc::c_int> {
     }
 }
 
+/// Converts `timeout` to the milliseconds `poll` takes, rounded up so
+/// short timeouts still wait, or -1 to wait forever for `None`.
+pub(crate) fn poll_millis(timeout: Option<Duration>) -> libc::c_int {
+    match timeout {
+        Some(timeout) => {
+            let millis = timeout.as_nanos().div_ceil(1_000_000);
+            millis.min(libc::c_int::MAX as u128) as libc::c_int
+        }
+        None => -1,
+    }
+}
+
+/// Polls `fd` for `events` until `deadline`, or forever if `None`, and
+/// returns the events that are ready.
+pub(crate) fn poll_events(
+    fd: RawFd,
+    events: libc::c_short,
+    deadline: Option<Instant>,
+) -> io::Result<libc::c_short> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    loop {
+        // Signals must not extend the wait.
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        match unsafe { libc::poll(&mut pfd, 1, poll_millis(remaining)) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(0),
+            _ => return Ok(pfd.revents),
+        }
+    }
+}
+
+fn check_capture_type(buf_type: v4l2_buf_type) -> io::Result<()> {
+    match buf_type {
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE
+        | v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a video capture buffer type",
+        )),
+    }
+}
+
+fn check_output_type(buf_type: v4l2_buf_type) -> io::Result<()> {
+    match buf_type {
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT
+        | v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a video output buffer type",
+        )),
+    }
+}
+
+/// Options to open a device with, like `std::fs::OpenOptions`.
+///
+/// By default devices are opened for reading and writing, blocking, and
+/// closed on exec. Opening read-only suffices to query capabilities,
+/// formats and controls, and works on nodes the user may only read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    nonblocking: bool,
+    close_on_exec: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions::new()
+    }
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            read: true,
+            write: true,
+            nonblocking: false,
+            close_on_exec: true,
+        }
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Opens with `O_NONBLOCK`, so dequeues fail with `WouldBlock` instead
+    /// of waiting for a buffer.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Opens with `O_CLOEXEC`, so the device is not inherited by child
+    /// processes.
+    pub fn close_on_exec(&mut self, close_on_exec: bool) -> &mut Self {
+        self.close_on_exec = close_on_exec;
+        self
+    }
+
+    /// Returns the flags to pass to `open`.
+    fn flags(&self) -> io::Result<libc::c_int> {
+        let mut flags = match (self.read, self.write) {
+            (true, true) => libc::O_RDWR,
+            (true, false) => libc::O_RDONLY,
+            (false, true) => libc::O_WRONLY,
+            (false, false) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "device must be opened for reading or writing",
+                ))
+            }
+        };
+        if self.nonblocking {
+            flags |= libc::O_NONBLOCK;
+        }
+        if self.close_on_exec {
+            flags |= libc::O_CLOEXEC;
+        }
+        Ok(flags)
+    }
+
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> io::Result<V4l2Device> {
+        let flags = self.flags()?;
+        let cstr = match CString::new(path.as_ref().as_os_str().as_bytes()) {
+            Ok(s) => s,
+            Err(_) => return Err(io::Error::from_raw_os_error(libc::EINVAL)),
+        };
+
+        let fd = cvt(unsafe { libc::open(cstr.as_ptr(), flags) })?;
+        Ok(V4l2Device {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+}
+
 /// Video4Linux 장치 파일 디스크립터를 나타내는 구조체.
 ///
+/// # Thread safety
+///
+/// A device is `Send` and `Sync`: the driver serializes ioctls on a file
+/// handle, so one thread may dequeue buffers while another queues them or
+/// sets controls. Changing formats or timings is possible through a shared
+/// reference too, but drivers refuse it with `EBUSY` while buffers are
+/// allocated, so it cannot pull buffers away from another thread.
 #[derive(Debug)]
 pub struct V4l2Device {
-    fd: libc::c_int,
+    fd: OwnedFd,
 }
 
 impl V4l2Device {
+    /// Opens `path` for reading and writing, see `OpenOptions` for other
+    /// modes.
     pub fn open<P: AsRef<Path>>(path: P) -> io::Result<V4l2Device> {
-        use libc::{EINVAL, O_RDWR};
-
-        let cstr = match CString::new(path.as_ref().as_os_str().as_bytes()) {
-            Ok(s) => s,
-            Err(_) => return Err(io::Error::from_raw_os_error(EINVAL)),
-        };
+        OpenOptions::new().close_on_exec(false).open(path)
+    }
 
+    /// Duplicates the handle with `dup`. Both handles refer to the same
+    /// open file, so e.g. controls can be set and events dequeued through
+    /// one on another thread while the other streams.
+    pub fn try_clone(&self) -> io::Result<V4l2Device> {
         Ok(V4l2Device {
-            fd: cvt(unsafe { libc::open(cstr.as_ptr(), O_RDWR) })?,
+            fd: self.fd.try_clone()?,
         })
     }
 
     pub fn capability(&self) -> io::Result<v4l2_capability> {
         unsafe {
             let mut caps = mem::zeroed::<v4l2_capability>();
-            cvt(libc::ioctl(self.fd, VIDIOC_QUERYCAP, &mut caps)).map(|_| caps)
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_QUERYCAP, &mut caps)).map(|_| caps)
         }
     }
 
+    /// Returns the capabilities of the opened node, which may be a subset
+    /// of those of the whole device.
+    pub fn device_caps(&self) -> io::Result<u32> {
+        self.capability().map(|caps| {
+            if caps.capabilities & V4L2_CAP_DEVICE_CAPS != 0 {
+                caps.device_caps
+            } else {
+                caps.capabilities
+            }
+        })
+    }
+
+    /// Returns the location of the device reported by the driver, e.g.
+    /// `usb-0000:00:14.0-2`.
+    pub fn bus_info(&self) -> io::Result<String> {
+        self.capability().map(|caps| info::cstr(&caps.bus_info))
+    }
+
+    /// Collects the capabilities, formats, streaming parameters, inputs
+    /// and controls of the device into one report, e.g. for bug reports.
+    pub fn info(&self) -> io::Result<DeviceInfo> {
+        DeviceInfo::query(self)
+    }
+
+    /// Inventories the device at `path` without disturbing a process that
+    /// is streaming from it.
+    ///
+    /// The device is opened read-only and non-blocking, and only queried:
+    /// capabilities, current formats and parameters, inputs, controls and
+    /// the supported formats are read, while `S_FMT`, `REQBUFS` and other
+    /// calls that change the state or claim the queue are never made.
+    pub fn probe<P: AsRef<Path>>(path: P) -> io::Result<Probe> {
+        let device = OpenOptions::new()
+            .write(false)
+            .nonblocking(true)
+            .open(path)?;
+        Ok(Probe {
+            info: device.info()?,
+            formats: device.capabilities_tree()?,
+        })
+    }
+
+    fn has_caps(&self, mask: u32) -> io::Result<bool> {
+        self.device_caps().map(|caps| caps & mask != 0)
+    }
+
+    /// Returns true if the node supports streaming I/O.
+    pub fn supports_streaming(&self) -> io::Result<bool> {
+        self.has_caps(V4L2_CAP_STREAMING)
+    }
+
+    /// Returns true if the node supports `read()` and `write()`.
+    pub fn supports_readwrite(&self) -> io::Result<bool> {
+        self.has_caps(V4L2_CAP_READWRITE)
+    }
+
+    /// Returns true if the node captures video, including the capture queue
+    /// of memory-to-memory devices.
+    pub fn supports_capture(&self) -> io::Result<bool> {
+        self.has_caps(
+            V4L2_CAP_VIDEO_CAPTURE
+                | V4L2_CAP_VIDEO_CAPTURE_MPLANE
+                | V4L2_CAP_VIDEO_M2M
+                | V4L2_CAP_VIDEO_M2M_MPLANE,
+        )
+    }
+
+    /// Returns true if the node outputs video, including the output queue
+    /// of memory-to-memory devices.
+    pub fn supports_output(&self) -> io::Result<bool> {
+        self.has_caps(
+            V4L2_CAP_VIDEO_OUTPUT
+                | V4L2_CAP_VIDEO_OUTPUT_MPLANE
+                | V4L2_CAP_VIDEO_M2M
+                | V4L2_CAP_VIDEO_M2M_MPLANE,
+        )
+    }
+
+    /// Returns true if the node uses the multi-planar API.
+    pub fn supports_mplane(&self) -> io::Result<bool> {
+        self.has_caps(
+            V4L2_CAP_VIDEO_CAPTURE_MPLANE
+                | V4L2_CAP_VIDEO_OUTPUT_MPLANE
+                | V4L2_CAP_VIDEO_M2M_MPLANE,
+        )
+    }
+
     fn enum_format(&self, buf_type: v4l2_buf_type, index: u32) -> io::Result<v4l2_fmtdesc> {
         unsafe {
-            let mut fmtdesc = mem::zeroed::<v4l2_fmtdesc>();
-            fmtdesc.index = index;
-            fmtdesc.typ = buf_type;
-
-            cvt(libc::ioctl(self.fd, VIDIOC_ENUM_FMT, &mut fmtdesc)).map(|_| fmtdesc)
+            let mut fmtdesc = v4l2_fmtdesc::new(buf_type, index);
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_ENUM_FMT,
+                &mut fmtdesc,
+            ))
+            .map(|_| fmtdesc)
         }
     }
 
@@ -65,22 +324,65 @@ impl V4l2Device {
 
     fn enum_frame_size(&self, pixel_format: u32, index: u32) -> io::Result<v4l2_frmsizeenum> {
         unsafe {
-            let mut frmsize = mem::zeroed::<v4l2_frmsizeenum>();
-            frmsize.index = index;
-            frmsize.pixel_format = pixel_format;
-
-            cvt(libc::ioctl(self.fd, VIDIOC_ENUM_FRAMESIZES, &mut frmsize)).map(|_| frmsize)
+            let mut frmsize = v4l2_frmsizeenum::new(pixel_format, index);
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_ENUM_FRAMESIZES,
+                &mut frmsize,
+            ))
+            .map(|_| frmsize)
         }
     }
 
     pub fn supported_frame_sizes<'a>(&'a self, pixel_format: u32) -> SupportedFrameSizes<'a> {
         SupportedFrameSizes {
             dev: self,
-            pixel_format: pixel_format,
+            pixel_format,
             index: 0,
         }
     }
 
+    fn enum_frame_interval(
+        &self,
+        pixel_format: u32,
+        width: u32,
+        height: u32,
+        index: u32,
+    ) -> io::Result<v4l2_frmivalenum> {
+        let mut frmival = v4l2_frmivalenum::new(pixel_format, width, height, index);
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_ENUM_FRAMEINTERVALS,
+                &mut frmival,
+            ))
+            .map(|_| frmival)
+        }
+    }
+
+    /// Returns the frame intervals supported for `pixel_format` at
+    /// `width`x`height`.
+    pub fn supported_frame_intervals(
+        &self,
+        pixel_format: u32,
+        width: u32,
+        height: u32,
+    ) -> SupportedFrameIntervals<'_> {
+        SupportedFrameIntervals {
+            dev: self,
+            pixel_format,
+            width,
+            height,
+            index: 0,
+        }
+    }
+
+    /// Returns the formats of every queue of the device, each with its
+    /// frame sizes and their frame intervals.
+    pub fn capabilities_tree(&self) -> io::Result<Vec<QueueFormats>> {
+        info::capabilities_tree(self)
+    }
+
     /// Returns current `v4l2_format` for the specified `v4l2_buf_type`.
     ///
     fn format(&self, buf_type: v4l2_buf_type) -> io::Result<v4l2_format> {
@@ -89,12 +391,12 @@ impl V4l2Device {
                 typ: buf_type,
                 fmt: mem::zeroed(),
             };
-            cvt(libc::ioctl(self.fd, VIDIOC_G_FMT, &mut fmt)).map(|_| fmt)
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_FMT, &mut fmt)).map(|_| fmt)
         }
     }
 
     fn set_format(&self, fmt: &mut v4l2_format) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_S_FMT, fmt)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_S_FMT, fmt)).map(|_| ()) }
     }
 
     /// Returns current `v4l2_format` for the specified `v4l2_buf_type` assuming
@@ -134,55 +436,507 @@ impl V4l2Device {
         self.set_pix_format(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT, fmt)
     }
 
+    fn window(&self, buf_type: v4l2_buf_type) -> io::Result<Window> {
+        // The first call only reports how many clips are set.
+        let fmt = self.format(buf_type)?;
+        let clipcount = unsafe { fmt.fmt.win.clipcount };
+        if clipcount == 0 {
+            return Ok(Window::from_raw(unsafe { &fmt.fmt.win }));
+        }
+
+        let mut raw = RawWindow::with_capacity(clipcount);
+        let mut fmt = v4l2_format {
+            typ: buf_type,
+            fmt: v4l2_format_fmt { win: *raw.as_raw() },
+        };
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_FMT, &mut fmt))? };
+        raw.update(unsafe { &fmt.fmt.win });
+        Ok(raw.to_window())
+    }
+
+    fn set_window(&self, buf_type: v4l2_buf_type, window: &Window) -> io::Result<Window> {
+        let mut raw = window.to_raw()?;
+        let mut fmt = v4l2_format {
+            typ: buf_type,
+            fmt: v4l2_format_fmt { win: *raw.as_raw() },
+        };
+        self.set_format(&mut fmt)?;
+        raw.update(unsafe { &fmt.fmt.win });
+        Ok(raw.to_window())
+    }
+
+    /// Returns the window of the video overlay.
+    pub fn overlay_window(&self) -> io::Result<Window> {
+        self.window(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OVERLAY)
+    }
+
+    /// Places the video overlay, and returns the window the driver chose.
+    pub fn set_overlay_window(&self, window: &Window) -> io::Result<Window> {
+        self.set_window(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OVERLAY, window)
+    }
+
+    /// Returns the window of the output overlay (OSD) on the outgoing
+    /// video.
+    pub fn output_overlay_window(&self) -> io::Result<Window> {
+        self.window(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT_OVERLAY)
+    }
+
+    /// Places the output overlay (OSD) on the outgoing video, and returns
+    /// the window the driver chose.
+    pub fn set_output_overlay_window(&self, window: &Window) -> io::Result<Window> {
+        self.set_window(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT_OVERLAY, window)
+    }
+
+    /// Returns the rectangle of selection `target`, a `V4L2_SEL_TGT_*`
+    /// constant, on the `buf_type` queue.
+    pub fn selection(&self, buf_type: v4l2_buf_type, target: u32) -> io::Result<v4l2_rect> {
+        let mut sel = v4l2_selection {
+            typ: buf_type as u32,
+            target,
+            flags: 0,
+            r: v4l2_rect {
+                left: 0,
+                top: 0,
+                width: 0,
+                height: 0,
+            },
+            reserved: [0; 9],
+        };
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_G_SELECTION,
+                &mut sel,
+            ))
+            .map(|_| sel.r)
+        }
+    }
+
+    /// Sets the rectangle of selection `target`, and returns the one the
+    /// driver chose. `flags` are `V4L2_SEL_FLAG_*` constants constraining
+    /// how the driver may adjust `rect`.
+    ///
+    /// Drivers that can crop or compose without reallocating buffers allow
+    /// this while streaming; others fail with `EBUSY`.
+    pub fn set_selection(
+        &self,
+        buf_type: v4l2_buf_type,
+        target: u32,
+        flags: u32,
+        rect: &v4l2_rect,
+    ) -> io::Result<v4l2_rect> {
+        let mut sel = v4l2_selection {
+            typ: buf_type as u32,
+            target,
+            flags,
+            r: *rect,
+            reserved: [0; 9],
+        };
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_S_SELECTION,
+                &mut sel,
+            ))
+            .map(|_| sel.r)
+        }
+    }
+
+    /// Returns the framebuffer the overlay is drawn onto.
+    pub fn framebuffer(&self) -> io::Result<v4l2_framebuffer> {
+        unsafe {
+            let mut fbuf = mem::zeroed::<v4l2_framebuffer>();
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_FBUF, &mut fbuf)).map(|_| fbuf)
+        }
+    }
+
+    /// Sets the overlay framebuffer. Changing `base` usually requires
+    /// `CAP_SYS_ADMIN`; the flags can be changed by anyone.
+    pub fn set_framebuffer(&self, fbuf: &v4l2_framebuffer) -> io::Result<()> {
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_S_FBUF, fbuf)).map(|_| ()) }
+    }
+
+    /// Replaces the `V4L2_FBUF_FLAG_*` flags of the overlay framebuffer,
+    /// e.g. to enable chroma keying or global alpha blending of an OSD, and
+    /// returns the flags the driver kept.
+    pub fn set_framebuffer_flags(&self, flags: u32) -> io::Result<u32> {
+        let mut fbuf = self.framebuffer()?;
+        fbuf.flags = flags;
+        self.set_framebuffer(&fbuf)?;
+        self.framebuffer().map(|fbuf| fbuf.flags)
+    }
+
+    /// Starts or stops the video overlay. Output overlays have no V4L2
+    /// switch; they are blanked through the framebuffer device instead.
+    pub fn overlay(&self, enable: bool) -> io::Result<()> {
+        let enable = enable as libc::c_int;
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_OVERLAY, &enable)).map(|_| ()) }
+    }
+
     fn stream_parm(&self, buf_type: v4l2_buf_type) -> io::Result<v4l2_streamparm> {
         unsafe {
             let mut parm = v4l2_streamparm {
                 typ: buf_type,
                 parm: mem::zeroed(),
             };
-            cvt(libc::ioctl(self.fd, VIDIOC_G_PARM, &mut parm)).map(|_| parm)
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_PARM, &mut parm)).map(|_| parm)
         }
     }
 
     fn set_stream_parm(&self, parm: &mut v4l2_streamparm) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_S_PARM, parm)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_S_PARM, parm)).map(|_| ()) }
     }
 
     pub fn capture_parm(&self) -> io::Result<v4l2_captureparm> {
-        self.stream_parm(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)
-            .map(|parm| unsafe { parm.parm.capture })
+        self.capture_parm_for(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE)
     }
 
     pub fn set_capture_parm(&self, parm: &v4l2_captureparm) -> io::Result<v4l2_captureparm> {
+        self.set_capture_parm_for(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE, parm)
+    }
+
+    pub fn output_parm(&self) -> io::Result<v4l2_outputparm> {
+        self.output_parm_for(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT)
+    }
+
+    pub fn set_output_parm(&self, parm: &v4l2_outputparm) -> io::Result<v4l2_outputparm> {
+        self.set_output_parm_for(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT, parm)
+    }
+
+    /// Returns the capture parameters of a single or multi-planar capture
+    /// queue.
+    pub fn capture_parm_for(&self, buf_type: v4l2_buf_type) -> io::Result<v4l2_captureparm> {
+        check_capture_type(buf_type)?;
+        self.stream_parm(buf_type)
+            .map(|parm| unsafe { parm.parm.capture })
+    }
+
+    pub fn set_capture_parm_for(
+        &self,
+        buf_type: v4l2_buf_type,
+        parm: &v4l2_captureparm,
+    ) -> io::Result<v4l2_captureparm> {
+        check_capture_type(buf_type)?;
         let mut parm = v4l2_streamparm {
-            typ: v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            typ: buf_type,
             parm: _v4l2_streamparm_parm { capture: *parm },
         };
         self.set_stream_parm(&mut parm)
             .map(|_| unsafe { parm.parm.capture })
     }
 
-    pub fn output_parm(&self) -> io::Result<v4l2_outputparm> {
-        self.stream_parm(v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT)
+    /// Returns the output parameters of a single or multi-planar output
+    /// queue.
+    pub fn output_parm_for(&self, buf_type: v4l2_buf_type) -> io::Result<v4l2_outputparm> {
+        check_output_type(buf_type)?;
+        self.stream_parm(buf_type)
             .map(|parm| unsafe { parm.parm.output })
     }
 
-    pub fn set_output_parm(&self, parm: &v4l2_outputparm) -> io::Result<v4l2_outputparm> {
+    pub fn set_output_parm_for(
+        &self,
+        buf_type: v4l2_buf_type,
+        parm: &v4l2_outputparm,
+    ) -> io::Result<v4l2_outputparm> {
+        check_output_type(buf_type)?;
         let mut parm = v4l2_streamparm {
-            typ: v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT,
+            typ: buf_type,
             parm: _v4l2_streamparm_parm { output: *parm },
         };
         self.set_stream_parm(&mut parm)
             .map(|_| unsafe { parm.parm.output })
     }
 
+    /// Sets the number of buffers the driver uses internally for `read()`,
+    /// and returns the number it accepted.
+    pub fn set_read_buffers(&self, buf_type: v4l2_buf_type, count: u32) -> io::Result<u32> {
+        let mut parm = self.capture_parm_for(buf_type)?;
+        parm.readbuffers = count;
+        self.set_capture_parm_for(buf_type, &parm)
+            .map(|parm| parm.readbuffers)
+    }
+
+    /// Sets the number of buffers the driver uses internally for `write()`,
+    /// and returns the number it accepted.
+    pub fn set_write_buffers(&self, buf_type: v4l2_buf_type, count: u32) -> io::Result<u32> {
+        let mut parm = self.output_parm_for(buf_type)?;
+        parm.writebuffers = count;
+        self.set_output_parm_for(buf_type, &parm)
+            .map(|parm| parm.writebuffers)
+    }
+
     pub fn input(&self) -> io::Result<i32> {
         let mut input = -1;
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_G_INPUT, &mut input)).map(|_| input) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_INPUT, &mut input)).map(|_| input) }
     }
 
     pub fn set_input(&self, input: i32) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_S_INPUT, &input)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_S_INPUT, &input)).map(|_| ()) }
+    }
+
+    /// Returns the video standard of the current input.
+    pub fn standard(&self) -> io::Result<v4l2_std_id> {
+        let mut std: v4l2_std_id = 0;
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_STD, &mut std)).map(|_| std) }
+    }
+
+    pub fn set_standard(&self, std: v4l2_std_id) -> io::Result<()> {
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_S_STD, &std)).map(|_| ()) }
+    }
+
+    /// Returns the standards the signal on the current input may be, as
+    /// sensed by the driver. Without a signal, drivers return
+    /// `V4L2_STD_UNKNOWN` or fail with `ENODATA`.
+    pub fn query_standard(&self) -> io::Result<v4l2_std_id> {
+        let mut std: v4l2_std_id = 0;
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_QUERYSTD, &mut std)).map(|_| std) }
+    }
+
+    /// Describes input `index`, including its current status.
+    pub fn enum_input(&self, index: u32) -> io::Result<v4l2_input> {
+        unsafe {
+            let mut input = mem::zeroed::<v4l2_input>();
+            input.index = index;
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_ENUMINPUT,
+                &mut input,
+            ))
+            .map(|_| input)
+        }
+    }
+
+    pub fn inputs(&self) -> Inputs<'_> {
+        Inputs {
+            dev: self,
+            index: 0,
+        }
+    }
+
+    pub fn query_control(&self, id: u32) -> io::Result<v4l2_queryctrl> {
+        unsafe {
+            let mut qctrl = mem::zeroed::<v4l2_queryctrl>();
+            qctrl.id = id;
+
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_QUERYCTRL,
+                &mut qctrl,
+            ))
+            .map(|_| qctrl)
+        }
+    }
+
+    /// Returns entry `index` of a menu control. Drivers fail with `EINVAL`
+    /// for entries they skip.
+    pub fn query_menu(&self, id: u32, index: u32) -> io::Result<v4l2_querymenu> {
+        let mut menu = v4l2_querymenu {
+            id,
+            index,
+            u: _v4l2_querymenu_u { value: 0 },
+            reserved: 0,
+        };
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_QUERYMENU,
+                &mut menu,
+            ))
+            .map(|_| menu)
+        }
+    }
+
+    /// Returns an iterator over all controls, using `V4L2_CTRL_FLAG_NEXT_CTRL`.
+    ///
+    pub fn controls(&self) -> Controls<'_> {
+        Controls { dev: self, id: 0 }
+    }
+
+    /// Describes all controls with their ranges and current values.
+    pub fn control_report(&self) -> Vec<ControlInfo> {
+        info::control_report(self)
+    }
+
+    pub fn control(&self, id: u32) -> io::Result<i32> {
+        let mut ctrl = v4l2_control { id, value: 0 };
+        unsafe {
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_G_CTRL, &mut ctrl)).map(|_| ctrl.value)
+        }
+    }
+
+    pub fn set_control(&self, id: u32, value: i32) -> io::Result<()> {
+        let mut ctrl = v4l2_control { id, value };
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_S_CTRL, &mut ctrl)).map(|_| ()) }
+    }
+
+    /// Sets a compound control, whose payload is the plain struct `value`.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be the payload type the driver expects for `id`.
+    pub unsafe fn set_compound_control<T: Copy>(&self, id: u32, value: &T) -> io::Result<()> {
+        let mut payload = *value;
+        let mut ctrls = [stateless::ext_control(id, &mut payload)];
+        self.set_ext_controls(V4L2_CTRL_WHICH_CUR_VAL, -1, &mut ctrls)
+    }
+
+    /// Sets several controls at once. With `V4L2_CTRL_WHICH_REQUEST_VAL`
+    /// they are stored in the media request `request_fd` instead, to be
+    /// applied when the request is queued.
+    ///
+    /// # Safety
+    ///
+    /// Payloads of compound controls must be of the types the driver
+    /// expects.
+    pub unsafe fn set_ext_controls(
+        &self,
+        which: u32,
+        request_fd: RawFd,
+        controls: &mut [v4l2_ext_control],
+    ) -> io::Result<()> {
+        let mut ctrls = v4l2_ext_controls {
+            which,
+            count: controls.len() as u32,
+            error_idx: 0,
+            request_fd,
+            reserved: [0],
+            controls: controls.as_mut_ptr(),
+        };
+        cvt(libc::ioctl(
+            self.fd.as_raw_fd(),
+            VIDIOC_S_EXT_CTRLS,
+            &mut ctrls,
+        ))
+        .map(|_| ())
+    }
+
+    /// Stores controls in `request`, e.g. the parsed headers of the frame
+    /// whose bitstream buffer is queued to the same request.
+    ///
+    /// # Safety
+    ///
+    /// Payloads of compound controls must be of the types the driver
+    /// expects.
+    pub unsafe fn set_request_controls(
+        &self,
+        request: &MediaRequest,
+        controls: &mut [v4l2_ext_control],
+    ) -> io::Result<()> {
+        self.set_ext_controls(V4L2_CTRL_WHICH_REQUEST_VAL, request.as_raw_fd(), controls)
+    }
+
+    /// Reads a compound control, whose payload is the plain struct `T`.
+    /// `which` is `V4L2_CTRL_WHICH_CUR_VAL` or `V4L2_CTRL_WHICH_DEF_VAL`.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be the payload type the driver returns for `id`.
+    pub unsafe fn compound_control<T: Copy>(&self, id: u32, which: u32) -> io::Result<T> {
+        let mut payload = mem::zeroed::<T>();
+        let mut ctrls = [stateless::ext_control(id, &mut payload)];
+        let mut arg = v4l2_ext_controls {
+            which,
+            count: 1,
+            error_idx: 0,
+            request_fd: -1,
+            reserved: [0],
+            controls: ctrls.as_mut_ptr(),
+        };
+        cvt(libc::ioctl(
+            self.fd.as_raw_fd(),
+            VIDIOC_G_EXT_CTRLS,
+            &mut arg,
+        ))?;
+        Ok(payload)
+    }
+
+    /// Returns the region automatic exposure, white balance and the other
+    /// algorithms of a UVC camera meter, in pixels of the current format.
+    pub fn metering_region(&self) -> io::Result<v4l2_rect> {
+        unsafe {
+            self.compound_control(
+                V4L2_CID_UVC_REGION_OF_INTEREST_RECT,
+                V4L2_CTRL_WHICH_CUR_VAL,
+            )
+        }
+    }
+
+    /// Restricts the automatic algorithms in `auto`, a mask of
+    /// `V4L2_UVC_REGION_OF_INTEREST_AUTO_*` bits, to `region` in pixels of
+    /// the current format, e.g. to expose for a face. Algorithms not in
+    /// `auto` go back to metering the whole frame. Returns the region the
+    /// camera chose.
+    ///
+    /// Fails with `EINVAL` on cameras without a region of interest
+    /// control. Those may still offer `V4L2_CID_EXPOSURE_METERING`, whose
+    /// spot mode meters the center of the frame.
+    pub fn set_metering_region(&self, region: &v4l2_rect, auto: i32) -> io::Result<v4l2_rect> {
+        unsafe { self.set_compound_control(V4L2_CID_UVC_REGION_OF_INTEREST_RECT, region)? };
+        self.set_control(V4L2_CID_UVC_REGION_OF_INTEREST_AUTO, auto)?;
+        self.metering_region()
+    }
+
+    /// Returns the JPEG settings of drivers predating the JPEG control
+    /// class.
+    pub fn jpeg_compression(&self) -> io::Result<v4l2_jpegcompression> {
+        unsafe {
+            let mut jpeg: v4l2_jpegcompression = mem::zeroed();
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_G_JPEGCOMP,
+                &mut jpeg,
+            ))
+            .map(|_| jpeg)
+        }
+    }
+
+    pub fn set_jpeg_compression(&self, jpeg: &v4l2_jpegcompression) -> io::Result<()> {
+        let mut jpeg = *jpeg;
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_S_JPEGCOMP,
+                &mut jpeg,
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Detects the digital video timings of the current input signal.
+    pub fn query_dv_timings(&self) -> io::Result<v4l2_dv_timings> {
+        unsafe {
+            let mut timings: v4l2_dv_timings = mem::zeroed();
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_QUERY_DV_TIMINGS,
+                &mut timings,
+            ))
+            .map(|_| timings)
+        }
+    }
+
+    pub fn dv_timings(&self) -> io::Result<v4l2_dv_timings> {
+        unsafe {
+            let mut timings: v4l2_dv_timings = mem::zeroed();
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_G_DV_TIMINGS,
+                &mut timings,
+            ))
+            .map(|_| timings)
+        }
+    }
+
+    pub fn set_dv_timings(&self, timings: &v4l2_dv_timings) -> io::Result<v4l2_dv_timings> {
+        let mut timings = *timings;
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_S_DV_TIMINGS,
+                &mut timings,
+            ))
+            .map(|_| timings)
+        }
     }
 
     pub fn request_buffers(
@@ -191,14 +945,71 @@ impl V4l2Device {
         memory: v4l2_memory,
         count: usize,
     ) -> io::Result<usize> {
+        self.reqbufs(buf_type, memory, count)
+            .map(|reqbufs| reqbufs.count as usize)
+    }
+
+    /// Returns the `V4L2_BUF_CAP_*` flags of the `buf_type` queue: the
+    /// memory types it supports and its optional features. Kernels before
+    /// 4.20 do not report them and return 0.
+    ///
+    /// The flags are queried by requesting zero mmap buffers, which frees
+    /// buffers requested before and fails with `EBUSY` while they are
+    /// mapped or streaming.
+    pub fn buffer_capabilities(&self, buf_type: v4l2_buf_type) -> io::Result<u32> {
+        self.reqbufs(buf_type, v4l2_memory::V4L2_MEMORY_MMAP, 0)
+            .map(|reqbufs| reqbufs.capabilities)
+    }
+
+    /// Returns the `V4L2_BUF_CAP_*` flags of the `buf_type` queue like
+    /// `buffer_capabilities`, but keeps the buffers, by asking to create
+    /// zero of them. Drivers that cannot create buffers fail with
+    /// `ENOTTY`.
+    pub fn peek_buffer_capabilities(&self, buf_type: v4l2_buf_type) -> io::Result<u32> {
+        unsafe {
+            let mut create = v4l2_create_buffers {
+                index: 0,
+                count: 0,
+                memory: v4l2_memory::V4L2_MEMORY_MMAP,
+                format: v4l2_format {
+                    typ: buf_type,
+                    fmt: mem::zeroed(),
+                },
+                capabilities: 0,
+                flags: 0,
+                max_num_buffers: 0,
+                reserved: [0; 5],
+            };
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_CREATE_BUFS,
+                &mut create,
+            ))
+            .map(|_| create.capabilities)
+        }
+    }
+
+    fn reqbufs(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+        count: usize,
+    ) -> io::Result<v4l2_requestbuffers> {
         let mut reqbufs = v4l2_requestbuffers {
             typ: buf_type,
             count: count as u32,
-            memory: memory,
-            reserved: [0; 2],
+            memory,
+            capabilities: 0,
+            flags: 0,
+            reserved: [0; 3],
         };
         unsafe {
-            cvt(libc::ioctl(self.fd, VIDIOC_REQBUFS, &mut reqbufs)).map(|_| reqbufs.count as usize)
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_REQBUFS,
+                &mut reqbufs,
+            ))
+            .map(|_| reqbufs)
         }
     }
 
@@ -208,27 +1019,53 @@ impl V4l2Device {
         memory: v4l2_memory,
         index: usize,
     ) -> io::Result<v4l2_buffer> {
-        unsafe {
-            let mut buf = mem::zeroed::<v4l2_buffer>();
-            buf.typ = buf_type;
-            buf.memory = memory;
-            buf.index = index as u32;
-
-            cvt(libc::ioctl(self.fd, VIDIOC_QUERYBUF, &mut buf)).map(|_| buf)
-        }
+        let mut buf = v4l2_buffer::new(buf_type, memory);
+        buf.index = index as u32;
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_QUERYBUF, &mut buf)).map(|_| buf) }
     }
 
     pub fn buffers<'a>(&'a self, buf_type: v4l2_buf_type, memory: v4l2_memory) -> Buffers<'a> {
         Buffers {
             dev: self,
             typ: buf_type,
-            memory: memory,
+            memory,
             index: 0,
         }
     }
 
+    /// Maps all mmap buffers of `buf_type` requested with
+    /// `request_buffers`.
+    pub(crate) fn map_buffers(&self, buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>> {
+        // The mappings outlive the duplicate, which is closed on return.
+        let file = File::from(self.fd.try_clone()?);
+
+        self.buffers(buf_type, v4l2_memory::V4L2_MEMORY_MMAP)
+            .map(|buf| unsafe {
+                MmapOptions::new()
+                    .len(buf.length as usize)
+                    .offset(buf.m.offset as u64)
+                    .map_mut(&file)
+            })
+            .collect()
+    }
+
     pub fn queue_buffer(&self, buf: &v4l2_buffer) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_QBUF, buf)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_QBUF, buf)).map(|_| ()) }
+    }
+
+    /// Queues `buf` to `request` instead of the driver, so it is only
+    /// processed once the request is queued, together with the controls
+    /// set in it.
+    #[cfg(not(feature = "sunxi-vfe"))]
+    pub fn queue_buffer_in_request(
+        &self,
+        buf: &v4l2_buffer,
+        request: &MediaRequest,
+    ) -> io::Result<()> {
+        let mut buf = buf.clone();
+        buf.flags |= V4L2_BUF_FLAG_REQUEST_FD;
+        buf.request_fd = request.as_raw_fd();
+        self.queue_buffer(&buf)
     }
 
     pub fn dequeue_buffer(
@@ -236,82 +1073,314 @@ impl V4l2Device {
         buf_type: v4l2_buf_type,
         memory: v4l2_memory,
     ) -> io::Result<v4l2_buffer> {
-        let mut buf: v4l2_buffer = unsafe { mem::zeroed() };
-        buf.typ = buf_type;
-        buf.memory = memory;
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_DQBUF, &mut buf)).map(|_| buf) }
+        let mut buf = v4l2_buffer::new(buf_type, memory);
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_DQBUF, &mut buf)).map(|_| buf) }
+    }
+
+    /// Exports a plane of an mmap buffer as a dmabuf file descriptor.
+    pub fn export_buffer(
+        &self,
+        buf_type: v4l2_buf_type,
+        index: usize,
+        plane: usize,
+    ) -> io::Result<OwnedFd> {
+        let mut expbuf = v4l2_exportbuffer {
+            typ: buf_type,
+            index: index as u32,
+            plane: plane as u32,
+            flags: (libc::O_RDWR | libc::O_CLOEXEC) as u32,
+            fd: -1,
+            reserved: [0; 11],
+        };
+        unsafe {
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_EXPBUF, &mut expbuf))?;
+            Ok(OwnedFd::from_raw_fd(expbuf.fd))
+        }
     }
 
     pub fn stream_on(&self, buf_type: v4l2_buf_type) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_STREAMON, &buf_type)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_STREAMON, &buf_type)).map(|_| ()) }
     }
 
     pub fn stream_off(&self, buf_type: v4l2_buf_type) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_STREAMOFF, &buf_type)).map(|_| ()) }
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_STREAMOFF,
+                &buf_type,
+            ))
+            .map(|_| ())
+        }
     }
 
-    pub fn subscribe_event(&self, event: u32) -> io::Result<()> {
+    fn subscribe(&self, event: u32, id: u32, flags: u32) -> io::Result<()> {
+        let sub = v4l2_event_subscription {
+            typ: event,
+            id,
+            flags,
+            reserved: [0; 5],
+        };
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_SUBSCRIBE_EVENT,
+                &sub,
+            ))
+            .map(|_| ())
+        }
+    }
+
+    fn unsubscribe(&self, event: u32, id: u32) -> io::Result<()> {
         let sub = v4l2_event_subscription {
             typ: event,
-            id: 0,
+            id,
             flags: 0,
             reserved: [0; 5],
         };
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_SUBSCRIBE_EVENT, &sub)).map(|_| ()) }
+        unsafe {
+            cvt(libc::ioctl(
+                self.fd.as_raw_fd(),
+                VIDIOC_UNSUBSCRIBE_EVENT,
+                &sub,
+            ))
+            .map(|_| ())
+        }
+    }
+
+    /// Subscribes to events of type `event`.
+    pub fn subscribe_event(&self, event: u32) -> io::Result<()> {
+        self.subscribe(event, 0, 0)
+    }
+
+    /// Subscribes to events of type `event`, with `flags` a mask of
+    /// `V4L2_EVENT_SUB_FL_*`, e.g. `SEND_INITIAL` to receive the current
+    /// state right away.
+    pub fn subscribe_event_with_flags(&self, event: u32, flags: u32) -> io::Result<()> {
+        self.subscribe(event, 0, flags)
+    }
+
+    /// Subscribes to changes of the control `id`.
+    ///
+    /// An `Event::Ctrl` carrying the current value is delivered right away,
+    /// followed by one for every change of its value, flags or range, e.g.
+    /// when auto exposure updates the exposure time.
+    pub fn subscribe_control(&self, id: u32) -> io::Result<()> {
+        self.subscribe(V4L2_EVENT_CTRL, id, V4L2_EVENT_SUB_FL_SEND_INITIAL)
+    }
+
+    /// Stops the change events of the control `id`.
+    pub fn unsubscribe_control(&self, id: u32) -> io::Result<()> {
+        self.unsubscribe(V4L2_EVENT_CTRL, id)
+    }
+
+    /// Stops the delivery of events of type `event`.
+    pub fn unsubscribe_event(&self, event: u32) -> io::Result<()> {
+        self.unsubscribe(event, 0)
+    }
+
+    /// Stops the delivery of all subscribed events.
+    pub fn unsubscribe_all_events(&self) -> io::Result<()> {
+        self.unsubscribe_event(V4L2_EVENT_ALL)
+    }
+
+    /// Subscribes to `V4L2_EVENT_FRAME_SYNC`, sent when the exposure of a
+    /// frame starts, e.g. to trigger a flash or strobe.
+    pub fn subscribe_frame_sync(&self) -> io::Result<()> {
+        self.subscribe_event(V4L2_EVENT_FRAME_SYNC)
+    }
+
+    /// Subscribes to `V4L2_EVENT_MOTION_DET`, sent by devices with hardware
+    /// motion detection when the set of regions with motion changes.
+    ///
+    /// Detection itself is configured with the `V4L2_CID_DETECT_MD_*`
+    /// controls, see `enable_motion_detection`.
+    pub fn subscribe_motion_detection(&self) -> io::Result<()> {
+        self.subscribe_event(V4L2_EVENT_MOTION_DET)
+    }
+
+    /// Enables global motion detection with the given threshold and
+    /// subscribes to its events.
+    pub fn enable_motion_detection(&self, threshold: i32) -> io::Result<()> {
+        self.set_control(V4L2_CID_DETECT_MD_GLOBAL_THRESHOLD, threshold)?;
+        self.set_control(V4L2_CID_DETECT_MD_MODE, V4L2_DETECT_MD_MODE_GLOBAL)?;
+        self.subscribe_motion_detection()
+    }
+
+    /// Subscribes to `V4L2_EVENT_SOURCE_CHANGE`, sent when the signal on
+    /// an input (e.g. HDMI) or the stream of a decoder changes resolution.
+    pub fn subscribe_source_change(&self) -> io::Result<()> {
+        self.subscribe_event(V4L2_EVENT_SOURCE_CHANGE)
+    }
+
+    /// Subscribes to `V4L2_EVENT_VSYNC`, sent on the vertical sync of the
+    /// video signal.
+    pub fn subscribe_vsync(&self) -> io::Result<()> {
+        self.subscribe_event(V4L2_EVENT_VSYNC)
+    }
+
+    /// Dequeues a pending event and decodes it.
+    pub fn dequeue_event(&self) -> io::Result<Event> {
+        self.dequeue_raw_event().map(|evt| Event::from_raw(&evt))
+    }
+
+    /// Dequeues a pending event and decodes it, together with the number
+    /// of events still pending after it.
+    pub fn dequeue_event_with_pending(&self) -> io::Result<(Event, u32)> {
+        self.dequeue_raw_event()
+            .map(|evt| (Event::from_raw(&evt), evt.pending))
+    }
+
+    /// Waits up to `timeout` for an event and dequeues it. Returns `None`
+    /// if no event arrived in time.
+    pub fn wait_event(&self, timeout: Duration) -> io::Result<Option<Event>> {
+        if self.wait_priority(Some(timeout))? {
+            self.dequeue_event().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Polls for `POLLPRI`, which signals pending events, and returns
+    /// whether it was ready within `timeout`, or waits forever for `None`.
+    ///
+    /// Streaming drivers report `POLLERR` whatever is polled for, e.g.
+    /// while not streaming, so this may also return false early.
+    fn wait_priority(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+        loop {
+            // Signals must not extend the wait, so what is left of it is
+            // recomputed on every try.
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            match cvt(unsafe { libc::poll(&mut pfd, 1, poll_millis(remaining)) }) {
+                Ok(_) => return Ok(pfd.revents & libc::POLLPRI != 0),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
     }
 
-    pub fn dequeue_event(&self) -> io::Result<v4l2_event> {
+    /// Dequeues all pending events at once, without blocking, e.g. to
+    /// catch up after a stall. Returns no events if none are pending.
+    pub fn drain_events(&self) -> io::Result<Vec<Event>> {
+        let mut events = Vec::new();
+        loop {
+            // DQEVENT blocks on blocking handles when no event is pending.
+            if !self.wait_priority(Some(Duration::ZERO))? {
+                return Ok(events);
+            }
+            let evt = match self.dequeue_raw_event() {
+                Ok(evt) => evt,
+                // Another handle may have taken the event in between.
+                Err(ref e) if e.raw_os_error() == Some(libc::ENOENT) => return Ok(events),
+                Err(e) => return Err(e),
+            };
+            events.reserve(evt.pending as usize + 1);
+            events.push(Event::from_raw(&evt));
+            if evt.pending == 0 {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Dequeues a pending event, including its sequence number, timestamp
+    /// and the number of events still pending.
+    pub fn dequeue_raw_event(&self) -> io::Result<v4l2_event> {
         unsafe {
-            let mut evt: v4l2_event = mem::uninitialized();
-            cvt(libc::ioctl(self.fd, VIDIOC_DQEVENT, &mut evt)).map(|_| evt)
+            let mut evt: v4l2_event = mem::zeroed();
+            cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_DQEVENT, &mut evt)).map(|_| evt)
         }
     }
 
-    // pub fn events(&self) -> Events {
-    //     Events { dev: self }
-    // }
+    /// Returns a blocking iterator over the subscribed events.
+    pub fn events(&self) -> Events<'_> {
+        Events {
+            dev: self,
+            done: false,
+        }
+    }
+
+    /// Returns an async source of the subscribed events, driven by the
+    /// priority readiness of the device.
+    ///
+    /// Must be called from within a tokio runtime.
+    #[cfg(feature = "async")]
+    pub fn async_events(&self) -> io::Result<AsyncEvents<'_>> {
+        use tokio::io::{unix::AsyncFd, Interest};
+
+        Ok(AsyncEvents {
+            dev: self,
+            fd: AsyncFd::with_interest(self.fd.as_raw_fd(), Interest::PRIORITY)?,
+        })
+    }
 }
 
 impl V4l2Device {
     pub fn get_register(&self, reg: &mut v4l2_dbg_register) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_DBG_G_REGISTER, reg)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_DBG_G_REGISTER, reg)).map(|_| ()) }
     }
 
     pub fn set_register(&self, reg: &v4l2_dbg_register) -> io::Result<()> {
-        unsafe { cvt(libc::ioctl(self.fd, VIDIOC_DBG_S_REGISTER, reg)).map(|_| ()) }
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), VIDIOC_DBG_S_REGISTER, reg)).map(|_| ()) }
+    }
+
+    /// Issues the ioctl `request`, e.g. a vendor-private one built with the
+    /// functions of `sys::ioctl`, and returns its non-negative result.
+    ///
+    /// # Safety
+    ///
+    /// `arg` must be of the type the driver expects for `request`.
+    pub unsafe fn ioctl<T>(&self, request: IoctlRequest, arg: &mut T) -> io::Result<libc::c_int> {
+        cvt(libc::ioctl(self.fd.as_raw_fd(), request, arg as *mut T))
     }
 }
 
-impl Drop for V4l2Device {
+impl AsFd for V4l2Device {
     #[inline]
-    fn drop(&mut self) {
-        unsafe {
-            let _ = libc::close(self.fd);
-        }
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
     }
 }
 
 impl AsRawFd for V4l2Device {
     #[inline]
-    fn as_raw_fd(&self) -> i32 {
-        self.fd
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
     }
 }
 
 impl IntoRawFd for V4l2Device {
     #[inline]
-    fn into_raw_fd(self) -> i32 {
-        self.fd
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
     }
 }
 
 impl FromRawFd for V4l2Device {
     #[inline]
-    unsafe fn from_raw_fd(fd: i32) -> V4l2Device {
+    unsafe fn from_raw_fd(fd: RawFd) -> V4l2Device {
+        V4l2Device {
+            fd: OwnedFd::from_raw_fd(fd),
+        }
+    }
+}
+
+impl From<OwnedFd> for V4l2Device {
+    fn from(fd: OwnedFd) -> V4l2Device {
         V4l2Device { fd }
     }
 }
 
+impl From<V4l2Device> for OwnedFd {
+    fn from(device: V4l2Device) -> OwnedFd {
+        device.fd
+    }
+}
+
 pub struct SupportedFormats<'a> {
     dev: &'a V4l2Device,
     buf_type: v4l2_buf_type,
@@ -338,12 +1407,72 @@ pub struct SupportedFrameSizes<'a> {
 }
 
 impl<'a> Iterator for SupportedFrameSizes<'a> {
-    type Item = v4l2_frmsizeenum;
+    type Item = FrameSize;
 
     fn next(&mut self) -> Option<Self::Item> {
         if let Ok(frmsize) = self.dev.enum_frame_size(self.pixel_format, self.index) {
             self.index += 1;
-            Some(frmsize)
+            Some(FrameSize::from_raw(&frmsize))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct SupportedFrameIntervals<'a> {
+    dev: &'a V4l2Device,
+    pixel_format: u32,
+    width: u32,
+    height: u32,
+    index: u32,
+}
+
+impl<'a> Iterator for SupportedFrameIntervals<'a> {
+    type Item = FrameInterval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Ok(frmival) =
+            self.dev
+                .enum_frame_interval(self.pixel_format, self.width, self.height, self.index)
+        {
+            self.index += 1;
+            Some(FrameInterval::from_raw(&frmival))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Controls<'a> {
+    dev: &'a V4l2Device,
+    id: u32,
+}
+
+impl<'a> Iterator for Controls<'a> {
+    type Item = v4l2_queryctrl;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Ok(qctrl) = self.dev.query_control(self.id | V4L2_CTRL_FLAG_NEXT_CTRL) {
+            self.id = qctrl.id;
+            Some(qctrl)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Inputs<'a> {
+    dev: &'a V4l2Device,
+    index: u32,
+}
+
+impl<'a> Iterator for Inputs<'a> {
+    type Item = v4l2_input;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Ok(input) = self.dev.enum_input(self.index) {
+            self.index += 1;
+            Some(input)
         } else {
             None
         }
@@ -370,14 +1499,165 @@ impl<'a> Iterator for Buffers<'a> {
     }
 }
 
-// pub struct Events<'a> {
-//     dev: &'a V4l2Device,
-// }
+/// Blocking iterator over events, see `V4l2Device::events`.
+///
+/// Waits for `POLLPRI` before dequeuing. It ends after yielding the first
+/// error, e.g. when the driver reports `POLLERR` instead of an event, as
+/// for an unplugged device or a queue that stopped streaming.
+pub struct Events<'a> {
+    dev: &'a V4l2Device,
+    done: bool,
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        if self.done {
+            return None;
+        }
+        let result = match self.dev.wait_priority(None) {
+            Ok(true) => self.dev.dequeue_event(),
+            Ok(false) => Err(io::Error::other(
+                "device reported an error instead of an event",
+            )),
+            Err(e) => Err(e),
+        };
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+/// Async source of events, see `V4l2Device::async_events`.
+///
+/// This is not a `Stream`: events are received by awaiting `recv` in a
+/// loop.
+#[cfg(feature = "async")]
+pub struct AsyncEvents<'a> {
+    dev: &'a V4l2Device,
+    fd: tokio::io::unix::AsyncFd<libc::c_int>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> AsyncEvents<'a> {
+    /// Waits for the next event.
+    pub async fn recv(&mut self) -> io::Result<Event> {
+        let mut guard = self.fd.ready(tokio::io::Interest::PRIORITY).await?;
+        let event = match self.dev.dequeue_raw_event() {
+            Ok(event) => event,
+            Err(e) => {
+                // E.g. `ENOENT` after a spurious wakeup. The readiness is
+                // stale then, and would fail every later call right away.
+                guard.clear_ready();
+                return Err(e);
+            }
+        };
+        if event.pending == 0 {
+            guard.clear_ready();
+        }
+        Ok(Event::from_raw(&event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_flags() {
+        let cloexec = libc::O_CLOEXEC;
+        assert_eq!(OpenOptions::new().flags().unwrap(), libc::O_RDWR | cloexec);
+        assert_eq!(
+            OpenOptions::new().write(false).flags().unwrap(),
+            libc::O_RDONLY | cloexec
+        );
+        assert_eq!(
+            OpenOptions::new().read(false).flags().unwrap(),
+            libc::O_WRONLY | cloexec
+        );
+        assert_eq!(
+            OpenOptions::new().nonblocking(true).flags().unwrap(),
+            libc::O_RDWR | libc::O_NONBLOCK | cloexec
+        );
+        assert_eq!(
+            OpenOptions::new().close_on_exec(false).flags().unwrap(),
+            libc::O_RDWR
+        );
 
-// impl<'a> Iterator for Events<'a> {
-//     type Item = v4l2_event;
+        let err = OpenOptions::new()
+            .read(false)
+            .write(false)
+            .flags()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn opens_with_flags() {
+        let device = OpenOptions::new()
+            .write(false)
+            .nonblocking(true)
+            .open("/dev/null")
+            .unwrap();
+        let fd = device.as_raw_fd();
+
+        let status = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        assert_eq!(status & libc::O_ACCMODE, libc::O_RDONLY);
+        assert_ne!(status & libc::O_NONBLOCK, 0);
+        let fd_flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_ne!(fd_flags & libc::FD_CLOEXEC, 0);
+    }
+
+    #[test]
+    fn polls_until_deadline() {
+        let (reader, mut writer) = std::os::unix::net::UnixStream::pair().unwrap();
+        let fd = reader.as_raw_fd();
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(20);
+        assert_eq!(poll_events(fd, libc::POLLIN, Some(deadline)).unwrap(), 0);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        // A deadline in the past polls once.
+        assert_eq!(poll_events(fd, libc::POLLIN, Some(start)).unwrap(), 0);
+
+        std::io::Write::write_all(&mut writer, b"!").unwrap();
+        let ready = poll_events(fd, libc::POLLIN, None).unwrap();
+        assert_ne!(ready & libc::POLLIN, 0);
+    }
 
-//     fn next(&mut self) -> Option<v4l2_event> {
-//         self.dev.dequeue_event().ok()
-//     }
-// }
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_events_wait_again_after_errors() {
+        use std::future::{poll_fn, Future};
+        use std::net::{TcpListener, TcpStream};
+        use std::task::Poll;
+
+        // Urgent data makes a socket ready for `POLLPRI`, like a pending
+        // event does a device, but `VIDIOC_DQEVENT` fails on it.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (receiver, _) = listener.accept().unwrap();
+        let sent = unsafe {
+            libc::send(
+                sender.as_raw_fd(),
+                b"!".as_ptr() as *const libc::c_void,
+                1,
+                libc::MSG_OOB,
+            )
+        };
+        assert_eq!(sent, 1);
+        let device = V4l2Device::from(OwnedFd::from(receiver));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut events = device.async_events().unwrap();
+            assert!(events.recv().await.is_err());
+
+            let mut next = Box::pin(events.recv());
+            let pending = poll_fn(|cx| Poll::Ready(next.as_mut().poll(cx).is_pending())).await;
+            assert!(pending);
+        });
+    }
+}
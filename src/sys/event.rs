@@ -0,0 +1,123 @@
+use super::uapi::*;
+
+/// A decoded V4L2 event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Vertical sync, with the `v4l2_field` value of the field that starts.
+    Vsync { field: u8 },
+    /// End of stream of a decoder or M2M device.
+    Eos,
+    /// A control changed; `changes` is a mask of `V4L2_EVENT_CTRL_CH_*`.
+    Ctrl { id: u32, value: i64, changes: u32 },
+    /// The exposure of frame `sequence` started.
+    FrameSync { sequence: u32 },
+    /// The source format changed; `changes` is a mask of
+    /// `V4L2_EVENT_SRC_CH_*`.
+    SourceChange { changes: u32 },
+    /// Motion was detected in the regions of `region_mask`.
+    MotionDet {
+        flags: u32,
+        frame_sequence: u32,
+        region_mask: u32,
+    },
+    /// Any other event type, including private driver events.
+    Unknown { typ: u32, data: [u8; 64] },
+}
+
+impl Event {
+    /// Decodes the payload of a dequeued event according to its type.
+    pub fn from_raw(event: &v4l2_event) -> Event {
+        // Every variant of the union is plain data, and the type tells which
+        // one the driver filled in.
+        unsafe {
+            match event.typ {
+                V4L2_EVENT_VSYNC => Event::Vsync {
+                    field: event.u.vsync.field,
+                },
+                V4L2_EVENT_EOS => Event::Eos,
+                V4L2_EVENT_CTRL => {
+                    let ctrl = &event.u.ctrl;
+                    let value = if ctrl.typ == V4L2_CTRL_TYPE_INTEGER64 {
+                        ctrl.v.value64
+                    } else {
+                        ctrl.v.value as i64
+                    };
+                    Event::Ctrl {
+                        id: event.id,
+                        value,
+                        changes: ctrl.changes,
+                    }
+                }
+                V4L2_EVENT_FRAME_SYNC => Event::FrameSync {
+                    sequence: event.u.frame_sync.frame_sequence,
+                },
+                V4L2_EVENT_SOURCE_CHANGE => Event::SourceChange {
+                    changes: event.u.src_change.changes,
+                },
+                V4L2_EVENT_MOTION_DET => {
+                    let md = &event.u.motion_det;
+                    Event::MotionDet {
+                        flags: md.flags,
+                        frame_sequence: md.frame_sequence,
+                        region_mask: md.region_mask,
+                    }
+                }
+                typ => Event::Unknown {
+                    typ,
+                    data: event.u.data,
+                },
+            }
+        }
+    }
+}
+
+impl Event {
+    /// Returns true for motion detection events reporting motion in at
+    /// least one region.
+    pub fn is_motion(&self) -> bool {
+        matches!(self, Event::MotionDet { region_mask, .. } if *region_mask != 0)
+    }
+
+    /// Returns the sequence number of the frame a motion detection event
+    /// refers to, if the driver provided it.
+    pub fn motion_frame_sequence(&self) -> Option<u32> {
+        match *self {
+            Event::MotionDet {
+                flags,
+                frame_sequence,
+                ..
+            } if flags & V4L2_EVENT_MD_FL_HAVE_FRAME_SEQ != 0 => Some(frame_sequence),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> From<&'a v4l2_event> for Event {
+    fn from(event: &'a v4l2_event) -> Event {
+        Event::from_raw(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_ctrl_event() {
+        let mut raw: v4l2_event = unsafe { std::mem::zeroed() };
+        raw.typ = V4L2_EVENT_CTRL;
+        raw.id = V4L2_CID_EXPOSURE_ABSOLUTE;
+        raw.u.ctrl.typ = V4L2_CTRL_TYPE_INTEGER;
+        raw.u.ctrl.changes = V4L2_EVENT_CTRL_CH_VALUE;
+        raw.u.ctrl.v.value = 250;
+
+        assert_eq!(
+            Event::from_raw(&raw),
+            Event::Ctrl {
+                id: V4L2_CID_EXPOSURE_ABSOLUTE,
+                value: 250,
+                changes: V4L2_EVENT_CTRL_CH_VALUE,
+            }
+        );
+    }
+}
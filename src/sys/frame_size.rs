@@ -0,0 +1,165 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::uapi::*;
+
+/// A frame size supported by a device for a pixel format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FrameSize {
+    Discrete {
+        width: u32,
+        height: u32,
+    },
+    Stepwise {
+        min_width: u32,
+        max_width: u32,
+        step_width: u32,
+        min_height: u32,
+        max_height: u32,
+        step_height: u32,
+    },
+    /// Any size within the range, in steps of one pixel.
+    Continuous {
+        min_width: u32,
+        max_width: u32,
+        min_height: u32,
+        max_height: u32,
+    },
+}
+
+impl FrameSize {
+    /// Decodes the result of `VIDIOC_ENUM_FRAMESIZES`.
+    pub fn from_raw(frmsize: &v4l2_frmsizeenum) -> FrameSize {
+        // The type tells which member of the union the driver filled in.
+        unsafe {
+            match frmsize.typ {
+                v4l2_frmsizetypes::V4L2_FRMSIZE_TYPE_DISCRETE => FrameSize::Discrete {
+                    width: frmsize.u.discrete.width,
+                    height: frmsize.u.discrete.height,
+                },
+                v4l2_frmsizetypes::V4L2_FRMSIZE_TYPE_STEPWISE => {
+                    let s = frmsize.u.stepwise;
+                    FrameSize::Stepwise {
+                        min_width: s.min_width,
+                        max_width: s.max_width,
+                        step_width: s.step_width,
+                        min_height: s.min_height,
+                        max_height: s.max_height,
+                        step_height: s.step_height,
+                    }
+                }
+                v4l2_frmsizetypes::V4L2_FRMSIZE_TYPE_CONTINUOUS => {
+                    let s = frmsize.u.stepwise;
+                    FrameSize::Continuous {
+                        min_width: s.min_width,
+                        max_width: s.max_width,
+                        min_height: s.min_height,
+                        max_height: s.max_height,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the largest width and height of the frame size.
+    pub fn max_size(&self) -> (u32, u32) {
+        match *self {
+            FrameSize::Discrete { width, height } => (width, height),
+            FrameSize::Stepwise {
+                max_width,
+                max_height,
+                ..
+            }
+            | FrameSize::Continuous {
+                max_width,
+                max_height,
+                ..
+            } => (max_width, max_height),
+        }
+    }
+
+    /// Returns true if `width`x`height` can be set with this frame size.
+    pub fn contains(&self, width: u32, height: u32) -> bool {
+        match *self {
+            FrameSize::Discrete {
+                width: w,
+                height: h,
+            } => (w, h) == (width, height),
+            FrameSize::Stepwise {
+                min_width,
+                max_width,
+                step_width,
+                min_height,
+                max_height,
+                step_height,
+            } => {
+                (min_width..=max_width).contains(&width)
+                    && (min_height..=max_height).contains(&height)
+                    && (width - min_width).is_multiple_of(step_width.max(1))
+                    && (height - min_height).is_multiple_of(step_height.max(1))
+            }
+            FrameSize::Continuous {
+                min_width,
+                max_width,
+                min_height,
+                max_height,
+            } => {
+                (min_width..=max_width).contains(&width)
+                    && (min_height..=max_height).contains(&height)
+            }
+        }
+    }
+}
+
+/// A frame interval supported by a device for a pixel format and frame
+/// size, in seconds as `(numerator, denominator)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FrameInterval {
+    Discrete {
+        numerator: u32,
+        denominator: u32,
+    },
+    Stepwise {
+        min: (u32, u32),
+        max: (u32, u32),
+        step: (u32, u32),
+    },
+    /// Any interval within the range.
+    Continuous {
+        min: (u32, u32),
+        max: (u32, u32),
+    },
+}
+
+impl FrameInterval {
+    /// Decodes the result of `VIDIOC_ENUM_FRAMEINTERVALS`.
+    pub fn from_raw(frmival: &v4l2_frmivalenum) -> FrameInterval {
+        let fract = |f: v4l2_fract| (f.numerator, f.denominator);
+        // The type tells which member of the union the driver filled in.
+        unsafe {
+            match frmival.typ {
+                v4l2_frmivaltypes::V4L2_FRMIVAL_TYPE_DISCRETE => FrameInterval::Discrete {
+                    numerator: frmival.u.discrete.numerator,
+                    denominator: frmival.u.discrete.denominator,
+                },
+                v4l2_frmivaltypes::V4L2_FRMIVAL_TYPE_STEPWISE => {
+                    let s = frmival.u.stepwise;
+                    FrameInterval::Stepwise {
+                        min: fract(s.min),
+                        max: fract(s.max),
+                        step: fract(s.step),
+                    }
+                }
+                v4l2_frmivaltypes::V4L2_FRMIVAL_TYPE_CONTINUOUS => {
+                    let s = frmival.u.stepwise;
+                    FrameInterval::Continuous {
+                        min: fract(s.min),
+                        max: fract(s.max),
+                    }
+                }
+            }
+        }
+    }
+}
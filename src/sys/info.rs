@@ -0,0 +1,432 @@
+use std::fmt;
+use std::io;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::device::V4l2Device;
+use super::frame_size::{FrameInterval, FrameSize};
+use super::uapi::*;
+
+/// Returns the string in a NUL padded byte array.
+pub(crate) fn cstr(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+const CAPABILITY_NAMES: &[(u32, &str)] = &[
+    (V4L2_CAP_VIDEO_CAPTURE, "Video Capture"),
+    (V4L2_CAP_VIDEO_OUTPUT, "Video Output"),
+    (V4L2_CAP_VIDEO_OVERLAY, "Video Overlay"),
+    (V4L2_CAP_VIDEO_OUTPUT_OVERLAY, "Video Output Overlay"),
+    (V4L2_CAP_VIDEO_CAPTURE_MPLANE, "Video Capture Multiplanar"),
+    (V4L2_CAP_VIDEO_OUTPUT_MPLANE, "Video Output Multiplanar"),
+    (
+        V4L2_CAP_VIDEO_M2M_MPLANE,
+        "Video Memory-to-Memory Multiplanar",
+    ),
+    (V4L2_CAP_VIDEO_M2M, "Video Memory-to-Memory"),
+    (V4L2_CAP_READWRITE, "Read/Write"),
+    (V4L2_CAP_STREAMING, "Streaming"),
+    (V4L2_CAP_DEVICE_CAPS, "Device Capabilities"),
+];
+
+/// A snapshot of the state of a device, as reported by `V4l2Device::info`.
+///
+/// Queries the driver does not support are left out. The `Display`
+/// implementation prints a report in the spirit of `v4l2-ctl --all`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceInfo {
+    pub capability: v4l2_capability,
+    pub capture_format: Option<v4l2_pix_format>,
+    pub output_format: Option<v4l2_pix_format>,
+    pub capture_parm: Option<v4l2_captureparm>,
+    /// Index of the current input.
+    pub input: Option<i32>,
+    pub inputs: Vec<v4l2_input>,
+    pub controls: Vec<ControlInfo>,
+}
+
+impl DeviceInfo {
+    pub(crate) fn query(dev: &V4l2Device) -> io::Result<DeviceInfo> {
+        let capability = dev.capability()?;
+        Ok(DeviceInfo {
+            capability,
+            capture_format: dev.capture_format().ok(),
+            output_format: dev.output_format().ok(),
+            capture_parm: dev.capture_parm().ok(),
+            input: dev.input().ok(),
+            inputs: dev.inputs().collect(),
+            controls: control_report(dev),
+        })
+    }
+}
+
+/// An inventory of a device taken without changing its state, as
+/// returned by `V4l2Device::probe`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Probe {
+    pub info: DeviceInfo,
+    pub formats: Vec<QueueFormats>,
+}
+
+/// A control with its range and current value, see
+/// `V4l2Device::control_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ControlInfo {
+    pub id: u32,
+    pub name: String,
+    /// One of `V4L2_CTRL_TYPE_*`.
+    pub typ: u32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
+    /// `V4L2_CTRL_FLAG_*` flags.
+    pub flags: u32,
+    /// The current value, if the control has one that can be read.
+    pub value: Option<i32>,
+}
+
+impl ControlInfo {
+    pub fn from_raw(qctrl: &v4l2_queryctrl, value: Option<i32>) -> ControlInfo {
+        ControlInfo {
+            id: qctrl.id,
+            name: cstr(&qctrl.name),
+            typ: qctrl.typ,
+            minimum: qctrl.minimum,
+            maximum: qctrl.maximum,
+            step: qctrl.step,
+            default_value: qctrl.default_value,
+            flags: qctrl.flags,
+            value,
+        }
+    }
+
+    /// Returns a writable integer control without a value, for tests.
+    #[cfg(test)]
+    pub(crate) fn integer(
+        id: u32,
+        minimum: i32,
+        maximum: i32,
+        step: i32,
+        default_value: i32,
+    ) -> ControlInfo {
+        ControlInfo {
+            id,
+            name: String::new(),
+            typ: V4L2_CTRL_TYPE_INTEGER,
+            minimum,
+            maximum,
+            step,
+            default_value,
+            flags: 0,
+            value: None,
+        }
+    }
+
+    /// Returns true for the entries that start a control class, which
+    /// have no value.
+    pub fn is_class(&self) -> bool {
+        self.typ == V4L2_CTRL_TYPE_CTRL_CLASS
+    }
+
+    /// Returns the value closest to `value` within range and, for
+    /// integers, on a step.
+    pub fn clamp(&self, value: i32) -> i32 {
+        let value = value.max(self.minimum).min(self.maximum);
+        if self.typ != V4L2_CTRL_TYPE_INTEGER || self.step <= 1 {
+            return value;
+        }
+        let step = i64::from(self.step);
+        let offset = i64::from(value) - i64::from(self.minimum);
+        let mut snapped = i64::from(self.minimum) + (offset + step / 2) / step * step;
+        if snapped > i64::from(self.maximum) {
+            snapped -= step;
+        }
+        snapped as i32
+    }
+
+    /// Checks that `value` can be set, as far as the driver's description
+    /// of the control tells: it must be writable and `value` within range,
+    /// on a step for integers and within the mask for bitmasks.
+    pub fn validate(&self, value: i32) -> io::Result<()> {
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+
+        if self.flags & (V4L2_CTRL_FLAG_DISABLED | V4L2_CTRL_FLAG_READ_ONLY) != 0 {
+            return invalid("control is not writable");
+        }
+        match self.typ {
+            V4L2_CTRL_TYPE_BITMASK => {
+                if value as u32 & !(self.maximum as u32) != 0 {
+                    return invalid("value has bits outside the control mask");
+                }
+            }
+            V4L2_CTRL_TYPE_INTEGER
+            | V4L2_CTRL_TYPE_BOOLEAN
+            | V4L2_CTRL_TYPE_MENU
+            | V4L2_CTRL_TYPE_INTEGER_MENU => {
+                if value < self.minimum || value > self.maximum {
+                    return invalid("value is out of the control range");
+                }
+                let step = i64::from(self.step.max(1));
+                if self.typ == V4L2_CTRL_TYPE_INTEGER
+                    && (i64::from(value) - i64::from(self.minimum)) % step != 0
+                {
+                    return invalid("value is not on a control step");
+                }
+            }
+            V4L2_CTRL_TYPE_BUTTON => {}
+            _ => return invalid("control does not take a 32-bit value"),
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn control_report(dev: &V4l2Device) -> Vec<ControlInfo> {
+    dev.controls()
+        .map(|qctrl| {
+            let readable = qctrl.flags & V4L2_CTRL_FLAG_WRITE_ONLY == 0
+                && !matches!(qctrl.typ, V4L2_CTRL_TYPE_CTRL_CLASS | V4L2_CTRL_TYPE_BUTTON);
+            let value = if readable {
+                dev.control(qctrl.id).ok()
+            } else {
+                None
+            };
+            ControlInfo::from_raw(&qctrl, value)
+        })
+        .collect()
+}
+
+/// The formats a queue supports, with their frame sizes and intervals, as
+/// returned by `V4l2Device::capabilities_tree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QueueFormats {
+    pub buf_type: v4l2_buf_type,
+    pub formats: Vec<FormatDesc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FormatDesc {
+    pub pixel_format: u32,
+    pub description: String,
+    /// `V4L2_FMT_FLAG_*` flags, e.g. for compressed formats.
+    pub flags: u32,
+    pub frame_sizes: Vec<FrameSizeDesc>,
+}
+
+/// A frame size with the frame intervals supported at it. Intervals of
+/// stepwise and continuous sizes are those of the largest size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrameSizeDesc {
+    pub size: FrameSize,
+    pub intervals: Vec<FrameInterval>,
+}
+
+/* Queues to enumerate for each device capability */
+const QUEUES: &[(u32, v4l2_buf_type)] = &[
+    (
+        V4L2_CAP_VIDEO_CAPTURE | V4L2_CAP_VIDEO_M2M,
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+    ),
+    (
+        V4L2_CAP_VIDEO_OUTPUT | V4L2_CAP_VIDEO_M2M,
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT,
+    ),
+    (
+        V4L2_CAP_VIDEO_OVERLAY,
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OVERLAY,
+    ),
+    (
+        V4L2_CAP_VIDEO_CAPTURE_MPLANE | V4L2_CAP_VIDEO_M2M_MPLANE,
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+    ),
+    (
+        V4L2_CAP_VIDEO_OUTPUT_MPLANE | V4L2_CAP_VIDEO_M2M_MPLANE,
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_OUTPUT_MPLANE,
+    ),
+];
+
+pub(crate) fn capabilities_tree(dev: &V4l2Device) -> io::Result<Vec<QueueFormats>> {
+    let caps = dev.device_caps()?;
+
+    let mut queues = Vec::new();
+    for &(mask, buf_type) in QUEUES {
+        if caps & mask == 0 {
+            continue;
+        }
+        let formats: Vec<FormatDesc> = dev
+            .supported_formats(buf_type)
+            .map(|fmtdesc| FormatDesc {
+                pixel_format: fmtdesc.pixelformat,
+                description: cstr(&fmtdesc.description),
+                flags: fmtdesc.flags,
+                frame_sizes: frame_sizes(dev, fmtdesc.pixelformat),
+            })
+            .collect();
+        if !formats.is_empty() {
+            queues.push(QueueFormats { buf_type, formats });
+        }
+    }
+    Ok(queues)
+}
+
+fn frame_sizes(dev: &V4l2Device, pixel_format: u32) -> Vec<FrameSizeDesc> {
+    dev.supported_frame_sizes(pixel_format)
+        .map(|size| {
+            let (width, height) = size.max_size();
+            FrameSizeDesc {
+                size,
+                intervals: dev
+                    .supported_frame_intervals(pixel_format, width, height)
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn write_caps(f: &mut fmt::Formatter<'_>, label: &str, caps: u32) -> fmt::Result {
+    writeln!(f, "\t{:<17}: 0x{:08x}", label, caps)?;
+    for &(bit, name) in CAPABILITY_NAMES {
+        if caps & bit != 0 {
+            writeln!(f, "\t\t{}", name)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_format(f: &mut fmt::Formatter<'_>, label: &str, fmt: &v4l2_pix_format) -> fmt::Result {
+    writeln!(f, "Format {}:", label)?;
+    writeln!(f, "\tWidth/Height      : {}/{}", fmt.width, fmt.height)?;
+    writeln!(
+        f,
+        "\tPixel Format      : '{}'",
+        cstr(&fmt.pixelformat.to_le_bytes())
+    )?;
+    writeln!(f, "\tField             : {:?}", fmt.field)?;
+    writeln!(f, "\tBytes per Line    : {}", fmt.bytesperline)?;
+    writeln!(f, "\tSize Image        : {}", fmt.sizeimage)?;
+    writeln!(f, "\tColorspace        : {:?}", fmt.colorspace)
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cap = &self.capability;
+        writeln!(f, "Driver Info:")?;
+        writeln!(f, "\tDriver name      : {}", cstr(&cap.driver))?;
+        writeln!(f, "\tCard type        : {}", cstr(&cap.card))?;
+        writeln!(f, "\tBus info         : {}", cstr(&cap.bus_info))?;
+        writeln!(
+            f,
+            "\tDriver version   : {}.{}.{}",
+            cap.version >> 16,
+            (cap.version >> 8) & 0xff,
+            cap.version & 0xff
+        )?;
+        write_caps(f, "Capabilities", cap.capabilities)?;
+        if cap.capabilities & V4L2_CAP_DEVICE_CAPS != 0 {
+            write_caps(f, "Device Caps", cap.device_caps)?;
+        }
+
+        for input in &self.inputs {
+            let current = if self.input == Some(input.index as i32) {
+                " (current)"
+            } else {
+                ""
+            };
+            let status = if input.status == 0 {
+                "ok".to_string()
+            } else {
+                format!("0x{:08x}", input.status)
+            };
+            writeln!(
+                f,
+                "Video input {}: {}{}, status {}",
+                input.index,
+                cstr(&input.name),
+                current,
+                status
+            )?;
+        }
+
+        if let Some(ref fmt) = self.capture_format {
+            write_format(f, "Video Capture", fmt)?;
+        }
+        if let Some(ref fmt) = self.output_format {
+            write_format(f, "Video Output", fmt)?;
+        }
+
+        if let Some(ref parm) = self.capture_parm {
+            let tpf = parm.timeperframe;
+            writeln!(f, "Streaming Parameters Video Capture:")?;
+            if parm.capability & V4L2_CAP_TIMEPERFRAME != 0 {
+                writeln!(f, "\tCapabilities     : timeperframe")?;
+            }
+            if tpf.numerator != 0 {
+                writeln!(
+                    f,
+                    "\tFrames per second: {:.3} ({}/{})",
+                    tpf.denominator as f64 / tpf.numerator as f64,
+                    tpf.denominator,
+                    tpf.numerator
+                )?;
+            }
+            writeln!(f, "\tRead buffers     : {}", parm.readbuffers)?;
+        }
+
+        for ctrl in &self.controls {
+            if ctrl.is_class() {
+                writeln!(f)?;
+                writeln!(f, "{}", ctrl.name)?;
+                continue;
+            }
+            let value = ctrl
+                .value
+                .map_or_else(|| "-".to_string(), |v| v.to_string());
+            writeln!(
+                f,
+                "\t{:<32} 0x{:08x}: min={} max={} step={} default={} value={}",
+                ctrl.name,
+                ctrl.id,
+                ctrl.minimum,
+                ctrl.maximum,
+                ctrl.step,
+                ctrl.default_value,
+                value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_control_values() {
+        let ctrl = ControlInfo {
+            value: Some(-2),
+            ..ControlInfo::integer(V4L2_CID_BRIGHTNESS, -10, 10, 4, -2)
+        };
+        assert!(ctrl.validate(-10).is_ok());
+        assert!(ctrl.validate(6).is_ok());
+        assert!(ctrl.validate(7).is_err());
+        assert!(ctrl.validate(14).is_err());
+        assert_eq!(ctrl.clamp(7), 6);
+        assert_eq!(ctrl.clamp(9), 10);
+        assert_eq!(ctrl.clamp(-20), -10);
+
+        let read_only = ControlInfo {
+            flags: V4L2_CTRL_FLAG_READ_ONLY,
+            ..ctrl
+        };
+        assert!(read_only.validate(-2).is_err());
+    }
+}
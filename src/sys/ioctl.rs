@@ -0,0 +1,81 @@
+//! Request codes for ioctls not covered by this crate, e.g. private
+//! ioctls of vendor drivers, to be issued with `V4l2Device::ioctl`.
+//!
+//! These are the `_IO`, `_IOR`, `_IOW` and `_IOWR` macros of the C API as
+//! const fns, so no direct dependency on nix is needed.
+
+use nix::sys::ioctl as nix_ioctl;
+
+/// The type of ioctl request codes on this platform.
+pub type IoctlRequest = nix_ioctl::ioctl_num_type;
+
+const fn ioc(dir: u8, ty: u8, nr: u8, size: usize) -> IoctlRequest {
+    ((dir as IoctlRequest & nix_ioctl::DIRMASK) << nix_ioctl::DIRSHIFT)
+        | ((ty as IoctlRequest & nix_ioctl::TYPEMASK) << nix_ioctl::TYPESHIFT)
+        | ((nr as IoctlRequest & nix_ioctl::NRMASK) << nix_ioctl::NRSHIFT)
+        | ((size as IoctlRequest & nix_ioctl::SIZEMASK) << nix_ioctl::SIZESHIFT)
+}
+
+/// Code of a request without argument, like `_IO`.
+pub const fn io(ty: u8, nr: u8) -> IoctlRequest {
+    ioc(nix_ioctl::NONE, ty, nr, 0)
+}
+
+/// Code of a request reading a `size` bytes argument, like `_IOR`.
+pub const fn ior(ty: u8, nr: u8, size: usize) -> IoctlRequest {
+    ioc(nix_ioctl::READ, ty, nr, size)
+}
+
+/// Code of a request writing a `size` bytes argument, like `_IOW`.
+pub const fn iow(ty: u8, nr: u8, size: usize) -> IoctlRequest {
+    ioc(nix_ioctl::WRITE, ty, nr, size)
+}
+
+/// Code of a request reading and writing a `size` bytes argument, like
+/// `_IOWR`.
+pub const fn iowr(ty: u8, nr: u8, size: usize) -> IoctlRequest {
+    ioc(nix_ioctl::READ | nix_ioctl::WRITE, ty, nr, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::uapi::*;
+    use std::mem;
+
+    #[test]
+    fn matches_uapi_codes() {
+        assert_eq!(
+            ior(b'V', 0, mem::size_of::<v4l2_capability>()),
+            VIDIOC_QUERYCAP
+        );
+        assert_eq!(iowr(b'V', 5, mem::size_of::<v4l2_format>()), VIDIOC_S_FMT);
+        assert_eq!(
+            iow(b'V', 18, mem::size_of::<libc::c_int>()),
+            VIDIOC_STREAMON
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(mem::size_of::<v4l2_buffer>(), 88);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(mem::size_of::<v4l2_create_buffers>(), 256);
+        assert_eq!(
+            iowr(b'V', 92, mem::size_of::<v4l2_create_buffers>()),
+            VIDIOC_CREATE_BUFS
+        );
+        assert_eq!(mem::size_of::<v4l2_selection>(), 64);
+        assert_eq!(mem::size_of::<v4l2_querymenu>(), 44);
+        assert_eq!(
+            iowr(b'V', 94, mem::size_of::<v4l2_selection>()),
+            VIDIOC_G_SELECTION
+        );
+        assert_eq!(mem::size_of::<media_v2_topology>(), 72);
+        assert_eq!(mem::size_of::<media_v2_interface>(), 112);
+        assert_eq!(iowr(b'|', 0x04, 72), MEDIA_IOC_G_TOPOLOGY);
+        assert_eq!(
+            ior(b'|', 0x05, mem::size_of::<libc::c_int>()),
+            MEDIA_IOC_REQUEST_ALLOC
+        );
+        assert_eq!(io(b'|', 0x80), MEDIA_REQUEST_IOC_QUEUE);
+        assert_eq!(io(b'|', 0x81), MEDIA_REQUEST_IOC_REINIT);
+    }
+}
@@ -0,0 +1,199 @@
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use memmap::MmapMut;
+
+use super::device::{poll_events, V4l2Device};
+use super::uapi::*;
+
+/// Queues buffers like `DeviceOps::queue_buffer`, from any thread.
+pub type QueueHandle = Box<dyn Fn(&v4l2_buffer) -> io::Result<()> + Send>;
+
+/// The device operations a `Capture` streams with.
+///
+/// `V4l2Device` implements them with ioctls on the device node. Other
+/// implementations stand in for a device, e.g. to test streaming code
+/// without hardware. Errors are reported as the driver would, with the
+/// errno of the failing call.
+///
+/// The trait is only reexported within the crate, so other crates can
+/// neither name nor implement it, and `Capture` is used with its default
+/// `V4l2Device` there.
+pub trait DeviceOps {
+    /// `VIDIOC_QUERYCAP`
+    fn capability(&self) -> io::Result<v4l2_capability>;
+
+    /// `VIDIOC_G_FMT` on the capture queue.
+    fn capture_format(&self) -> io::Result<v4l2_pix_format>;
+
+    /// `VIDIOC_S_FMT` on the capture queue.
+    fn set_capture_format(&self, fmt: &v4l2_pix_format) -> io::Result<v4l2_pix_format>;
+
+    /// `VIDIOC_REQBUFS`, returning the number of buffers allocated.
+    fn request_buffers(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+        count: usize,
+    ) -> io::Result<usize>;
+
+    /// Maps all mmap buffers of `buf_type` requested with
+    /// `request_buffers`.
+    fn map_buffers(&self, buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>>;
+
+    /// `VIDIOC_QBUF`
+    fn queue_buffer(&self, buf: &v4l2_buffer) -> io::Result<()>;
+
+    /// Returns a handle for owned frames to queue their buffer from the
+    /// thread that drops them. Without one, the capture queues those
+    /// buffers itself on the next dequeue.
+    fn queue_handle(&self) -> Option<QueueHandle> {
+        None
+    }
+
+    /// `VIDIOC_DQBUF`
+    fn dequeue_buffer(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+    ) -> io::Result<v4l2_buffer>;
+
+    /// `VIDIOC_STREAMON`
+    fn stream_on(&self, buf_type: v4l2_buf_type) -> io::Result<()>;
+
+    /// `VIDIOC_STREAMOFF`
+    fn stream_off(&self, buf_type: v4l2_buf_type) -> io::Result<()>;
+
+    /// Reads a frame with `read()`, for drivers without streaming I/O.
+    fn read(&self, data: &mut [u8]) -> io::Result<usize>;
+
+    /// Returns true if a frame can be dequeued or read without blocking.
+    /// Devices that cannot tell return false.
+    fn frame_ready(&self) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Waits up to `timeout` for a frame and returns false if none
+    /// arrived. Devices that cannot tell return true right away.
+    fn wait_frame(&self, timeout: Duration) -> io::Result<bool> {
+        let _ = timeout;
+        Ok(true)
+    }
+
+    /// `VIDIOC_G_SELECTION`. Devices without selections fail with
+    /// `ENOTTY`.
+    fn selection(&self, buf_type: v4l2_buf_type, target: u32) -> io::Result<v4l2_rect> {
+        let _ = (buf_type, target);
+        Err(io::Error::from_raw_os_error(libc::ENOTTY))
+    }
+
+    /// `VIDIOC_S_SELECTION`. Devices without selections fail with
+    /// `ENOTTY`.
+    fn set_selection(
+        &self,
+        buf_type: v4l2_buf_type,
+        target: u32,
+        flags: u32,
+        rect: &v4l2_rect,
+    ) -> io::Result<v4l2_rect> {
+        let _ = (buf_type, target, flags, rect);
+        Err(io::Error::from_raw_os_error(libc::ENOTTY))
+    }
+}
+
+impl DeviceOps for V4l2Device {
+    fn capability(&self) -> io::Result<v4l2_capability> {
+        V4l2Device::capability(self)
+    }
+
+    fn capture_format(&self) -> io::Result<v4l2_pix_format> {
+        V4l2Device::capture_format(self)
+    }
+
+    fn set_capture_format(&self, fmt: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+        V4l2Device::set_capture_format(self, fmt)
+    }
+
+    fn request_buffers(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+        count: usize,
+    ) -> io::Result<usize> {
+        V4l2Device::request_buffers(self, buf_type, memory, count)
+    }
+
+    fn map_buffers(&self, buf_type: v4l2_buf_type) -> io::Result<Vec<MmapMut>> {
+        V4l2Device::map_buffers(self, buf_type)
+    }
+
+    fn queue_buffer(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        V4l2Device::queue_buffer(self, buf)
+    }
+
+    fn queue_handle(&self) -> Option<QueueHandle> {
+        let device = self.try_clone().ok()?;
+        Some(Box::new(move |buf| device.queue_buffer(buf)))
+    }
+
+    fn dequeue_buffer(
+        &self,
+        buf_type: v4l2_buf_type,
+        memory: v4l2_memory,
+    ) -> io::Result<v4l2_buffer> {
+        V4l2Device::dequeue_buffer(self, buf_type, memory)
+    }
+
+    fn stream_on(&self, buf_type: v4l2_buf_type) -> io::Result<()> {
+        V4l2Device::stream_on(self, buf_type)
+    }
+
+    fn stream_off(&self, buf_type: v4l2_buf_type) -> io::Result<()> {
+        V4l2Device::stream_off(self, buf_type)
+    }
+
+    fn read(&self, data: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe {
+            libc::read(
+                self.as_raw_fd(),
+                data.as_mut_ptr() as *mut libc::c_void,
+                data.len(),
+            )
+        };
+        if n == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    }
+
+    fn frame_ready(&self) -> io::Result<bool> {
+        let events = poll_events(self.as_raw_fd(), libc::POLLIN, Some(Instant::now()))?;
+        Ok(events & libc::POLLIN != 0)
+    }
+
+    fn wait_frame(&self, timeout: Duration) -> io::Result<bool> {
+        // Errors are reported by the dequeue that follows.
+        let events = poll_events(
+            self.as_raw_fd(),
+            libc::POLLIN,
+            Some(Instant::now() + timeout),
+        )?;
+        Ok(events != 0)
+    }
+
+    fn selection(&self, buf_type: v4l2_buf_type, target: u32) -> io::Result<v4l2_rect> {
+        V4l2Device::selection(self, buf_type, target)
+    }
+
+    fn set_selection(
+        &self,
+        buf_type: v4l2_buf_type,
+        target: u32,
+        flags: u32,
+        rect: &v4l2_rect,
+    ) -> io::Result<v4l2_rect> {
+        V4l2Device::set_selection(self, buf_type, target, flags, rect)
+    }
+}
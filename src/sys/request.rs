@@ -0,0 +1,76 @@
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+use super::device::{cvt, poll_millis};
+use super::uapi::*;
+
+/// A media request, which binds controls and buffers to one frame, as
+/// stateless decoders need.
+///
+/// A request is allocated on the media device of the video device, e.g.
+/// `/dev/media0`, and filled with `V4l2Device::set_request_controls` and
+/// `V4l2Device::queue_buffer_in_request`. Queuing it hands everything to
+/// the driver at once. Once it completed, it can be reused with `reinit`.
+#[derive(Debug)]
+pub struct MediaRequest {
+    fd: OwnedFd,
+}
+
+impl MediaRequest {
+    /// Allocates a request on the media device `media`.
+    pub fn alloc<F: AsRawFd>(media: &F) -> io::Result<MediaRequest> {
+        let mut fd: libc::c_int = -1;
+        unsafe {
+            cvt(libc::ioctl(
+                media.as_raw_fd(),
+                MEDIA_IOC_REQUEST_ALLOC,
+                &mut fd,
+            ))?;
+            Ok(MediaRequest {
+                fd: OwnedFd::from_raw_fd(fd),
+            })
+        }
+    }
+
+    /// Queues the request. Its controls and buffers can no longer be
+    /// changed until it completed and is reinitialized.
+    pub fn queue(&self) -> io::Result<()> {
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), MEDIA_REQUEST_IOC_QUEUE)).map(|_| ()) }
+    }
+
+    /// Waits up to `timeout`, or forever if `None`, for the queued request
+    /// to complete. Returns false if it did not complete in time.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+        loop {
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            match cvt(unsafe { libc::poll(&mut pfd, 1, poll_millis(remaining)) }) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result.map(|n| n > 0),
+            }
+        }
+    }
+
+    /// Empties a completed request, so it can be filled and queued again.
+    pub fn reinit(&self) -> io::Result<()> {
+        unsafe { cvt(libc::ioctl(self.fd.as_raw_fd(), MEDIA_REQUEST_IOC_REINIT)).map(|_| ()) }
+    }
+}
+
+impl AsRawFd for MediaRequest {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for MediaRequest {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
@@ -0,0 +1,405 @@
+//! Controls of stateless decoders, as in `v4l2-controls.h`.
+//!
+//! Stateless decoders like rkvdec and hantro on Rockchip boards do not
+//! parse the bitstream. The application parses the headers and passes
+//! them with each frame as the compound controls below, bound to a
+//! `MediaRequest` together with the bitstream buffer:
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use v4l2::sys::stateless::*;
+//! # use v4l2::sys::uapi::*;
+//! # use v4l2::sys::{MediaRequest, V4l2Device};
+//! # #[cfg(not(feature = "sunxi-vfe"))]
+//! # fn run(decoder: &V4l2Device, bitstream: &v4l2_buffer) -> std::io::Result<()> {
+//! let media = File::open("/dev/media0")?;
+//! let request = MediaRequest::alloc(&media)?;
+//! let mut sps = v4l2_ctrl_hevc_sps::default();
+//! // Fill in the parsed headers.
+//! let mut controls = [ext_control(V4L2_CID_STATELESS_HEVC_SPS, &mut sps)];
+//! unsafe { decoder.set_request_controls(&request, &mut controls)? };
+//! decoder.queue_buffer_in_request(bitstream, &request)?;
+//! request.queue()?;
+//! request.wait(None)?;
+//! // Dequeue the decoded frame, then reuse the request.
+//! request.reinit()?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![allow(non_camel_case_types)]
+
+use std::mem;
+
+use super::uapi::*;
+
+pub const V4L2_CTRL_CLASS_CODEC_STATELESS: u32 = 0x00a4_0000;
+pub const V4L2_CID_CODEC_STATELESS_BASE: u32 = V4L2_CTRL_CLASS_CODEC_STATELESS | 0x900;
+
+/// Builds an entry of `v4l2_ext_controls` pointing to the compound payload
+/// `value`, which must outlive the ioctl it is used in.
+pub fn ext_control<T>(id: u32, value: &mut T) -> v4l2_ext_control {
+    v4l2_ext_control {
+        id,
+        size: mem::size_of::<T>() as u32,
+        reserved2: [0],
+        u: _v4l2_ext_control_u {
+            ptr: value as *mut T as *mut libc::c_void,
+        },
+    }
+}
+
+/* HEVC */
+
+pub const V4L2_CID_STATELESS_HEVC_SPS: u32 = V4L2_CID_CODEC_STATELESS_BASE + 400;
+pub const V4L2_CID_STATELESS_HEVC_PPS: u32 = V4L2_CID_CODEC_STATELESS_BASE + 401;
+pub const V4L2_CID_STATELESS_HEVC_SLICE_PARAMS: u32 = V4L2_CID_CODEC_STATELESS_BASE + 402;
+pub const V4L2_CID_STATELESS_HEVC_SCALING_MATRIX: u32 = V4L2_CID_CODEC_STATELESS_BASE + 403;
+pub const V4L2_CID_STATELESS_HEVC_DECODE_PARAMS: u32 = V4L2_CID_CODEC_STATELESS_BASE + 404;
+pub const V4L2_CID_STATELESS_HEVC_DECODE_MODE: u32 = V4L2_CID_CODEC_STATELESS_BASE + 405;
+pub const V4L2_CID_STATELESS_HEVC_START_CODE: u32 = V4L2_CID_CODEC_STATELESS_BASE + 406;
+pub const V4L2_CID_STATELESS_HEVC_ENTRY_POINT_OFFSETS: u32 = V4L2_CID_CODEC_STATELESS_BASE + 407;
+
+/* values for V4L2_CID_STATELESS_HEVC_DECODE_MODE */
+pub const V4L2_STATELESS_HEVC_DECODE_MODE_SLICE_BASED: i32 = 0;
+pub const V4L2_STATELESS_HEVC_DECODE_MODE_FRAME_BASED: i32 = 1;
+
+/* values for V4L2_CID_STATELESS_HEVC_START_CODE */
+pub const V4L2_STATELESS_HEVC_START_CODE_NONE: i32 = 0;
+pub const V4L2_STATELESS_HEVC_START_CODE_ANNEX_B: i32 = 1;
+
+pub const V4L2_HEVC_SLICE_TYPE_B: u8 = 0;
+pub const V4L2_HEVC_SLICE_TYPE_P: u8 = 1;
+pub const V4L2_HEVC_SLICE_TYPE_I: u8 = 2;
+
+pub const V4L2_HEVC_SPS_FLAG_SEPARATE_COLOUR_PLANE: u64 = 1 << 0;
+pub const V4L2_HEVC_SPS_FLAG_SCALING_LIST_ENABLED: u64 = 1 << 1;
+pub const V4L2_HEVC_SPS_FLAG_AMP_ENABLED: u64 = 1 << 2;
+pub const V4L2_HEVC_SPS_FLAG_SAMPLE_ADAPTIVE_OFFSET: u64 = 1 << 3;
+pub const V4L2_HEVC_SPS_FLAG_PCM_ENABLED: u64 = 1 << 4;
+pub const V4L2_HEVC_SPS_FLAG_PCM_LOOP_FILTER_DISABLED: u64 = 1 << 5;
+pub const V4L2_HEVC_SPS_FLAG_LONG_TERM_REF_PICS_PRESENT: u64 = 1 << 6;
+pub const V4L2_HEVC_SPS_FLAG_SPS_TEMPORAL_MVP_ENABLED: u64 = 1 << 7;
+pub const V4L2_HEVC_SPS_FLAG_STRONG_INTRA_SMOOTHING_ENABLED: u64 = 1 << 8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_hevc_sps {
+    pub video_parameter_set_id: u8,
+    pub seq_parameter_set_id: u8,
+    pub pic_width_in_luma_samples: u16,
+    pub pic_height_in_luma_samples: u16,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub log2_max_pic_order_cnt_lsb_minus4: u8,
+    pub sps_max_dec_pic_buffering_minus1: u8,
+    pub sps_max_num_reorder_pics: u8,
+    pub sps_max_latency_increase_plus1: u8,
+    pub log2_min_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_luma_coding_block_size: u8,
+    pub log2_min_luma_transform_block_size_minus2: u8,
+    pub log2_diff_max_min_luma_transform_block_size: u8,
+    pub max_transform_hierarchy_depth_inter: u8,
+    pub max_transform_hierarchy_depth_intra: u8,
+    pub pcm_sample_bit_depth_luma_minus1: u8,
+    pub pcm_sample_bit_depth_chroma_minus1: u8,
+    pub log2_min_pcm_luma_coding_block_size_minus3: u8,
+    pub log2_diff_max_min_pcm_luma_coding_block_size: u8,
+    pub num_short_term_ref_pic_sets: u8,
+    pub num_long_term_ref_pics_sps: u8,
+    pub chroma_format_idc: u8,
+    pub sps_max_sub_layers_minus1: u8,
+    pub reserved: [u8; 6],
+    pub flags: u64,
+}
+
+pub const V4L2_HEVC_PPS_FLAG_DEPENDENT_SLICE_SEGMENT_ENABLED: u64 = 1 << 0;
+pub const V4L2_HEVC_PPS_FLAG_OUTPUT_FLAG_PRESENT: u64 = 1 << 1;
+pub const V4L2_HEVC_PPS_FLAG_SIGN_DATA_HIDING_ENABLED: u64 = 1 << 2;
+pub const V4L2_HEVC_PPS_FLAG_CABAC_INIT_PRESENT: u64 = 1 << 3;
+pub const V4L2_HEVC_PPS_FLAG_CONSTRAINED_INTRA_PRED: u64 = 1 << 4;
+pub const V4L2_HEVC_PPS_FLAG_TRANSFORM_SKIP_ENABLED: u64 = 1 << 5;
+pub const V4L2_HEVC_PPS_FLAG_CU_QP_DELTA_ENABLED: u64 = 1 << 6;
+pub const V4L2_HEVC_PPS_FLAG_PPS_SLICE_CHROMA_QP_OFFSETS_PRESENT: u64 = 1 << 7;
+pub const V4L2_HEVC_PPS_FLAG_WEIGHTED_PRED: u64 = 1 << 8;
+pub const V4L2_HEVC_PPS_FLAG_WEIGHTED_BIPRED: u64 = 1 << 9;
+pub const V4L2_HEVC_PPS_FLAG_TRANSQUANT_BYPASS_ENABLED: u64 = 1 << 10;
+pub const V4L2_HEVC_PPS_FLAG_TILES_ENABLED: u64 = 1 << 11;
+pub const V4L2_HEVC_PPS_FLAG_ENTROPY_CODING_SYNC_ENABLED: u64 = 1 << 12;
+pub const V4L2_HEVC_PPS_FLAG_LOOP_FILTER_ACROSS_TILES_ENABLED: u64 = 1 << 13;
+pub const V4L2_HEVC_PPS_FLAG_PPS_LOOP_FILTER_ACROSS_SLICES_ENABLED: u64 = 1 << 14;
+pub const V4L2_HEVC_PPS_FLAG_DEBLOCKING_FILTER_OVERRIDE_ENABLED: u64 = 1 << 15;
+pub const V4L2_HEVC_PPS_FLAG_PPS_DISABLE_DEBLOCKING_FILTER: u64 = 1 << 16;
+pub const V4L2_HEVC_PPS_FLAG_LISTS_MODIFICATION_PRESENT: u64 = 1 << 17;
+pub const V4L2_HEVC_PPS_FLAG_SLICE_SEGMENT_HEADER_EXTENSION_PRESENT: u64 = 1 << 18;
+pub const V4L2_HEVC_PPS_FLAG_DEBLOCKING_FILTER_CONTROL_PRESENT: u64 = 1 << 19;
+pub const V4L2_HEVC_PPS_FLAG_UNIFORM_SPACING: u64 = 1 << 20;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_hevc_pps {
+    pub pic_parameter_set_id: u8,
+    pub num_extra_slice_header_bits: u8,
+    pub num_ref_idx_l0_default_active_minus1: u8,
+    pub num_ref_idx_l1_default_active_minus1: u8,
+    pub init_qp_minus26: i8,
+    pub diff_cu_qp_delta_depth: u8,
+    pub pps_cb_qp_offset: i8,
+    pub pps_cr_qp_offset: i8,
+    pub num_tile_columns_minus1: u8,
+    pub num_tile_rows_minus1: u8,
+    pub column_width_minus1: [u8; 20],
+    pub row_height_minus1: [u8; 22],
+    pub pps_beta_offset_div2: i8,
+    pub pps_tc_offset_div2: i8,
+    pub log2_parallel_merge_level_minus2: u8,
+    pub reserved: u8,
+    pub flags: u64,
+}
+
+pub const V4L2_HEVC_DPB_ENTRIES_NUM_MAX: usize = 16;
+
+pub const V4L2_HEVC_DPB_ENTRY_LONG_TERM_REFERENCE: u8 = 0x01;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_hevc_dpb_entry {
+    pub timestamp: u64,
+    pub flags: u8,
+    pub field_pic: u8,
+    pub reserved: u16,
+    pub pic_order_cnt_val: i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_hevc_pred_weight_table {
+    pub delta_luma_weight_l0: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub luma_offset_l0: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub delta_chroma_weight_l0: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub chroma_offset_l0: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub delta_luma_weight_l1: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub luma_offset_l1: [i8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub delta_chroma_weight_l1: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub chroma_offset_l1: [[i8; 2]; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub luma_log2_weight_denom: u8,
+    pub delta_chroma_log2_weight_denom: i8,
+}
+
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_SLICE_SAO_LUMA: u64 = 1 << 0;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_SLICE_SAO_CHROMA: u64 = 1 << 1;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_SLICE_TEMPORAL_MVP_ENABLED: u64 = 1 << 2;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_MVD_L1_ZERO: u64 = 1 << 3;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_CABAC_INIT: u64 = 1 << 4;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_COLLOCATED_FROM_L0: u64 = 1 << 5;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_USE_INTEGER_MV: u64 = 1 << 6;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_SLICE_DEBLOCKING_FILTER_DISABLED: u64 = 1 << 7;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_SLICE_LOOP_FILTER_ACROSS_SLICES_ENABLED: u64 = 1 << 8;
+pub const V4L2_HEVC_SLICE_PARAMS_FLAG_DEPENDENT_SLICE_SEGMENT: u64 = 1 << 9;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_hevc_slice_params {
+    pub bit_size: u32,
+    pub data_byte_offset: u32,
+    pub num_entry_point_offsets: u32,
+    pub nal_unit_type: u8,
+    pub nuh_temporal_id_plus1: u8,
+    pub slice_type: u8,
+    pub colour_plane_id: u8,
+    pub slice_pic_order_cnt: i32,
+    pub num_ref_idx_l0_active_minus1: u8,
+    pub num_ref_idx_l1_active_minus1: u8,
+    pub collocated_ref_idx: u8,
+    pub five_minus_max_num_merge_cand: u8,
+    pub slice_qp_delta: i8,
+    pub slice_cb_qp_offset: i8,
+    pub slice_cr_qp_offset: i8,
+    pub slice_act_y_qp_offset: i8,
+    pub slice_act_cb_qp_offset: i8,
+    pub slice_act_cr_qp_offset: i8,
+    pub slice_beta_offset_div2: i8,
+    pub slice_tc_offset_div2: i8,
+    pub pic_struct: u8,
+    pub reserved0: [u8; 3],
+    pub slice_segment_addr: u32,
+    pub ref_idx_l0: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub ref_idx_l1: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub short_term_ref_pic_set_size: u16,
+    pub long_term_ref_pic_set_size: u16,
+    pub pred_weight_table: v4l2_hevc_pred_weight_table,
+    pub reserved1: [u8; 2],
+    pub flags: u64,
+}
+
+pub const V4L2_HEVC_DECODE_PARAM_FLAG_IRAP_PIC: u64 = 0x1;
+pub const V4L2_HEVC_DECODE_PARAM_FLAG_IDR_PIC: u64 = 0x2;
+pub const V4L2_HEVC_DECODE_PARAM_FLAG_NO_OUTPUT_OF_PRIOR: u64 = 0x4;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_hevc_decode_params {
+    pub pic_order_cnt_val: i32,
+    pub short_term_ref_pic_set_size: u16,
+    pub long_term_ref_pic_set_size: u16,
+    pub num_active_dpb_entries: u8,
+    pub num_poc_st_curr_before: u8,
+    pub num_poc_st_curr_after: u8,
+    pub num_poc_lt_curr: u8,
+    pub poc_st_curr_before: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub poc_st_curr_after: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub poc_lt_curr: [u8; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub num_delta_pocs_of_ref_rps_idx: u8,
+    pub reserved: [u8; 3],
+    pub dpb: [v4l2_hevc_dpb_entry; V4L2_HEVC_DPB_ENTRIES_NUM_MAX],
+    pub flags: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct v4l2_ctrl_hevc_scaling_matrix {
+    pub scaling_list_4x4: [[u8; 16]; 6],
+    pub scaling_list_8x8: [[u8; 64]; 6],
+    pub scaling_list_16x16: [[u8; 64]; 6],
+    pub scaling_list_32x32: [[u8; 64]; 2],
+    pub scaling_list_dc_coef_16x16: [u8; 6],
+    pub scaling_list_dc_coef_32x32: [u8; 2],
+}
+
+/* VP9 */
+
+pub const V4L2_CID_STATELESS_VP9_FRAME: u32 = V4L2_CID_CODEC_STATELESS_BASE + 300;
+pub const V4L2_CID_STATELESS_VP9_COMPRESSED_HDR: u32 = V4L2_CID_CODEC_STATELESS_BASE + 301;
+
+pub const V4L2_VP9_LOOP_FILTER_FLAG_DELTA_ENABLED: u8 = 0x1;
+pub const V4L2_VP9_LOOP_FILTER_FLAG_DELTA_UPDATE: u8 = 0x2;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_vp9_loop_filter {
+    pub ref_deltas: [i8; 4],
+    pub mode_deltas: [i8; 2],
+    pub level: u8,
+    pub sharpness: u8,
+    pub flags: u8,
+    pub reserved: [u8; 7],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_vp9_quantization {
+    pub base_q_idx: u8,
+    pub delta_q_y_dc: i8,
+    pub delta_q_uv_dc: i8,
+    pub delta_q_uv_ac: i8,
+    pub reserved: [u8; 4],
+}
+
+pub const V4L2_VP9_SEGMENTATION_FLAG_ENABLED: u8 = 0x01;
+pub const V4L2_VP9_SEGMENTATION_FLAG_UPDATE_MAP: u8 = 0x02;
+pub const V4L2_VP9_SEGMENTATION_FLAG_TEMPORAL_UPDATE: u8 = 0x04;
+pub const V4L2_VP9_SEGMENTATION_FLAG_UPDATE_DATA: u8 = 0x08;
+pub const V4L2_VP9_SEGMENTATION_FLAG_ABS_OR_DELTA_UPDATE: u8 = 0x10;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_vp9_segmentation {
+    pub feature_data: [[i16; 4]; 8],
+    pub feature_enabled: [u8; 8],
+    pub tree_probs: [u8; 7],
+    pub pred_probs: [u8; 3],
+    pub flags: u8,
+    pub reserved: [u8; 5],
+}
+
+pub const V4L2_VP9_FRAME_FLAG_KEY_FRAME: u32 = 0x001;
+pub const V4L2_VP9_FRAME_FLAG_SHOW_FRAME: u32 = 0x002;
+pub const V4L2_VP9_FRAME_FLAG_ERROR_RESILIENT: u32 = 0x004;
+pub const V4L2_VP9_FRAME_FLAG_INTRA_ONLY: u32 = 0x008;
+pub const V4L2_VP9_FRAME_FLAG_ALLOW_HIGH_PREC_MV: u32 = 0x010;
+pub const V4L2_VP9_FRAME_FLAG_REFRESH_FRAME_CTX: u32 = 0x020;
+pub const V4L2_VP9_FRAME_FLAG_PARALLEL_DEC_MODE: u32 = 0x040;
+pub const V4L2_VP9_FRAME_FLAG_X_SUBSAMPLING: u32 = 0x080;
+pub const V4L2_VP9_FRAME_FLAG_Y_SUBSAMPLING: u32 = 0x100;
+pub const V4L2_VP9_FRAME_FLAG_COLOR_RANGE_FULL_SWING: u32 = 0x200;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_ctrl_vp9_frame {
+    pub lf: v4l2_vp9_loop_filter,
+    pub quant: v4l2_vp9_quantization,
+    pub seg: v4l2_vp9_segmentation,
+    pub flags: u32,
+    pub compressed_header_size: u16,
+    pub uncompressed_header_size: u16,
+    pub frame_width_minus_1: u16,
+    pub frame_height_minus_1: u16,
+    pub render_width_minus_1: u16,
+    pub render_height_minus_1: u16,
+    pub last_frame_ts: u64,
+    pub golden_frame_ts: u64,
+    pub alt_frame_ts: u64,
+    pub ref_frame_sign_bias: u8,
+    pub reset_frame_context: u8,
+    pub frame_context_idx: u8,
+    pub profile: u8,
+    pub bit_depth: u8,
+    pub interpolation_filter: u8,
+    pub tile_cols_log2: u8,
+    pub tile_rows_log2: u8,
+    pub reference_mode: u8,
+    pub reserved: [u8; 7],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct v4l2_vp9_mv_probs {
+    pub joint: [u8; 3],
+    pub sign: [u8; 2],
+    pub classes: [[u8; 10]; 2],
+    pub class0_bit: [u8; 2],
+    pub bits: [[u8; 10]; 2],
+    pub class0_fr: [[[u8; 3]; 2]; 2],
+    pub fr: [[u8; 3]; 2],
+    pub class0_hp: [u8; 2],
+    pub hp: [u8; 2],
+}
+
+// Mirrors the kernel layout, coefficient probabilities included.
+#[allow(clippy::type_complexity)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct v4l2_ctrl_vp9_compressed_hdr {
+    pub tx_mode: u8,
+    pub tx8: [[u8; 1]; 2],
+    pub tx16: [[u8; 2]; 2],
+    pub tx32: [[u8; 3]; 2],
+    pub coef: [[[[[[u8; 3]; 6]; 6]; 2]; 2]; 4],
+    pub skip: [u8; 3],
+    pub inter_mode: [[u8; 3]; 7],
+    pub interp_filter: [[u8; 2]; 4],
+    pub is_inter: [u8; 4],
+    pub comp_mode: [u8; 5],
+    pub single_ref: [[u8; 2]; 5],
+    pub comp_ref: [u8; 5],
+    pub y_mode: [[u8; 9]; 4],
+    pub uv_mode: [[u8; 9]; 10],
+    pub partition: [[u8; 3]; 16],
+    pub mv: v4l2_vp9_mv_probs,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_sizes_match_kernel() {
+        assert_eq!(mem::size_of::<v4l2_ctrl_hevc_sps>(), 40);
+        assert_eq!(mem::size_of::<v4l2_ctrl_hevc_pps>(), 64);
+        assert_eq!(mem::size_of::<v4l2_ctrl_hevc_slice_params>(), 280);
+        assert_eq!(mem::size_of::<v4l2_ctrl_hevc_decode_params>(), 328);
+        assert_eq!(mem::size_of::<v4l2_ctrl_hevc_scaling_matrix>(), 1000);
+        assert_eq!(mem::size_of::<v4l2_ctrl_vp9_frame>(), 168);
+        assert_eq!(mem::size_of::<v4l2_ctrl_vp9_compressed_hdr>(), 2040);
+    }
+}
@@ -1,9 +1,11 @@
 #![allow(non_camel_case_types)]
 
-use core::mem;
+use core::{fmt, mem};
 
 use libc::{c_char, c_int, c_ulong, c_void, timespec, timeval};
 use nix::sys::ioctl::ioctl_num_type;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 // videodev2.h
 
@@ -11,6 +13,7 @@ pub const VIDEO_MAX_PLANES: usize = 8;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum v4l2_field {
     V4L2_FIELD_ANY = 0,
     V4L2_FIELD_NONE = 1,
@@ -26,14 +29,17 @@ pub enum v4l2_field {
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum v4l2_memory {
     V4L2_MEMORY_MMAP = 1,
     V4L2_MEMORY_USERPTR = 2,
     V4L2_MEMORY_OVERLAY = 3,
+    V4L2_MEMORY_DMABUF = 4,
 }
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum v4l2_colorspace {
     V4L2_COLORSPACE_SMPTE170M = 1,
     V4L2_COLORSPACE_SMPTE240M = 2,
@@ -47,6 +53,7 @@ pub enum v4l2_colorspace {
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum v4l2_buf_type {
     V4L2_BUF_TYPE_VIDEO_CAPTURE = 1,
     V4L2_BUF_TYPE_VIDEO_OUTPUT = 2,
@@ -63,35 +70,39 @@ pub enum v4l2_buf_type {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct v4l2_rect {
-    left: i32,
-    top: i32,
-    width: i32,
-    height: i32,
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_fract {
     pub numerator: u32,
     pub denominator: u32,
 }
 
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_capability {
-    driver: [u8; 16],
-    card: [u8; 32],
-    bus_info: [u8; 32],
-    version: u32,
-    capabilities: u32,
-    device_caps: u32,
-    reserved: [u32; 3],
+    pub driver: [u8; 16],
+    pub card: [u8; 32],
+    pub bus_info: [u8; 32],
+    pub version: u32,
+    pub capabilities: u32,
+    pub device_caps: u32,
+    pub reserved: [u32; 3],
 }
 
 #[cfg(not(feature = "sunxi-vfe"))]
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_pix_format {
     pub width: u32,
     pub height: u32,
@@ -105,7 +116,8 @@ pub struct v4l2_pix_format {
 
 #[cfg(feature = "sunxi-vfe")]
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_pix_format {
     pub width: u32,
     pub height: u32,
@@ -116,21 +128,44 @@ pub struct v4l2_pix_format {
     pub colorspace: v4l2_colorspace,
     pub private: u32,
     pub rot_angle: u32,
+    #[cfg_attr(feature = "serde", serde(skip, default = "core::ptr::null_mut"))]
     pub subchannel: *mut v4l2_pix_format,
 }
 
+// `subchannel` is only dereferenced by the driver during `VIDIOC_S_FMT`.
+#[cfg(feature = "sunxi-vfe")]
+unsafe impl Send for v4l2_pix_format {}
+
 macro_rules! v4l2_fourcc {
     ( $a:expr, $b:expr, $c:expr, $d:expr ) => {
         ($a as u32) | (($b as u32) << 8) | (($c as u32) << 16) | (($d as u32) << 24)
     };
 }
 
+/* RGB formats */
+pub const V4L2_PIX_FMT_BGR24: u32 = v4l2_fourcc!('B', 'G', 'R', '3'); /* 24  BGR-8-8-8     */
+pub const V4L2_PIX_FMT_RGB24: u32 = v4l2_fourcc!('R', 'G', 'B', '3'); /* 24  RGB-8-8-8     */
+pub const V4L2_PIX_FMT_ABGR32: u32 = v4l2_fourcc!('A', 'R', '2', '4'); /* 32  BGRA-8-8-8-8  */
+pub const V4L2_PIX_FMT_XBGR32: u32 = v4l2_fourcc!('X', 'R', '2', '4'); /* 32  BGRX-8-8-8-8  */
+
+/* Grey formats */
+pub const V4L2_PIX_FMT_GREY: u32 = v4l2_fourcc!('G', 'R', 'E', 'Y'); /*  8  Greyscale     */
+
 pub const V4L2_PIX_FMT_YVU420: u32 = v4l2_fourcc!('Y', 'V', '1', '2'); /* 12  YVU 4:2:0     */
 pub const V4L2_PIX_FMT_YUV420: u32 = v4l2_fourcc!('Y', 'U', '1', '2'); /* 12  YUV 4:2:0     */
 pub const V4L2_PIX_FMT_YUYV: u32 = v4l2_fourcc!('Y', 'U', 'Y', 'V'); /* 16  YUV 4:2:2     */
 pub const V4L2_PIX_FMT_NV12: u32 = v4l2_fourcc!('N', 'V', '1', '2'); /* 12  Y/CbCr 4:2:0  */
 pub const V4L2_PIX_FMT_NV21: u32 = v4l2_fourcc!('N', 'V', '2', '1'); /* 12  Y/CrCb 4:2:0  */
 
+/* Bayer formats */
+pub const V4L2_PIX_FMT_SBGGR8: u32 = v4l2_fourcc!('B', 'A', '8', '1'); /*  8  BGBG.. GRGR.. */
+pub const V4L2_PIX_FMT_SGBRG8: u32 = v4l2_fourcc!('G', 'B', 'R', 'G'); /*  8  GBGB.. RGRG.. */
+pub const V4L2_PIX_FMT_SGRBG8: u32 = v4l2_fourcc!('G', 'R', 'B', 'G'); /*  8  GRGR.. BGBG.. */
+pub const V4L2_PIX_FMT_SRGGB8: u32 = v4l2_fourcc!('R', 'G', 'G', 'B'); /*  8  RGRG.. GBGB.. */
+pub const V4L2_PIX_FMT_SBGGR10: u32 = v4l2_fourcc!('B', 'G', '1', '0'); /* 10  BGBG.. GRGR.. */
+/* 10bit raw bayer packed, 5 bytes for every 4 pixels */
+pub const V4L2_PIX_FMT_SBGGR10P: u32 = v4l2_fourcc!('p', 'B', 'A', 'A');
+
 // /* compressed formats */
 pub const V4L2_PIX_FMT_MJPEG: u32 = v4l2_fourcc!('M', 'J', 'P', 'G'); /* Motion-JPEG   */
 pub const V4L2_PIX_FMT_JPEG: u32 = v4l2_fourcc!('J', 'P', 'E', 'G'); /* JFIF JPEG     */
@@ -147,9 +182,14 @@ pub const V4L2_PIX_FMT_MPEG2: u32 = v4l2_fourcc!('M', 'P', 'G', '2'); /* MPEG-2
 // #define V4L2_PIX_FMT_VC1_ANNEX_G v4l2_fourcc('V', 'C', '1', 'G') /* SMPTE 421M Annex G compliant stream */
 // #define V4L2_PIX_FMT_VC1_ANNEX_L v4l2_fourcc('V', 'C', '1', 'L') /* SMPTE 421M Annex L compliant stream */
 pub const V4L2_PIX_FMT_VP8: u32 = v4l2_fourcc!('V', 'P', '8', '0'); /* VP8 */
+pub const V4L2_PIX_FMT_VP9: u32 = v4l2_fourcc!('V', 'P', '9', '0'); /* VP9 */
+pub const V4L2_PIX_FMT_HEVC: u32 = v4l2_fourcc!('H', 'E', 'V', 'C'); /* HEVC aka H.265 */
+pub const V4L2_PIX_FMT_VP9_FRAME: u32 = v4l2_fourcc!('V', 'P', '9', 'F'); /* VP9 parsed frame */
+pub const V4L2_PIX_FMT_HEVC_SLICE: u32 = v4l2_fourcc!('S', '2', '6', '5'); /* HEVC parsed slices */
 
 #[repr(C)]
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_fmtdesc {
     pub index: u32,
     pub typ: v4l2_buf_type,
@@ -159,8 +199,23 @@ pub struct v4l2_fmtdesc {
     pub reserved: [u32; 4],
 }
 
+impl v4l2_fmtdesc {
+    /// Returns the query of format `index` of `typ` queues for
+    /// `VIDIOC_ENUM_FMT`.
+    pub fn new(typ: v4l2_buf_type, index: u32) -> v4l2_fmtdesc {
+        let mut fmtdesc = mem::MaybeUninit::<v4l2_fmtdesc>::zeroed();
+        unsafe {
+            let ptr = fmtdesc.as_mut_ptr();
+            core::ptr::addr_of_mut!((*ptr).typ).write(typ);
+            (*ptr).index = index;
+            fmtdesc.assume_init()
+        }
+    }
+}
+
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum v4l2_frmsizetypes {
     V4L2_FRMSIZE_TYPE_DISCRETE = 1,
     V4L2_FRMSIZE_TYPE_CONTINUOUS = 2,
@@ -169,6 +224,7 @@ pub enum v4l2_frmsizetypes {
 
 #[repr(C)]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_frmsize_discrete {
     pub width: u32,
     pub height: u32,
@@ -176,6 +232,7 @@ pub struct v4l2_frmsize_discrete {
 
 #[repr(C)]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_frmsize_stepwise {
     pub min_width: u32,
     pub max_width: u32,
@@ -202,26 +259,139 @@ pub struct v4l2_frmsizeenum {
     pub reserved: [u32; 2],
 }
 
+impl v4l2_frmsizeenum {
+    /// Returns the query of frame size `index` of `pixel_format` for
+    /// `VIDIOC_ENUM_FRAMESIZES`. The driver fills in `typ`.
+    pub fn new(pixel_format: u32, index: u32) -> v4l2_frmsizeenum {
+        let mut frmsize = mem::MaybeUninit::<v4l2_frmsizeenum>::zeroed();
+        unsafe {
+            let ptr = frmsize.as_mut_ptr();
+            core::ptr::addr_of_mut!((*ptr).typ)
+                .write(v4l2_frmsizetypes::V4L2_FRMSIZE_TYPE_DISCRETE);
+            (*ptr).index = index;
+            (*ptr).pixel_format = pixel_format;
+            frmsize.assume_init()
+        }
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum v4l2_frmivaltypes {
+    V4L2_FRMIVAL_TYPE_DISCRETE = 1,
+    V4L2_FRMIVAL_TYPE_CONTINUOUS = 2,
+    V4L2_FRMIVAL_TYPE_STEPWISE = 3,
+}
+
 #[repr(C)]
-#[derive(Clone)]
+#[derive(Clone, Copy)]
+pub struct v4l2_frmival_stepwise {
+    pub min: v4l2_fract,  /* Minimum frame interval [s] */
+    pub max: v4l2_fract,  /* Maximum frame interval [s] */
+    pub step: v4l2_fract, /* Frame interval step size [s] */
+}
+
+#[repr(C)]
+pub union _v4l2_frmivalenum_u {
+    pub discrete: v4l2_fract,
+    pub stepwise: v4l2_frmival_stepwise,
+}
+
+#[repr(C)]
+pub struct v4l2_frmivalenum {
+    pub index: u32,        /* Frame format index */
+    pub pixel_format: u32, /* Pixel format */
+    pub width: u32,        /* Frame width */
+    pub height: u32,       /* Frame height */
+    pub typ: v4l2_frmivaltypes,
+
+    pub u: _v4l2_frmivalenum_u,
+
+    pub reserved: [u32; 2],
+}
+
+impl v4l2_frmivalenum {
+    /// Returns the query of frame interval `index` of `pixel_format` at
+    /// `width`x`height` for `VIDIOC_ENUM_FRAMEINTERVALS`. The driver fills
+    /// in `typ`.
+    pub fn new(pixel_format: u32, width: u32, height: u32, index: u32) -> v4l2_frmivalenum {
+        let mut frmival = mem::MaybeUninit::<v4l2_frmivalenum>::zeroed();
+        unsafe {
+            let ptr = frmival.as_mut_ptr();
+            core::ptr::addr_of_mut!((*ptr).typ)
+                .write(v4l2_frmivaltypes::V4L2_FRMIVAL_TYPE_DISCRETE);
+            (*ptr).index = index;
+            (*ptr).pixel_format = pixel_format;
+            (*ptr).width = width;
+            (*ptr).height = height;
+            frmival.assume_init()
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct v4l2_timecode {
-    typ: u32,
-    flags: u32,
-    frames: u8,
-    seconds: u8,
-    minutes: u8,
-    hours: u8,
-    userbits: [u8; 4],
+    pub typ: u32,
+    pub flags: u32,
+    pub frames: u8,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub userbits: [u8; 4],
+}
+
+/// Formats the timecode the SMPTE way, as `HH:MM:SS:FF`, or `HH:MM:SS;FF`
+/// for drop-frame timecodes.
+impl fmt::Display for v4l2_timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.flags & V4L2_TC_FLAG_DROPFRAME != 0 {
+            ';'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
 }
 
+/* Timecode types */
+pub const V4L2_TC_TYPE_24FPS: u32 = 1;
+pub const V4L2_TC_TYPE_25FPS: u32 = 2;
+pub const V4L2_TC_TYPE_30FPS: u32 = 3;
+pub const V4L2_TC_TYPE_50FPS: u32 = 4;
+pub const V4L2_TC_TYPE_60FPS: u32 = 5;
+
+/* Timecode flags */
+pub const V4L2_TC_FLAG_DROPFRAME: u32 = 0x0001;
+pub const V4L2_TC_FLAG_COLORFRAME: u32 = 0x0002;
+pub const V4L2_TC_USERBITS_FIELD: u32 = 0x000c;
+pub const V4L2_TC_USERBITS_USERDEFINED: u32 = 0x0000;
+pub const V4L2_TC_USERBITS_8BITCHARS: u32 = 0x0008;
+
 #[repr(C)]
 pub struct v4l2_requestbuffers {
     pub count: u32,
     pub typ: v4l2_buf_type,
     pub memory: v4l2_memory,
-    pub reserved: [u32; 2],
+    pub capabilities: u32,
+    pub flags: u8,
+    pub reserved: [u8; 3],
 }
 
+/* capabilities for struct v4l2_requestbuffers and v4l2_create_buffers */
+pub const V4L2_BUF_CAP_SUPPORTS_MMAP: u32 = 1 << 0;
+pub const V4L2_BUF_CAP_SUPPORTS_USERPTR: u32 = 1 << 1;
+pub const V4L2_BUF_CAP_SUPPORTS_DMABUF: u32 = 1 << 2;
+pub const V4L2_BUF_CAP_SUPPORTS_REQUESTS: u32 = 1 << 3;
+pub const V4L2_BUF_CAP_SUPPORTS_ORPHANED_BUFS: u32 = 1 << 4;
+pub const V4L2_BUF_CAP_SUPPORTS_M2M_HOLD_CAPTURE_BUF: u32 = 1 << 5;
+pub const V4L2_BUF_CAP_SUPPORTS_MMAP_CACHE_HINTS: u32 = 1 << 6;
+
 #[repr(C)]
 pub union _v4l2_plane_m {
     mem_offset: u32,
@@ -243,6 +413,7 @@ pub union _v4l2_buffer_m {
     pub offset: u32,
     pub userptr: c_ulong,
     pub planes: *mut v4l2_plane,
+    pub fd: i32,
 }
 
 #[repr(C)]
@@ -261,30 +432,125 @@ pub struct v4l2_buffer {
     pub memory: v4l2_memory,
     pub m: _v4l2_buffer_m,
     pub length: u32,
+    // The Allwinner BSP kernels predate the request API.
+    #[cfg(feature = "sunxi-vfe")]
     pub input: u32,
+    #[cfg(feature = "sunxi-vfe")]
     pub reserved: u32,
+    #[cfg(not(feature = "sunxi-vfe"))]
+    pub reserved2: u32,
+    /// Media request the buffer is queued to, with
+    /// `V4L2_BUF_FLAG_REQUEST_FD`.
+    #[cfg(not(feature = "sunxi-vfe"))]
+    pub request_fd: i32,
+}
+
+impl v4l2_buffer {
+    /// Returns a buffer of `typ` and `memory` with all other fields zeroed.
+    ///
+    /// Zero is neither a valid buffer type nor memory, so a `v4l2_buffer`
+    /// cannot be built with `mem::zeroed`.
+    pub fn new(typ: v4l2_buf_type, memory: v4l2_memory) -> v4l2_buffer {
+        let mut buf = mem::MaybeUninit::<v4l2_buffer>::zeroed();
+        unsafe {
+            let ptr = buf.as_mut_ptr();
+            core::ptr::addr_of_mut!((*ptr).typ).write(typ);
+            core::ptr::addr_of_mut!((*ptr).memory).write(memory);
+            buf.assume_init()
+        }
+    }
+}
+
+/*  Flags for 'flags' field */
+pub const V4L2_BUF_FLAG_MAPPED: u32 = 0x0000_0001;
+pub const V4L2_BUF_FLAG_QUEUED: u32 = 0x0000_0002;
+pub const V4L2_BUF_FLAG_DONE: u32 = 0x0000_0004;
+pub const V4L2_BUF_FLAG_KEYFRAME: u32 = 0x0000_0008;
+pub const V4L2_BUF_FLAG_PFRAME: u32 = 0x0000_0010;
+pub const V4L2_BUF_FLAG_BFRAME: u32 = 0x0000_0020;
+pub const V4L2_BUF_FLAG_ERROR: u32 = 0x0000_0040;
+pub const V4L2_BUF_FLAG_IN_REQUEST: u32 = 0x0000_0080;
+pub const V4L2_BUF_FLAG_TIMECODE: u32 = 0x0000_0100;
+pub const V4L2_BUF_FLAG_M2M_HOLD_CAPTURE_BUF: u32 = 0x0000_0200;
+pub const V4L2_BUF_FLAG_PREPARED: u32 = 0x0000_0400;
+pub const V4L2_BUF_FLAG_NO_CACHE_INVALIDATE: u32 = 0x0000_0800;
+pub const V4L2_BUF_FLAG_NO_CACHE_CLEAN: u32 = 0x0000_1000;
+pub const V4L2_BUF_FLAG_TIMESTAMP_MASK: u32 = 0x0000_e000;
+pub const V4L2_BUF_FLAG_TIMESTAMP_UNKNOWN: u32 = 0x0000_0000;
+pub const V4L2_BUF_FLAG_TIMESTAMP_MONOTONIC: u32 = 0x0000_2000;
+pub const V4L2_BUF_FLAG_TIMESTAMP_COPY: u32 = 0x0000_4000;
+pub const V4L2_BUF_FLAG_LAST: u32 = 0x0010_0000;
+pub const V4L2_BUF_FLAG_REQUEST_FD: u32 = 0x0080_0000;
+
+#[repr(C)]
+pub struct v4l2_exportbuffer {
+    pub typ: v4l2_buf_type,
+    pub index: u32,
+    pub plane: u32,
+    pub flags: u32,
+    pub fd: i32,
+    pub reserved: [u32; 11],
+}
+
+#[repr(C)]
+pub struct _v4l2_framebuffer_fmt {
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: u32,
+    pub field: v4l2_field,
+    pub bytesperline: u32,
+    pub sizeimage: u32,
+    pub colorspace: v4l2_colorspace,
+    pub private: u32,
+}
+
+#[repr(C)]
+pub struct v4l2_framebuffer {
+    pub capability: u32,
+    pub flags: u32,
+    pub base: *mut c_void,
+    pub fmt: _v4l2_framebuffer_fmt,
 }
 
+/*  Flags for the 'capability' field. Read only */
+pub const V4L2_FBUF_CAP_EXTERNOVERLAY: u32 = 0x0001;
+pub const V4L2_FBUF_CAP_CHROMAKEY: u32 = 0x0002;
+pub const V4L2_FBUF_CAP_LIST_CLIPPING: u32 = 0x0004;
+pub const V4L2_FBUF_CAP_BITMAP_CLIPPING: u32 = 0x0008;
+pub const V4L2_FBUF_CAP_LOCAL_ALPHA: u32 = 0x0010;
+pub const V4L2_FBUF_CAP_GLOBAL_ALPHA: u32 = 0x0020;
+pub const V4L2_FBUF_CAP_LOCAL_INV_ALPHA: u32 = 0x0040;
+pub const V4L2_FBUF_CAP_SRC_CHROMAKEY: u32 = 0x0080;
+/*  Flags for the 'flags' field. */
+pub const V4L2_FBUF_FLAG_PRIMARY: u32 = 0x0001;
+pub const V4L2_FBUF_FLAG_OVERLAY: u32 = 0x0002;
+pub const V4L2_FBUF_FLAG_CHROMAKEY: u32 = 0x0004;
+pub const V4L2_FBUF_FLAG_LOCAL_ALPHA: u32 = 0x0008;
+pub const V4L2_FBUF_FLAG_GLOBAL_ALPHA: u32 = 0x0010;
+pub const V4L2_FBUF_FLAG_LOCAL_INV_ALPHA: u32 = 0x0020;
+pub const V4L2_FBUF_FLAG_SRC_CHROMAKEY: u32 = 0x0040;
+
 #[repr(C)]
 pub struct v4l2_clip {
-    c: v4l2_rect,
-    next: *mut v4l2_clip,
+    pub c: v4l2_rect,
+    pub next: *mut v4l2_clip,
 }
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct v4l2_window {
-    w: v4l2_rect,
-    field: v4l2_field,
-    chromakey: u32,
-    clips: *mut v4l2_clip,
-    clipcount: u32,
-    bitmap: *mut c_void,
-    global_alpha: u8,
+    pub w: v4l2_rect,
+    pub field: v4l2_field,
+    pub chromakey: u32,
+    pub clips: *mut v4l2_clip,
+    pub clipcount: u32,
+    pub bitmap: *mut c_void,
+    pub global_alpha: u8,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_captureparm {
     pub capability: u32,
     pub capturemode: u32,
@@ -296,10 +562,142 @@ pub struct v4l2_captureparm {
 
 pub const V4L2_MODE_HIGHQUALITY: u32 = 0x0001; /*  High quality imaging mode */
 
+/// Argument of the deprecated `VIDIOC_G_JPEGCOMP` and `VIDIOC_S_JPEGCOMP`,
+/// superseded by the JPEG control class.
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_snake_case)]
+pub struct v4l2_jpegcompression {
+    pub quality: i32,
+    pub APPn: i32,
+    pub APP_len: i32,
+    pub APP_data: [u8; 60],
+    pub COM_len: i32,
+    pub COM_data: [u8; 60],
+    pub jpeg_markers: u32,
+}
+
+pub type v4l2_std_id = u64;
+
+/* one bit for each */
+pub const V4L2_STD_PAL_B: v4l2_std_id = 0x0000_0001;
+pub const V4L2_STD_PAL_B1: v4l2_std_id = 0x0000_0002;
+pub const V4L2_STD_PAL_G: v4l2_std_id = 0x0000_0004;
+pub const V4L2_STD_PAL_H: v4l2_std_id = 0x0000_0008;
+pub const V4L2_STD_PAL_I: v4l2_std_id = 0x0000_0010;
+pub const V4L2_STD_PAL_D: v4l2_std_id = 0x0000_0020;
+pub const V4L2_STD_PAL_D1: v4l2_std_id = 0x0000_0040;
+pub const V4L2_STD_PAL_K: v4l2_std_id = 0x0000_0080;
+
+pub const V4L2_STD_PAL_M: v4l2_std_id = 0x0000_0100;
+pub const V4L2_STD_PAL_N: v4l2_std_id = 0x0000_0200;
+pub const V4L2_STD_PAL_NC: v4l2_std_id = 0x0000_0400;
+pub const V4L2_STD_PAL_60: v4l2_std_id = 0x0000_0800;
+
+pub const V4L2_STD_NTSC_M: v4l2_std_id = 0x0000_1000; /* BTSC */
+pub const V4L2_STD_NTSC_M_JP: v4l2_std_id = 0x0000_2000; /* EIA-J */
+pub const V4L2_STD_NTSC_443: v4l2_std_id = 0x0000_4000;
+pub const V4L2_STD_NTSC_M_KR: v4l2_std_id = 0x0000_8000; /* FM A2 */
+
+pub const V4L2_STD_SECAM_B: v4l2_std_id = 0x0001_0000;
+pub const V4L2_STD_SECAM_D: v4l2_std_id = 0x0002_0000;
+pub const V4L2_STD_SECAM_G: v4l2_std_id = 0x0004_0000;
+pub const V4L2_STD_SECAM_H: v4l2_std_id = 0x0008_0000;
+pub const V4L2_STD_SECAM_K: v4l2_std_id = 0x0010_0000;
+pub const V4L2_STD_SECAM_K1: v4l2_std_id = 0x0020_0000;
+pub const V4L2_STD_SECAM_L: v4l2_std_id = 0x0040_0000;
+pub const V4L2_STD_SECAM_LC: v4l2_std_id = 0x0080_0000;
+
+/* some merged standards */
+pub const V4L2_STD_NTSC: v4l2_std_id = V4L2_STD_NTSC_M | V4L2_STD_NTSC_M_JP | V4L2_STD_NTSC_M_KR;
+pub const V4L2_STD_SECAM_DK: v4l2_std_id = V4L2_STD_SECAM_D | V4L2_STD_SECAM_K | V4L2_STD_SECAM_K1;
+pub const V4L2_STD_SECAM: v4l2_std_id = V4L2_STD_SECAM_B
+    | V4L2_STD_SECAM_G
+    | V4L2_STD_SECAM_H
+    | V4L2_STD_SECAM_DK
+    | V4L2_STD_SECAM_L
+    | V4L2_STD_SECAM_LC;
+pub const V4L2_STD_PAL_BG: v4l2_std_id = V4L2_STD_PAL_B | V4L2_STD_PAL_B1 | V4L2_STD_PAL_G;
+pub const V4L2_STD_PAL_DK: v4l2_std_id = V4L2_STD_PAL_D | V4L2_STD_PAL_D1 | V4L2_STD_PAL_K;
+pub const V4L2_STD_PAL: v4l2_std_id =
+    V4L2_STD_PAL_BG | V4L2_STD_PAL_DK | V4L2_STD_PAL_H | V4L2_STD_PAL_I;
+
+/* Standards grouped by lines and frame rate */
+pub const V4L2_STD_525_60: v4l2_std_id =
+    V4L2_STD_PAL_M | V4L2_STD_PAL_60 | V4L2_STD_NTSC | V4L2_STD_NTSC_443;
+pub const V4L2_STD_625_50: v4l2_std_id =
+    V4L2_STD_PAL | V4L2_STD_PAL_N | V4L2_STD_PAL_NC | V4L2_STD_SECAM;
+
+pub const V4L2_STD_UNKNOWN: v4l2_std_id = 0;
+pub const V4L2_STD_ALL: v4l2_std_id = V4L2_STD_525_60 | V4L2_STD_625_50;
+
+/*
+ *	V I D E O   I N P U T S
+ */
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct v4l2_input {
+    pub index: u32,     /*  Which input */
+    pub name: [u8; 32], /*  Label */
+    pub typ: u32,       /*  Type of input */
+    pub audioset: u32,  /*  Associated audios (bitfield) */
+    pub tuner: u32,     /*  Tuner index */
+    pub std: v4l2_std_id,
+    pub status: u32,
+    pub capabilities: u32,
+    pub reserved: [u32; 3],
+}
+
+/*  Values for the 'type' field */
+pub const V4L2_INPUT_TYPE_TUNER: u32 = 1;
+pub const V4L2_INPUT_TYPE_CAMERA: u32 = 2;
+pub const V4L2_INPUT_TYPE_TOUCH: u32 = 3;
+
+/* field 'status' - general */
+pub const V4L2_IN_ST_NO_POWER: u32 = 0x0000_0001; /* Attached device is off */
+pub const V4L2_IN_ST_NO_SIGNAL: u32 = 0x0000_0002;
+pub const V4L2_IN_ST_NO_COLOR: u32 = 0x0000_0004;
+
+/* field 'status' - analog */
+pub const V4L2_IN_ST_NO_H_LOCK: u32 = 0x0000_0100; /* No horizontal sync lock */
+pub const V4L2_IN_ST_COLOR_KILL: u32 = 0x0000_0200; /* Color killer is active */
+pub const V4L2_IN_ST_NO_V_LOCK: u32 = 0x0000_0400; /* No vertical sync lock */
+pub const V4L2_IN_ST_NO_STD_LOCK: u32 = 0x0000_0800; /* No standard format lock */
+
+/* field 'status' - digital */
+pub const V4L2_IN_ST_NO_SYNC: u32 = 0x0001_0000; /* No synchronization lock */
+pub const V4L2_IN_ST_NO_EQU: u32 = 0x0002_0000; /* No equalizer lock */
+pub const V4L2_IN_ST_NO_CARRIER: u32 = 0x0004_0000; /* Carrier recovery failed */
+
+/* field 'status' - VCR and set-top box */
+pub const V4L2_IN_ST_MACROVISION: u32 = 0x0100_0000; /* Macrovision detected */
+pub const V4L2_IN_ST_NO_ACCESS: u32 = 0x0200_0000; /* Conditional access denied */
+pub const V4L2_IN_ST_VTR: u32 = 0x0400_0000; /* VTR time constant */
+
+/* capabilities flags */
+pub const V4L2_IN_CAP_DV_TIMINGS: u32 = 0x0000_0002; /* Supports S_DV_TIMINGS */
+pub const V4L2_IN_CAP_STD: u32 = 0x0000_0004; /* Supports S_STD */
+pub const V4L2_IN_CAP_NATIVE_SIZE: u32 = 0x0000_0008; /* Supports setting native size */
+
+/* Values for 'capabilities' field of v4l2_capability */
+pub const V4L2_CAP_VIDEO_CAPTURE: u32 = 0x0000_0001; /* Is a video capture device */
+pub const V4L2_CAP_VIDEO_OUTPUT: u32 = 0x0000_0002; /* Is a video output device */
+pub const V4L2_CAP_VIDEO_OVERLAY: u32 = 0x0000_0004; /* Can do video overlay */
+pub const V4L2_CAP_VIDEO_OUTPUT_OVERLAY: u32 = 0x0000_0200; /* Can do video output overlay */
+pub const V4L2_CAP_VIDEO_CAPTURE_MPLANE: u32 = 0x0000_1000; /* Is a video capture device that supports multiplanar formats */
+pub const V4L2_CAP_VIDEO_OUTPUT_MPLANE: u32 = 0x0000_2000; /* Is a video output device that supports multiplanar formats */
+pub const V4L2_CAP_VIDEO_M2M_MPLANE: u32 = 0x0000_4000; /* Is a video mem-to-mem device that supports multiplanar formats */
+pub const V4L2_CAP_VIDEO_M2M: u32 = 0x0000_8000; /* Is a video mem-to-mem device */
+pub const V4L2_CAP_READWRITE: u32 = 0x0100_0000; /* read/write systemcalls */
+pub const V4L2_CAP_STREAMING: u32 = 0x0400_0000; /* streaming I/O ioctls */
+pub const V4L2_CAP_DEVICE_CAPS: u32 = 0x8000_0000; /* sets device capabilities field */
+
 pub const V4L2_CAP_TIMEPERFRAME: u32 = 0x1000; /*  timeperframe field is supported */
 
 #[repr(C)]
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct v4l2_outputparm {
     pub capability: u32,
     pub outputmode: u32,
@@ -369,6 +767,19 @@ pub struct v4l2_format {
     pub fmt: v4l2_format_fmt,
 }
 
+/// Argument of `VIDIOC_CREATE_BUFS`.
+#[repr(C)]
+pub struct v4l2_create_buffers {
+    pub index: u32,
+    pub count: u32,
+    pub memory: v4l2_memory,
+    pub format: v4l2_format,
+    pub capabilities: u32,
+    pub flags: u32,
+    pub max_num_buffers: u32,
+    pub reserved: [u32; 5],
+}
+
 #[repr(C)]
 pub union _v4l2_streamparm_parm {
     pub capture: v4l2_captureparm,
@@ -382,38 +793,395 @@ pub struct v4l2_streamparm {
     pub parm: _v4l2_streamparm_parm,
 }
 
-pub const V4L2_EVENT_PRIVATE_START: u32 = 0x08000000;
+/// Argument of `VIDIOC_G_SELECTION` and `VIDIOC_S_SELECTION`. `typ` is a
+/// `v4l2_buf_type`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct v4l2_selection {
+    pub typ: u32,
+    pub target: u32,
+    pub flags: u32,
+    pub r: v4l2_rect,
+    pub reserved: [u32; 9],
+}
+
+/* Selection targets */
+pub const V4L2_SEL_TGT_CROP: u32 = 0x0000;
+pub const V4L2_SEL_TGT_CROP_DEFAULT: u32 = 0x0001;
+pub const V4L2_SEL_TGT_CROP_BOUNDS: u32 = 0x0002;
+pub const V4L2_SEL_TGT_NATIVE_SIZE: u32 = 0x0003;
+pub const V4L2_SEL_TGT_COMPOSE: u32 = 0x0100;
+pub const V4L2_SEL_TGT_COMPOSE_DEFAULT: u32 = 0x0101;
+pub const V4L2_SEL_TGT_COMPOSE_BOUNDS: u32 = 0x0102;
+pub const V4L2_SEL_TGT_COMPOSE_PADDED: u32 = 0x0103;
+
+/* Selection flags */
+pub const V4L2_SEL_FLAG_GE: u32 = 1 << 0;
+pub const V4L2_SEL_FLAG_LE: u32 = 1 << 1;
+pub const V4L2_SEL_FLAG_KEEP_CONFIG: u32 = 1 << 2;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct v4l2_control {
+    pub id: u32,
+    pub value: i32,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub union _v4l2_ext_control_u {
+    pub value: i32,
+    pub value64: i64,
+    pub ptr: *mut c_void,
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct v4l2_ext_control {
+    pub id: u32,
+    pub size: u32,
+    pub reserved2: [u32; 1],
+    pub u: _v4l2_ext_control_u,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct v4l2_ext_controls {
+    pub which: u32,
+    pub count: u32,
+    pub error_idx: u32,
+    pub request_fd: i32,
+    pub reserved: [u32; 1],
+    pub controls: *mut v4l2_ext_control,
+}
+
+pub const V4L2_CTRL_WHICH_CUR_VAL: u32 = 0;
+pub const V4L2_CTRL_WHICH_DEF_VAL: u32 = 0x0f00_0000;
+pub const V4L2_CTRL_WHICH_REQUEST_VAL: u32 = 0x0f01_0000;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct v4l2_queryctrl {
+    pub id: u32,
+    pub typ: u32,
+    pub name: [u8; 32],
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
+    pub flags: u32,
+    pub reserved: [u32; 2],
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub union _v4l2_querymenu_u {
+    pub name: [u8; 32],
+    pub value: i64,
+}
 
+/// A menu entry, a name for `V4L2_CTRL_TYPE_MENU` and a value for
+/// `V4L2_CTRL_TYPE_INTEGER_MENU` controls.
 #[repr(C, packed)]
 #[derive(Clone, Copy)]
+pub struct v4l2_querymenu {
+    pub id: u32,
+    pub index: u32,
+    pub u: _v4l2_querymenu_u,
+    pub reserved: u32,
+}
+
+pub const V4L2_CTRL_TYPE_INTEGER: u32 = 1;
+pub const V4L2_CTRL_TYPE_BOOLEAN: u32 = 2;
+pub const V4L2_CTRL_TYPE_MENU: u32 = 3;
+pub const V4L2_CTRL_TYPE_BUTTON: u32 = 4;
+pub const V4L2_CTRL_TYPE_INTEGER64: u32 = 5;
+pub const V4L2_CTRL_TYPE_CTRL_CLASS: u32 = 6;
+pub const V4L2_CTRL_TYPE_STRING: u32 = 7;
+pub const V4L2_CTRL_TYPE_BITMASK: u32 = 8;
+pub const V4L2_CTRL_TYPE_INTEGER_MENU: u32 = 9;
+pub const V4L2_CTRL_TYPE_RECT: u32 = 0x0107;
+
+pub const V4L2_CTRL_FLAG_DISABLED: u32 = 0x0001;
+pub const V4L2_CTRL_FLAG_GRABBED: u32 = 0x0002;
+pub const V4L2_CTRL_FLAG_READ_ONLY: u32 = 0x0004;
+pub const V4L2_CTRL_FLAG_UPDATE: u32 = 0x0008;
+pub const V4L2_CTRL_FLAG_INACTIVE: u32 = 0x0010;
+pub const V4L2_CTRL_FLAG_SLIDER: u32 = 0x0020;
+pub const V4L2_CTRL_FLAG_WRITE_ONLY: u32 = 0x0040;
+pub const V4L2_CTRL_FLAG_VOLATILE: u32 = 0x0080;
+
+pub const V4L2_CTRL_FLAG_NEXT_CTRL: u32 = 0x8000_0000;
+
+pub const V4L2_CTRL_CLASS_USER: u32 = 0x0098_0000; /* Old-style 'user' controls */
+pub const V4L2_CTRL_CLASS_MPEG: u32 = 0x0099_0000; /* MPEG-compression controls */
+pub const V4L2_CTRL_CLASS_CAMERA: u32 = 0x009a_0000; /* Camera class controls */
+pub const V4L2_CTRL_CLASS_JPEG: u32 = 0x009d_0000; /* JPEG-compression controls */
+pub const V4L2_CTRL_CLASS_IMAGE_PROC: u32 = 0x009f_0000; /* Image processing controls */
+pub const V4L2_CTRL_CLASS_DETECT: u32 = 0x00a3_0000; /* Detection controls */
+
+pub const V4L2_CID_BASE: u32 = V4L2_CTRL_CLASS_USER | 0x900;
+pub const V4L2_CID_BRIGHTNESS: u32 = V4L2_CID_BASE;
+pub const V4L2_CID_CONTRAST: u32 = V4L2_CID_BASE + 1;
+pub const V4L2_CID_SATURATION: u32 = V4L2_CID_BASE + 2;
+pub const V4L2_CID_HUE: u32 = V4L2_CID_BASE + 3;
+pub const V4L2_CID_AUTO_WHITE_BALANCE: u32 = V4L2_CID_BASE + 12;
+pub const V4L2_CID_DO_WHITE_BALANCE: u32 = V4L2_CID_BASE + 13;
+pub const V4L2_CID_RED_BALANCE: u32 = V4L2_CID_BASE + 14;
+pub const V4L2_CID_BLUE_BALANCE: u32 = V4L2_CID_BASE + 15;
+pub const V4L2_CID_GAMMA: u32 = V4L2_CID_BASE + 16;
+pub const V4L2_CID_EXPOSURE: u32 = V4L2_CID_BASE + 17;
+pub const V4L2_CID_AUTOGAIN: u32 = V4L2_CID_BASE + 18;
+pub const V4L2_CID_GAIN: u32 = V4L2_CID_BASE + 19;
+pub const V4L2_CID_HFLIP: u32 = V4L2_CID_BASE + 20;
+pub const V4L2_CID_VFLIP: u32 = V4L2_CID_BASE + 21;
+pub const V4L2_CID_POWER_LINE_FREQUENCY: u32 = V4L2_CID_BASE + 24;
+pub const V4L2_CID_WHITE_BALANCE_TEMPERATURE: u32 = V4L2_CID_BASE + 26;
+pub const V4L2_CID_SHARPNESS: u32 = V4L2_CID_BASE + 27;
+pub const V4L2_CID_BACKLIGHT_COMPENSATION: u32 = V4L2_CID_BASE + 28;
+pub const V4L2_CID_ROTATE: u32 = V4L2_CID_BASE + 34;
+pub const V4L2_CID_MIN_BUFFERS_FOR_CAPTURE: u32 = V4L2_CID_BASE + 39;
+pub const V4L2_CID_LASTP1: u32 = V4L2_CID_BASE + 44;
+pub const V4L2_CID_PRIVATE_BASE: u32 = 0x0800_0000;
+
+pub const V4L2_CID_CAMERA_CLASS_BASE: u32 = V4L2_CTRL_CLASS_CAMERA | 0x900;
+pub const V4L2_CID_EXPOSURE_AUTO: u32 = V4L2_CID_CAMERA_CLASS_BASE + 1;
+pub const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = V4L2_CID_CAMERA_CLASS_BASE + 2;
+pub const V4L2_CID_EXPOSURE_AUTO_PRIORITY: u32 = V4L2_CID_CAMERA_CLASS_BASE + 3;
+pub const V4L2_CID_AUTO_EXPOSURE_BIAS: u32 = V4L2_CID_CAMERA_CLASS_BASE + 19;
+pub const V4L2_CID_AUTO_N_PRESET_WHITE_BALANCE: u32 = V4L2_CID_CAMERA_CLASS_BASE + 20;
+pub const V4L2_CID_FOCUS_ABSOLUTE: u32 = V4L2_CID_CAMERA_CLASS_BASE + 10;
+pub const V4L2_CID_FOCUS_AUTO: u32 = V4L2_CID_CAMERA_CLASS_BASE + 12;
+pub const V4L2_CID_ZOOM_ABSOLUTE: u32 = V4L2_CID_CAMERA_CLASS_BASE + 13;
+pub const V4L2_CID_EXPOSURE_METERING: u32 = V4L2_CID_CAMERA_CLASS_BASE + 25;
+
+/* values for V4L2_CID_EXPOSURE_AUTO */
+pub const V4L2_EXPOSURE_AUTO: i32 = 0;
+pub const V4L2_EXPOSURE_MANUAL: i32 = 1;
+pub const V4L2_EXPOSURE_SHUTTER_PRIORITY: i32 = 2;
+pub const V4L2_EXPOSURE_APERTURE_PRIORITY: i32 = 3;
+
+/* values for V4L2_CID_EXPOSURE_METERING */
+pub const V4L2_EXPOSURE_METERING_AVERAGE: i32 = 0;
+pub const V4L2_EXPOSURE_METERING_CENTER_WEIGHTED: i32 = 1;
+pub const V4L2_EXPOSURE_METERING_SPOT: i32 = 2;
+pub const V4L2_EXPOSURE_METERING_MATRIX: i32 = 3;
+
+/* values for V4L2_CID_AUTO_N_PRESET_WHITE_BALANCE */
+pub const V4L2_WHITE_BALANCE_MANUAL: i32 = 0;
+pub const V4L2_WHITE_BALANCE_AUTO: i32 = 1;
+pub const V4L2_WHITE_BALANCE_INCANDESCENT: i32 = 2;
+pub const V4L2_WHITE_BALANCE_FLUORESCENT: i32 = 3;
+pub const V4L2_WHITE_BALANCE_FLUORESCENT_H: i32 = 4;
+pub const V4L2_WHITE_BALANCE_HORIZON: i32 = 5;
+pub const V4L2_WHITE_BALANCE_DAYLIGHT: i32 = 6;
+pub const V4L2_WHITE_BALANCE_FLASH: i32 = 7;
+pub const V4L2_WHITE_BALANCE_CLOUDY: i32 = 8;
+pub const V4L2_WHITE_BALANCE_SHADE: i32 = 9;
+
+/* uvcvideo region of interest, a V4L2_CTRL_TYPE_RECT in pixels of the
+ * current format */
+pub const V4L2_CID_USER_UVC_BASE: u32 = V4L2_CID_BASE + 0x11e0;
+pub const V4L2_CID_UVC_REGION_OF_INTEREST_RECT: u32 = V4L2_CID_USER_UVC_BASE + 1;
+pub const V4L2_CID_UVC_REGION_OF_INTEREST_AUTO: u32 = V4L2_CID_USER_UVC_BASE + 2;
+
+/* bits of V4L2_CID_UVC_REGION_OF_INTEREST_AUTO */
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_EXPOSURE: i32 = 1 << 0;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_IRIS: i32 = 1 << 1;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_WHITE_BALANCE: i32 = 1 << 2;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_FOCUS: i32 = 1 << 3;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_FACE_DETECT: i32 = 1 << 4;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_DETECT_AND_TRACK: i32 = 1 << 5;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_IMAGE_STABILIZATION: i32 = 1 << 6;
+pub const V4L2_UVC_REGION_OF_INTEREST_AUTO_HIGHER_QUALITY: i32 = 1 << 7;
+
+pub const V4L2_CID_CODEC_BASE: u32 = V4L2_CTRL_CLASS_MPEG | 0x900;
+pub const V4L2_CID_MPEG_VIDEO_B_FRAMES: u32 = V4L2_CID_CODEC_BASE + 202;
+pub const V4L2_CID_MPEG_VIDEO_GOP_SIZE: u32 = V4L2_CID_CODEC_BASE + 203;
+pub const V4L2_CID_MPEG_VIDEO_BITRATE_MODE: u32 = V4L2_CID_CODEC_BASE + 206;
+pub const V4L2_CID_MPEG_VIDEO_BITRATE: u32 = V4L2_CID_CODEC_BASE + 207;
+pub const V4L2_CID_MPEG_VIDEO_BITRATE_PEAK: u32 = V4L2_CID_CODEC_BASE + 208;
+pub const V4L2_CID_MPEG_VIDEO_FRAME_RC_ENABLE: u32 = V4L2_CID_CODEC_BASE + 215;
+pub const V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MODE: u32 = V4L2_CID_CODEC_BASE + 221;
+pub const V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MAX_MB: u32 = V4L2_CID_CODEC_BASE + 222;
+pub const V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MAX_BYTES: u32 = V4L2_CID_CODEC_BASE + 223;
+pub const V4L2_CID_MPEG_VIDEO_FORCE_KEY_FRAME: u32 = V4L2_CID_CODEC_BASE + 229;
+pub const V4L2_CID_MPEG_VIDEO_INTRA_REFRESH_PERIOD: u32 = V4L2_CID_CODEC_BASE + 237;
+pub const V4L2_CID_MPEG_VIDEO_H264_I_PERIOD: u32 = V4L2_CID_CODEC_BASE + 358;
+pub const V4L2_CID_MPEG_VIDEO_H264_LEVEL: u32 = V4L2_CID_CODEC_BASE + 359;
+pub const V4L2_CID_MPEG_VIDEO_H264_PROFILE: u32 = V4L2_CID_CODEC_BASE + 363;
+pub const V4L2_CID_MPEG_VIDEO_VPX_NUM_PARTITIONS: u32 = V4L2_CID_CODEC_BASE + 500;
+pub const V4L2_CID_MPEG_VIDEO_VPX_IMD_DISABLE_4X4: u32 = V4L2_CID_CODEC_BASE + 501;
+pub const V4L2_CID_MPEG_VIDEO_VPX_NUM_REF_FRAMES: u32 = V4L2_CID_CODEC_BASE + 502;
+pub const V4L2_CID_MPEG_VIDEO_VPX_FILTER_LEVEL: u32 = V4L2_CID_CODEC_BASE + 503;
+pub const V4L2_CID_MPEG_VIDEO_VPX_FILTER_SHARPNESS: u32 = V4L2_CID_CODEC_BASE + 504;
+pub const V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_REF_PERIOD: u32 = V4L2_CID_CODEC_BASE + 505;
+pub const V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_SEL: u32 = V4L2_CID_CODEC_BASE + 506;
+pub const V4L2_CID_MPEG_VIDEO_VPX_MIN_QP: u32 = V4L2_CID_CODEC_BASE + 507;
+pub const V4L2_CID_MPEG_VIDEO_VPX_MAX_QP: u32 = V4L2_CID_CODEC_BASE + 508;
+pub const V4L2_CID_MPEG_VIDEO_VPX_I_FRAME_QP: u32 = V4L2_CID_CODEC_BASE + 509;
+pub const V4L2_CID_MPEG_VIDEO_VPX_P_FRAME_QP: u32 = V4L2_CID_CODEC_BASE + 510;
+pub const V4L2_CID_MPEG_VIDEO_VP8_PROFILE: u32 = V4L2_CID_CODEC_BASE + 511;
+pub const V4L2_CID_MPEG_VIDEO_VP9_PROFILE: u32 = V4L2_CID_CODEC_BASE + 512;
+pub const V4L2_CID_MPEG_VIDEO_VP9_LEVEL: u32 = V4L2_CID_CODEC_BASE + 513;
+pub const V4L2_CID_MPEG_VIDEO_CONSTANT_QUALITY: u32 = V4L2_CID_CODEC_BASE + 645;
+
+/* values for V4L2_CID_MPEG_VIDEO_BITRATE_MODE */
+pub const V4L2_MPEG_VIDEO_BITRATE_MODE_VBR: i32 = 0;
+pub const V4L2_MPEG_VIDEO_BITRATE_MODE_CBR: i32 = 1;
+pub const V4L2_MPEG_VIDEO_BITRATE_MODE_CQ: i32 = 2;
+
+/* values for V4L2_CID_MPEG_VIDEO_MULTI_SLICE_MODE */
+pub const V4L2_MPEG_VIDEO_MULTI_SLICE_MODE_SINGLE: i32 = 0;
+pub const V4L2_MPEG_VIDEO_MULTI_SLICE_MODE_MAX_MB: i32 = 1;
+pub const V4L2_MPEG_VIDEO_MULTI_SLICE_MODE_MAX_BYTES: i32 = 2;
+
+/* values for V4L2_CID_MPEG_VIDEO_VPX_NUM_PARTITIONS */
+pub const V4L2_CID_MPEG_VIDEO_VPX_1_PARTITION: i32 = 0;
+pub const V4L2_CID_MPEG_VIDEO_VPX_2_PARTITIONS: i32 = 1;
+pub const V4L2_CID_MPEG_VIDEO_VPX_4_PARTITIONS: i32 = 2;
+pub const V4L2_CID_MPEG_VIDEO_VPX_8_PARTITIONS: i32 = 3;
+
+/* values for V4L2_CID_MPEG_VIDEO_VPX_NUM_REF_FRAMES */
+pub const V4L2_CID_MPEG_VIDEO_VPX_1_REF_FRAME: i32 = 0;
+pub const V4L2_CID_MPEG_VIDEO_VPX_2_REF_FRAME: i32 = 1;
+pub const V4L2_CID_MPEG_VIDEO_VPX_3_REF_FRAME: i32 = 2;
+
+/* values for V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_SEL */
+pub const V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_USE_PREV: i32 = 0;
+pub const V4L2_CID_MPEG_VIDEO_VPX_GOLDEN_FRAME_USE_REF_PERIOD: i32 = 1;
+
+/* values for V4L2_CID_MPEG_VIDEO_VP8_PROFILE and V4L2_CID_MPEG_VIDEO_VP9_PROFILE */
+pub const V4L2_MPEG_VIDEO_VPX_PROFILE_0: i32 = 0;
+pub const V4L2_MPEG_VIDEO_VPX_PROFILE_1: i32 = 1;
+pub const V4L2_MPEG_VIDEO_VPX_PROFILE_2: i32 = 2;
+pub const V4L2_MPEG_VIDEO_VPX_PROFILE_3: i32 = 3;
+
+/* values for V4L2_CID_MPEG_VIDEO_H264_LEVEL */
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_1_0: i32 = 0;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_1B: i32 = 1;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_1_1: i32 = 2;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_1_2: i32 = 3;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_1_3: i32 = 4;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_2_0: i32 = 5;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_2_1: i32 = 6;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_2_2: i32 = 7;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_3_0: i32 = 8;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_3_1: i32 = 9;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_3_2: i32 = 10;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_4_0: i32 = 11;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_4_1: i32 = 12;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_4_2: i32 = 13;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_5_0: i32 = 14;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_5_1: i32 = 15;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_5_2: i32 = 16;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_6_0: i32 = 17;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_6_1: i32 = 18;
+pub const V4L2_MPEG_VIDEO_H264_LEVEL_6_2: i32 = 19;
+
+/* values for V4L2_CID_MPEG_VIDEO_H264_PROFILE */
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_BASELINE: i32 = 0;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_CONSTRAINED_BASELINE: i32 = 1;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_MAIN: i32 = 2;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_EXTENDED: i32 = 3;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH: i32 = 4;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_10: i32 = 5;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_422: i32 = 6;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_444_PREDICTIVE: i32 = 7;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_10_INTRA: i32 = 8;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_422_INTRA: i32 = 9;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_HIGH_444_INTRA: i32 = 10;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_CAVLC_444_INTRA: i32 = 11;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_SCALABLE_BASELINE: i32 = 12;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_SCALABLE_HIGH: i32 = 13;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_SCALABLE_HIGH_INTRA: i32 = 14;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_STEREO_HIGH: i32 = 15;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_MULTIVIEW_HIGH: i32 = 16;
+pub const V4L2_MPEG_VIDEO_H264_PROFILE_CONSTRAINED_HIGH: i32 = 17;
+
+pub const V4L2_CID_JPEG_CLASS_BASE: u32 = V4L2_CTRL_CLASS_JPEG | 0x900;
+pub const V4L2_CID_JPEG_COMPRESSION_QUALITY: u32 = V4L2_CID_JPEG_CLASS_BASE + 3;
+
+pub const V4L2_CID_IMAGE_PROC_CLASS_BASE: u32 = V4L2_CTRL_CLASS_IMAGE_PROC | 0x900;
+pub const V4L2_CID_DIGITAL_GAIN: u32 = V4L2_CID_IMAGE_PROC_CLASS_BASE + 5;
+
+pub const V4L2_CID_DETECT_CLASS_BASE: u32 = V4L2_CTRL_CLASS_DETECT | 0x900;
+pub const V4L2_CID_DETECT_MD_MODE: u32 = V4L2_CID_DETECT_CLASS_BASE + 1;
+pub const V4L2_CID_DETECT_MD_GLOBAL_THRESHOLD: u32 = V4L2_CID_DETECT_CLASS_BASE + 2;
+pub const V4L2_CID_DETECT_MD_THRESHOLD_GRID: u32 = V4L2_CID_DETECT_CLASS_BASE + 3;
+pub const V4L2_CID_DETECT_MD_REGION_GRID: u32 = V4L2_CID_DETECT_CLASS_BASE + 4;
+
+/* values for V4L2_CID_DETECT_MD_MODE */
+pub const V4L2_DETECT_MD_MODE_DISABLED: i32 = 0;
+pub const V4L2_DETECT_MD_MODE_GLOBAL: i32 = 1;
+pub const V4L2_DETECT_MD_MODE_THRESHOLD_GRID: i32 = 2;
+pub const V4L2_DETECT_MD_MODE_REGION_GRID: i32 = 3;
+
+pub const V4L2_EVENT_ALL: u32 = 0;
+pub const V4L2_EVENT_VSYNC: u32 = 1;
+pub const V4L2_EVENT_EOS: u32 = 2;
+pub const V4L2_EVENT_CTRL: u32 = 3;
+pub const V4L2_EVENT_FRAME_SYNC: u32 = 4;
+pub const V4L2_EVENT_SOURCE_CHANGE: u32 = 5;
+pub const V4L2_EVENT_MOTION_DET: u32 = 6;
+pub const V4L2_EVENT_PRIVATE_START: u32 = 0x08000000;
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
 pub struct v4l2_event_vsync {
-    field: u8,
+    /* one of enum v4l2_field */
+    pub field: u8,
 }
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub union _v4l2_event_ctrl_value {
-    value: i32,
-    value64: i64,
+    pub value: i32,
+    pub value64: i64,
 }
 
+/* Payload for V4L2_EVENT_CTRL */
+pub const V4L2_EVENT_CTRL_CH_VALUE: u32 = 1 << 0;
+pub const V4L2_EVENT_CTRL_CH_FLAGS: u32 = 1 << 1;
+pub const V4L2_EVENT_CTRL_CH_RANGE: u32 = 1 << 2;
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct v4l2_event_ctrl {
-    changes: u32,
-    typ: u32,
-    v: _v4l2_event_ctrl_value,
-    flags: u32,
-    minimum: i32,
-    maximum: i32,
-    step: i32,
-    default_value: i32,
+    pub changes: u32,
+    pub typ: u32,
+    pub v: _v4l2_event_ctrl_value,
+    pub flags: u32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub step: i32,
+    pub default_value: i32,
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct v4l2_event_frame_sync {
-    frame_sequence: u32,
+    pub frame_sequence: u32,
+}
+
+pub const V4L2_EVENT_SRC_CH_RESOLUTION: u32 = 1 << 0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_event_src_change {
+    pub changes: u32,
+}
+
+pub const V4L2_EVENT_MD_FL_HAVE_FRAME_SEQ: u32 = 1 << 0;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct v4l2_event_motion_det {
+    pub flags: u32,
+    pub frame_sequence: u32,
+    pub region_mask: u32,
 }
 
 #[repr(C)]
@@ -421,6 +1189,8 @@ pub union _v4l2_event_u {
     pub vsync: v4l2_event_vsync,
     pub ctrl: v4l2_event_ctrl,
     pub frame_sync: v4l2_event_frame_sync,
+    pub src_change: v4l2_event_src_change,
+    pub motion_det: v4l2_event_motion_det,
     pub data: [u8; 64],
 }
 
@@ -435,6 +1205,9 @@ pub struct v4l2_event {
     pub reserved: [u32; 8],
 }
 
+pub const V4L2_EVENT_SUB_FL_SEND_INITIAL: u32 = 1 << 0;
+pub const V4L2_EVENT_SUB_FL_ALLOW_FEEDBACK: u32 = 1 << 1;
+
 #[repr(C)]
 pub struct v4l2_event_subscription {
     pub typ: u32,
@@ -443,6 +1216,47 @@ pub struct v4l2_event_subscription {
     pub reserved: [u32; 5],
 }
 
+pub const V4L2_DV_BT_656_1120: u32 = 0; /* BT.656/1120 timing type */
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct v4l2_bt_timings {
+    pub width: u32,
+    pub height: u32,
+    pub interlaced: u32,
+    pub polarities: u32,
+    pub pixelclock: u64,
+    pub hfrontporch: u32,
+    pub hsync: u32,
+    pub hbackporch: u32,
+    pub vfrontporch: u32,
+    pub vsync: u32,
+    pub vbackporch: u32,
+    pub il_vfrontporch: u32,
+    pub il_vsync: u32,
+    pub il_vbackporch: u32,
+    pub standards: u32,
+    pub flags: u32,
+    pub picture_aspect: v4l2_fract,
+    pub cea861_vic: u8,
+    pub hdmi_vic: u8,
+    pub reserved: [u8; 46],
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub union _v4l2_dv_timings_u {
+    pub bt: v4l2_bt_timings,
+    pub reserved: [u32; 32],
+}
+
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+pub struct v4l2_dv_timings {
+    pub typ: u32,
+    pub u: _v4l2_dv_timings_u,
+}
+
 #[repr(C)]
 pub union _v4l2_dbg_match_u {
     addr: u32,
@@ -475,7 +1289,14 @@ pub const VIDIOC_REQBUFS: ioctl_num_type =
     request_code_readwrite!(b'V', 8, mem::size_of::<v4l2_requestbuffers>());
 pub const VIDIOC_QUERYBUF: ioctl_num_type =
     request_code_readwrite!(b'V', 9, mem::size_of::<v4l2_buffer>());
-
+pub const VIDIOC_G_FBUF: ioctl_num_type =
+    request_code_read!(b'V', 10, mem::size_of::<v4l2_framebuffer>());
+pub const VIDIOC_S_FBUF: ioctl_num_type =
+    request_code_write!(b'V', 11, mem::size_of::<v4l2_framebuffer>());
+pub const VIDIOC_OVERLAY: ioctl_num_type = request_code_write!(b'V', 14, mem::size_of::<c_int>());
+
+pub const VIDIOC_EXPBUF: ioctl_num_type =
+    request_code_readwrite!(b'V', 16, mem::size_of::<v4l2_exportbuffer>());
 pub const VIDIOC_QBUF: ioctl_num_type =
     request_code_readwrite!(b'V', 15, mem::size_of::<v4l2_buffer>());
 pub const VIDIOC_DQBUF: ioctl_num_type =
@@ -486,29 +1307,213 @@ pub const VIDIOC_G_PARM: ioctl_num_type =
     request_code_readwrite!(b'V', 21, mem::size_of::<v4l2_streamparm>());
 pub const VIDIOC_S_PARM: ioctl_num_type =
     request_code_readwrite!(b'V', 22, mem::size_of::<v4l2_streamparm>());
+pub const VIDIOC_G_CTRL: ioctl_num_type =
+    request_code_readwrite!(b'V', 27, mem::size_of::<v4l2_control>());
+pub const VIDIOC_S_CTRL: ioctl_num_type =
+    request_code_readwrite!(b'V', 28, mem::size_of::<v4l2_control>());
+pub const VIDIOC_QUERYCTRL: ioctl_num_type =
+    request_code_readwrite!(b'V', 36, mem::size_of::<v4l2_queryctrl>());
+pub const VIDIOC_QUERYMENU: ioctl_num_type =
+    request_code_readwrite!(b'V', 37, mem::size_of::<v4l2_querymenu>());
+pub const VIDIOC_G_JPEGCOMP: ioctl_num_type =
+    request_code_read!(b'V', 61, mem::size_of::<v4l2_jpegcompression>());
+pub const VIDIOC_S_JPEGCOMP: ioctl_num_type =
+    request_code_write!(b'V', 62, mem::size_of::<v4l2_jpegcompression>());
+pub const VIDIOC_G_STD: ioctl_num_type =
+    request_code_read!(b'V', 23, mem::size_of::<v4l2_std_id>());
+pub const VIDIOC_S_STD: ioctl_num_type =
+    request_code_write!(b'V', 24, mem::size_of::<v4l2_std_id>());
+pub const VIDIOC_QUERYSTD: ioctl_num_type =
+    request_code_read!(b'V', 63, mem::size_of::<v4l2_std_id>());
+pub const VIDIOC_ENUMINPUT: ioctl_num_type =
+    request_code_readwrite!(b'V', 26, mem::size_of::<v4l2_input>());
 pub const VIDIOC_G_INPUT: ioctl_num_type = request_code_read!(b'V', 38, mem::size_of::<c_int>());
 pub const VIDIOC_S_INPUT: ioctl_num_type =
     request_code_readwrite!(b'V', 39, mem::size_of::<c_int>());
 
+pub const VIDIOC_G_EXT_CTRLS: ioctl_num_type =
+    request_code_readwrite!(b'V', 71, mem::size_of::<v4l2_ext_controls>());
+pub const VIDIOC_S_EXT_CTRLS: ioctl_num_type =
+    request_code_readwrite!(b'V', 72, mem::size_of::<v4l2_ext_controls>());
+pub const VIDIOC_TRY_EXT_CTRLS: ioctl_num_type =
+    request_code_readwrite!(b'V', 73, mem::size_of::<v4l2_ext_controls>());
 pub const VIDIOC_ENUM_FRAMESIZES: ioctl_num_type =
     request_code_readwrite!(b'V', 74, mem::size_of::<v4l2_frmsizeenum>());
+pub const VIDIOC_ENUM_FRAMEINTERVALS: ioctl_num_type =
+    request_code_readwrite!(b'V', 75, mem::size_of::<v4l2_frmivalenum>());
 
 pub const VIDIOC_DBG_S_REGISTER: ioctl_num_type =
     request_code_write!(b'V', 79, mem::size_of::<v4l2_dbg_register>());
 pub const VIDIOC_DBG_G_REGISTER: ioctl_num_type =
     request_code_readwrite!(b'V', 80, mem::size_of::<v4l2_dbg_register>());
 
+pub const VIDIOC_S_DV_TIMINGS: ioctl_num_type =
+    request_code_readwrite!(b'V', 87, mem::size_of::<v4l2_dv_timings>());
+pub const VIDIOC_G_DV_TIMINGS: ioctl_num_type =
+    request_code_readwrite!(b'V', 88, mem::size_of::<v4l2_dv_timings>());
 pub const VIDIOC_DQEVENT: ioctl_num_type =
     request_code_read!(b'V', 89, mem::size_of::<v4l2_event>());
+pub const VIDIOC_CREATE_BUFS: ioctl_num_type =
+    request_code_readwrite!(b'V', 92, mem::size_of::<v4l2_create_buffers>());
 pub const VIDIOC_SUBSCRIBE_EVENT: ioctl_num_type =
     request_code_write!(b'V', 90, mem::size_of::<v4l2_event_subscription>());
+pub const VIDIOC_UNSUBSCRIBE_EVENT: ioctl_num_type =
+    request_code_write!(b'V', 91, mem::size_of::<v4l2_event_subscription>());
+pub const VIDIOC_G_SELECTION: ioctl_num_type =
+    request_code_readwrite!(b'V', 94, mem::size_of::<v4l2_selection>());
+pub const VIDIOC_S_SELECTION: ioctl_num_type =
+    request_code_readwrite!(b'V', 95, mem::size_of::<v4l2_selection>());
+pub const VIDIOC_QUERY_DV_TIMINGS: ioctl_num_type =
+    request_code_read!(b'V', 99, mem::size_of::<v4l2_dv_timings>());
+
+// media.h
+
+pub const MEDIA_INTF_T_V4L_VIDEO: u32 = 0x0000_0200;
+pub const MEDIA_INTF_T_ALSA_PCM_CAPTURE: u32 = 0x0000_0300;
+pub const MEDIA_INTF_T_ALSA_PCM_PLAYBACK: u32 = 0x0000_0301;
+pub const MEDIA_INTF_T_ALSA_CONTROL: u32 = 0x0000_0302;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct media_v2_intf_devnode {
+    pub major: u32,
+    pub minor: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union _media_v2_interface_u {
+    pub devnode: media_v2_intf_devnode,
+    pub raw: [u32; 16],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct media_v2_interface {
+    pub id: u32,
+    pub intf_type: u32,
+    pub flags: u32,
+    pub reserved: [u32; 9],
+    pub u: _media_v2_interface_u,
+}
+
+#[repr(C)]
+pub struct media_v2_topology {
+    pub topology_version: u64,
+    pub num_entities: u32,
+    pub reserved1: u32,
+    pub ptr_entities: u64,
+    pub num_interfaces: u32,
+    pub reserved2: u32,
+    pub ptr_interfaces: u64,
+    pub num_pads: u32,
+    pub reserved3: u32,
+    pub ptr_pads: u64,
+    pub num_links: u32,
+    pub reserved4: u32,
+    pub ptr_links: u64,
+}
+
+pub const MEDIA_IOC_G_TOPOLOGY: ioctl_num_type =
+    request_code_readwrite!(b'|', 0x04, mem::size_of::<media_v2_topology>());
+pub const MEDIA_IOC_REQUEST_ALLOC: ioctl_num_type =
+    request_code_read!(b'|', 0x05, mem::size_of::<c_int>());
+pub const MEDIA_REQUEST_IOC_QUEUE: ioctl_num_type = request_code_none!(b'|', 0x80);
+pub const MEDIA_REQUEST_IOC_REINIT: ioctl_num_type = request_code_none!(b'|', 0x81);
 
 #[cfg(feature = "sunxi-vfe")]
 mod sunxi_vfe {
+    use super::*;
+
     pub const V4L2_MODE_VIDEO: u32 = 0x0002; /*  Added by raymonxiu For video capture */
     pub const V4L2_MODE_IMAGE: u32 = 0x0003; /*  Added by raymonxiu For image capture */
     pub const V4L2_MODE_PREVIEW: u32 = 0x0004; /*  Added by raymonxiu For preview capture */
+
+    /* ISP controls of the Allwinner BSP kernel */
+    pub const V4L2_CID_HOR_VISUAL_ANGLE: u32 = V4L2_CID_CAMERA_CLASS_BASE + 36;
+    pub const V4L2_CID_VER_VISUAL_ANGLE: u32 = V4L2_CID_CAMERA_CLASS_BASE + 37;
+    pub const V4L2_CID_FOCUS_LENGTH: u32 = V4L2_CID_CAMERA_CLASS_BASE + 38;
+    pub const V4L2_CID_R_GAIN: u32 = V4L2_CID_CAMERA_CLASS_BASE + 39;
+    pub const V4L2_CID_G_GAIN: u32 = V4L2_CID_CAMERA_CLASS_BASE + 40;
+    pub const V4L2_CID_B_GAIN: u32 = V4L2_CID_CAMERA_CLASS_BASE + 41;
+    pub const V4L2_CID_FLASH_LED_MODE_V1: u32 = V4L2_CID_CAMERA_CLASS_BASE + 42;
+
+    /* values for V4L2_CID_FLASH_LED_MODE_V1 */
+    pub const V4L2_FLASH_MODE_NONE: i32 = 0;
+    pub const V4L2_FLASH_MODE_ON: i32 = 1;
+    pub const V4L2_FLASH_MODE_TORCH: i32 = 2;
+    pub const V4L2_FLASH_MODE_AUTO: i32 = 3;
+    pub const V4L2_FLASH_MODE_RED_EYE: i32 = 4;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct isp_stat_buf {
+        pub buf: *mut c_void,
+        pub buf_size: u32,
+    }
+
+    pub const BASE_VIDIOC_PRIVATE: u8 = 192;
+
+    pub const VIDIOC_ISP_AE_STAT_REQ: ioctl_num_type = request_code_readwrite!(
+        b'V',
+        BASE_VIDIOC_PRIVATE + 1,
+        mem::size_of::<isp_stat_buf>()
+    );
+    pub const VIDIOC_ISP_HIST_STAT_REQ: ioctl_num_type = request_code_readwrite!(
+        b'V',
+        BASE_VIDIOC_PRIVATE + 2,
+        mem::size_of::<isp_stat_buf>()
+    );
+    pub const VIDIOC_ISP_AF_STAT_REQ: ioctl_num_type = request_code_readwrite!(
+        b'V',
+        BASE_VIDIOC_PRIVATE + 3,
+        mem::size_of::<isp_stat_buf>()
+    );
+    pub const VIDIOC_ISP_GAMMA_REQ: ioctl_num_type = request_code_readwrite!(
+        b'V',
+        BASE_VIDIOC_PRIVATE + 5,
+        mem::size_of::<isp_stat_buf>()
+    );
 }
 
 #[cfg(feature = "sunxi-vfe")]
 pub use self::sunxi_vfe::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timecode(hours: u8, minutes: u8, seconds: u8, frames: u8, flags: u32) -> v4l2_timecode {
+        v4l2_timecode {
+            typ: V4L2_TC_TYPE_30FPS,
+            flags,
+            frames,
+            seconds,
+            minutes,
+            hours,
+            userbits: [0; 4],
+        }
+    }
+
+    #[test]
+    fn formats_smpte_timecodes() {
+        assert_eq!(timecode(0, 0, 0, 0, 0).to_string(), "00:00:00:00");
+        assert_eq!(timecode(1, 2, 3, 4, 0).to_string(), "01:02:03:04");
+        assert_eq!(timecode(23, 59, 59, 29, 0).to_string(), "23:59:59:29");
+        assert_eq!(
+            timecode(1, 2, 3, 4, V4L2_TC_FLAG_COLORFRAME).to_string(),
+            "01:02:03:04"
+        );
+    }
+
+    #[test]
+    fn formats_drop_frame_timecodes() {
+        let flags = V4L2_TC_FLAG_DROPFRAME;
+        assert_eq!(timecode(0, 1, 0, 2, flags).to_string(), "00:01:00;02");
+        assert_eq!(timecode(10, 9, 59, 29, flags).to_string(), "10:09:59;29");
+        assert_eq!(
+            timecode(0, 1, 0, 2, flags | V4L2_TC_FLAG_COLORFRAME).to_string(),
+            "00:01:00;02"
+        );
+    }
+}
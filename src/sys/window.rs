@@ -0,0 +1,223 @@
+use std::io;
+use std::ptr;
+
+use super::uapi::*;
+
+/// Placement of a video overlay on the target framebuffer.
+///
+/// Parts of the window can be masked out with a list of clip rectangles,
+/// or with a bitmap holding one bit per window pixel, set where the video
+/// is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    /// Position of the overlay, relative to the framebuffer origin.
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+    pub field: v4l2_field,
+    /// Framebuffer pixel value the overlay replaces, if chroma keying is
+    /// enabled with `V4L2_FBUF_FLAG_CHROMAKEY`.
+    pub chromakey: u32,
+    /// Alpha of the whole overlay, if `V4L2_FBUF_FLAG_GLOBAL_ALPHA` is set.
+    pub global_alpha: u8,
+    /// Rectangles, relative to the window, where the video is hidden.
+    pub clips: Vec<v4l2_rect>,
+    /// Clipping bitmap of `bitmap_len(width, height)` bytes.
+    pub bitmap: Option<Vec<u8>>,
+}
+
+/// Returns the size in bytes of the clipping bitmap of a window. Each line
+/// is padded to whole bytes.
+pub fn bitmap_len(width: u32, height: u32) -> usize {
+    width.div_ceil(8) as usize * height as usize
+}
+
+impl Window {
+    pub fn new(left: i32, top: i32, width: u32, height: u32) -> Window {
+        Window {
+            left,
+            top,
+            width,
+            height,
+            field: v4l2_field::V4L2_FIELD_ANY,
+            chromakey: 0,
+            global_alpha: 255,
+            clips: Vec::new(),
+            bitmap: None,
+        }
+    }
+
+    pub fn field(mut self, field: v4l2_field) -> Window {
+        self.field = field;
+        self
+    }
+
+    pub fn chromakey(mut self, chromakey: u32) -> Window {
+        self.chromakey = chromakey;
+        self
+    }
+
+    pub fn global_alpha(mut self, alpha: u8) -> Window {
+        self.global_alpha = alpha;
+        self
+    }
+
+    /// Hides the video in `rect`, given relative to the window.
+    pub fn clip(mut self, rect: v4l2_rect) -> Window {
+        self.clips.push(rect);
+        self
+    }
+
+    pub fn bitmap(mut self, bitmap: Vec<u8>) -> Window {
+        self.bitmap = Some(bitmap);
+        self
+    }
+
+    /// Decodes the placement of a raw window. The clip list and bitmap are
+    /// not read, as they are only valid as long as their owner keeps them.
+    pub fn from_raw(win: &v4l2_window) -> Window {
+        Window {
+            left: win.w.left,
+            top: win.w.top,
+            width: win.w.width as u32,
+            height: win.w.height as u32,
+            field: win.field,
+            chromakey: win.chromakey,
+            global_alpha: win.global_alpha,
+            clips: Vec::new(),
+            bitmap: None,
+        }
+    }
+
+    /// Builds the raw window, together with the clip array and bitmap it
+    /// points to.
+    pub fn to_raw(&self) -> io::Result<RawWindow> {
+        if let Some(ref bitmap) = self.bitmap {
+            if bitmap.len() != bitmap_len(self.width, self.height) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "clipping bitmap does not match the window size",
+                ));
+            }
+        }
+
+        let clips = self
+            .clips
+            .iter()
+            .map(|&c| v4l2_clip {
+                c,
+                next: ptr::null_mut(),
+            })
+            .collect();
+        Ok(RawWindow::new(self, clips, self.bitmap.clone()))
+    }
+}
+
+impl<'a> From<&'a v4l2_window> for Window {
+    fn from(win: &'a v4l2_window) -> Window {
+        Window::from_raw(win)
+    }
+}
+
+/// A `v4l2_window` owning the clip array and bitmap it points to, so that
+/// the pointers stay valid while it is passed to the driver.
+pub struct RawWindow {
+    raw: v4l2_window,
+    clips: Vec<v4l2_clip>,
+    bitmap: Option<Vec<u8>>,
+}
+
+impl RawWindow {
+    fn new(window: &Window, mut clips: Vec<v4l2_clip>, mut bitmap: Option<Vec<u8>>) -> RawWindow {
+        RawWindow {
+            raw: v4l2_window {
+                w: v4l2_rect {
+                    left: window.left,
+                    top: window.top,
+                    width: window.width as i32,
+                    height: window.height as i32,
+                },
+                field: window.field,
+                chromakey: window.chromakey,
+                clips: if clips.is_empty() {
+                    ptr::null_mut()
+                } else {
+                    clips.as_mut_ptr()
+                },
+                clipcount: clips.len() as u32,
+                bitmap: bitmap
+                    .as_mut()
+                    .map_or(ptr::null_mut(), |b| b.as_mut_ptr() as *mut _),
+                global_alpha: window.global_alpha,
+            },
+            clips,
+            bitmap,
+        }
+    }
+
+    /// Returns a raw window with room for `clipcount` clip rectangles, to
+    /// be filled by `VIDIOC_G_FMT`.
+    pub(crate) fn with_capacity(clipcount: u32) -> RawWindow {
+        let empty = v4l2_rect {
+            left: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+        };
+        let clips = (0..clipcount)
+            .map(|_| v4l2_clip {
+                c: empty,
+                next: ptr::null_mut(),
+            })
+            .collect();
+        RawWindow::new(&Window::new(0, 0, 0, 0), clips, None)
+    }
+
+    pub fn as_raw(&self) -> &v4l2_window {
+        &self.raw
+    }
+
+    /// Takes over the values the driver returned in `win`, keeping the
+    /// pointers to the owned clip array and bitmap.
+    pub(crate) fn update(&mut self, win: &v4l2_window) {
+        self.raw = v4l2_window {
+            clips: self.raw.clips,
+            bitmap: self.raw.bitmap,
+            ..*win
+        };
+    }
+
+    /// Decodes the window, including the clips the driver kept.
+    pub fn to_window(&self) -> Window {
+        let count = (self.raw.clipcount as usize).min(self.clips.len());
+        Window {
+            clips: self.clips[..count].iter().map(|clip| clip.c).collect(),
+            bitmap: self.bitmap.clone(),
+            ..Window::from_raw(&self.raw)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_window_owns_clips() {
+        let rect = v4l2_rect {
+            left: 8,
+            top: 8,
+            width: 16,
+            height: 16,
+        };
+        let window = Window::new(10, 20, 64, 48).clip(rect).clip(rect);
+        let raw = window.to_raw().unwrap();
+        assert_eq!(raw.as_raw().clipcount, 2);
+        assert_eq!(unsafe { (*raw.as_raw().clips.add(1)).c }, rect);
+        assert_eq!(raw.to_window(), window);
+
+        assert!(window.clone().bitmap(vec![0; 10]).to_raw().is_err());
+        assert!(window.bitmap(vec![0; 8 * 48]).to_raw().is_ok());
+    }
+}
@@ -0,0 +1,212 @@
+//! A `Capture` whose stream state is part of its type, so that calls in
+//! the wrong state, such as taking frames before streaming or preparing
+//! buffers while streaming, do not compile.
+//!
+//! ```no_run
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0").open_configured()?;
+//! let prepared = capture.prepare(4).map_err(|(_, e)| e)?;
+//! let mut capture = match prepared.start() {
+//!     Ok(capture) => capture,
+//!     // Still prepared, so starting can be retried.
+//!     Err((prepared, _)) => prepared.start().map_err(|(_, e)| e)?,
+//! };
+//! let (buf, _data) = capture.take_frame()?;
+//! capture.requeue(buf.index as usize)?;
+//! let capture = capture.stop().map_err(|(_, e)| e)?.unprepare();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Frames are only taken while streaming:
+//!
+//! ```compile_fail
+//! # fn run() -> std::io::Result<()> {
+//! let mut capture = v4l2::Capture::with_device("/dev/video0").open_configured()?;
+//! capture.take_frame()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Streaming needs buffers:
+//!
+//! ```compile_fail
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0").open_configured()?;
+//! let _ = capture.start();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Buffers are not prepared again while streaming:
+//!
+//! ```compile_fail
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0").open_configured()?;
+//! let capture = capture.prepare(4).map_err(|(_, e)| e)?;
+//! let capture = capture.start().map_err(|(_, e)| e)?;
+//! let _ = capture.prepare(4);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The format is not changed while buffers are prepared:
+//!
+//! ```compile_fail
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0").open_configured()?;
+//! let mut capture = capture.prepare(4).map_err(|(_, e)| e)?;
+//! let format = *capture.format();
+//! capture.reconfigure(&format)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A capture that is not streaming cannot be stopped:
+//!
+//! ```compile_fail
+//! # fn run() -> std::io::Result<()> {
+//! let capture = v4l2::Capture::with_device("/dev/video0").open_configured()?;
+//! let capture = capture.prepare(4).map_err(|(_, e)| e)?;
+//! let _ = capture.stop();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Transitions consume the capture. If one fails, the capture is handed
+//! back in its old state together with the error.
+
+use std::io;
+use std::marker::PhantomData;
+
+use memmap::MmapMut;
+
+use crate::frame::Frame;
+use crate::stats::CaptureStats;
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+/// Opened and configured, without buffers.
+pub enum Configured {}
+
+/// Buffers prepared, not streaming.
+pub enum Prepared {}
+
+/// Streaming.
+pub enum Streaming {}
+
+/// The result of a transition from state `Old` to `New`: the capture in
+/// its new state, or in its old state with the error.
+pub type Transition<New, Old> = Result<TypedCapture<New>, (TypedCapture<Old>, io::Error)>;
+
+/// A `crate::Capture` in state `S`. The capture is boxed so that
+/// transitions, which move it, stay cheap.
+pub struct TypedCapture<S> {
+    inner: Box<crate::Capture>,
+    state: PhantomData<S>,
+}
+
+impl<S> TypedCapture<S> {
+    fn with_state<T>(self) -> TypedCapture<T> {
+        TypedCapture {
+            inner: self.inner,
+            state: PhantomData,
+        }
+    }
+
+    /// Returns the underlying device, e.g. to set controls.
+    pub fn device(&self) -> &V4l2Device {
+        self.inner.device()
+    }
+
+    pub fn format(&self) -> &v4l2_pix_format {
+        self.inner.format()
+    }
+
+    pub fn stats(&self) -> CaptureStats {
+        self.inner.stats()
+    }
+
+    /// Returns the untyped capture, whose state is checked at runtime.
+    pub fn into_inner(self) -> crate::Capture {
+        *self.inner
+    }
+}
+
+impl TypedCapture<Configured> {
+    /// Wraps `capture`, releasing any buffers it prepared.
+    pub fn new(mut capture: crate::Capture) -> TypedCapture<Configured> {
+        capture.unprepare();
+        TypedCapture {
+            inner: Box::new(capture),
+            state: PhantomData,
+        }
+    }
+
+    /// Changes the format, see `crate::Capture::reconfigure`.
+    pub fn reconfigure(&mut self, format: &v4l2_pix_format) -> io::Result<v4l2_pix_format> {
+        self.inner.reconfigure(format)
+    }
+
+    /// Prepares `count` buffers. On failure, buffers prepared on the way
+    /// are released again.
+    pub fn prepare(mut self, count: usize) -> Transition<Prepared, Configured> {
+        match self.inner.prepare_mmapped(count) {
+            Ok(()) => Ok(self.with_state()),
+            Err(e) => {
+                self.inner.unprepare();
+                Err((self, e))
+            }
+        }
+    }
+}
+
+impl TypedCapture<Prepared> {
+    /// Queues the buffers and starts streaming.
+    pub fn start(self) -> Transition<Streaming, Prepared> {
+        match self.inner.start() {
+            Ok(()) => Ok(self.with_state()),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    /// Unmaps the buffers and releases them in the driver.
+    pub fn unprepare(mut self) -> TypedCapture<Configured> {
+        self.inner.unprepare();
+        self.with_state()
+    }
+}
+
+impl TypedCapture<Streaming> {
+    /// Dequeues a frame, see `crate::Capture::take_frame`.
+    pub fn take_frame(&mut self) -> io::Result<(v4l2_buffer, &mut MmapMut)> {
+        self.inner.take_frame()
+    }
+
+    /// Dequeues a frame as a read-only `Frame` view.
+    pub fn dequeue_frame(&self) -> io::Result<Frame<'_>> {
+        self.inner.dequeue_frame()
+    }
+
+    pub fn return_frame(&self, buf: &v4l2_buffer) -> io::Result<()> {
+        self.inner.return_frame(buf)
+    }
+
+    pub fn requeue(&self, index: usize) -> io::Result<()> {
+        self.inner.requeue(index)
+    }
+
+    /// Stops and starts streaming again, see `crate::Capture::restart`.
+    pub fn restart(&self) -> io::Result<()> {
+        self.inner.restart()
+    }
+
+    /// Stops streaming. Buffers the application holds stay valid until
+    /// returned.
+    pub fn stop(self) -> Transition<Prepared, Streaming> {
+        match self.inner.stop() {
+            Ok(()) => Ok(self.with_state()),
+            Err(e) => Err((self, e)),
+        }
+    }
+}
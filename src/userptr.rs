@@ -0,0 +1,133 @@
+//! Page-aligned buffers for `V4L2_MEMORY_USERPTR` streaming.
+//!
+//! Drivers pin user pointer buffers page by page, and many reject buffers
+//! that do not start on a page boundary or whose length is not a multiple
+//! of the page size with `EINVAL` at `VIDIOC_QBUF`. Heap allocations give
+//! no such guarantee, so these buffers are anonymous mappings instead.
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::slice;
+
+use memmap::MmapMut;
+
+use crate::sys::uapi::*;
+
+/// Returns the size of a memory page.
+pub fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// A page-aligned buffer whose length is a whole number of pages.
+///
+/// With guard pages, an inaccessible page is mapped on either side, so
+/// that a driver or application overrunning the buffer faults instead of
+/// corrupting other memory.
+pub struct UserPtrBuffer {
+    map: MmapMut,
+    data: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for UserPtrBuffer {}
+unsafe impl Sync for UserPtrBuffer {}
+
+impl UserPtrBuffer {
+    /// Allocates a zeroed buffer of at least `size` bytes.
+    pub fn new(size: usize) -> io::Result<UserPtrBuffer> {
+        UserPtrBuffer::allocate(size, false)
+    }
+
+    /// Allocates a zeroed buffer of at least `size` bytes between two
+    /// guard pages.
+    pub fn with_guard_pages(size: usize) -> io::Result<UserPtrBuffer> {
+        UserPtrBuffer::allocate(size, true)
+    }
+
+    /// Allocates a buffer like `new`, as the mapping that holds it, for
+    /// the buffers of a `Capture`.
+    pub(crate) fn new_mapping(size: usize) -> io::Result<MmapMut> {
+        Ok(UserPtrBuffer::allocate(size, false)?.map)
+    }
+
+    /// Allocates a buffer large enough for a frame in `format`.
+    pub fn for_format(format: &v4l2_pix_format, guard_pages: bool) -> io::Result<UserPtrBuffer> {
+        UserPtrBuffer::allocate(format.sizeimage as usize, guard_pages)
+    }
+
+    fn allocate(size: usize, guard_pages: bool) -> io::Result<UserPtrBuffer> {
+        if size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer size must not be zero",
+            ));
+        }
+
+        let page = page_size();
+        let len = size.div_ceil(page) * page;
+        let guard = if guard_pages { page } else { 0 };
+        let map_len = len + 2 * guard;
+
+        let mut map = MmapMut::map_anon(map_len)?;
+        let data = unsafe { map.as_mut_ptr().add(guard) };
+        if guard_pages {
+            for page_start in [map.as_mut_ptr(), unsafe { data.add(len) }] {
+                let addr = page_start as *mut libc::c_void;
+                if unsafe { libc::mprotect(addr, guard, libc::PROT_NONE) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(UserPtrBuffer { map, data, len })
+    }
+
+    /// Returns the address to pass in `v4l2_buffer.m.userptr`.
+    pub fn userptr(&self) -> libc::c_ulong {
+        self.data as libc::c_ulong
+    }
+
+    /// Describes the buffer for queuing as buffer `index` of a
+    /// `V4L2_MEMORY_USERPTR` queue.
+    pub fn to_buffer(&self, buf_type: v4l2_buf_type, index: u32) -> v4l2_buffer {
+        let mut buf = v4l2_buffer::new(buf_type, v4l2_memory::V4L2_MEMORY_USERPTR);
+        buf.index = index;
+        buf.m.userptr = self.userptr();
+        buf.length = self.len as u32;
+        buf
+    }
+}
+
+impl Deref for UserPtrBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data, self.len) }
+    }
+}
+
+impl DerefMut for UserPtrBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.data, self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_aligned_with_guards() {
+        let page = page_size();
+        let mut buffer = UserPtrBuffer::with_guard_pages(page + 1).unwrap();
+        assert_eq!(buffer.userptr() as usize % page, 0);
+        assert_eq!(buffer.len(), 2 * page);
+        assert_eq!(buffer.map.len(), 4 * page);
+
+        buffer[2 * page - 1] = 0xff;
+        assert_eq!(buffer[0], 0);
+
+        let mapping = UserPtrBuffer::new_mapping(page + 1).unwrap();
+        assert_eq!(mapping.as_ptr() as usize % page, 0);
+        assert_eq!(mapping.len(), 2 * page);
+    }
+}
@@ -0,0 +1,99 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::output::Output;
+use crate::sys::uapi::*;
+use crate::sys::V4l2Device;
+
+/// Feeds frames into a v4l2loopback node, so that other applications can
+/// open it as a webcam.
+///
+/// Frames are paced to the configured frame rate: `write_frame` sleeps
+/// until the next frame is due.
+pub struct VirtualCamera {
+    output: Output,
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl VirtualCamera {
+    /// Opens the loopback node at `path` for `width`x`height` frames in
+    /// `pixelformat` at `fps` frames per second.
+    pub fn open(
+        path: &str,
+        width: u32,
+        height: u32,
+        pixelformat: u32,
+        fps: u32,
+    ) -> io::Result<VirtualCamera> {
+        if fps == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame rate must not be zero",
+            ));
+        }
+
+        let device = V4l2Device::open(path)?;
+        let mut format = device.output_format()?;
+        format.width = width;
+        format.height = height;
+        format.pixelformat = pixelformat;
+        format.field = v4l2_field::V4L2_FIELD_NONE;
+        format.bytesperline = 0;
+        format.sizeimage = 0;
+        let output = Output::from_device(device, &format)?;
+
+        // Lets readers see the frame rate; pacing does not depend on it.
+        if let Ok(mut parm) = output.device().output_parm() {
+            parm.timeperframe = v4l2_fract {
+                numerator: 1,
+                denominator: fps,
+            };
+            let _ = output.device().set_output_parm(&parm);
+        }
+
+        Ok(VirtualCamera {
+            output,
+            interval: Duration::from_secs(1) / fps,
+            next: None,
+        })
+    }
+
+    /// Returns the underlying output, e.g. for the negotiated format.
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Writes a frame of exactly `sizeimage` bytes once it is due.
+    ///
+    /// If the caller falls behind by more than a frame, pacing restarts
+    /// from now instead of writing the missed frames in a burst.
+    pub fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() != self.output.format().sizeimage as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size does not match the format",
+            ));
+        }
+
+        let now = Instant::now();
+        let due = match self.next {
+            Some(next) if next + self.interval > now => {
+                if next > now {
+                    thread::sleep(next - now);
+                }
+                next
+            }
+            _ => now,
+        };
+
+        self.output.write_frame(data)?;
+        self.next = Some(due + self.interval);
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! White balance ramps, for time-lapses that keep colors consistent while
+//! the light changes.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::sys::uapi::*;
+use crate::sys::{ControlInfo, V4l2Device};
+
+/// Controls that switch white balance to automatic, with their manual
+/// value.
+const AUTO_CONTROLS: [(u32, i32); 2] = [
+    (V4L2_CID_AUTO_WHITE_BALANCE, 0),
+    (
+        V4L2_CID_AUTO_N_PRESET_WHITE_BALANCE,
+        V4L2_WHITE_BALANCE_MANUAL,
+    ),
+];
+
+/// A linear ramp of `V4L2_CID_WHITE_BALANCE_TEMPERATURE`, in Kelvin.
+///
+/// Automatic white balance is switched off while ramping. The ramp is not
+/// timed by itself: `update` sets the temperature due at the time it is
+/// called, e.g. before each frame of a time-lapse.
+pub struct TemperatureRamp {
+    control: ControlInfo,
+    from: i32,
+    to: i32,
+    duration: Duration,
+    start: Instant,
+    auto: Vec<(u32, i32)>,
+    last: Option<i32>,
+}
+
+impl TemperatureRamp {
+    /// Switches `device` to manual white balance and starts ramping from
+    /// `from` to `to` over `duration`. Both ends are clamped to the range
+    /// the driver reports. If that fails, automatic white balance is
+    /// switched back on.
+    pub fn start(
+        device: &V4l2Device,
+        from: i32,
+        to: i32,
+        duration: Duration,
+    ) -> io::Result<TemperatureRamp> {
+        let qctrl = device.query_control(V4L2_CID_WHITE_BALANCE_TEMPERATURE)?;
+        let control = ControlInfo::from_raw(&qctrl, None);
+        let (from, to) = (control.clamp(from), control.clamp(to));
+        control.validate(from)?;
+
+        let mut auto = Vec::new();
+        for &(id, manual) in &AUTO_CONTROLS {
+            if let Ok(value) = device.control(id) {
+                if value != manual {
+                    if let Err(e) = device.set_control(id, manual) {
+                        let _ = set_controls(device, &auto);
+                        return Err(e);
+                    }
+                    auto.push((id, value));
+                }
+            }
+        }
+
+        let mut ramp = TemperatureRamp {
+            control,
+            from,
+            to,
+            duration,
+            start: Instant::now(),
+            auto,
+            last: None,
+        };
+        if let Err(e) = ramp.update(device) {
+            let _ = set_controls(device, &ramp.auto);
+            return Err(e);
+        }
+        Ok(ramp)
+    }
+
+    /// Returns the temperature due `elapsed` after the start.
+    pub fn temperature_at(&self, elapsed: Duration) -> i32 {
+        if elapsed >= self.duration {
+            return self.to;
+        }
+        let delta = i128::from(self.to) - i128::from(self.from);
+        let value = i128::from(self.from)
+            + delta * elapsed.as_nanos() as i128 / self.duration.as_nanos() as i128;
+        self.control.clamp(value as i32)
+    }
+
+    /// Sets the temperature due now, if it changed since the last update,
+    /// and returns it.
+    pub fn update(&mut self, device: &V4l2Device) -> io::Result<i32> {
+        let value = self.temperature_at(self.start.elapsed());
+        if self.last != Some(value) {
+            device.set_control(V4L2_CID_WHITE_BALANCE_TEMPERATURE, value)?;
+            self.last = Some(value);
+        }
+        Ok(value)
+    }
+
+    /// Returns true once the ramp reached its end temperature.
+    pub fn is_done(&self) -> bool {
+        self.start.elapsed() >= self.duration
+    }
+
+    /// Switches automatic white balance back on, if it was on when the
+    /// ramp started. Otherwise the end temperature stays set.
+    pub fn restore(self, device: &V4l2Device) -> io::Result<()> {
+        set_controls(device, &self.auto)
+    }
+}
+
+/// Sets the automatic white balance controls in `auto` back to their
+/// saved values.
+fn set_controls(device: &V4l2Device, auto: &[(u32, i32)]) -> io::Result<()> {
+    for &(id, value) in auto {
+        device.set_control(id, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_on_control_steps() {
+        let ramp = TemperatureRamp {
+            control: ControlInfo::integer(V4L2_CID_WHITE_BALANCE_TEMPERATURE, 2800, 6500, 10, 4600),
+            from: 6500,
+            to: 3000,
+            duration: Duration::from_secs(3600),
+            start: Instant::now(),
+            auto: Vec::new(),
+            last: None,
+        };
+        assert_eq!(ramp.temperature_at(Duration::from_secs(0)), 6500);
+        assert_eq!(ramp.temperature_at(Duration::from_secs(1800)), 4750);
+        assert_eq!(ramp.temperature_at(Duration::from_secs(1)), 6500);
+        assert_eq!(ramp.temperature_at(Duration::from_secs(7200)), 3000);
+    }
+}
@@ -0,0 +1,56 @@
+//! `Capture::dequeue_into` allocates nothing once streaming. The counting
+//! allocator is global, so this test has a binary of its own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use v4l2::faulty::MemoryDevice;
+use v4l2::sys::uapi::*;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts the allocations of each thread.
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn dequeues_into_caller_buffer_without_allocating() {
+    let mut capture = MemoryDevice::new(4, 2).into_capture();
+    capture.prepare_mmapped(2).unwrap();
+    capture.start().unwrap();
+
+    let mut buf = v4l2_buffer::new(
+        v4l2_buf_type::V4L2_BUF_TYPE_VIDEO_CAPTURE,
+        v4l2_memory::V4L2_MEMORY_MMAP,
+    );
+    let before = allocations();
+    for _ in 0..16 {
+        assert_eq!(capture.dequeue_into(&mut buf).unwrap().len(), 8);
+        capture.return_frame(&buf).unwrap();
+    }
+    assert_eq!(allocations(), before);
+}